@@ -1,8 +1,13 @@
 use crate::app_state::AppState;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use winit::{
-    application::ApplicationHandler, event::*, event_loop::ActiveEventLoop, window::Window,
+    application::ApplicationHandler,
+    event::*,
+    event_loop::ActiveEventLoop,
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
 };
 
 #[cfg(target_arch = "wasm32")]
@@ -16,17 +21,20 @@ pub struct App {
     state: Option<AppState>,
     /// Module path from CLI arguments (takes precedence over config)
     cli_module: Option<String>,
+    /// Scene file from CLI arguments, opened on startup
+    cli_scene: Option<PathBuf>,
 }
 
 impl Default for App {
     fn default() -> Self {
-        Self::new(None)
+        Self::new(None, None)
     }
 }
 
 impl App {
     pub fn new(
         cli_module: Option<String>,
+        cli_scene: Option<PathBuf>,
         #[cfg(target_arch = "wasm32")] event_loop: &EventLoop<AppState>,
     ) -> Self {
         #[cfg(target_arch = "wasm32")]
@@ -34,6 +42,7 @@ impl App {
         Self {
             state: None,
             cli_module,
+            cli_scene,
             #[cfg(target_arch = "wasm32")]
             proxy,
         }
@@ -72,7 +81,12 @@ impl ApplicationHandler<AppState> for App {
             // If we are not on web we can use pollster to
             // await the
             self.state = Some(
-                pollster::block_on(AppState::new(window, self.cli_module.take())).unwrap(),
+                pollster::block_on(AppState::new(
+                    window,
+                    self.cli_module.take(),
+                    self.cli_scene.take(),
+                ))
+                .unwrap(),
             );
         }
 
@@ -83,7 +97,7 @@ impl ApplicationHandler<AppState> for App {
                     assert!(
                         proxy
                             .send_event(
-                                AppState::new(window, None)
+                                AppState::new(window, None, None)
                                     .await
                                     .expect("Unable to create canvas!!!")
                             )
@@ -113,13 +127,11 @@ impl ApplicationHandler<AppState> for App {
         _window_id: winit::window::WindowId,
         event: WindowEvent,
     ) {
-        if let Some(state) = self.state.as_mut() {
-            state.event(&event);
-        }
         let state = match &mut self.state {
             Some(canvas) => canvas,
             None => return,
         };
+        let consumed = state.event(&event);
 
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
@@ -137,9 +149,23 @@ impl ApplicationHandler<AppState> for App {
                 }
             }
             WindowEvent::RedrawRequested => state.handle_redraw(),
-            WindowEvent::MouseInput { state, button, .. } => match (button, state.is_pressed()) {
-                (MouseButton::Left, true) => {}
-                (MouseButton::Left, false) => {}
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F12),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => state.screenshot(),
+            WindowEvent::MouseInput {
+                state: button_state,
+                button,
+                ..
+            } => match (button, button_state.is_pressed()) {
+                (MouseButton::Left, true) if !consumed => state.begin_click(),
+                (MouseButton::Left, false) => state.end_click(),
                 _ => {}
             },
 
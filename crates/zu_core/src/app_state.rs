@@ -1,3 +1,4 @@
+use crate::camera_controller::CameraController;
 use crate::egui_tools::EguiRenderer;
 use crate::gui::EngineGui;
 
@@ -7,9 +8,12 @@ use crate::styles::default_dark::default_dark_theme;
 use egui_wgpu::wgpu::SurfaceError;
 use egui_wgpu::{ScreenDescriptor, wgpu};
 use log::info;
+use std::path::PathBuf;
 use std::sync::Arc;
-use wgpu::{ExperimentalFeatures, Instance, InstanceFlags, Limits, PresentMode};
+use std::time::Instant;
+use wgpu::{ExperimentalFeatures, Instance, InstanceFlags, PresentMode};
 
+use glam::Vec2;
 use winit::event::WindowEvent;
 
 #[cfg(target_arch = "wasm32")]
@@ -30,10 +34,21 @@ pub struct AppState {
     vsync_enabled: bool,
     instance: Instance,
     recreate_render_pass_manager: bool,
+    camera_controller: CameraController,
+    last_frame: Instant,
+    /// Where the left mouse button went down over the scene.
+    click_start: Option<Vec2>,
 }
 
+/// Pixels the cursor may move between press and release for it to still count as a click.
+const CLICK_SLOP: f32 = 4.0;
+
 impl AppState {
-    pub async fn new(window: Arc<Window>, _cli_module: Option<String>) -> anyhow::Result<Self> {
+    pub async fn new(
+        window: Arc<Window>,
+        _cli_module: Option<String>,
+        cli_scene: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
         info!("Creating App State...");
         let mut flags = InstanceFlags::default();
         flags.remove(InstanceFlags::VALIDATION);
@@ -71,20 +86,12 @@ impl AppState {
             adapter.limits()
         );
 
-        let features = wgpu::Features::PUSH_CONSTANTS
-            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-            | wgpu::Features::FLOAT32_FILTERABLE;
-        let mut limits = Limits::default();
-        limits.max_push_constant_size = 128;
-        limits.max_compute_workgroup_size_x = 64;
-        limits.max_compute_workgroup_size_y = 64;
-        limits.max_compute_invocations_per_workgroup = 1024;
-
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: features,
-                required_limits: limits,
+                required_features: RenderPassManager::required_features()
+                    | RenderPassManager::optional_features(),
+                required_limits: RenderPassManager::required_limits(),
                 memory_hints: Default::default(),
                 trace: wgpu::Trace::Off,
                 experimental_features: ExperimentalFeatures::disabled(),
@@ -139,10 +146,13 @@ impl AppState {
 
         let scale_factor = 1.0;
 
-        let engine_gui = EngineGui::new(egui_renderer.context());
+        let mut engine_gui = EngineGui::new(egui_renderer.context());
 
-        let render_pass_manager =
-            RenderPassManager::new(&device, &queue, &surface_config, width, height);
+        let mut render_pass_manager =
+            RenderPassManager::new(&device, &queue, surface_config.format, width, height);
+        if let Some(path) = cli_scene {
+            engine_gui.open_scene(path, render_pass_manager.get_options());
+        }
 
         info!("App State created!!");
 
@@ -161,6 +171,9 @@ impl AppState {
             vsync_enabled: true,
             instance,
             recreate_render_pass_manager: false,
+            camera_controller: CameraController::default(),
+            last_frame: Instant::now(),
+            click_start: None,
         })
     }
 
@@ -219,16 +232,28 @@ impl AppState {
         if self.recreate_render_pass_manager {
             self.recreate_render_pass_manager = false;
             let options = self.render_pass_manager.get_options().clone();
+            let capture_options = self.render_pass_manager.capture_manager().options.clone();
+            let octree = std::mem::take(self.render_pass_manager.octree_mut());
             self.render_pass_manager = RenderPassManager::new(
                 &self.device,
                 &self.queue,
-                &self.surface_config,
+                self.surface_config.format,
                 width,
                 height,
             );
             *self.render_pass_manager.get_options() = options;
+            self.render_pass_manager.capture_manager().options = capture_options;
+            *self.render_pass_manager.octree_mut() = octree;
         }
 
+        // Capped so a stalled frame doesn't fly the camera off
+        let now = Instant::now();
+        let dt = (now - self.last_frame).as_secs_f32().min(0.1);
+        self.last_frame = now;
+        let options = self.render_pass_manager.get_options();
+        self.camera_controller
+            .update(&mut options.camera, &options.raymarching_objects, dt);
+
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [width, height],
             pixels_per_point: self.window.scale_factor() as f32 * self.scale_factor,
@@ -266,8 +291,14 @@ impl AppState {
             // Begin egui frame before rendering GUI
             self.egui_renderer.begin_frame(&self.window);
 
+            let path_tracing_samples = self.render_pass_manager.path_tracing_samples();
+            let (render_options, capture_manager) =
+                self.render_pass_manager.get_options_and_capture();
             self.engine_gui.render_gui(
-                self.render_pass_manager.get_options(),
+                render_options,
+                capture_manager,
+                path_tracing_samples,
+                &mut self.camera_controller,
                 &mut self.vsync_enabled,
                 &mut self.recreate_render_pass_manager,
             );
@@ -286,6 +317,17 @@ impl AppState {
             );
 
             self.queue.submit(Some(encoder.finish()));
+
+            if let Err(err) = self
+                .render_pass_manager
+                .finish_captures(&self.device, &self.queue)
+            {
+                log::error!("Capture failed: {err}");
+            }
+            if let Some(pick) = self.render_pass_manager.poll_pick(&self.device) {
+                let objects = &self.render_pass_manager.get_options().raymarching_objects;
+                self.engine_gui.select_object(objects, pick.object);
+            }
         }
 
         surface_texture.present();
@@ -296,7 +338,36 @@ impl AppState {
         self.window.request_redraw();
     }
 
-    pub fn event(&mut self, event: &WindowEvent) {
-        self.egui_renderer.handle_input(&self.window, event);
+    /// Queues a screenshot of the next frame (F12).
+    pub fn screenshot(&mut self) {
+        self.render_pass_manager.capture_manager().screenshot();
+    }
+
+    /// Returns whether the GUI consumed the event.
+    pub fn event(&mut self, event: &WindowEvent) -> bool {
+        let consumed = self.egui_renderer.handle_input(&self.window, event);
+        self.camera_controller.event(event, consumed);
+        consumed
+    }
+
+    /// Left mouse button pressed over the scene.
+    pub fn begin_click(&mut self) {
+        // Pressing a gizmo handle drags it instead
+        self.click_start = self
+            .camera_controller
+            .cursor()
+            .filter(|_| !self.engine_gui.gizmo_active());
+    }
+
+    /// Left mouse button released, picks the object under the cursor if it didn't move since
+    /// [`AppState::begin_click`].
+    pub fn end_click(&mut self) {
+        if let (Some(start), Some(cursor)) =
+            (self.click_start.take(), self.camera_controller.cursor())
+            && start.distance(cursor) <= CLICK_SLOP
+        {
+            self.render_pass_manager
+                .pick(cursor.x as u32, cursor.y as u32);
+        }
     }
 }
@@ -0,0 +1,201 @@
+//! Fly and orbit controls for the [`Camera`], driven by the window's keyboard and mouse events.
+//!
+//! Holding the right mouse button looks around. [`CameraMode::Fly`] moves along the view with
+//! WASD, down and up with Q and E, faster while shift is held, and the wheel changes the speed.
+//! [`CameraMode::Orbit`] circles the orbited object, or the point in front of the camera when
+//! there is none, and the wheel moves closer or further away.
+
+use std::collections::HashSet;
+
+use glam::{Vec2, Vec3};
+use winit::event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::render_passes::raymarching_passes::camera::Camera;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+use crate::scene_graph::{object_index, world_position};
+
+/// Speed multiplier while shift is held.
+const BOOST: f32 = 4.0;
+/// Wheel lines a trackpad's pixel scroll counts as.
+const PIXELS_PER_LINE: f32 = 40.0;
+/// Closest the orbit zooms in.
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    #[default]
+    Fly,
+    Orbit,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 2] = [CameraMode::Fly, CameraMode::Orbit];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CameraMode::Fly => "Fly",
+            CameraMode::Orbit => "Orbit",
+        }
+    }
+}
+
+pub struct CameraController {
+    pub mode: CameraMode,
+    /// [`RaymarchingObject::id`] of the object [`CameraMode::Orbit`] circles. Switching to the
+    /// mode in the GUI sets it to the selected object.
+    pub orbit_object: Option<u64>,
+    /// Fly speed in scene units per second.
+    pub speed: f32,
+    /// Radians turned per pixel the mouse moves.
+    pub sensitivity: f32,
+    /// How far in front of the camera the orbit pivots without an orbited object.
+    pub orbit_distance: f32,
+    held: HashSet<KeyCode>,
+    looking: bool,
+    cursor: Option<Vec2>,
+    look: Vec2,
+    scroll: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::Fly,
+            orbit_object: None,
+            speed: 2.0,
+            sensitivity: 0.004,
+            orbit_distance: 3.0,
+            held: HashSet::new(),
+            looking: false,
+            cursor: None,
+            look: Vec2::ZERO,
+            scroll: 0.0,
+        }
+    }
+}
+
+impl CameraController {
+    /// Collects the input of a window event. Presses the GUI `consumed` are ignored, releases
+    /// always count so no key or button stays held.
+    pub fn event(&mut self, event: &WindowEvent, consumed: bool) {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(code),
+                        state,
+                        ..
+                    },
+                ..
+            } => match state {
+                ElementState::Pressed if !consumed => self.set_key_held(*code, true),
+                ElementState::Released => self.set_key_held(*code, false),
+                _ => {}
+            },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => self.looking = state.is_pressed() && !consumed,
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vec2::new(position.x as f32, position.y as f32);
+                if let Some(cursor) = self.cursor
+                    && self.looking
+                {
+                    self.look += position - cursor;
+                }
+                self.cursor = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseWheel { delta, .. } if !consumed => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+            }
+            WindowEvent::Focused(false) => {
+                self.held.clear();
+                self.looking = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Presses or releases `code` without a window event.
+    pub fn set_key_held(&mut self, code: KeyCode, held: bool) {
+        if held {
+            self.held.insert(code);
+        } else {
+            self.held.remove(&code);
+        }
+    }
+
+    /// Last cursor position in physical pixels, `None` while it's outside the window.
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// Moves `camera` by the input collected since the last update, `dt` seconds ago.
+    /// `objects` are the scene's, to find the orbited object in.
+    pub fn update(&mut self, camera: &mut Camera, objects: &[RaymarchingObject], dt: f32) {
+        let look = std::mem::take(&mut self.look) * self.sensitivity;
+        let scroll = std::mem::take(&mut self.scroll);
+        match self.mode {
+            CameraMode::Fly => self.fly(camera, look, scroll, dt),
+            CameraMode::Orbit => {
+                let target = self
+                    .orbit_object
+                    .and_then(|id| object_index(objects, id))
+                    .map(|index| world_position(objects, index));
+                self.orbit(camera, target, look, scroll);
+            }
+        }
+    }
+
+    fn fly(&mut self, camera: &mut Camera, look: Vec2, scroll: f32, dt: f32) {
+        if look != Vec2::ZERO {
+            let (yaw, pitch) = camera.yaw_pitch();
+            camera.set_yaw_pitch(yaw + look.x, pitch + look.y);
+        }
+        if scroll != 0.0 {
+            self.speed = (self.speed * 1.2f32.powf(scroll)).clamp(0.01, 1000.0);
+        }
+
+        let axis = |negative, positive| self.key(positive) as i32 - self.key(negative) as i32;
+        let movement = camera.right() * axis(KeyCode::KeyA, KeyCode::KeyD) as f32
+            + camera.forward() * axis(KeyCode::KeyS, KeyCode::KeyW) as f32
+            + Vec3::Y * axis(KeyCode::KeyQ, KeyCode::KeyE) as f32;
+        if movement != Vec3::ZERO {
+            let boost = if self.key(KeyCode::ShiftLeft) || self.key(KeyCode::ShiftRight) {
+                BOOST
+            } else {
+                1.0
+            };
+            camera.position += movement.normalize() * self.speed * boost * dt;
+        }
+    }
+
+    /// Circles `target`, or the pivot [`Self::orbit_distance`] in front of the camera.
+    fn orbit(&mut self, camera: &mut Camera, target: Option<Vec3>, look: Vec2, scroll: f32) {
+        let facing = target.is_none_or(|target| {
+            let direction = (target - camera.position).normalize_or_zero();
+            camera.forward().dot(direction) > 0.9999
+        });
+        if look == Vec2::ZERO && scroll == 0.0 && facing {
+            return;
+        }
+        let pivot = target.unwrap_or(camera.position + camera.forward() * self.orbit_distance);
+        let distance =
+            (camera.position.distance(pivot) * 0.9f32.powf(scroll)).max(MIN_ORBIT_DISTANCE);
+        camera.look_at(pivot);
+        let (yaw, pitch) = camera.yaw_pitch();
+        camera.set_yaw_pitch(yaw + look.x, pitch + look.y);
+        camera.position = pivot - camera.forward() * distance;
+        self.orbit_distance = distance;
+    }
+
+    fn key(&self, code: KeyCode) -> bool {
+        self.held.contains(&code)
+    }
+}
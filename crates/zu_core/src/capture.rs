@@ -0,0 +1,360 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use egui_probe::EguiProbe;
+use log::{error, info};
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, Texture, TextureFormat, TextureView};
+
+use crate::render_passes::quad_vertex::QuadVertexRenderPass;
+use crate::render_passes::show_pass::ShowRenderPass;
+use crate::texture_manager::{TextureManager, readback::read_texture, textures::EngineTexture};
+
+/// What a capture reads back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureSource {
+    /// Tone-mapped output of the show pass, saved as PNG.
+    ToneMapped,
+    /// Raw contents of a named `TextureManager` texture. Float formats are saved as PFM,
+    /// 8-bit formats as PNG.
+    Texture(String),
+}
+
+#[derive(Debug, Clone, EguiProbe)]
+pub struct CaptureOptions {
+    pub directory: String,
+    /// Also save the raw HDR "Raymarching" texture next to every screenshot.
+    pub save_hdr: bool,
+    #[egui_probe(range = 0.001..=1.0)]
+    pub sequence_timestep: f32,
+    /// Number of frames in a sequence, 0 records until stopped.
+    pub sequence_frames: u32,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            directory: "captures".into(),
+            save_hdr: false,
+            sequence_timestep: 1.0 / 30.0,
+            sequence_frames: 0,
+        }
+    }
+}
+
+struct PendingCapture {
+    source: CaptureSource,
+    path: PathBuf,
+    /// The show pass rendered the tone-mapped target in the capture's frame.
+    tone_mapped: bool,
+}
+
+struct SequenceState {
+    directory: PathBuf,
+    frame: u32,
+}
+
+/// Copies the final frame (or any named texture) back to the CPU and writes it to disk.
+pub struct CaptureManager {
+    pub options: CaptureOptions,
+    show_pass: ShowRenderPass,
+    target: Option<(Texture, TextureView)>,
+    pending: Vec<PendingCapture>,
+    encoded: Vec<PendingCapture>,
+    sequence: Option<SequenceState>,
+    screenshot_count: u32,
+}
+
+impl CaptureManager {
+    pub const TONE_MAPPED_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+    pub const HDR_TEXTURE: &str = "Raymarching";
+
+    pub fn new(device: &Device, quad_render_pass: &QuadVertexRenderPass) -> Self {
+        Self {
+            options: Default::default(),
+            show_pass: ShowRenderPass::new(device, Self::TONE_MAPPED_FORMAT, quad_render_pass),
+            target: None,
+            pending: Vec::new(),
+            encoded: Vec::new(),
+            sequence: None,
+            screenshot_count: 0,
+        }
+    }
+
+    /// Queues a capture of `source` for the next rendered frame.
+    pub fn capture(&mut self, source: CaptureSource, path: impl Into<PathBuf>) {
+        self.pending.push(PendingCapture {
+            source,
+            path: path.into(),
+            tone_mapped: false,
+        });
+    }
+
+    /// Queues a tone-mapped screenshot (and the HDR texture if enabled) into the capture directory.
+    pub fn screenshot(&mut self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let stem = format!("screenshot_{}_{}", timestamp, self.screenshot_count);
+        self.screenshot_count += 1;
+        let directory = PathBuf::from(&self.options.directory);
+        self.queue_frame(&directory, &stem);
+    }
+
+    pub fn start_sequence(&mut self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let directory = Path::new(&self.options.directory).join(format!("sequence_{timestamp}"));
+        info!("Recording sequence to {}", directory.display());
+        self.sequence = Some(SequenceState {
+            directory,
+            frame: 0,
+        });
+    }
+
+    pub fn stop_sequence(&mut self) {
+        if let Some(sequence) = self.sequence.take() {
+            info!("Sequence stopped after {} frames", sequence.frame);
+        }
+    }
+
+    pub fn is_recording_sequence(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    /// Called at the start of a frame. While a sequence is recording this queues its next frame
+    /// and returns the fixed-timestep scene time that frame must be rendered at.
+    pub fn begin_frame(&mut self) -> Option<f32> {
+        let sequence = self.sequence.as_ref()?;
+        let frames = self.options.sequence_frames;
+        if frames != 0 && sequence.frame >= frames {
+            self.stop_sequence();
+            return None;
+        }
+
+        let frame = sequence.frame;
+        let directory = sequence.directory.clone();
+        self.queue_frame(&directory, &format!("frame_{frame:05}"));
+        if let Some(sequence) = self.sequence.as_mut() {
+            sequence.frame += 1;
+        }
+        Some(frame as f32 * self.options.sequence_timestep)
+    }
+
+    fn queue_frame(&mut self, directory: &Path, stem: &str) {
+        self.capture(
+            CaptureSource::ToneMapped,
+            directory.join(format!("{stem}.png")),
+        );
+        if self.options.save_hdr {
+            self.capture(
+                CaptureSource::Texture(Self::HDR_TEXTURE.into()),
+                directory.join(format!("{stem}.pfm")),
+            );
+        }
+    }
+
+    /// Re-runs the show pass into an offscreen target if a tone-mapped capture is pending.
+    /// Without an `input_texture` those captures fail instead of saving an older frame.
+    pub fn encode(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        input_texture: Option<&BindGroup>,
+        quad_render_pass: &QuadVertexRenderPass,
+        width: u32,
+        height: u32,
+    ) {
+        if self.pending.is_empty() {
+            return;
+        }
+        puffin::profile_function!();
+
+        let needs_tone_mapped = self
+            .pending
+            .iter()
+            .any(|capture| capture.source == CaptureSource::ToneMapped);
+        let tone_mapped = match (needs_tone_mapped, input_texture) {
+            (true, Some(input_texture)) => {
+                let view = ensure_target(&mut self.target, device, width, height);
+                self.show_pass
+                    .render(encoder, input_texture, view, quad_render_pass);
+                true
+            }
+            _ => false,
+        };
+
+        for capture in &mut self.pending {
+            capture.tone_mapped = tone_mapped;
+        }
+        self.encoded.append(&mut self.pending);
+    }
+
+    /// Reads back and writes every capture encoded this frame. Must be called after the
+    /// frame's command buffer was submitted. Returns the written paths, or an error listing
+    /// the captures that failed once all the others were written.
+    pub fn finish(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &TextureManager,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        if self.encoded.is_empty() {
+            return Ok(Vec::new());
+        }
+        puffin::profile_function!();
+
+        let mut written = Vec::new();
+        let mut failed = Vec::new();
+        for capture in std::mem::take(&mut self.encoded) {
+            match self.write_capture(device, queue, texture_manager, &capture) {
+                Ok(()) => {
+                    info!("Saved capture {}", capture.path.display());
+                    written.push(capture.path);
+                }
+                Err(err) => {
+                    error!("Failed to save capture {}: {err:#}", capture.path.display());
+                    failed.push(format!("{}: {err:#}", capture.path.display()));
+                }
+            }
+        }
+        if !failed.is_empty() {
+            anyhow::bail!(
+                "Failed to save {} of {} captures:\n{}",
+                failed.len(),
+                failed.len() + written.len(),
+                failed.join("\n")
+            );
+        }
+        Ok(written)
+    }
+
+    fn write_capture(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &TextureManager,
+        capture: &PendingCapture,
+    ) -> anyhow::Result<()> {
+        let texture = match &capture.source {
+            CaptureSource::ToneMapped => self
+                .target
+                .as_ref()
+                .filter(|_| capture.tone_mapped)
+                .map(|(texture, _)| texture),
+            CaptureSource::Texture(name) => texture_manager
+                .get_texture(name)
+                .map(|texture| texture.texture()),
+        };
+        let Some(texture) = texture else {
+            anyhow::bail!("Nothing to capture for {:?}", capture.source);
+        };
+
+        if let Some(parent) = capture.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let pixels = read_texture(device, queue, texture)?;
+        match texture.format() {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+                write_png(&capture.path, texture.width(), texture.height(), &pixels)
+            }
+            TextureFormat::Rgba16Float => {
+                // The readback Vec<u8> is not f16 aligned, so decode byte pairs
+                let rgba: Vec<f32> = pixels
+                    .chunks_exact(2)
+                    .map(|bytes| half::f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+                    .collect();
+                write_pfm(&capture.path, texture.width(), texture.height(), &rgba)
+            }
+            TextureFormat::Rgba32Float => {
+                let rgba: Vec<f32> = pixels
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect();
+                write_pfm(&capture.path, texture.width(), texture.height(), &rgba)
+            }
+            format => anyhow::bail!("Capturing {:?} textures is not supported", format),
+        }
+    }
+}
+
+fn ensure_target<'a>(
+    target: &'a mut Option<(Texture, TextureView)>,
+    device: &Device,
+    width: u32,
+    height: u32,
+) -> &'a TextureView {
+    let matches = target
+        .as_ref()
+        .is_some_and(|(texture, _)| texture.width() == width && texture.height() == height);
+    if !matches {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: CaptureManager::TONE_MAPPED_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        *target = Some((texture, view));
+    }
+    &target.as_ref().unwrap().1
+}
+
+/// Writes tightly packed RGBA8 pixels as PNG.
+pub fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> anyhow::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads a PNG as tightly packed RGBA8 pixels, returning `(width, height, pixels)`.
+pub fn read_png(path: &Path) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::ALPHA);
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        anyhow::bail!(
+            "{} is {:?} {:?}, expected 8-bit RGBA",
+            path.display(),
+            info.color_type,
+            info.bit_depth
+        );
+    }
+    pixels.truncate(info.buffer_size());
+    Ok((info.width, info.height, pixels))
+}
+
+/// Writes RGBA f32 pixels (top row first) as an RGB Portable Float Map, dropping alpha.
+pub fn write_pfm(path: &Path, width: u32, height: u32, rgba: &[f32]) -> anyhow::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    // Negative scale marks little-endian data
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    // PFM stores rows bottom to top
+    for row in rgba.chunks_exact(width as usize * 4).rev() {
+        for pixel in row.chunks_exact(4) {
+            for channel in &pixel[..3] {
+                file.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
@@ -51,8 +51,9 @@ impl EguiRenderer {
         }
     }
 
-    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
-        let _ = self.state.on_window_event(window, event);
+    /// Returns whether egui consumed the event, so the scene shouldn't react to it.
+    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.state.on_window_event(window, event).consumed
     }
 
     pub fn ppp(&mut self, v: f32) {
@@ -0,0 +1,509 @@
+//! Translate, rotate and scale handles for the selected object, drawn over the window.
+//!
+//! The handles are projected with [`Camera::project`], the camera the raymarcher renders with,
+//! and painted on an egui background layer below the windows. Dragging one intersects the
+//! pointer's [`Camera::pixel_ray`] with the handle's axis, plane or ring and edits the object's
+//! transform every frame. Translation and rotation follow the world axes, scaling the object's
+//! own axes.
+
+use egui::{Color32, Context, Id, LayerId, Order, Pos2, Stroke};
+use glam::{Mat4, Quat, Vec2, Vec3};
+
+use crate::render_passes::raymarching_passes::camera::Camera;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::{
+    MIN_SCALE, RaymarchingObject,
+};
+use crate::scene_graph::SceneGraph;
+
+/// Length of the handles as a share of half the view's height.
+const GIZMO_SIZE: f32 = 0.25;
+/// How close in points the pointer has to be to grab a handle.
+const GRAB_DISTANCE: f32 = 8.0;
+const RING_SEGMENTS: usize = 64;
+/// Plane handles span this range of the two axes they move along.
+const PLANE_HANDLE: (f32, f32) = (0.25, 0.45);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    pub const ALL: [GizmoMode; 3] = [GizmoMode::Translate, GizmoMode::Rotate, GizmoMode::Scale];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GizmoMode::Translate => "Translate",
+            GizmoMode::Rotate => "Rotate",
+            GizmoMode::Scale => "Scale",
+        }
+    }
+}
+
+/// A part of the gizmo. Axes are 0 for x, 1 for y and 2 for z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    /// Moves along the axis.
+    Axis(usize),
+    /// Moves in the plane the axis is the normal of.
+    Plane(usize),
+    /// Turns around the axis.
+    Ring(usize),
+    /// Scales along the axis.
+    ScaleAxis(usize),
+    /// Scales along all axes.
+    UniformScale,
+}
+
+/// Where the handles are, in world space.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    pivot: Vec3,
+    axes: [Vec3; 3],
+    /// World length of the handles.
+    size: f32,
+}
+
+enum Outline {
+    Segments(Vec<[Vec2; 2]>),
+    Polygon(Vec<Vec2>),
+    Point(Vec2),
+}
+
+struct Drag {
+    handle: Handle,
+    object: usize,
+    frame: Frame,
+    /// The object as it was when the drag started.
+    start: RaymarchingObject,
+    /// Parent space to world space.
+    parent: Mat4,
+    /// Point on the handle's axis or plane that was grabbed.
+    grab: Vec3,
+    grab_pointer: Vec2,
+}
+
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    /// Rounds moves to the grid, rotations to the angle step and scaling to the scale step.
+    /// Holding ctrl while dragging flips it.
+    pub snap: bool,
+    /// Grid spacing in world units.
+    pub grid: f32,
+    /// Angle step in degrees.
+    pub angle_step: f32,
+    pub scale_step: f32,
+    hovered: Option<Handle>,
+    drag: Option<Drag>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            snap: false,
+            grid: 0.25,
+            angle_step: 15.0,
+            scale_step: 0.1,
+            hovered: None,
+            drag: None,
+        }
+    }
+}
+
+impl Gizmo {
+    /// Whether the pointer is over a handle or dragging one, clicks then belong to the gizmo.
+    pub fn is_active(&self) -> bool {
+        self.hovered.is_some() || self.drag.is_some()
+    }
+
+    /// Draws the handles of object `selected` and drags them with the pointer. Returns whether
+    /// the object changed.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        camera: &Camera,
+        objects: &mut [RaymarchingObject],
+        selected: Option<usize>,
+    ) -> bool {
+        let Some(selected) = selected.filter(|index| *index < objects.len()) else {
+            self.hovered = None;
+            self.drag = None;
+            return false;
+        };
+        let viewport = ctx.viewport_rect();
+        let size = Vec2::new(viewport.width(), viewport.height());
+        let (pointer, pressed, down, ctrl) = ctx.input(|input| {
+            (
+                input
+                    .pointer
+                    .latest_pos()
+                    .map(|pos| Vec2::new(pos.x - viewport.min.x, pos.y - viewport.min.y)),
+                input.pointer.primary_pressed(),
+                input.pointer.primary_down(),
+                input.modifiers.ctrl,
+            )
+        });
+
+        let mut changed = false;
+        if self.drag.is_some() {
+            match pointer {
+                Some(pointer) if down => {
+                    changed = self.drag_to(camera, size, objects, pointer, self.snap != ctrl);
+                }
+                _ => self.end_drag(),
+            }
+        } else {
+            self.hovered = pointer
+                .filter(|_| !ctx.is_pointer_over_area())
+                .and_then(|pointer| self.handle_at(camera, size, objects, selected, pointer));
+            if let (Some(handle), Some(pointer), true) = (self.hovered, pointer, pressed) {
+                self.begin_drag(handle, camera, size, objects, selected, pointer);
+            }
+        }
+
+        let frame = match &self.drag {
+            Some(drag) => Frame {
+                pivot: SceneGraph::new(objects).world_position(drag.object),
+                ..drag.frame
+            },
+            None => self.frame(camera, objects, selected),
+        };
+        let active = self.drag.as_ref().map(|drag| drag.handle).or(self.hovered);
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("Gizmo")));
+        let to_pos = |point: Vec2| Pos2::new(point.x + viewport.min.x, point.y + viewport.min.y);
+        for (handle, outline) in self.outlines(camera, size, &frame) {
+            let color = if active == Some(handle) {
+                Color32::from_rgb(255, 210, 60)
+            } else {
+                handle_color(handle)
+            };
+            match outline {
+                Outline::Segments(segments) => {
+                    for [a, b] in &segments {
+                        painter.line_segment([to_pos(*a), to_pos(*b)], Stroke::new(2.0, color));
+                    }
+                    // Arrow and box tips show which handles move and which scale
+                    if let Some([_, tip]) = segments.last() {
+                        match handle {
+                            Handle::Axis(_) => painter.circle_filled(to_pos(*tip), 4.0, color),
+                            Handle::ScaleAxis(_) => painter.rect_filled(
+                                egui::Rect::from_center_size(to_pos(*tip), egui::vec2(7.0, 7.0)),
+                                0.0,
+                                color,
+                            ),
+                            _ => continue,
+                        };
+                    }
+                }
+                Outline::Polygon(points) => {
+                    painter.add(egui::Shape::convex_polygon(
+                        points.into_iter().map(to_pos).collect(),
+                        color.gamma_multiply(0.5),
+                        Stroke::new(1.0, color),
+                    ));
+                }
+                Outline::Point(point) => {
+                    painter.circle_filled(to_pos(point), 5.0, color);
+                }
+            }
+        }
+        changed
+    }
+
+    /// The handle of object `selected` under `pointer`, in an image `size` large.
+    pub fn handle_at(
+        &self,
+        camera: &Camera,
+        size: Vec2,
+        objects: &[RaymarchingObject],
+        selected: usize,
+        pointer: Vec2,
+    ) -> Option<Handle> {
+        let frame = self.frame(camera, objects, selected);
+        self.outlines(camera, size, &frame)
+            .into_iter()
+            .filter_map(|(handle, outline)| {
+                let distance = match outline {
+                    Outline::Segments(segments) => segments
+                        .iter()
+                        .map(|[a, b]| segment_distance(pointer, *a, *b))
+                        .fold(f32::INFINITY, f32::min),
+                    // Lines over a plane handle still win
+                    Outline::Polygon(points) => {
+                        if inside_convex(pointer, &points) {
+                            GRAB_DISTANCE * 0.5
+                        } else {
+                            f32::INFINITY
+                        }
+                    }
+                    Outline::Point(point) => pointer.distance(point) * 0.5,
+                };
+                (distance <= GRAB_DISTANCE).then_some((handle, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(handle, _)| handle)
+    }
+
+    /// Grabs `handle` of object `selected` at `pointer`. Returns false where the pointer's ray
+    /// misses the handle's plane.
+    pub fn begin_drag(
+        &mut self,
+        handle: Handle,
+        camera: &Camera,
+        size: Vec2,
+        objects: &[RaymarchingObject],
+        selected: usize,
+        pointer: Vec2,
+    ) -> bool {
+        let frame = self.frame(camera, objects, selected);
+        let ray = camera.pixel_ray(pointer, size);
+        let Some(grab) = grab_point(handle, &frame, camera.position, ray) else {
+            return false;
+        };
+        let scene = SceneGraph::new(objects);
+        let parent = objects[selected]
+            .parent()
+            .filter(|parent| *parent < objects.len())
+            .map_or(Mat4::IDENTITY, |parent| scene.world_transform(parent));
+        self.drag = Some(Drag {
+            handle,
+            object: selected,
+            frame,
+            start: objects[selected].clone(),
+            parent,
+            grab,
+            grab_pointer: pointer,
+        });
+        true
+    }
+
+    /// Moves the grabbed handle to `pointer` and edits the object to match. Returns whether it
+    /// changed.
+    pub fn drag_to(
+        &mut self,
+        camera: &Camera,
+        size: Vec2,
+        objects: &mut [RaymarchingObject],
+        pointer: Vec2,
+        snap: bool,
+    ) -> bool {
+        let Some(drag) = &self.drag else {
+            return false;
+        };
+        let Some(object) = objects.get_mut(drag.object) else {
+            self.drag = None;
+            return false;
+        };
+        let frame = &drag.frame;
+        let ray = camera.pixel_ray(pointer, size);
+        let snapped = |value: f32, step: f32| {
+            if snap && step > 0.0 {
+                (value / step).round() * step
+            } else {
+                value
+            }
+        };
+
+        let mut edited = drag.start.clone();
+        match drag.handle {
+            Handle::Axis(axis) | Handle::Plane(axis) => {
+                let Some(point) = grab_point(drag.handle, frame, camera.position, ray) else {
+                    return false;
+                };
+                let mut world = frame.pivot + point - drag.grab;
+                let moved = if let Handle::Axis(_) = drag.handle {
+                    vec![axis]
+                } else {
+                    vec![(axis + 1) % 3, (axis + 2) % 3]
+                };
+                for component in moved {
+                    world[component] = snapped(world[component], self.grid);
+                }
+                edited.position = drag.parent.inverse().transform_point3(world);
+            }
+            Handle::Ring(axis) => {
+                let Some(point) = grab_point(drag.handle, frame, camera.position, ray) else {
+                    return false;
+                };
+                let (from, to) = (drag.grab - frame.pivot, point - frame.pivot);
+                let normal = frame.axes[axis];
+                let angle = normal.dot(from.cross(to)).atan2(from.dot(to));
+                let angle = snapped(angle.to_degrees(), self.angle_step).to_radians();
+                let local_axis = drag
+                    .parent
+                    .inverse()
+                    .transform_vector3(normal)
+                    .normalize_or(normal);
+                edited.rotation =
+                    (Quat::from_axis_angle(local_axis, angle) * drag.start.rotation).normalize();
+            }
+            Handle::ScaleAxis(axis) => {
+                let Some(point) = grab_point(drag.handle, frame, camera.position, ray) else {
+                    return false;
+                };
+                let direction = frame.axes[axis];
+                let grabbed = (drag.grab - frame.pivot).dot(direction);
+                if grabbed.abs() < 1e-4 {
+                    return false;
+                }
+                let factor = (point - frame.pivot).dot(direction) / grabbed;
+                let scale = snapped(drag.start.scale[axis] * factor, self.scale_step);
+                edited.scale[axis] = scale.max(MIN_SCALE);
+            }
+            Handle::UniformScale => {
+                let Some(pivot) = camera.project(frame.pivot, size) else {
+                    return false;
+                };
+                let grabbed = drag.grab_pointer.distance(pivot);
+                if grabbed < 1.0 {
+                    return false;
+                }
+                let factor = snapped(pointer.distance(pivot) / grabbed, self.scale_step);
+                edited.scale = (drag.start.scale * factor).max(Vec3::splat(MIN_SCALE));
+            }
+        }
+
+        let changed = *object != edited;
+        *object = edited;
+        changed
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    fn frame(&self, camera: &Camera, objects: &[RaymarchingObject], selected: usize) -> Frame {
+        let world = SceneGraph::new(objects).world_transform(selected);
+        let pivot = world.w_axis.truncate();
+        let axes = if self.mode == GizmoMode::Scale {
+            [
+                world.x_axis.truncate().normalize_or(Vec3::X),
+                world.y_axis.truncate().normalize_or(Vec3::Y),
+                world.z_axis.truncate().normalize_or(Vec3::Z),
+            ]
+        } else {
+            [Vec3::X, Vec3::Y, Vec3::Z]
+        };
+        Frame {
+            pivot,
+            axes,
+            size: camera.position.distance(pivot) * camera.tan_half_fov() * GIZMO_SIZE,
+        }
+    }
+
+    /// Projected handles of the current mode, parts behind the camera left out.
+    fn outlines(&self, camera: &Camera, size: Vec2, frame: &Frame) -> Vec<(Handle, Outline)> {
+        let project = |point: Vec3| camera.project(point, size);
+        let segments = |points: &[Vec3]| {
+            Outline::Segments(
+                points
+                    .windows(2)
+                    .filter_map(|pair| Some([project(pair[0])?, project(pair[1])?]))
+                    .collect(),
+            )
+        };
+        let axis_line =
+            |axis: usize| segments(&[frame.pivot, frame.pivot + frame.axes[axis] * frame.size]);
+
+        let mut outlines = Vec::new();
+        for axis in 0..3 {
+            let (u, v) = (frame.axes[(axis + 1) % 3], frame.axes[(axis + 2) % 3]);
+            match self.mode {
+                GizmoMode::Translate => {
+                    outlines.push((Handle::Axis(axis), axis_line(axis)));
+                    let (near, far) = PLANE_HANDLE;
+                    let corners: Option<Vec<_>> =
+                        [(near, near), (far, near), (far, far), (near, far)]
+                            .into_iter()
+                            .map(|(a, b)| project(frame.pivot + (u * a + v * b) * frame.size))
+                            .collect();
+                    if let Some(corners) = corners {
+                        outlines.push((Handle::Plane(axis), Outline::Polygon(corners)));
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let ring: Vec<_> = (0..=RING_SEGMENTS)
+                        .map(|segment| {
+                            let angle =
+                                segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                            frame.pivot + (u * angle.cos() + v * angle.sin()) * frame.size
+                        })
+                        .collect();
+                    outlines.push((Handle::Ring(axis), segments(&ring)));
+                }
+                GizmoMode::Scale => outlines.push((Handle::ScaleAxis(axis), axis_line(axis))),
+            }
+        }
+        if self.mode == GizmoMode::Scale
+            && let Some(pivot) = project(frame.pivot)
+        {
+            outlines.push((Handle::UniformScale, Outline::Point(pivot)));
+        }
+        outlines
+    }
+}
+
+fn handle_color(handle: Handle) -> Color32 {
+    match handle {
+        Handle::Axis(axis) | Handle::Plane(axis) | Handle::Ring(axis) | Handle::ScaleAxis(axis) => {
+            [
+                Color32::from_rgb(230, 70, 70),
+                Color32::from_rgb(110, 200, 70),
+                Color32::from_rgb(70, 130, 230),
+            ][axis]
+        }
+        Handle::UniformScale => Color32::from_gray(220),
+    }
+}
+
+/// Point of the handle's axis or plane the ray from `origin` along `direction` grabs.
+fn grab_point(handle: Handle, frame: &Frame, origin: Vec3, direction: Vec3) -> Option<Vec3> {
+    match handle {
+        Handle::Axis(axis) | Handle::ScaleAxis(axis) => {
+            closest_on_axis(frame.pivot, frame.axes[axis], origin, direction)
+        }
+        Handle::Plane(axis) | Handle::Ring(axis) => {
+            let normal = frame.axes[axis];
+            let facing = direction.dot(normal);
+            if facing.abs() < 1e-4 {
+                return None;
+            }
+            let distance = (frame.pivot - origin).dot(normal) / facing;
+            (distance > 0.0).then(|| origin + direction * distance)
+        }
+        Handle::UniformScale => Some(frame.pivot),
+    }
+}
+
+/// Point of the line through `point` along `axis` closest to the ray, `None` when they're
+/// parallel.
+fn closest_on_axis(point: Vec3, axis: Vec3, origin: Vec3, direction: Vec3) -> Option<Vec3> {
+    let offset = point - origin;
+    let along = axis.dot(direction);
+    let denominator = 1.0 - along * along;
+    if denominator < 1e-4 {
+        return None;
+    }
+    let t = (along * direction.dot(offset) - axis.dot(offset)) / denominator;
+    Some(point + axis * t)
+}
+
+fn segment_distance(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = ((point - a).dot(ab) / ab.length_squared().max(1e-6)).clamp(0.0, 1.0);
+    point.distance(a + ab * t)
+}
+
+fn inside_convex(point: Vec2, polygon: &[Vec2]) -> bool {
+    let side = |i: usize| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        (b - a).perp_dot(point - a)
+    };
+    let sides: Vec<_> = (0..polygon.len()).map(side).collect();
+    // Strictly, so planes seen edge on can't be grabbed
+    sides.iter().all(|side| *side > 0.0) || sides.iter().all(|side| *side < 0.0)
+}
@@ -0,0 +1,596 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use glam::{Quat, Vec2, Vec3, Vec4};
+use log::info;
+
+use crate::capture::{read_png, write_png};
+use crate::headless::HeadlessRenderer;
+use crate::render_passes::raymarching_passes::ambient_occlusion::{AmbientOcclusion, AoMode};
+use crate::render_passes::raymarching_passes::camera::Camera;
+use crate::render_passes::raymarching_passes::csg::CsgOperation;
+use crate::render_passes::raymarching_passes::light::{Light, LightKind};
+use crate::render_passes::raymarching_passes::material::{Material, Pattern};
+use crate::render_passes::raymarching_passes::primitives::{Primitive, PrimitiveKind};
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+use crate::render_passes::raymarching_passes::volumetrics::{Clouds, FogVolume};
+use crate::render_passes::render_pass_manager::RenderOptions;
+
+/// A named scene rendered at a fixed `time` and compared against `<name>.png`.
+#[derive(Debug, Clone)]
+pub struct GoldenScene {
+    pub name: &'static str,
+    pub time: f32,
+    pub options: RenderOptions,
+}
+
+/// Scenes covering lighting, shadows, materials and the atmosphere.
+pub fn scenes() -> Vec<GoldenScene> {
+    let sphere = |x: f32, y: f32, z: f32, radius: f32, material: i32| {
+        RaymarchingObject::sphere(Vec3::new(x, y, z), radius, material)
+    };
+
+    // The default camera looks down +z, away from the default objects
+    let camera = Camera::new(Vec3::new(0.0, 0.0, -3.0), 0.0, 0.0);
+
+    vec![
+        GoldenScene {
+            name: "default",
+            time: 0.0,
+            options: RenderOptions {
+                camera,
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "low_sun",
+            time: 0.0,
+            options: RenderOptions {
+                camera,
+                sun_dir: Vec3::new(1.0, 0.08, 0.3),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "shadows",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    sphere(0.0, 0.25, 0.0, 0.4, 0),
+                    sphere(-1.2, -0.45, 0.8, 0.3, 0),
+                ],
+                camera,
+                sun_dir: Vec3::new(0.6, 1.0, -0.4),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "materials",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    sphere(-1.1, 0.0, 0.0, 0.45, 0),
+                    sphere(0.0, 0.0, 0.0, 0.45, 2),
+                    sphere(1.1, 0.0, 0.0, 0.45, 1),
+                ],
+                camera,
+                sun_dir: Vec3::new(0.5, 1.0, -0.8),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "primitives",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: PrimitiveKind::ALL
+                    .iter()
+                    .filter(|kind| **kind != PrimitiveKind::Plane)
+                    .enumerate()
+                    .map(|(index, kind)| {
+                        let x = (index % 4) as f32 * 1.3 - 1.95;
+                        let y = 0.5 - (index / 4) as f32 * 1.1;
+                        let primitive = Primitive::new(*kind, kind.default_params() * 0.8);
+                        RaymarchingObject::new(Vec3::new(x, y, 1.5), primitive, 0)
+                    })
+                    .chain([RaymarchingObject::new(
+                        Vec3::new(0.0, 0.0, 3.5),
+                        Primitive::new(PrimitiveKind::Plane, Vec4::new(0.0, 0.0, -1.0, 0.0)),
+                        1,
+                    )])
+                    .collect(),
+                camera,
+                sun_dir: Vec3::new(0.5, 1.0, -0.8),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "csg",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    // Sphere intersected with a box gives a rounded die
+                    RaymarchingObject::new(
+                        Vec3::new(1.4, 0.0, 0.5),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(0.3, 0.3, 0.3, 0.0)),
+                        1,
+                    ),
+                    sphere(1.4, 0.0, 0.5, 0.4, 2).with_operation(CsgOperation::Intersection, 0.0),
+                    // Smooth union blending white into red
+                    sphere(-1.3, 0.0, 0.5, 0.4, 0),
+                    sphere(-0.8, 0.1, 0.5, 0.35, 1).with_operation(CsgOperation::SmoothUnion, 0.3),
+                    // Operations apply to everything listed above them
+                    sphere(-1.05, 0.45, 0.3, 0.25, 0)
+                        .with_operation(CsgOperation::SmoothSubtraction, 0.1),
+                    // Rounded box with a sphere carved out of its front
+                    RaymarchingObject::new(
+                        Vec3::new(0.3, 0.0, 0.8),
+                        Primitive::new(PrimitiveKind::RoundBox, Vec4::new(0.4, 0.4, 0.4, 0.05)),
+                        0,
+                    ),
+                    sphere(0.3, 0.2, 0.35, 0.35, 2).with_operation(CsgOperation::Subtraction, 0.0),
+                ],
+                camera,
+                sun_dir: Vec3::new(0.5, 1.0, -0.8),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "transforms",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    // Rotated parent box, its children follow its rotation and scale
+                    RaymarchingObject::new(
+                        Vec3::new(-0.3, 0.0, 0.8),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(0.3, 0.3, 0.3, 0.0)),
+                        0,
+                    )
+                    .with_transform(
+                        Quat::from_rotation_y(0.6) * Quat::from_rotation_x(0.4),
+                        Vec3::splat(1.2),
+                    ),
+                    RaymarchingObject::new(
+                        Vec3::new(0.0, 0.5, 0.0),
+                        Primitive::new(PrimitiveKind::Torus, Vec4::new(0.3, 0.08, 0.0, 0.0)),
+                        1,
+                    )
+                    .with_parent(0),
+                    RaymarchingObject::sphere(Vec3::new(0.0, 0.3, 0.0), 0.15, 2).with_parent(1),
+                    // Non-uniformly scaled sphere
+                    RaymarchingObject::sphere(Vec3::new(1.2, 0.0, 0.6), 0.3, 1)
+                        .with_transform(Quat::from_rotation_z(0.5), Vec3::new(2.0, 0.8, 1.0)),
+                ],
+                camera,
+                sun_dir: Vec3::new(0.5, 1.0, -0.8),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "material_table",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    sphere(-1.35, 0.0, 0.5, 0.4, 1),
+                    sphere(-0.45, 0.0, 0.5, 0.4, 2),
+                    sphere(0.45, 0.0, 0.5, 0.4, 3),
+                    // Outside the table, shades as the missing material
+                    sphere(1.35, 0.0, 0.5, 0.4, 7),
+                ],
+                materials: vec![
+                    Material::new("Floor", Vec3::ONE, 1.0).with_pattern(Pattern::Checker),
+                    Material {
+                        metallic: 1.0,
+                        ..Material::new("Gold", Vec3::new(1.0, 0.78, 0.34), 0.3)
+                    },
+                    Material {
+                        emissive: Vec3::new(1.0, 0.5, 0.1),
+                        emissive_strength: 2.0,
+                        ..Material::new("Lamp", Vec3::splat(0.1), 1.0)
+                    },
+                    Material::new("Blue", Vec3::new(0.2, 0.4, 1.0), 0.6),
+                ],
+                camera,
+                sun_dir: Vec3::new(0.5, 1.0, -0.8),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "pbr",
+            time: 0.0,
+            options: {
+                // Metals on top, dielectrics below, roughness increasing to the right
+                let roughness = [0.1, 0.3, 0.5, 0.7, 0.9];
+                let mut materials = vec![Material::new("Floor", Vec3::splat(0.5), 1.0)];
+                let mut objects = Vec::new();
+                for (row, metallic) in [1.0, 0.0].into_iter().enumerate() {
+                    for (column, roughness) in roughness.into_iter().enumerate() {
+                        objects.push(sphere(
+                            column as f32 * 0.8 - 1.6,
+                            0.45 - row as f32 * 0.8,
+                            0.8,
+                            0.35,
+                            materials.len() as i32,
+                        ));
+                        materials.push(Material {
+                            metallic,
+                            ..Material::new("Sample", Vec3::new(0.95, 0.64, 0.54), roughness)
+                        });
+                    }
+                }
+                RenderOptions {
+                    raymarching_objects: objects,
+                    materials,
+                    camera,
+                    sun_dir: Vec3::new(0.3, 0.6, -1.0),
+                    ..Default::default()
+                }
+            },
+        },
+        GoldenScene {
+            name: "night_lights",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    sphere(-0.9, -0.35, 1.0, 0.4, 0),
+                    sphere(0.0, -0.35, 1.4, 0.4, 1),
+                    sphere(0.9, -0.35, 1.0, 0.4, 0),
+                ],
+                lights: vec![
+                    Light::point(Vec3::new(-0.3, 0.4, 0.3), Vec3::new(1.0, 0.7, 0.4), 2.0),
+                    Light {
+                        direction: Vec3::NEG_Y,
+                        inner_angle: 15.0,
+                        outer_angle: 25.0,
+                        ..Light::point(Vec3::new(0.9, 1.5, 1.0), Vec3::ONE, 6.0)
+                            .with_kind(LightKind::Spot)
+                    },
+                    Light {
+                        size: Vec2::splat(0.3),
+                        ..Light::point(Vec3::new(-1.6, 0.2, 1.8), Vec3::new(0.3, 0.5, 1.0), 3.0)
+                            .with_kind(LightKind::Sphere)
+                    },
+                ],
+                camera,
+                sun_intensity: 0.0,
+                ambient: 0.02,
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "indoor",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    // Room open towards the camera, lit by a panel in the ceiling
+                    RaymarchingObject::new(
+                        Vec3::new(0.0, 0.45, 1.5),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(2.0, 1.2, 2.0, 0.0)),
+                        0,
+                    ),
+                    RaymarchingObject::new(
+                        Vec3::new(0.0, 0.45, 1.0),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(1.9, 1.1, 2.4, 0.0)),
+                        0,
+                    )
+                    .with_operation(CsgOperation::Subtraction, 0.0),
+                    sphere(-0.6, -0.3, 2.0, 0.35, 1),
+                    RaymarchingObject::new(
+                        Vec3::new(0.6, -0.35, 1.8),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(0.3, 0.3, 0.3, 0.0)),
+                        0,
+                    ),
+                ],
+                lights: vec![Light {
+                    direction: Vec3::NEG_Y,
+                    size: Vec2::new(0.6, 0.3),
+                    ..Light::point(Vec3::new(0.0, 1.5, 1.6), Vec3::ONE, 15.0)
+                        .with_kind(LightKind::Rect)
+                }],
+                camera,
+                sun_dir: Vec3::new(0.5, 1.0, 0.8),
+                ambient: 0.03,
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "sky",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: Vec::new(),
+                camera: Camera::new(Vec3::new(0.0, 0.0, 3.0), 0.0, -0.48),
+                sun_dir: Vec3::new(-0.4, 0.5, 1.0),
+                clouds: Clouds {
+                    coverage: 0.45,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "contact",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    sphere(-0.6, -0.3, 0.6, 0.45, 0),
+                    RaymarchingObject::new(
+                        Vec3::new(0.55, -0.45, 0.9),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(0.3, 0.3, 0.3, 0.0)),
+                        1,
+                    ),
+                ],
+                camera: Camera::new(Vec3::new(0.0, 0.2, -2.0), 0.0, 0.2),
+                sun_dir: Vec3::new(0.8, 1.0, 0.6),
+                ambient_occlusion: AmbientOcclusion {
+                    mode: AoMode::Sdf,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "reflections",
+            time: 0.0,
+            options: RenderOptions {
+                raymarching_objects: vec![
+                    sphere(-1.1, -0.3, 1.6, 0.45, 1),
+                    sphere(0.0, -0.35, 1.2, 0.4, 2),
+                    sphere(1.1, -0.35, 1.6, 0.4, 4),
+                    // A pool resting just above the floor
+                    RaymarchingObject::new(
+                        Vec3::new(0.0, -0.6, 0.1),
+                        Primitive::new(PrimitiveKind::RoundBox, Vec4::new(1.6, 0.14, 0.5, 0.05)),
+                        3,
+                    ),
+                ],
+                materials: vec![
+                    Material::new("Floor", Vec3::ONE, 1.0).with_pattern(Pattern::Checker),
+                    Material::chrome(),
+                    Material::glass(),
+                    Material::water(),
+                    Material {
+                        metallic: 1.0,
+                        ..Material::new("Brushed gold", Vec3::new(1.0, 0.78, 0.34), 0.25)
+                    },
+                ],
+                camera: Camera::new(Vec3::new(0.0, 0.6, -2.2), 0.0, 0.3),
+                sun_dir: Vec3::new(0.6, 0.5, 0.8),
+                ..Default::default()
+            },
+        },
+        GoldenScene {
+            name: "volumetrics",
+            time: 0.0,
+            options: RenderOptions {
+                // Pillars in a bank of fog, lit from behind so they cast shafts towards the camera
+                raymarching_objects: vec![
+                    RaymarchingObject::new(
+                        Vec3::new(-0.7, 0.25, 1.6),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(0.15, 1.0, 0.15, 0.0)),
+                        0,
+                    ),
+                    RaymarchingObject::new(
+                        Vec3::new(0.5, 0.25, 1.9),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(0.15, 1.0, 0.15, 0.0)),
+                        0,
+                    ),
+                ],
+                fog_volumes: vec![FogVolume {
+                    anisotropy: 0.6,
+                    edge: 0.3,
+                    ..FogVolume::new(
+                        Vec3::new(0.0, 0.1, 1.2),
+                        Primitive::new(PrimitiveKind::Box, Vec4::new(1.8, 0.85, 1.0, 0.0)),
+                        0.6,
+                    )
+                }],
+                camera: Camera::new(Vec3::new(0.0, 0.0, -1.8), 0.0, -0.23),
+                sun_dir: Vec3::new(-0.3, 0.35, 1.0),
+                ambient: 0.15,
+                ..Default::default()
+            },
+        },
+    ]
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// Largest allowed per-channel difference before a pixel counts as mismatched.
+    pub per_channel: u8,
+    /// Fraction of pixels that may mismatch, absorbs rasterizer differences between adapters.
+    pub max_mismatched_fraction: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            per_channel: 8,
+            max_mismatched_fraction: 0.001,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub total_pixels: usize,
+    pub max_difference: u8,
+    /// RGBA8 image: mismatched pixels in red over a dimmed copy of the reference.
+    pub diff_image: Vec<u8>,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: Tolerance) -> bool {
+        self.mismatched_pixels as f32
+            <= tolerance.max_mismatched_fraction * self.total_pixels as f32
+    }
+}
+
+/// Compares two equally sized RGBA8 images.
+pub fn compare(reference: &[u8], actual: &[u8], tolerance: Tolerance) -> Comparison {
+    let total_pixels = reference.len() / 4;
+    if reference.len() != actual.len() {
+        return Comparison {
+            mismatched_pixels: total_pixels,
+            total_pixels,
+            max_difference: u8::MAX,
+            diff_image: vec![255; reference.len()],
+        };
+    }
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff_image = Vec::with_capacity(reference.len());
+    for (expected, got) in reference.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let difference = expected
+            .iter()
+            .zip(got)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or_default();
+        max_difference = max_difference.max(difference);
+        if difference > tolerance.per_channel {
+            mismatched_pixels += 1;
+            diff_image.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            diff_image.extend_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, 255]);
+        }
+    }
+
+    Comparison {
+        mismatched_pixels,
+        total_pixels,
+        max_difference,
+        diff_image,
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenOutcome {
+    Passed(Comparison),
+    Failed(Comparison),
+    MissingReference,
+    Blessed,
+}
+
+#[derive(Debug)]
+pub struct GoldenResult {
+    pub name: &'static str,
+    pub outcome: GoldenOutcome,
+}
+
+impl GoldenResult {
+    pub fn is_ok(&self) -> bool {
+        matches!(
+            self.outcome,
+            GoldenOutcome::Passed(_) | GoldenOutcome::Blessed
+        )
+    }
+}
+
+impl fmt::Display for GoldenResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            GoldenOutcome::Passed(comparison) => write!(
+                f,
+                "{}: ok (max difference {})",
+                self.name, comparison.max_difference
+            ),
+            GoldenOutcome::Failed(comparison) => write!(
+                f,
+                "{}: FAILED, {}/{} pixels differ (max difference {})",
+                self.name,
+                comparison.mismatched_pixels,
+                comparison.total_pixels,
+                comparison.max_difference
+            ),
+            GoldenOutcome::MissingReference => {
+                write!(
+                    f,
+                    "{}: no reference image, run with blessing enabled",
+                    self.name
+                )
+            }
+            GoldenOutcome::Blessed => write!(f, "{}: reference updated", self.name),
+        }
+    }
+}
+
+/// Renders golden scenes with a [`HeadlessRenderer`] and checks them against reference PNGs.
+pub struct GoldenHarness {
+    /// Directory holding the checked-in `<scene>.png` references.
+    pub reference_dir: PathBuf,
+    /// Where `<scene>.actual.png` and `<scene>.diff.png` are written for failing scenes.
+    pub output_dir: PathBuf,
+    pub tolerance: Tolerance,
+    /// Overwrite references with the current output instead of comparing.
+    pub bless: bool,
+}
+
+impl GoldenHarness {
+    pub fn run_scene(
+        &self,
+        renderer: &mut HeadlessRenderer,
+        scene: &GoldenScene,
+    ) -> anyhow::Result<GoldenResult> {
+        *renderer.get_options() = scene.options.clone();
+        renderer.render_pass_manager().set_time(Some(scene.time));
+        let actual = renderer.render()?;
+        let (width, height) = renderer.size();
+
+        let reference_path = self.reference_dir.join(format!("{}.png", scene.name));
+        if self.bless {
+            fs::create_dir_all(&self.reference_dir)?;
+            write_png(&reference_path, width, height, &actual)?;
+            info!("Blessed {}", reference_path.display());
+            return Ok(GoldenResult {
+                name: scene.name,
+                outcome: GoldenOutcome::Blessed,
+            });
+        }
+
+        fs::create_dir_all(&self.output_dir)?;
+        let actual_path = self.output_dir.join(format!("{}.actual.png", scene.name));
+        if !reference_path.exists() {
+            write_png(&actual_path, width, height, &actual)?;
+            return Ok(GoldenResult {
+                name: scene.name,
+                outcome: GoldenOutcome::MissingReference,
+            });
+        }
+
+        let (reference_width, reference_height, reference) = read_png(&reference_path)?;
+        let mut comparison = compare(&reference, &actual, self.tolerance);
+        if (reference_width, reference_height) != (width, height) {
+            comparison.mismatched_pixels = comparison.total_pixels;
+        }
+
+        if comparison.passes(self.tolerance) {
+            return Ok(GoldenResult {
+                name: scene.name,
+                outcome: GoldenOutcome::Passed(comparison),
+            });
+        }
+
+        write_png(&actual_path, width, height, &actual)?;
+        if comparison.diff_image.len() == actual.len() {
+            let diff_path = self.output_dir.join(format!("{}.diff.png", scene.name));
+            write_png(&diff_path, width, height, &comparison.diff_image)?;
+        }
+        Ok(GoldenResult {
+            name: scene.name,
+            outcome: GoldenOutcome::Failed(comparison),
+        })
+    }
+
+    pub fn run_all(
+        &self,
+        renderer: &mut HeadlessRenderer,
+        scenes: &[GoldenScene],
+    ) -> anyhow::Result<Vec<GoldenResult>> {
+        scenes
+            .iter()
+            .map(|scene| self.run_scene(renderer, scene))
+            .collect()
+    }
+}
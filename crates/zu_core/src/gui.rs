@@ -1,13 +1,41 @@
+use std::path::PathBuf;
+
+use crate::camera_controller::{CameraController, CameraMode};
+use crate::capture::CaptureManager;
+use crate::gizmo::{Gizmo, GizmoMode};
+use crate::history::History;
+use crate::outliner::Outliner;
+use crate::render_passes::raymarching_passes::material::{Material, remove_material};
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
 use crate::render_passes::render_pass_manager::RenderOptions;
+use crate::scene;
+use crate::scene_graph::object_index;
 use crate::widgets::usage_diagnostics::UsageDiagnostics;
-use egui::Context;
 use egui::Widget;
+use egui::{Context, Key, KeyboardShortcut, Modifiers};
 use egui_probe::Probe;
 use puffin_egui::profiler_window;
 
+const UNDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+
 pub struct EngineGui {
     egui_context: Context,
     open_profiler_window: bool,
+    open_materials_window: bool,
+    open_history_window: bool,
+    open_outliner_window: bool,
+    /// Index of the material edited in the materials window.
+    selected_material: usize,
+    /// Object tree and selection, objects are also selected by clicking them in the window.
+    outliner: Outliner,
+    /// Handles for moving the selected object around in the window.
+    gizmo: Gizmo,
+    /// Undo steps of everything edited in the GUI.
+    history: History,
+    /// File the scene was last opened from or saved to, target of File > Save.
+    scene_path: Option<PathBuf>,
 }
 
 impl EngineGui {
@@ -15,24 +43,379 @@ impl EngineGui {
         Self {
             egui_context: context.clone(),
             open_profiler_window: false,
+            open_materials_window: false,
+            open_history_window: false,
+            open_outliner_window: true,
+            selected_material: 0,
+            outliner: Outliner::default(),
+            gizmo: Gizmo::default(),
+            history: History::default(),
+            scene_path: None,
+        }
+    }
+
+    /// Selects the object with id `object` and brings its row in the outliner into view. `None`,
+    /// or an id no longer in `objects`, clears the selection.
+    pub fn select_object(&mut self, objects: &[RaymarchingObject], object: Option<u64>) {
+        let index = object.and_then(|id| object_index(objects, id));
+        self.outliner.select(objects, index);
+        self.open_outliner_window |= index.is_some();
+    }
+
+    /// Whether the pointer is over a gizmo handle or dragging one.
+    pub fn gizmo_active(&self) -> bool {
+        self.gizmo.is_active()
+    }
+
+    /// Replaces `render_options` with the scene at `path`, keeping them if it fails to load.
+    pub fn open_scene(&mut self, path: PathBuf, render_options: &mut RenderOptions) {
+        match scene::load(&path) {
+            Ok(options) => {
+                log::info!("Opened scene {}", path.display());
+                *render_options = options;
+                self.history.reset(render_options);
+                self.scene_path = Some(path);
+            }
+            Err(err) => log::error!("Failed to open scene: {err:#}"),
+        }
+    }
+
+    pub fn save_scene(&mut self, path: PathBuf, render_options: &RenderOptions) {
+        match scene::save(&path, render_options) {
+            Ok(()) => {
+                log::info!("Saved scene {}", path.display());
+                self.scene_path = Some(path);
+            }
+            Err(err) => log::error!("Failed to save scene: {err:#}"),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn file_menu(&mut self, ui: &mut egui::Ui, render_options: &mut RenderOptions) {
+        let dialog = || rfd::FileDialog::new().add_filter("Scene", &[scene::SCENE_EXTENSION]);
+        if ui.button("Open…").clicked() {
+            ui.close();
+            if let Some(path) = dialog().pick_file() {
+                self.open_scene(path, render_options);
+            }
+        }
+        if let Some(path) = self.scene_path.clone()
+            && ui.button("Save").clicked()
+        {
+            ui.close();
+            self.save_scene(path, render_options);
+        }
+        if ui.button("Save as…").clicked() {
+            ui.close();
+            if let Some(path) = dialog().set_file_name("scene.toml").save_file() {
+                self.save_scene(path, render_options);
+            }
+        }
+    }
+
+    /// Material list with the selected material's editor, and a material picker per object.
+    fn materials_window(&mut self, ui: &mut egui::Ui, render_options: &mut RenderOptions) {
+        let materials = &mut render_options.materials;
+        let objects = &mut render_options.raymarching_objects;
+        let has_selection = self.selected_material < materials.len();
+        ui.horizontal(|ui| {
+            if ui.button("New").clicked() {
+                materials.push(Material::default());
+                self.selected_material = materials.len() - 1;
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Duplicate"))
+                .clicked()
+            {
+                let mut copy = materials[self.selected_material].clone();
+                copy.name += " copy";
+                materials.push(copy);
+                self.selected_material = materials.len() - 1;
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Delete"))
+                .on_hover_text("Objects using it switch to material 0")
+                .clicked()
+            {
+                remove_material(materials, objects, self.selected_material);
+                self.selected_material = self.selected_material.min(materials.len().max(1) - 1);
+            }
+        });
+
+        for (index, material) in materials.iter().enumerate() {
+            ui.selectable_value(
+                &mut self.selected_material,
+                index,
+                format!("{index}: {}", material.name),
+            );
+        }
+        if let Some(material) = materials.get_mut(self.selected_material) {
+            ui.separator();
+            Probe::new(material).show(ui);
+        }
+
+        ui.collapsing("Objects", |ui| {
+            let name = |index: i32| {
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| materials.get(index))
+                    .map_or("Missing", |material| material.name.as_str())
+            };
+            for (index, object) in objects.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{index}  {}", object.name));
+                    egui::ComboBox::from_id_salt(("Object material", index))
+                        .selected_text(name(object.material))
+                        .show_ui(ui, |ui| {
+                            for material in 0..materials.len() as i32 {
+                                ui.selectable_value(&mut object.material, material, name(material));
+                            }
+                        });
+                });
+            }
+        });
+    }
+
+    /// Undo and redo, and every step to jump back or forward to.
+    fn history_window(&mut self, ui: &mut egui::Ui, render_options: &mut RenderOptions) {
+        let ctx = ui.ctx().clone();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                .on_hover_text(ctx.format_shortcut(&UNDO_SHORTCUT))
+                .clicked()
+            {
+                self.history.undo(render_options);
+            }
+            if ui
+                .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                .on_hover_text(ctx.format_shortcut(&REDO_SHORTCUT))
+                .clicked()
+            {
+                self.history.redo(render_options);
+            }
+        });
+        ui.separator();
+
+        let mut jump = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let labels = std::iter::once("Start")
+                .chain((self.history.steps().iter()).map(|step| step.label.as_str()));
+            for (position, label) in labels.enumerate() {
+                let current = position == self.history.position();
+                // Steps that would be redone are greyed out
+                let text = if position > self.history.position() {
+                    egui::RichText::new(label).weak()
+                } else {
+                    egui::RichText::new(label)
+                };
+                if ui.selectable_label(current, text).clicked() {
+                    jump = Some(position);
+                }
+            }
+        });
+        if let Some(position) = jump {
+            self.history.jump_to(position, render_options);
+        }
+    }
+
+    /// Ctrl+Z and Ctrl+Shift+Z, left to text fields while one is focused.
+    fn undo_shortcuts(&mut self, render_options: &mut RenderOptions) {
+        let ctx = &self.egui_context;
+        if ctx.wants_keyboard_input() || ctx.input(|input| input.pointer.any_down()) {
+            return;
+        }
+        // Redo first, the undo shortcut matches with shift held too
+        if ctx.input_mut(|input| input.consume_shortcut(&REDO_SHORTCUT)) {
+            self.history.redo(render_options);
+        } else if ctx.input_mut(|input| input.consume_shortcut(&UNDO_SHORTCUT)) {
+            self.history.undo(render_options);
         }
     }
 
     pub fn render_gui(
         &mut self,
         render_options: &mut RenderOptions,
+        capture_manager: &mut CaptureManager,
+        path_tracing_samples: u32,
+        camera_controller: &mut CameraController,
         vsync_enabled: &mut bool,
         recreate_render_pass_manager: &mut bool,
     ) {
-        egui::Window::new("Engine Window").show(&self.egui_context, |ui| {
-            Probe::new(render_options).show(ui);
-            UsageDiagnostics {}.ui(ui);
-            ui.checkbox(vsync_enabled, "Vsync enabled");
-            ui.checkbox(&mut self.open_profiler_window, "Open profiler window");
-            *recreate_render_pass_manager = ui.button("Recreate Render Pass Manager").clicked();
+        self.undo_shortcuts(render_options);
+        #[cfg(not(target_arch = "wasm32"))]
+        egui::TopBottomPanel::top("Menu bar").show(&self.egui_context.clone(), |ui| {
+            egui::MenuBar::new().ui(ui, |ui| {
+                ui.menu_button("File", |ui| self.file_menu(ui, render_options));
+            });
         });
+        egui::Window::new("Engine Window")
+            .vscroll(true)
+            .show(&self.egui_context.clone(), |ui| {
+                if let Some(path) = &self.scene_path {
+                    ui.label(format!("Scene: {}", path.display()));
+                }
+                Probe::new(&mut *render_options).show(ui);
+                if render_options.path_tracing.enabled {
+                    ui.label(format!("Path tracing: {path_tracing_samples} samples"));
+                }
+                ui.collapsing("Camera controls", |ui| {
+                    let objects = &render_options.raymarching_objects;
+                    let active = self.outliner.active(objects);
+                    camera_controls(ui, camera_controller, objects, active)
+                });
+                ui.collapsing("Gizmo", |ui| gizmo_settings(ui, &mut self.gizmo));
+                ui.collapsing("Capture", |ui| {
+                    Probe::new(&mut capture_manager.options).show(ui);
+                    ui.horizontal(|ui| {
+                        if ui.button("Screenshot (F12)").clicked() {
+                            capture_manager.screenshot();
+                        }
+                        if capture_manager.is_recording_sequence() {
+                            if ui.button("Stop sequence").clicked() {
+                                capture_manager.stop_sequence();
+                            }
+                        } else if ui.button("Record sequence").clicked() {
+                            capture_manager.start_sequence();
+                        }
+                    });
+                });
+                UsageDiagnostics {}.ui(ui);
+                ui.checkbox(vsync_enabled, "Vsync enabled");
+                ui.checkbox(&mut self.open_outliner_window, "Open outliner window");
+                ui.checkbox(&mut self.open_materials_window, "Open materials window");
+                ui.checkbox(&mut self.open_history_window, "Open history window");
+                ui.checkbox(&mut self.open_profiler_window, "Open profiler window");
+                *recreate_render_pass_manager = ui.button("Recreate Render Pass Manager").clicked();
+            });
+        let mut open_outliner_window = self.open_outliner_window;
+        egui::Window::new("Outliner")
+            .open(&mut open_outliner_window)
+            .vscroll(true)
+            .show(&self.egui_context.clone(), |ui| {
+                self.outliner
+                    .show(ui, &mut render_options.raymarching_objects)
+            });
+        self.open_outliner_window = open_outliner_window;
+        let mut open_materials_window = self.open_materials_window;
+        egui::Window::new("Materials")
+            .open(&mut open_materials_window)
+            .show(&self.egui_context.clone(), |ui| {
+                self.materials_window(ui, render_options)
+            });
+        self.open_materials_window = open_materials_window;
+        let mut open_history_window = self.open_history_window;
+        egui::Window::new("History")
+            .open(&mut open_history_window)
+            .show(&self.egui_context.clone(), |ui| {
+                self.history_window(ui, render_options)
+            });
+        self.open_history_window = open_history_window;
+        let active = self.outliner.active(&render_options.raymarching_objects);
+        self.gizmo.show(
+            &self.egui_context,
+            &render_options.camera,
+            &mut render_options.raymarching_objects,
+            active,
+        );
+
+        // Drags and typing become one step once they end
+        let editing = self.egui_context.wants_keyboard_input()
+            || self.egui_context.input(|input| input.pointer.any_down());
+        self.history.record(render_options, editing);
         if self.open_profiler_window {
             profiler_window(&self.egui_context);
         }
     }
 }
+
+/// Mode and snapping steps of the selected object's handles.
+fn gizmo_settings(ui: &mut egui::Ui, gizmo: &mut Gizmo) {
+    ui.label("Select an object by clicking it, then drag its handles");
+    egui::ComboBox::from_label("Handles")
+        .selected_text(gizmo.mode.name())
+        .show_ui(ui, |ui| {
+            for mode in GizmoMode::ALL {
+                ui.selectable_value(&mut gizmo.mode, mode, mode.name());
+            }
+        });
+    ui.checkbox(&mut gizmo.snap, "Snap (hold ctrl to flip)");
+    ui.add(
+        egui::DragValue::new(&mut gizmo.grid)
+            .range(0.01..=10.0)
+            .speed(0.01)
+            .prefix("Grid: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut gizmo.angle_step)
+            .range(1.0..=90.0)
+            .suffix("°")
+            .prefix("Angle step: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut gizmo.scale_step)
+            .range(0.01..=1.0)
+            .speed(0.01)
+            .prefix("Scale step: "),
+    );
+}
+
+/// Mode, orbited object and speeds of the fly and orbit controls. Switching to orbiting circles
+/// the `active` object.
+fn camera_controls(
+    ui: &mut egui::Ui,
+    controller: &mut CameraController,
+    objects: &[RaymarchingObject],
+    active: Option<usize>,
+) {
+    ui.label("Hold the right mouse button to look around");
+    let previous_mode = controller.mode;
+    egui::ComboBox::from_label("Mode")
+        .selected_text(controller.mode.name())
+        .show_ui(ui, |ui| {
+            for mode in CameraMode::ALL {
+                ui.selectable_value(&mut controller.mode, mode, mode.name());
+            }
+        });
+    if controller.mode == CameraMode::Orbit && previous_mode != CameraMode::Orbit {
+        controller.orbit_object = active.map(|index| objects[index].id);
+    }
+    match controller.mode {
+        CameraMode::Fly => {
+            ui.label("WASD to move, Q and E down and up, shift to speed up");
+            ui.add(
+                egui::Slider::new(&mut controller.speed, 0.01..=1000.0)
+                    .logarithmic(true)
+                    .text("Speed"),
+            );
+        }
+        CameraMode::Orbit => {
+            let name = |object: Option<usize>| match object {
+                Some(index) => format!("{index}  {}", objects[index].name),
+                None => "Point in front".to_owned(),
+            };
+            // Orbited objects deleted since fall back to the point in front
+            let orbited = controller
+                .orbit_object
+                .and_then(|id| object_index(objects, id));
+            egui::ComboBox::from_label("Orbit around")
+                .selected_text(name(orbited))
+                .show_ui(ui, |ui| {
+                    for object in std::iter::once(None).chain((0..objects.len()).map(Some)) {
+                        if ui
+                            .selectable_label(orbited == object, name(object))
+                            .clicked()
+                        {
+                            controller.orbit_object = object.map(|index| objects[index].id);
+                        }
+                    }
+                });
+            ui.label("Scroll to zoom");
+        }
+    }
+    ui.add(
+        egui::Slider::new(&mut controller.sensitivity, 0.0005..=0.02)
+            .logarithmic(true)
+            .text("Mouse sensitivity"),
+    );
+}
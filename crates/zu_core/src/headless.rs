@@ -0,0 +1,167 @@
+use log::info;
+use wgpu::{ExperimentalFeatures, InstanceFlags, TextureFormat};
+
+use crate::render_passes::raymarching_passes::picking::PickResult;
+use crate::render_passes::render_pass_manager::{RenderOptions, RenderPassManager};
+use crate::texture_manager::readback::read_texture;
+
+/// Renders the raymarching and show passes into an offscreen texture, without a window or surface.
+///
+/// Used for batch renders and automated tests; works on software adapters (llvmpipe, lavapipe, WARP).
+pub struct HeadlessRenderer {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub adapter_info: wgpu::AdapterInfo,
+    render_pass_manager: RenderPassManager,
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+impl HeadlessRenderer {
+    /// Format of the tone-mapped output returned by [`HeadlessRenderer::render`].
+    pub const OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+    /// Creates a device without a surface. If no hardware adapter is available,
+    /// or `force_fallback_adapter` is set, a software adapter is used instead.
+    pub async fn new(
+        width: u32,
+        height: u32,
+        force_fallback_adapter: bool,
+    ) -> anyhow::Result<Self> {
+        info!("Creating headless renderer {}x{}...", width, height);
+        let width = width.max(1);
+        let height = height.max(1);
+
+        let mut flags = InstanceFlags::default();
+        flags.remove(InstanceFlags::VALIDATION);
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            flags,
+            ..Default::default()
+        });
+
+        let request_adapter = |force_fallback_adapter| {
+            instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+        };
+        let adapter = match request_adapter(force_fallback_adapter).await {
+            Ok(adapter) => adapter,
+            Err(err) if !force_fallback_adapter => {
+                log::warn!("No hardware adapter ({err}), trying fallback adapter");
+                request_adapter(true).await?
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let adapter_info = adapter.get_info();
+        info!("Headless adapter: {:?}", adapter_info);
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("Headless device"),
+                required_features: RenderPassManager::required_features()
+                    | (RenderPassManager::optional_features() & adapter.features()),
+                required_limits: RenderPassManager::required_limits(),
+                memory_hints: Default::default(),
+                trace: wgpu::Trace::Off,
+                experimental_features: ExperimentalFeatures::disabled(),
+            })
+            .await?;
+
+        let render_pass_manager =
+            RenderPassManager::new(&device, &queue, Self::OUTPUT_FORMAT, width, height);
+        let (output_texture, output_view) = create_output_texture(&device, width, height);
+
+        Ok(Self {
+            device,
+            queue,
+            adapter_info,
+            render_pass_manager,
+            output_texture,
+            output_view,
+            width,
+            height,
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 || (self.width == width && self.height == height) {
+            return;
+        }
+        self.render_pass_manager
+            .resize(width, height, &self.device, &self.queue);
+        (self.output_texture, self.output_view) =
+            create_output_texture(&self.device, width, height);
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Renders one frame and returns tightly packed [`Self::OUTPUT_FORMAT`] pixels, row by row.
+    pub fn render(&mut self) -> anyhow::Result<Vec<u8>> {
+        puffin::profile_function!();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless encoder"),
+            });
+        self.render_pass_manager
+            .render(&self.queue, &self.output_view, &mut encoder, &self.device);
+        self.queue.submit(Some(encoder.finish()));
+        self.render_pass_manager
+            .finish_captures(&self.device, &self.queue)?;
+
+        read_texture(&self.device, &self.queue, &self.output_texture)
+    }
+
+    /// Renders one frame that picks the object at pixel `(x, y)`, and waits for the result.
+    pub fn pick(&mut self, x: u32, y: u32) -> anyhow::Result<PickResult> {
+        self.render_pass_manager.pick(x, y);
+        self.render()?;
+        if let Some(pick) = self.render_pass_manager.poll_pick(&self.device) {
+            return Ok(pick);
+        }
+        self.device.poll(wgpu::PollType::wait_indefinitely())?;
+        self.render_pass_manager
+            .poll_pick(&self.device)
+            .ok_or_else(|| anyhow::anyhow!("Pick at ({x}, {y}) wasn't read back"))
+    }
+
+    pub fn get_options(&mut self) -> &mut RenderOptions {
+        self.render_pass_manager.get_options()
+    }
+
+    pub fn render_pass_manager(&mut self) -> &mut RenderPassManager {
+        &mut self.render_pass_manager
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+fn create_output_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Headless output"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HeadlessRenderer::OUTPUT_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
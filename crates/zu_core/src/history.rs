@@ -0,0 +1,244 @@
+//! Undo and redo of the edits made to [`RenderOptions`].
+//!
+//! The GUI records the options after every frame. A frame that changed them becomes a step
+//! holding the parts that changed as they were before and after: the objects, the materials,
+//! the lights or the remaining settings. Nothing is recorded while the user is still editing,
+//! holding the pointer or typing into a field, so a whole drag lands as one step once it ends.
+//! The camera is left out, it moves with the fly and orbit controls all the time.
+
+use std::cmp::Ordering;
+
+use crate::render_passes::raymarching_passes::light::Light;
+use crate::render_passes::raymarching_passes::material::Material;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+use crate::render_passes::render_pass_manager::RenderOptions;
+
+/// Steps kept before the oldest are dropped.
+pub const MAX_STEPS: usize = 256;
+
+/// A part of the options a step swaps out.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Objects(Vec<RaymarchingObject>),
+    Materials(Vec<Material>),
+    Lights(Vec<Light>),
+    /// Everything but the lists above and the camera, see [`settings`].
+    Settings(Box<RenderOptions>),
+}
+
+impl Part {
+    fn apply(&self, options: &mut RenderOptions) {
+        match self {
+            Part::Objects(objects) => options.raymarching_objects.clone_from(objects),
+            Part::Materials(materials) => options.materials.clone_from(materials),
+            Part::Lights(lights) => options.lights.clone_from(lights),
+            Part::Settings(settings) => {
+                let camera = options.camera;
+                let objects = std::mem::take(&mut options.raymarching_objects);
+                let materials = std::mem::take(&mut options.materials);
+                let lights = std::mem::take(&mut options.lights);
+                *options = RenderOptions {
+                    camera,
+                    raymarching_objects: objects,
+                    materials,
+                    lights,
+                    ..(**settings).clone()
+                };
+            }
+        }
+    }
+}
+
+/// One undoable edit.
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// What changed, shown in the history window.
+    pub label: String,
+    before: Vec<Part>,
+    after: Vec<Part>,
+}
+
+pub struct History {
+    /// The options as of the last step, what the next one is diffed against.
+    current: RenderOptions,
+    steps: Vec<Step>,
+    /// Steps before this index are applied, the ones after it can be redone.
+    position: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(&RenderOptions::default())
+    }
+}
+
+impl History {
+    pub fn new(options: &RenderOptions) -> Self {
+        Self {
+            current: options.clone(),
+            steps: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Forgets every step and starts over from `options`, after a scene is opened.
+    pub fn reset(&mut self, options: &RenderOptions) {
+        *self = Self::new(options);
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Number of steps applied, 0 at the state the history started from.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.position < self.steps.len()
+    }
+
+    /// Adds a step if `options` changed since the last one, dropping the steps that could be
+    /// redone. While `editing` the change is held back so it joins what follows. Returns
+    /// whether a step was added.
+    pub fn record(&mut self, options: &RenderOptions, editing: bool) -> bool {
+        if editing {
+            return false;
+        }
+        self.current.camera = options.camera;
+        if self.current == *options {
+            return false;
+        }
+
+        let (mut before, mut after) = (Vec::new(), Vec::new());
+        let mut labels = Vec::new();
+        let current = &self.current;
+        if current.raymarching_objects != options.raymarching_objects {
+            before.push(Part::Objects(current.raymarching_objects.clone()));
+            after.push(Part::Objects(options.raymarching_objects.clone()));
+            labels.push(list_label(
+                "object",
+                &current.raymarching_objects,
+                &options.raymarching_objects,
+            ));
+        }
+        if current.materials != options.materials {
+            before.push(Part::Materials(current.materials.clone()));
+            after.push(Part::Materials(options.materials.clone()));
+            labels.push(list_label(
+                "material",
+                &current.materials,
+                &options.materials,
+            ));
+        }
+        if current.lights != options.lights {
+            before.push(Part::Lights(current.lights.clone()));
+            after.push(Part::Lights(options.lights.clone()));
+            labels.push(list_label("light", &current.lights, &options.lights));
+        }
+        let (old, new) = (settings(current), settings(options));
+        if old != new {
+            labels.push(settings_label(&old, &new));
+            before.push(Part::Settings(Box::new(old)));
+            after.push(Part::Settings(Box::new(new)));
+        }
+
+        self.steps.truncate(self.position);
+        self.steps.push(Step {
+            label: labels.join(", "),
+            before,
+            after,
+        });
+        if self.steps.len() > MAX_STEPS {
+            self.steps.remove(0);
+        }
+        self.position = self.steps.len();
+        self.current = options.clone();
+        true
+    }
+
+    /// Reverts the last applied step. Returns false when there is none.
+    pub fn undo(&mut self, options: &mut RenderOptions) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        self.position -= 1;
+        apply(
+            &self.steps[self.position].before,
+            options,
+            &mut self.current,
+        );
+        true
+    }
+
+    /// Applies the first step after the position. Returns false when there is none.
+    pub fn redo(&mut self, options: &mut RenderOptions) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        apply(&self.steps[self.position].after, options, &mut self.current);
+        self.position += 1;
+        true
+    }
+
+    /// Undoes or redoes up to `position`, the state after that many steps.
+    pub fn jump_to(&mut self, position: usize, options: &mut RenderOptions) {
+        let position = position.min(self.steps.len());
+        while self.position > position && self.undo(options) {}
+        while self.position < position && self.redo(options) {}
+    }
+}
+
+/// Applies `parts` to the edited options and to the history's copy of them.
+fn apply(parts: &[Part], options: &mut RenderOptions, current: &mut RenderOptions) {
+    for part in parts {
+        part.apply(options);
+        part.apply(current);
+    }
+}
+
+/// `options` without the parts diffed separately.
+fn settings(options: &RenderOptions) -> RenderOptions {
+    RenderOptions {
+        camera: Default::default(),
+        raymarching_objects: Vec::new(),
+        materials: Vec::new(),
+        lights: Vec::new(),
+        ..options.clone()
+    }
+}
+
+fn list_label<T: PartialEq>(name: &str, before: &[T], after: &[T]) -> String {
+    match after.len().cmp(&before.len()) {
+        Ordering::Greater => format!("Add {name}"),
+        Ordering::Less => format!("Delete {name}"),
+        Ordering::Equal => {
+            let mut changed = (before.iter().zip(after).enumerate()).filter(|(_, (a, b))| a != b);
+            match (changed.next(), changed.next()) {
+                (Some((index, _)), None) => format!("Edit {name} {index}"),
+                _ => format!("Edit {name}s"),
+            }
+        }
+    }
+}
+
+/// Names the settings that changed, by their names in scene files.
+fn settings_label(before: &RenderOptions, after: &RenderOptions) -> String {
+    let table = |options: &RenderOptions| toml::Table::try_from(options).unwrap_or_default();
+    let (before, after) = (table(before), table(after));
+    let changed: Vec<_> = after
+        .iter()
+        .filter(|(key, value)| before.get(*key) != Some(*value))
+        .map(|(key, _)| key.replace('_', " "))
+        .collect();
+    if changed.is_empty() {
+        "Edit settings".to_owned()
+    } else {
+        format!("Edit {}", changed.join(", "))
+    }
+}
@@ -1,8 +1,20 @@
 pub mod app;
 pub mod app_state;
+pub mod camera_controller;
+pub mod capture;
 pub mod egui_tools;
+pub mod gizmo;
+pub mod golden;
 pub mod gui;
+pub mod headless;
+pub mod history;
+pub mod object_grid;
+pub mod octree;
+pub mod outliner;
+pub(crate) mod probes;
 pub mod render_passes;
+pub mod scene;
+pub mod scene_graph;
 pub mod styles;
 pub mod texture_manager;
 pub mod widgets;
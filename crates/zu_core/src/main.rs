@@ -1,5 +1,6 @@
 use log::info;
 use std::env;
+use std::path::PathBuf;
 use winit::event_loop::{ControlFlow, EventLoop};
 use zu_core::{app::App, start_puffin_server};
 
@@ -12,9 +13,16 @@ use backtrace::Backtrace;
 #[cfg(target_os = "windows")]
 use std::panic::{self, PanicInfo};
 
-/// Parse CLI arguments and return module path if specified
-fn parse_args() -> Option<String> {
+#[derive(Default)]
+struct CliArgs {
+    module: Option<String>,
+    scene: Option<PathBuf>,
+}
+
+/// Parse CLI arguments
+fn parse_args() -> CliArgs {
     let args: Vec<String> = env::args().collect();
+    let mut cli_args = CliArgs::default();
 
     // Skip program name
     let mut args_iter = args.iter().skip(1);
@@ -22,7 +30,10 @@ fn parse_args() -> Option<String> {
     while let Some(arg) = args_iter.next() {
         match arg.as_str() {
             "-m" | "--module" => {
-                return args_iter.next().cloned();
+                cli_args.module = args_iter.next().cloned();
+            }
+            "-s" | "--scene" => {
+                cli_args.scene = args_iter.next().map(PathBuf::from);
             }
             "--help" | "-h" => {
                 println!("Zurie Engine");
@@ -31,22 +42,24 @@ fn parse_args() -> Option<String> {
                 println!();
                 println!("Options:");
                 println!("  -m, --module <PATH>  Path to WASM module to load");
+                println!("  -s, --scene <PATH>   Scene file to open");
                 println!("  -h, --help           Show this help message");
                 println!();
                 println!("Examples:");
                 println!("  zu_core                                    # Use engine.toml or show picker");
                 println!("  zu_core game.wasm                          # Load game.wasm");
                 println!("  zu_core -m ./target/wasm32-wasip2/release/my_game.wasm");
+                println!("  zu_core --scene my_scene.toml              # Open a scene file");
                 std::process::exit(0);
             }
             path if !path.starts_with('-') => {
-                return Some(path.to_string());
+                cli_args.module = Some(path.to_string());
             }
             _ => {}
         }
     }
 
-    None
+    cli_args
 }
 
 pub fn main() {
@@ -87,29 +100,33 @@ pub fn main() {
     start_puffin_server();
 
     // Parse CLI arguments
-    let cli_module = parse_args();
-    if let Some(ref path) = cli_module {
+    let cli_args = parse_args();
+    if let Some(ref path) = cli_args.module {
         info!("CLI module path: {}", path);
     }
+    if let Some(ref path) = cli_args.scene {
+        info!("CLI scene path: {}", path.display());
+    }
 
     info!("Starting App");
     #[cfg(not(target_arch = "wasm32"))]
     {
-        pollster::block_on(run(cli_module));
+        pollster::block_on(run(cli_args));
     }
     #[cfg(target_arch = "wasm32")]
     {
-        wasm_bindgen_futures::spawn_local(run(None));
+        wasm_bindgen_futures::spawn_local(run(CliArgs::default()));
     }
 }
 
-async fn run(cli_module: Option<String>) {
+async fn run(cli_args: CliArgs) {
     let event_loop = EventLoop::with_user_event().build().unwrap();
 
     event_loop.set_control_flow(ControlFlow::Poll);
 
     let mut app = App::new(
-        cli_module,
+        cli_args.module,
+        cli_args.scene,
         #[cfg(target_arch = "wasm32")]
         &event_loop,
     );
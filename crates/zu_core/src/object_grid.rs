@@ -0,0 +1,343 @@
+//! Uniform grid over the raymarching objects' world bounds.
+//!
+//! Every cell lists the objects whose bounds, padded by [`GRID_MARGIN`], overlap it, in object
+//! list order so CSG operations still fold in the same order. `map()` in
+//! `raymarching_compute.wgsl` only evaluates the cell around the sample point plus the
+//! unbounded objects (planes and intersections, which clip everything before them). Objects
+//! outside the cell are at least the distance to the cell's faces plus the margin away, which
+//! bounds the step. [`ObjectGrid::lookup`] is the CPU mirror of that lookup. Hidden objects
+//! are in no list, so they are never evaluated but keep their index for picking.
+//!
+//! Scenes with fewer than [`MIN_GRID_OBJECTS`] bounded objects get no cells and march every
+//! object with unclamped steps, which is cheaper there and renders exactly like without a grid.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, UVec3, Vec3};
+
+use crate::render_passes::raymarching_passes::csg::CsgOperation;
+use crate::render_passes::raymarching_passes::primitives::sd_box;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+use crate::scene_graph::SceneGraph;
+
+/// Padding around every object's bounds. Larger than the march hit distance, so the step bound
+/// of a cell never registers as a hit.
+pub const GRID_MARGIN: f32 = 0.1;
+/// Keeps huge scenes with a few tiny objects from allocating millions of cells.
+pub const MAX_CELLS_PER_AXIS: u32 = 64;
+/// Bounded objects needed before the scene is binned into cells.
+pub const MIN_GRID_OBJECTS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn expanded(&self, amount: f32) -> Aabb {
+        Aabb::new(self.min - amount, self.max + amount)
+    }
+
+    /// Box around the eight transformed corners.
+    pub fn transformed(&self, transform: Mat4) -> Aabb {
+        let center = transform.transform_point3(self.center());
+        let half = self.size() * 0.5;
+        let extent = transform.x_axis.truncate().abs() * half.x
+            + transform.y_axis.truncate().abs() * half.y
+            + transform.z_axis.truncate().abs() * half.z;
+        Aabb::new(center - extent, center + extent)
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite()
+    }
+}
+
+/// Matches `ObjectGridInfo` in `raymarching_compute.wgsl`. A zero `dims` means no cells.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct GpuObjectGridInfo {
+    pub origin: [f32; 3],
+    pub cell_size: f32,
+    pub dims: [u32; 3],
+    /// The first `global_count` entries of the index buffer are evaluated everywhere, the
+    /// unbounded objects or in small scenes all of them.
+    pub global_count: u32,
+}
+
+/// Range of a cell's object indices in the index buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct GpuGridCell {
+    pub first: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectGrid {
+    origin: Vec3,
+    cell_size: f32,
+    dims: UVec3,
+    global_count: usize,
+    cells: Vec<GpuGridCell>,
+    /// Unbounded objects followed by every cell's objects, ascending within each run.
+    indices: Vec<u32>,
+}
+
+impl ObjectGrid {
+    pub fn new(scene: &SceneGraph) -> Self {
+        let objects = scene.objects();
+        let shown: Vec<_> = (0..objects.len())
+            .map(|index| scene.is_visible(index))
+            .collect();
+        let mut bounds: Vec<_> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| match object.operation() {
+                CsgOperation::Intersection | CsgOperation::SmoothIntersection => None,
+                _ => scene
+                    .world_bounds(index)
+                    .map(|bounds| bounds.expanded(GRID_MARGIN + blend_radius(object)))
+                    .filter(Aabb::is_finite),
+            })
+            .collect();
+        let grid = Self::from_bounds(&bounds, &shown);
+        if grid.cells.is_empty() {
+            return grid;
+        }
+
+        // Smooth operations also pull in the surfaces before them, so objects within reach of
+        // a blend are padded by its radius to stay in every cell the blend touches
+        let mut padding = vec![0.0_f32; objects.len()];
+        for (index, object) in objects.iter().enumerate() {
+            let blend = blend_radius(object);
+            let Some(reach) = bounds[index].filter(|_| blend > 0.0 && shown[index]) else {
+                continue;
+            };
+            for cell in grid.cells_overlapping(&reach.expanded(blend)) {
+                let cell = grid.cells[cell];
+                for &other in &grid.indices[cell.first as usize..(cell.first + cell.count) as usize]
+                {
+                    let other = other as usize;
+                    if other < index {
+                        padding[other] = padding[other].max(blend);
+                    }
+                }
+            }
+        }
+        if padding.iter().all(|padding| *padding == 0.0) {
+            return grid;
+        }
+        for (bounds, padding) in bounds.iter_mut().zip(padding) {
+            *bounds = bounds.map(|bounds| bounds.expanded(padding));
+        }
+        Self::from_bounds(&bounds, &shown)
+    }
+
+    /// Bins every `shown` object with bounds, `None` marks unbounded objects.
+    fn from_bounds(bounds: &[Option<Aabb>], shown: &[bool]) -> Self {
+        let bounded_count = (bounds.iter().zip(shown))
+            .filter(|(bounds, shown)| **shown && bounds.is_some())
+            .count();
+        let few_objects = bounded_count < MIN_GRID_OBJECTS;
+        let mut indices: Vec<u32> = (0..bounds.len())
+            .filter(|index| shown[*index] && (few_objects || bounds[*index].is_none()))
+            .map(|index| index as u32)
+            .collect();
+        let global_count = indices.len();
+        let bounds: Vec<_> = bounds
+            .iter()
+            .zip(shown)
+            .map(|(bounds, shown)| bounds.filter(|_| *shown))
+            .collect();
+        let grid_bounds = bounds.iter().flatten().copied().reduce(|a, b| a.union(&b));
+        let Some(grid_bounds) = grid_bounds.filter(|_| !few_objects) else {
+            return Self {
+                global_count,
+                indices,
+                ..Default::default()
+            };
+        };
+
+        let extent = grid_bounds.size();
+        let volume = extent.max(Vec3::splat(GRID_MARGIN)).element_product();
+        let cell_size = (volume / bounded_count as f32)
+            .cbrt()
+            .max(extent.max_element() / MAX_CELLS_PER_AXIS as f32);
+        let dims = (extent / cell_size)
+            .ceil()
+            .clamp(Vec3::ONE, Vec3::splat(MAX_CELLS_PER_AXIS as f32))
+            .as_uvec3();
+
+        let mut grid = Self {
+            origin: grid_bounds.min,
+            cell_size,
+            dims,
+            global_count,
+            cells: vec![GpuGridCell::default(); (dims.x * dims.y * dims.z) as usize],
+            indices: Vec::new(),
+        };
+
+        // Counting sort into the cells, filling in object order keeps every cell ascending
+        for bounds in bounds.iter().flatten() {
+            for cell in grid.cells_overlapping(bounds) {
+                grid.cells[cell].count += 1;
+            }
+        }
+        let mut first = global_count as u32;
+        for cell in &mut grid.cells {
+            cell.first = first;
+            first += cell.count;
+        }
+        indices.resize(first as usize, 0);
+        let mut filled = vec![0; grid.cells.len()];
+        for (index, bounds) in bounds.iter().enumerate() {
+            let Some(bounds) = bounds else {
+                continue;
+            };
+            for cell in grid.cells_overlapping(bounds) {
+                indices[(grid.cells[cell].first + filled[cell]) as usize] = index as u32;
+                filled[cell] += 1;
+            }
+        }
+        grid.indices = indices;
+        grid
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn info(&self) -> GpuObjectGridInfo {
+        GpuObjectGridInfo {
+            origin: self.origin.to_array(),
+            cell_size: self.cell_size,
+            dims: self.dims.to_array(),
+            global_count: self.global_count as u32,
+        }
+    }
+
+    pub fn cells(&self) -> &[GpuGridCell] {
+        &self.cells
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Objects to evaluate at `p` in list order, mirrors `grid_lookup` in the shader.
+    pub fn lookup(&self, p: Vec3) -> GridLookup<'_> {
+        let mut lookup = GridLookup {
+            indices: &self.indices,
+            global: 0..self.global_count,
+            cell: 0..0,
+            bound: crate::octree::EMPTY_DISTANCE,
+        };
+        if self.cells.is_empty() {
+            return lookup;
+        }
+
+        let local = p - self.origin;
+        let size = self.dims.as_vec3() * self.cell_size;
+        let outside = sd_box(local - size * 0.5, size * 0.5);
+        if outside > 0.0 {
+            lookup.bound = outside + GRID_MARGIN;
+            return lookup;
+        }
+
+        let cell = (local / self.cell_size)
+            .max(Vec3::ZERO)
+            .as_uvec3()
+            .min(self.dims - 1);
+        let cell_min = cell.as_vec3() * self.cell_size;
+        let to_exit = (local - cell_min).min(cell_min + self.cell_size - local);
+        lookup.bound = to_exit.min_element().max(0.0) + GRID_MARGIN;
+        let cell = self.cells[cell_index(self.dims, cell)];
+        lookup.cell = cell.first as usize..(cell.first + cell.count) as usize;
+        lookup
+    }
+
+    fn cells_overlapping(&self, bounds: &Aabb) -> impl Iterator<Item = usize> + use<> {
+        let dims = self.dims;
+        let to_cell = |p: Vec3| {
+            ((p - self.origin) / self.cell_size)
+                .max(Vec3::ZERO)
+                .as_uvec3()
+                .min(dims - 1)
+        };
+        let (min, max) = (to_cell(bounds.min), to_cell(bounds.max));
+        (min.z..=max.z).flat_map(move |z| {
+            (min.y..=max.y).flat_map(move |y| {
+                (min.x..=max.x).map(move |x| cell_index(dims, UVec3::new(x, y, z)))
+            })
+        })
+    }
+}
+
+/// Radius the object blends with the objects before it, 0 for sharp operations.
+fn blend_radius(object: &RaymarchingObject) -> f32 {
+    match object.operation() {
+        CsgOperation::SmoothUnion | CsgOperation::SmoothSubtraction => object.blend_radius.max(0.0),
+        _ => 0.0,
+    }
+}
+
+fn cell_index(dims: UVec3, cell: UVec3) -> usize {
+    (cell.x + cell.y * dims.x + cell.z * dims.x * dims.y) as usize
+}
+
+/// Merges the unbounded objects with a cell's objects in ascending list order.
+pub struct GridLookup<'a> {
+    indices: &'a [u32],
+    global: Range<usize>,
+    cell: Range<usize>,
+    /// Lower bound on the distance to every object not yielded.
+    pub bound: f32,
+}
+
+impl Iterator for GridLookup<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let global = self.global.clone().next().map(|i| self.indices[i]);
+        let cell = self.cell.clone().next().map(|i| self.indices[i]);
+        let index = match (global, cell) {
+            (Some(global), Some(cell)) if global < cell => {
+                self.global.next();
+                global
+            }
+            (_, Some(cell)) => {
+                self.cell.next();
+                cell
+            }
+            (Some(global), None) => {
+                self.global.next();
+                global
+            }
+            (None, None) => return None,
+        };
+        Some(index as usize)
+    }
+}
@@ -0,0 +1,439 @@
+//! Sparse voxel octree of signed distance bricks.
+//!
+//! The root cube (`origin`, `size`) is split `depth` times; only leaf cells that contain
+//! surface get a [`Brick`] of `BRICK_SIZE`³ distance samples. Bricks hold a narrow band around
+//! the surface, leaf cells entirely inside it are marked solid and all others stay empty.
+//! [`Octree::to_gpu`] flattens the tree into the node/brick buffers traversed by `sd_octree`
+//! in `raymarching_compute.wgsl`, [`Octree::distance`] is the CPU mirror of that traversal.
+
+use bytemuck::{Pod, Zeroable};
+use glam::{UVec3, Vec3};
+
+/// Samples per brick edge. Samples sit on the cell corners, so neighbouring bricks share a face.
+pub const BRICK_SIZE: usize = 8;
+pub const BRICK_VOXELS: usize = BRICK_SIZE * BRICK_SIZE * BRICK_SIZE;
+/// `GpuOctreeNode::brick` of nodes without a brick.
+pub const NO_BRICK: u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Brick {
+    /// Distances in world units, x fastest then y then z.
+    pub distances: Box<[f32; BRICK_VOXELS]>,
+}
+
+impl Brick {
+    pub fn filled(distance: f32) -> Self {
+        Self {
+            distances: Box::new([distance; BRICK_VOXELS]),
+        }
+    }
+
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        x + y * BRICK_SIZE + z * BRICK_SIZE * BRICK_SIZE
+    }
+
+    /// Trilinear sample, `t` is the position inside the cell in 0..1.
+    pub fn sample(&self, t: Vec3) -> f32 {
+        let max_index = (BRICK_SIZE - 2) as f32;
+        let grid = t.clamp(Vec3::ZERO, Vec3::ONE) * (BRICK_SIZE - 1) as f32;
+        let base = grid.floor().min(Vec3::splat(max_index));
+        let f = grid - base;
+        let (x, y, z) = (base.x as usize, base.y as usize, base.z as usize);
+        let d =
+            |dx: usize, dy: usize, dz: usize| self.distances[Self::index(x + dx, y + dy, z + dz)];
+
+        let x00 = d(0, 0, 0) + (d(1, 0, 0) - d(0, 0, 0)) * f.x;
+        let x10 = d(0, 1, 0) + (d(1, 1, 0) - d(0, 1, 0)) * f.x;
+        let x01 = d(0, 0, 1) + (d(1, 0, 1) - d(0, 0, 1)) * f.x;
+        let x11 = d(0, 1, 1) + (d(1, 1, 1) - d(0, 1, 1)) * f.x;
+        let y0 = x00 + (x10 - x00) * f.y;
+        let y1 = x01 + (x11 - x01) * f.y;
+        y0 + (y1 - y0) * f.z
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf(Brick),
+    /// Leaf cell inside the surface, further than the band from it.
+    Solid,
+    Branch(Box<[Node; 8]>),
+}
+
+impl Node {
+    fn is_empty(&self) -> bool {
+        matches!(self, Node::Empty)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Octree {
+    origin: Vec3,
+    size: f32,
+    depth: u32,
+    pub material: i32,
+    root: Node,
+}
+
+impl Default for Octree {
+    fn default() -> Self {
+        Self::new(Vec3::splat(-16.0), 32.0, 5)
+    }
+}
+
+impl Octree {
+    /// Cube with minimum corner `origin` and edge `size`, split `depth` times down to the leaves.
+    pub fn new(origin: Vec3, size: f32, depth: u32) -> Self {
+        Self {
+            origin,
+            size,
+            depth,
+            material: 0,
+            root: Node::Empty,
+        }
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty()
+    }
+
+    /// Edge length of a leaf cell.
+    pub fn leaf_size(&self) -> f32 {
+        self.size / (1u32 << self.depth) as f32
+    }
+
+    /// Leaf cells per axis.
+    pub fn resolution(&self) -> u32 {
+        1 << self.depth
+    }
+
+    /// Leaf cell containing `position`, `None` outside the root cube.
+    pub fn leaf_at(&self, position: Vec3) -> Option<UVec3> {
+        let cell = ((position - self.origin) / self.leaf_size()).floor();
+        let resolution = self.resolution() as f32;
+        (cell.cmpge(Vec3::ZERO).all() && cell.cmplt(Vec3::splat(resolution)).all())
+            .then(|| cell.as_uvec3())
+    }
+
+    pub fn leaf_min(&self, leaf: UVec3) -> Vec3 {
+        self.origin + leaf.as_vec3() * self.leaf_size()
+    }
+
+    /// Node of `leaf`, or the empty node above it.
+    fn node(&self, leaf: UVec3) -> &Node {
+        let mut node = &self.root;
+        for level in (0..self.depth).rev() {
+            match node {
+                Node::Branch(children) => node = &children[octant(leaf, level)],
+                _ => return node,
+            }
+        }
+        node
+    }
+
+    pub fn brick(&self, leaf: UVec3) -> Option<&Brick> {
+        match self.node(leaf) {
+            Node::Leaf(brick) => Some(brick),
+            _ => None,
+        }
+    }
+
+    /// Whether `leaf` is entirely inside the surface.
+    pub fn is_solid(&self, leaf: UVec3) -> bool {
+        matches!(self.node(leaf), Node::Solid)
+    }
+
+    pub fn insert_brick(&mut self, leaf: UVec3, brick: Brick) {
+        self.set_leaf(leaf, Node::Leaf(brick));
+    }
+
+    /// Marks `leaf` as inside the surface, replacing its brick.
+    pub fn insert_solid(&mut self, leaf: UVec3) {
+        self.set_leaf(leaf, Node::Solid);
+    }
+
+    fn set_leaf(&mut self, leaf: UVec3, leaf_node: Node) {
+        assert!(
+            leaf.max_element() < self.resolution(),
+            "Leaf {leaf} outside octree"
+        );
+        let mut node = &mut self.root;
+        for level in (0..self.depth).rev() {
+            if !matches!(node, Node::Branch(_)) {
+                *node = Node::Branch(Default::default());
+            }
+            let Node::Branch(children) = node else {
+                unreachable!()
+            };
+            node = &mut children[octant(leaf, level)];
+        }
+        *node = leaf_node;
+    }
+
+    /// Empties `leaf`, returning its brick, and prunes branches left without children.
+    pub fn remove_brick(&mut self, leaf: UVec3) -> Option<Brick> {
+        fn remove(node: &mut Node, leaf: UVec3, level: u32) -> Option<Brick> {
+            match node {
+                Node::Leaf(_) | Node::Solid if level == 0 => match std::mem::take(node) {
+                    Node::Leaf(brick) => Some(brick),
+                    _ => None,
+                },
+                Node::Branch(children) if level > 0 => {
+                    let removed = remove(&mut children[octant(leaf, level - 1)], leaf, level - 1);
+                    if children.iter().all(Node::is_empty) {
+                        *node = Node::Empty;
+                    }
+                    removed
+                }
+                _ => None,
+            }
+        }
+        remove(&mut self.root, leaf, self.depth)
+    }
+
+    /// Position of sample `(x, y, z)` of the brick in `leaf`.
+    pub fn sample_position(&self, leaf: UVec3, x: usize, y: usize, z: usize) -> Vec3 {
+        let spacing = self.leaf_size() / (BRICK_SIZE - 1) as f32;
+        self.leaf_min(leaf) + Vec3::new(x as f32, y as f32, z as f32) * spacing
+    }
+
+    /// Unions the shape described by `sdf` into the tree. Only leaves overlapping
+    /// `bounds_min..bounds_max` are visited, those within the narrow band get bricks and those
+    /// further inside are marked solid.
+    pub fn insert_sdf(&mut self, bounds_min: Vec3, bounds_max: Vec3, sdf: impl Fn(Vec3) -> f32) {
+        self.edit_sdf(bounds_min, bounds_max, |position, current| {
+            current.min(sdf(position))
+        });
+    }
+
+    /// Carves the shape described by `sdf` out of the tree.
+    pub fn remove_sdf(&mut self, bounds_min: Vec3, bounds_max: Vec3, sdf: impl Fn(Vec3) -> f32) {
+        self.edit_sdf(bounds_min, bounds_max, |position, current| {
+            current.max(-sdf(position))
+        });
+    }
+
+    fn edit_sdf(&mut self, bounds_min: Vec3, bounds_max: Vec3, edit: impl Fn(Vec3, f32) -> f32) {
+        let leaf_size = self.leaf_size();
+        let band = leaf_size;
+        let last = self.resolution() as f32 - 1.0;
+        let first_leaf = ((bounds_min - self.origin) / leaf_size)
+            .floor()
+            .clamp(Vec3::ZERO, Vec3::splat(last))
+            .as_uvec3();
+        let last_leaf = ((bounds_max - self.origin) / leaf_size)
+            .floor()
+            .clamp(Vec3::ZERO, Vec3::splat(last))
+            .as_uvec3();
+
+        for z in first_leaf.z..=last_leaf.z {
+            for y in first_leaf.y..=last_leaf.y {
+                for x in first_leaf.x..=last_leaf.x {
+                    let leaf = UVec3::new(x, y, z);
+                    let mut brick = match self.node(leaf) {
+                        Node::Leaf(brick) => brick.clone(),
+                        Node::Solid => Brick::filled(-band),
+                        _ => Brick::filled(band),
+                    };
+                    let mut near_surface = false;
+                    for sz in 0..BRICK_SIZE {
+                        for sy in 0..BRICK_SIZE {
+                            for sx in 0..BRICK_SIZE {
+                                let index = Brick::index(sx, sy, sz);
+                                let position = self.sample_position(leaf, sx, sy, sz);
+                                let distance = edit(position, brick.distances[index]);
+                                brick.distances[index] = distance.clamp(-band, band);
+                                near_surface |= distance.abs() < band;
+                            }
+                        }
+                    }
+                    if near_surface {
+                        self.insert_brick(leaf, brick);
+                    } else if brick.distances[0] < 0.0 {
+                        // Samples are closer than the band, so without one near the surface
+                        // the whole cell is on one side of it
+                        self.insert_solid(leaf);
+                    } else {
+                        self.remove_brick(leaf);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Brick distance at `position`, minus the band in solid cells and `None` in empty ones.
+    pub fn sample(&self, position: Vec3) -> Option<f32> {
+        let leaf = self.leaf_at(position)?;
+        match self.node(leaf) {
+            Node::Leaf(brick) => {
+                Some(brick.sample((position - self.leaf_min(leaf)) / self.leaf_size()))
+            }
+            Node::Solid => Some(-self.leaf_size()),
+            _ => None,
+        }
+    }
+
+    /// Conservative distance to the voxel surface, the same traversal `sd_octree` runs on the GPU.
+    pub fn distance(&self, position: Vec3) -> f32 {
+        if self.is_empty() {
+            return EMPTY_DISTANCE;
+        }
+        let local = position - self.origin;
+        let half = Vec3::splat(self.size * 0.5);
+        let outside = sd_box(local - half, half);
+        if outside > 0.0 {
+            return outside + self.leaf_size() * EXIT_EPSILON;
+        }
+
+        let mut node = &self.root;
+        let mut cell_min = Vec3::ZERO;
+        let mut cell_size = self.size;
+        loop {
+            match node {
+                Node::Leaf(brick) => return brick.sample((local - cell_min) / cell_size),
+                // Like a brick clamped to the band
+                Node::Solid => return -self.leaf_size(),
+                Node::Branch(children) => {
+                    cell_size *= 0.5;
+                    let upper = local.cmpge(cell_min + cell_size);
+                    let child = upper.bitmask() as usize;
+                    cell_min += Vec3::select(upper, Vec3::splat(cell_size), Vec3::ZERO);
+                    node = &children[child];
+                }
+                Node::Empty => break,
+            }
+        }
+
+        // Empty cells have no surface inside, so leaving the cell is always safe
+        let to_min = local - cell_min;
+        let to_max = cell_min + cell_size - local;
+        to_min.min(to_max).min_element().max(0.0) + self.leaf_size() * EXIT_EPSILON
+    }
+
+    /// Flattens the tree breadth first, children of a node are stored contiguously. Solid
+    /// cells share one brick filled with minus the band.
+    pub fn to_gpu(&self) -> GpuOctree {
+        let mut nodes = Vec::new();
+        let mut bricks = Vec::new();
+        let mut solid_brick = None;
+        if !self.is_empty() {
+            let mut queue = std::collections::VecDeque::from([&self.root]);
+            nodes.push(GpuOctreeNode::default());
+            let mut next_index = 0;
+            while let Some(node) = queue.pop_front() {
+                let index = next_index;
+                next_index += 1;
+                match node {
+                    Node::Empty => {}
+                    Node::Leaf(brick) => {
+                        nodes[index].brick = (bricks.len() / BRICK_VOXELS) as u32;
+                        bricks.extend_from_slice(&brick.distances[..]);
+                    }
+                    Node::Solid => {
+                        nodes[index].brick = *solid_brick.get_or_insert_with(|| {
+                            let brick = (bricks.len() / BRICK_VOXELS) as u32;
+                            bricks.extend_from_slice(&[-self.leaf_size(); BRICK_VOXELS]);
+                            brick
+                        });
+                    }
+                    Node::Branch(children) => {
+                        nodes[index].first_child = nodes.len() as u32;
+                        for (child_index, child) in children.iter().enumerate() {
+                            if !child.is_empty() {
+                                nodes[index].child_mask |= 1 << child_index;
+                                nodes.push(GpuOctreeNode::default());
+                                queue.push_back(child);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        GpuOctree {
+            info: GpuOctreeInfo {
+                origin: self.origin.to_array(),
+                size: self.size,
+                node_count: nodes.len() as u32,
+                depth: self.depth,
+                material: self.material,
+                leaf_size: self.leaf_size(),
+            },
+            nodes,
+            bricks,
+        }
+    }
+}
+
+/// Distance reported for an empty tree, large enough to never win a union.
+pub const EMPTY_DISTANCE: f32 = 1e9;
+/// Fraction of a leaf cell added when stepping out of empty space, so marching never stalls.
+const EXIT_EPSILON: f32 = 0.01;
+
+/// Child slot of `leaf` at the level whose cells are `2^level` leaves wide.
+fn octant(leaf: UVec3, level: u32) -> usize {
+    let bit = (leaf >> level) & UVec3::ONE;
+    (bit.x | (bit.y << 1) | (bit.z << 2)) as usize
+}
+
+fn sd_box(p: Vec3, b: Vec3) -> f32 {
+    let q = p.abs() - b;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+}
+
+/// Matches `OctreeInfo` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct GpuOctreeInfo {
+    pub origin: [f32; 3],
+    pub size: f32,
+    pub node_count: u32,
+    pub depth: u32,
+    pub material: i32,
+    pub leaf_size: f32,
+}
+
+/// Matches `OctreeNode` in `raymarching_compute.wgsl`. A node either has children
+/// (`child_mask != 0`) or a brick (`brick != NO_BRICK`), otherwise it is empty. Solid cells
+/// point at a brick filled with minus the band.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuOctreeNode {
+    pub first_child: u32,
+    pub child_mask: u32,
+    pub brick: u32,
+    pub _pad: u32,
+}
+
+impl Default for GpuOctreeNode {
+    fn default() -> Self {
+        Self {
+            first_child: 0,
+            child_mask: 0,
+            brick: NO_BRICK,
+            _pad: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GpuOctree {
+    pub info: GpuOctreeInfo,
+    pub nodes: Vec<GpuOctreeNode>,
+    /// `BRICK_VOXELS` distances per brick, indexed by `GpuOctreeNode::brick`.
+    pub bricks: Vec<f32>,
+}
@@ -0,0 +1,347 @@
+//! The outliner window: the objects as a tree of their parents, or the ones matching a search
+//! as a flat list.
+//!
+//! Objects are tracked by [`RaymarchingObject::id`], so the selection survives reordering,
+//! deleting and undo. Rows are numbered with the object's index in the list, the order CSG
+//! operations fold in, and dragging a row by its handle moves the object to another index.
+
+use std::collections::HashSet;
+
+use egui::{Modifiers, RichText, Stroke, Ui};
+use egui_probe::Probe;
+
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::{
+    RaymarchingObject, next_object_id,
+};
+use crate::scene_graph::{move_object, object_index, remove_object};
+
+/// Indentation per tree level in points.
+const INDENT: f32 = 14.0;
+
+/// A shown row, `depth` levels down the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    pub index: usize,
+    pub depth: usize,
+    pub has_children: bool,
+}
+
+#[derive(Default)]
+pub struct Outliner {
+    /// Ids of the selected objects, the last is the active one the properties show and the
+    /// gizmo moves.
+    pub selection: Vec<u64>,
+    /// Shows only the objects whose name contains it, ignoring case.
+    pub filter: String,
+    /// Start of the range a shift click selects.
+    anchor: Option<u64>,
+    /// Objects whose children are folded away.
+    collapsed: HashSet<u64>,
+    /// Object whose name is edited in its row, and whether the field still needs focus.
+    renaming: Option<(u64, bool)>,
+    /// Brings the active object's row into view with the next frame.
+    scroll_to_active: bool,
+}
+
+impl Outliner {
+    /// Index of the active object.
+    pub fn active(&self, objects: &[RaymarchingObject]) -> Option<usize> {
+        self.selection
+            .last()
+            .and_then(|id| object_index(objects, *id))
+    }
+
+    pub fn is_selected(&self, id: u64) -> bool {
+        self.selection.contains(&id)
+    }
+
+    /// Selects only object `index`, unfolding its ancestors and scrolling to it. `None` clears
+    /// the selection.
+    pub fn select(&mut self, objects: &[RaymarchingObject], index: Option<usize>) {
+        let id = index
+            .and_then(|index| objects.get(index))
+            .map(|object| object.id);
+        self.selection = id.into_iter().collect();
+        self.anchor = id;
+        self.scroll_to_active = id.is_some();
+
+        let mut current = index;
+        for _ in 0..objects.len() {
+            let Some(parent) = current
+                .and_then(|index| objects.get(index))
+                .and_then(RaymarchingObject::parent)
+                .filter(|parent| *parent < objects.len())
+            else {
+                break;
+            };
+            self.collapsed.remove(&objects[parent].id);
+            current = Some(parent);
+        }
+    }
+
+    /// Selects like a click on the row of object `index`: ctrl toggles it, shift selects the
+    /// `rows` from the one clicked last, and a plain click selects only it.
+    pub fn click(
+        &mut self,
+        objects: &[RaymarchingObject],
+        rows: &[Row],
+        index: usize,
+        modifiers: Modifiers,
+    ) {
+        let Some(id) = objects.get(index).map(|object| object.id) else {
+            return;
+        };
+        let row_of = |index: usize| rows.iter().position(|row| row.index == index);
+        let anchor = self
+            .anchor
+            .and_then(|anchor| object_index(objects, anchor))
+            .and_then(row_of);
+
+        if let (true, Some(anchor), Some(clicked)) = (modifiers.shift, anchor, row_of(index)) {
+            if !modifiers.command {
+                self.selection.clear();
+            }
+            let range = anchor.min(clicked)..=anchor.max(clicked);
+            for row in &rows[range] {
+                let id = objects[row.index].id;
+                if !self.is_selected(id) {
+                    self.selection.push(id);
+                }
+            }
+            // The clicked object becomes the active one
+            self.selection.retain(|selected| *selected != id);
+            self.selection.push(id);
+        } else if modifiers.command {
+            if self.is_selected(id) {
+                self.selection.retain(|selected| *selected != id);
+            } else {
+                self.selection.push(id);
+            }
+            self.anchor = Some(id);
+        } else {
+            self.selection = vec![id];
+            self.anchor = Some(id);
+        }
+    }
+
+    /// Rows in display order. Depth first through the tree with siblings in list order, the
+    /// children of folded objects left out. With a filter, the matching objects in list order.
+    pub fn rows(&self, objects: &[RaymarchingObject]) -> Vec<Row> {
+        let mut children = vec![Vec::new(); objects.len()];
+        for (index, object) in objects.iter().enumerate() {
+            if let Some(parent) = object
+                .parent()
+                .filter(|parent| *parent < objects.len() && *parent != index)
+            {
+                children[parent].push(index);
+            }
+        }
+
+        let filter = self.filter.trim().to_lowercase();
+        if !filter.is_empty() {
+            return (objects.iter().enumerate())
+                .filter(|(_, object)| object.name.to_lowercase().contains(&filter))
+                .map(|(index, _)| Row {
+                    index,
+                    depth: 0,
+                    has_children: !children[index].is_empty(),
+                })
+                .collect();
+        }
+
+        // Roots first, then whatever only a parent cycle reaches, like SceneGraph does
+        let roots = (0..objects.len()).filter(|index| {
+            objects[*index]
+                .parent()
+                .is_none_or(|parent| parent >= objects.len() || parent == *index)
+        });
+        let mut visited = vec![false; objects.len()];
+        let mut rows = Vec::new();
+        let mut stack = Vec::new();
+        for start in roots.chain(0..objects.len()) {
+            stack.push((start, 0, false));
+            while let Some((index, depth, folded)) = stack.pop() {
+                if std::mem::replace(&mut visited[index], true) {
+                    continue;
+                }
+                if !folded {
+                    rows.push(Row {
+                        index,
+                        depth,
+                        has_children: !children[index].is_empty(),
+                    });
+                }
+                let folded = folded || self.collapsed.contains(&objects[index].id);
+                for child in children[index].iter().rev() {
+                    stack.push((*child, depth + 1, folded));
+                }
+            }
+        }
+        rows
+    }
+
+    /// Appends a copy of every selected object and selects the copies. Copies of a selected
+    /// object's children stay children of its copy.
+    pub fn duplicate_selection(&mut self, objects: &mut Vec<RaymarchingObject>) {
+        let originals: Vec<_> = (0..objects.len())
+            .filter(|index| self.is_selected(objects[*index].id))
+            .collect();
+        let first_copy = objects.len();
+        let copy_of = |index: usize| {
+            originals
+                .iter()
+                .position(|original| *original == index)
+                .map(|position| first_copy + position)
+        };
+        let mut copies: Vec<_> = originals
+            .iter()
+            .map(|index| {
+                let mut copy = objects[*index].clone();
+                copy.id = next_object_id();
+                copy.name += " copy";
+                if let Some(parent) = copy.parent().and_then(copy_of) {
+                    copy.parent = parent as i32;
+                }
+                copy
+            })
+            .collect();
+        self.selection = copies.iter().map(|copy| copy.id).collect();
+        self.anchor = self.selection.last().copied();
+        objects.append(&mut copies);
+    }
+
+    /// Removes every selected object, their children move to their parents.
+    pub fn delete_selection(&mut self, objects: &mut Vec<RaymarchingObject>) {
+        for index in (0..objects.len()).rev() {
+            if self.is_selected(objects[index].id) {
+                remove_object(objects, index);
+            }
+        }
+        self.selection.clear();
+        self.anchor = None;
+    }
+
+    /// The toolbar, search field and rows, with the active object's properties below.
+    pub fn show(&mut self, ui: &mut Ui, objects: &mut Vec<RaymarchingObject>) {
+        self.selection
+            .retain(|id| object_index(objects, *id).is_some());
+        let has_selection = !self.selection.is_empty();
+        ui.horizontal(|ui| {
+            if ui.button("New").clicked() {
+                objects.push(RaymarchingObject::default());
+                self.select(objects, Some(objects.len() - 1));
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Duplicate"))
+                .clicked()
+            {
+                self.duplicate_selection(objects);
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Delete"))
+                .on_hover_text("Children move to the deleted object's parent")
+                .clicked()
+            {
+                self.delete_selection(objects);
+            }
+        });
+        ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Search"));
+        ui.separator();
+
+        let rows = self.rows(objects);
+        let active = self.active(objects);
+        let scroll = std::mem::take(&mut self.scroll_to_active);
+        let mut clicked = None;
+        let mut moved = None;
+        for row in &rows {
+            let object = &mut objects[row.index];
+            let id = object.id;
+            let selected = self.is_selected(id);
+            let response = ui
+                .horizontal(|ui| {
+                    ui.add_space(row.depth as f32 * INDENT);
+                    ui.dnd_drag_source(ui.id().with(("Outliner row", id)), row.index, |ui| {
+                        ui.label("☰")
+                    })
+                    .response
+                    .on_hover_text("Drag to reorder, objects combine in list order");
+                    if row.has_children {
+                        let folded = self.collapsed.contains(&id);
+                        if ui.small_button(if folded { "⏵" } else { "⏷" }).clicked()
+                            && !self.collapsed.remove(&id)
+                        {
+                            self.collapsed.insert(id);
+                        }
+                    }
+                    let mut shown = !object.hidden;
+                    if ui
+                        .toggle_value(&mut shown, "👁")
+                        .on_hover_text("Shown")
+                        .changed()
+                    {
+                        object.hidden = !shown;
+                    }
+                    ui.toggle_value(&mut object.solo, "S")
+                        .on_hover_text("Solo, while any object is soloed only those render");
+
+                    if let Some((renamed, focus)) = &mut self.renaming
+                        && *renamed == id
+                    {
+                        let edit = ui.text_edit_singleline(&mut object.name);
+                        if std::mem::take(focus) {
+                            edit.request_focus();
+                        } else if !edit.has_focus() {
+                            self.renaming = None;
+                        }
+                        return;
+                    }
+                    let mut text = RichText::new(format!("{}  {}", row.index, object.name));
+                    if object.hidden {
+                        text = text.weak();
+                    }
+                    let label = ui
+                        .selectable_label(selected, text)
+                        .on_hover_text("Double click to rename");
+                    if label.double_clicked() {
+                        self.renaming = Some((id, true));
+                    } else if label.clicked() {
+                        clicked = Some(row.index);
+                    }
+                    if scroll && active == Some(row.index) {
+                        label.scroll_to_me(Some(egui::Align::Center));
+                    }
+                })
+                .response;
+
+            // A line where the dragged object lands
+            if let Some(from) = response.dnd_hover_payload::<usize>()
+                && *from != row.index
+            {
+                let y = if *from < row.index {
+                    response.rect.bottom()
+                } else {
+                    response.rect.top()
+                };
+                let stroke = Stroke::new(2.0, ui.visuals().selection.stroke.color);
+                ui.painter().hline(response.rect.x_range(), y, stroke);
+            }
+            if let Some(from) = response.dnd_release_payload::<usize>() {
+                moved = Some((*from, row.index));
+            }
+        }
+
+        if let Some(index) = clicked {
+            let modifiers = ui.input(|input| input.modifiers);
+            self.click(objects, &rows, index, modifiers);
+        }
+        if let Some((from, to)) = moved {
+            move_object(objects, from, to);
+        }
+
+        if let Some(active) = self.active(objects) {
+            ui.separator();
+            ui.strong(&objects[active].name);
+            Probe::new(&mut objects[active]).show(ui);
+        }
+    }
+}
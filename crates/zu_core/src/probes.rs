@@ -0,0 +1,32 @@
+//! Probe widgets for the glam types the option structs share, for `#[egui_probe(with ...)]`.
+
+use egui::{Response, Ui};
+use egui_probe::Style;
+use glam::{Vec2, Vec3};
+
+pub(crate) fn probe_vec2(value: &mut Vec2, ui: &mut Ui, _style: &Style) -> Response {
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut value.x).speed(0.01));
+        ui.add(egui::DragValue::new(&mut value.y).speed(0.01));
+    })
+    .response
+}
+
+pub(crate) fn probe_vec3(value: &mut Vec3, ui: &mut Ui, _style: &Style) -> Response {
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut value.x).speed(0.01));
+        ui.add(egui::DragValue::new(&mut value.y).speed(0.01));
+        ui.add(egui::DragValue::new(&mut value.z).speed(0.01));
+    })
+    .response
+}
+
+/// Linear RGB.
+pub(crate) fn probe_color(value: &mut Vec3, ui: &mut Ui, _style: &Style) -> Response {
+    let mut color = value.to_array();
+    let response = ui
+        .horizontal(|ui| ui.color_edit_button_rgb(&mut color))
+        .response;
+    *value = Vec3::from_array(color);
+    response
+}
@@ -3,3 +3,4 @@ pub mod raymarching_passes;
 pub mod quad_vertex;
 pub mod render_pass_manager;
 pub mod show_pass;
+pub mod storage_buffer;
@@ -0,0 +1,197 @@
+//! Ambient occlusion of the uniform ambient light.
+//!
+//! [`AoMode::Sdf`] probes the distance field along the normal while shading,
+//! `ambient_occlusion` in `raymarching_compute.wgsl`. [`AoMode::Horizon`] runs afterwards as
+//! [`HorizonAoPass`] over the "Depth normal" texture: for a few screen directions it finds how
+//! far the neighbouring surfaces rise above the tangent plane and darkens the share of the
+//! pixel that came from ambient light.
+
+use bytemuck::{Pod, Zeroable, bytes_of};
+use egui::{Response, Ui};
+use egui_probe::{EguiProbe, Style};
+use serde::{Deserialize, Serialize};
+use wgpu::{CommandEncoder, ComputePipelineDescriptor, Device, PushConstantRange, ShaderStages};
+
+use crate::render_passes::raymarching_passes::camera::GpuCamera;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingConstants;
+use crate::texture_manager::{TextureManager, textures::EngineTexture};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AoMode {
+    #[default]
+    Off,
+    /// Taps the distance field along the normal, exact but one `map` per sample.
+    Sdf,
+    /// Screen space horizons over the depth and normals, a pass after the raymarching.
+    Horizon,
+}
+
+impl AoMode {
+    pub const ALL: [AoMode; 3] = [AoMode::Off, AoMode::Sdf, AoMode::Horizon];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AoMode::Off => "Off",
+            AoMode::Sdf => "Distance field",
+            AoMode::Horizon => "Horizon based",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AmbientOcclusion {
+    #[egui_probe(with probe_mode)]
+    pub mode: AoMode,
+    /// Distance field taps, or steps along every horizon direction.
+    #[egui_probe(range = 1..=32)]
+    pub samples: u32,
+    /// Furthest surfaces still occlude, in scene units.
+    #[egui_probe(range = 0.01..=5.0)]
+    pub radius: f32,
+    /// Screen directions searched for horizons.
+    #[egui_probe(range = 1..=16)]
+    pub directions: u32,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            mode: AoMode::Off,
+            samples: 5,
+            radius: 0.5,
+            directions: 6,
+        }
+    }
+}
+
+impl AmbientOcclusion {
+    /// Taps for the shader's `ambient_occlusion`, 0 unless the distance field mode is on.
+    pub fn sdf_samples(&self) -> u32 {
+        match self.mode {
+            AoMode::Sdf => self.samples.max(1),
+            _ => 0,
+        }
+    }
+}
+
+/// Matches `HorizonAoConstants` in `horizon_ao.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct HorizonAoConstants {
+    /// Columns of [`GpuCamera::ray_basis`].
+    pub ray_basis: [[f32; 4]; 3],
+    pub texture_size: [f32; 2],
+    pub radius: f32,
+    /// Width of a pixel at unit distance.
+    pub pixel_angle: f32,
+    pub directions: u32,
+    pub steps: u32,
+    pub _pad: [u32; 2],
+}
+
+impl HorizonAoConstants {
+    pub fn new(
+        constants: &RaymarchingConstants,
+        camera: &GpuCamera,
+        options: &AmbientOcclusion,
+    ) -> Self {
+        let basis = camera.ray_basis();
+        let [_, height] = constants.texture_size;
+        Self {
+            ray_basis: [
+                basis.x_axis.extend(0.0).to_array(),
+                basis.y_axis.extend(0.0).to_array(),
+                basis.z_axis.extend(0.0).to_array(),
+            ],
+            texture_size: constants.texture_size,
+            radius: options.radius.max(0.01),
+            pixel_angle: 2.0 * camera.tan_half_fov / height.max(1.0),
+            directions: options.directions.max(1),
+            steps: options.samples.max(1),
+            _pad: [0; 2],
+        }
+    }
+}
+
+pub struct HorizonAoPass {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl HorizonAoPass {
+    pub fn new(device: &Device, texture_manager: &TextureManager) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/horizon_ao.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Horizon AO pass layout descriptor"),
+            bind_group_layouts: &[
+                texture_manager.get_compute_mut_bind_group_layout(),
+                texture_manager.get_compute_mut_f32_bind_group_layout(),
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..size_of::<HorizonAoConstants>() as u32,
+            }],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Horizon AO pass"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: Some("horizon_ao_main"),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+        Self { pipeline }
+    }
+
+    /// Darkens the ambient light in "Raymarching" with the horizons found in "Depth normal".
+    pub fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        texture_manager: &TextureManager,
+        constants: &RaymarchingConstants,
+        camera: &GpuCamera,
+        options: &AmbientOcclusion,
+    ) {
+        let constants = HorizonAoConstants::new(constants, camera, options);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Horizon AO pass"),
+            timestamp_writes: Default::default(),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_push_constants(0, bytes_of(&constants));
+        compute_pass.set_bind_group(
+            0,
+            texture_manager
+                .get_texture("Raymarching")
+                .unwrap()
+                .compute_mut_group_f16(),
+            &[],
+        );
+        compute_pass.set_bind_group(
+            1,
+            texture_manager
+                .get_texture("Depth normal")
+                .unwrap()
+                .compute_mut_group_f32(),
+            &[],
+        );
+        let [width, height] = constants.texture_size;
+        compute_pass.dispatch_workgroups(
+            (width as u32).div_ceil(8),
+            (height as u32).div_ceil(8),
+            1,
+        );
+    }
+}
+
+fn probe_mode(value: &mut AoMode, ui: &mut Ui, _style: &Style) -> Response {
+    egui::ComboBox::from_id_salt(ui.next_auto_id())
+        .selected_text(value.name())
+        .show_ui(ui, |ui| {
+            for mode in AoMode::ALL {
+                ui.selectable_value(value, mode, mode.name());
+            }
+        })
+        .response
+}
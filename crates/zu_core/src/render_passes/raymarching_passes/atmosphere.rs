@@ -0,0 +1,567 @@
+//! Sky lookup tables, `atmosphere_luts.wgsl`, sampled by `sky` in `raymarching_compute.wgsl`.
+//!
+//! Three compute passes replace the per pixel integration of the sky: transmittance to the
+//! top of the atmosphere, multiple scattering and the sky as seen from the camera. They only
+//! run again when the [`AtmosphereParams`] or the sun's elevation change, the sky-view table
+//! is relative to the sun's azimuth.
+
+use std::num::NonZero;
+
+use bytemuck::{Pod, Zeroable, bytes_of};
+use egui::{Response, Ui};
+use egui_probe::{EguiProbe, Style};
+use glam::{DVec3, Vec3};
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    CommandEncoder, ComputePipeline, ComputePipelineDescriptor, Device, PushConstantRange, Queue,
+    Sampler, SamplerBindingType, ShaderStages, StorageTextureAccess, TextureFormat,
+    TextureSampleType, TextureView, TextureViewDimension,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::render_passes::raymarching_passes::read_shader;
+
+pub const TRANSMITTANCE_LUT_SIZE: (u32, u32) = (256, 64);
+pub const MULTI_SCATTERING_LUT_SIZE: (u32, u32) = (32, 32);
+pub const SKY_VIEW_LUT_SIZE: (u32, u32) = (192, 108);
+
+/// Mie particles absorb part of the light they don't scatter, `MIE_EXTINCTION_RATIO` in the
+/// shader.
+pub const MIE_EXTINCTION_RATIO: f64 = 1.11;
+/// The camera sits one meter above the ground, in km.
+pub const VIEW_HEIGHT: f32 = 0.001;
+
+/// Earth-like atmosphere around the scene. Scattering coefficients are per megameter,
+/// heights and radii in km.
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AtmosphereParams {
+    /// Rayleigh scattering at the ground, small molecules that make the sky blue.
+    #[egui_probe(with probe_coefficients)]
+    pub rayleigh: Vec3,
+    /// Height over which the Rayleigh density falls off by e.
+    #[egui_probe(range = 0.1..=100.0)]
+    pub rayleigh_height: f32,
+    /// Mie scattering at the ground, aerosols that make the haze around the sun.
+    #[egui_probe(with probe_coefficients)]
+    pub mie: Vec3,
+    #[egui_probe(range = 0.1..=100.0)]
+    pub mie_height: f32,
+    /// Forward scattering of the Mie phase function.
+    #[egui_probe(range = 0.0..=0.999)]
+    pub mie_g: f32,
+    /// Ozone absorption at the ground, tints sunsets.
+    #[egui_probe(with probe_coefficients)]
+    pub ozone: Vec3,
+    #[egui_probe(range = 0.1..=100.0)]
+    pub ozone_height: f32,
+    #[egui_probe(range = 1.0..=100000.0)]
+    pub ground_radius: f32,
+    /// Radius of the top of the atmosphere, clamped above the ground.
+    #[egui_probe(range = 1.0..=100000.0)]
+    pub atmosphere_radius: f32,
+    /// Reflectance of the planet for light bounced back into the sky.
+    #[egui_probe(range = 0.0..=1.0)]
+    pub ground_albedo: f32,
+    /// Km of air per scene unit, how much haze the aerial perspective puts in front of
+    /// distant geometry.
+    #[egui_probe(range = 0.0..=10.0)]
+    pub distance_scale: f32,
+}
+
+impl Default for AtmosphereParams {
+    fn default() -> Self {
+        Self {
+            rayleigh: Vec3::new(5.8, 13.5, 33.1),
+            rayleigh_height: 8.0,
+            mie: Vec3::splat(21.0),
+            mie_height: 1.2,
+            mie_g: 0.76,
+            ozone: Vec3::new(2.0556, 4.9788, 0.2136),
+            ozone_height: 8.0,
+            ground_radius: 6360.0,
+            atmosphere_radius: 6420.0,
+            ground_albedo: 0.3,
+            distance_scale: 0.1,
+        }
+    }
+}
+
+impl AtmosphereParams {
+    pub fn to_gpu(&self) -> GpuAtmosphere {
+        // Per megameter to per km
+        let rayleigh = self.rayleigh.max(Vec3::ZERO) * 1e-3;
+        let mie = self.mie.max(Vec3::ZERO) * 1e-3;
+        let ozone = self.ozone.max(Vec3::ZERO) * 1e-3;
+        let rayleigh_height = self.rayleigh_height.max(0.01);
+        let mie_height = self.mie_height.max(0.01);
+        let ozone_height = self.ozone_height.max(0.01);
+        let bottom_radius = self.ground_radius.max(1.0);
+        let top_radius = self.atmosphere_radius.max(bottom_radius + 0.1);
+
+        // Scaled so a sun at the zenith lights the ground with 2 per channel through the
+        // atmosphere. Exact integral of the exponential densities, in f64 since the
+        // differences of exponentials cancel in f32
+        let optical_depth = |coefficient: Vec3, scale_height: f32| {
+            let h = scale_height as f64;
+            let from = (-(VIEW_HEIGHT as f64) / h).exp();
+            let to = (-((top_radius - bottom_radius) as f64) / h).exp();
+            coefficient.as_dvec3() * h * (from - to)
+        };
+        let zenith_transmittance = (-(optical_depth(rayleigh, rayleigh_height)
+            + optical_depth(mie, mie_height) * MIE_EXTINCTION_RATIO
+            + optical_depth(ozone, ozone_height)))
+        .exp();
+        let sun_illuminance = DVec3::splat(2.0) / zenith_transmittance;
+
+        GpuAtmosphere {
+            rayleigh_scattering: rayleigh.to_array(),
+            rayleigh_height,
+            mie_scattering: mie.to_array(),
+            mie_height,
+            ozone_absorption: ozone.to_array(),
+            ozone_height,
+            ground_albedo: [self.ground_albedo.clamp(0.0, 1.0); 3],
+            mie_g: self.mie_g.clamp(0.0, 0.999),
+            sun_illuminance: sun_illuminance.as_vec3().to_array(),
+            bottom_radius,
+            top_radius,
+            view_height: VIEW_HEIGHT,
+            distance_scale: self.distance_scale.max(0.0),
+            _pad: 0.0,
+        }
+    }
+}
+
+/// Matches `Atmosphere` in `atmosphere_common.wgsl`. Distances in km, coefficients per km.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuAtmosphere {
+    pub rayleigh_scattering: [f32; 3],
+    pub rayleigh_height: f32,
+    pub mie_scattering: [f32; 3],
+    pub mie_height: f32,
+    pub ozone_absorption: [f32; 3],
+    pub ozone_height: f32,
+    pub ground_albedo: [f32; 3],
+    pub mie_g: f32,
+    /// Sun illuminance at the top of the atmosphere, before the sun's color and intensity.
+    pub sun_illuminance: [f32; 3],
+    pub bottom_radius: f32,
+    pub top_radius: f32,
+    pub view_height: f32,
+    pub distance_scale: f32,
+    pub _pad: f32,
+}
+
+/// Tables that have to be generated again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LutUpdate {
+    None,
+    /// Only the sun moved up or down.
+    SkyView,
+    All,
+}
+
+/// Which parameters the lookup tables were generated with.
+#[derive(Debug, Default)]
+pub struct LutCache {
+    params: Option<AtmosphereParams>,
+    sun_elevation: Option<f32>,
+}
+
+impl LutCache {
+    /// Tables that are stale for `params` and `sun_dir`, remembering both as generated.
+    pub fn update(&mut self, params: &AtmosphereParams, sun_dir: Vec3) -> LutUpdate {
+        let sun_elevation = sun_dir.normalize_or_zero().y;
+        let update = if self.params.as_ref() != Some(params) {
+            LutUpdate::All
+        } else if self.sun_elevation != Some(sun_elevation) {
+            LutUpdate::SkyView
+        } else {
+            LutUpdate::None
+        };
+        self.params = Some(*params);
+        self.sun_elevation = Some(sun_elevation);
+        update
+    }
+}
+
+struct Lut {
+    view: TextureView,
+    /// Output of the pass that generates it.
+    output_bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+impl Lut {
+    fn new(device: &Device, name: &str, size: (u32, u32), output_layout: &BindGroupLayout) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            label: Some(name),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(name),
+            layout: output_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&view),
+            }],
+        });
+        Self {
+            view,
+            output_bind_group,
+            size,
+        }
+    }
+}
+
+/// What the raymarching pass samples the sky from.
+#[derive(Clone)]
+pub struct AtmosphereResources {
+    pub params: Buffer,
+    pub transmittance: TextureView,
+    pub multi_scattering: TextureView,
+    pub sky_view: TextureView,
+    pub sampler: Sampler,
+}
+
+impl AtmosphereResources {
+    /// Layout entries for the params, the transmittance, multi-scattering and sky-view tables
+    /// and the sampler, from `first_binding` on.
+    pub fn layout_entries(first_binding: u32) -> [BindGroupLayoutEntry; 5] {
+        [
+            params_layout_entry(first_binding),
+            lut_layout_entry(first_binding + 1),
+            lut_layout_entry(first_binding + 2),
+            lut_layout_entry(first_binding + 3),
+            sampler_layout_entry(first_binding + 4),
+        ]
+    }
+
+    pub fn bind_group_entries(&self, first_binding: u32) -> [BindGroupEntry<'_>; 5] {
+        [
+            BindGroupEntry {
+                binding: first_binding,
+                resource: self.params.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: first_binding + 1,
+                resource: BindingResource::TextureView(&self.transmittance),
+            },
+            BindGroupEntry {
+                binding: first_binding + 2,
+                resource: BindingResource::TextureView(&self.multi_scattering),
+            },
+            BindGroupEntry {
+                binding: first_binding + 3,
+                resource: BindingResource::TextureView(&self.sky_view),
+            },
+            BindGroupEntry {
+                binding: first_binding + 4,
+                resource: BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
+
+pub struct AtmospherePass {
+    transmittance_pipeline: ComputePipeline,
+    multi_scattering_pipeline: ComputePipeline,
+    sky_view_pipeline: ComputePipeline,
+    params: Buffer,
+    params_bind_group: BindGroup,
+    /// Transmittance table and sampler, inputs of the two later passes.
+    transmittance_bind_group: BindGroup,
+    multi_scattering_bind_group: BindGroup,
+    transmittance: Lut,
+    multi_scattering: Lut,
+    sky_view: Lut,
+    sampler: Sampler,
+    cache: LutCache,
+}
+
+impl AtmospherePass {
+    pub fn new(device: &Device) -> Self {
+        let source = read_shader("atmosphere_luts.wgsl");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Atmosphere lookup tables shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let params_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Atmosphere params bind group layout"),
+            entries: &[params_layout_entry(0)],
+        });
+        let output_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Atmosphere lookup table output bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format: TextureFormat::Rgba16Float,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+        let transmittance_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Atmosphere transmittance bind group layout"),
+            entries: &[lut_layout_entry(0), sampler_layout_entry(1)],
+        });
+        let multi_scattering_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Atmosphere multi-scattering bind group layout"),
+            entries: &[lut_layout_entry(0)],
+        });
+
+        let pipeline = |label, entry_point, layouts: &[&BindGroupLayout], push_constants| {
+            let push_constant_ranges: &[PushConstantRange] = if push_constants {
+                &[PushConstantRange {
+                    stages: ShaderStages::COMPUTE,
+                    range: 0..size_of::<[f32; 4]>() as u32,
+                }]
+            } else {
+                &[]
+            };
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: layouts,
+                push_constant_ranges,
+            });
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: Default::default(),
+            })
+        };
+        let transmittance_pipeline = pipeline(
+            "Atmosphere transmittance pass",
+            "transmittance_main",
+            &[&params_layout, &output_layout],
+            false,
+        );
+        let multi_scattering_pipeline = pipeline(
+            "Atmosphere multi-scattering pass",
+            "multi_scattering_main",
+            &[&params_layout, &output_layout, &transmittance_layout],
+            false,
+        );
+        let sky_view_pipeline = pipeline(
+            "Atmosphere sky-view pass",
+            "sky_view_main",
+            &[
+                &params_layout,
+                &output_layout,
+                &transmittance_layout,
+                &multi_scattering_layout,
+            ],
+            true,
+        );
+
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Atmosphere params"),
+            contents: bytes_of(&AtmosphereParams::default().to_gpu()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Atmosphere lookup table sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let transmittance = Lut::new(
+            device,
+            "Atmosphere transmittance",
+            TRANSMITTANCE_LUT_SIZE,
+            &output_layout,
+        );
+        let multi_scattering = Lut::new(
+            device,
+            "Atmosphere multi-scattering",
+            MULTI_SCATTERING_LUT_SIZE,
+            &output_layout,
+        );
+        let sky_view = Lut::new(
+            device,
+            "Atmosphere sky-view",
+            SKY_VIEW_LUT_SIZE,
+            &output_layout,
+        );
+
+        let params_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Atmosphere params bind group"),
+            layout: &params_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            }],
+        });
+        let transmittance_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Atmosphere transmittance bind group"),
+            layout: &transmittance_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&transmittance.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        let multi_scattering_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Atmosphere multi-scattering bind group"),
+            layout: &multi_scattering_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&multi_scattering.view),
+            }],
+        });
+
+        Self {
+            transmittance_pipeline,
+            multi_scattering_pipeline,
+            sky_view_pipeline,
+            params,
+            params_bind_group,
+            transmittance_bind_group,
+            multi_scattering_bind_group,
+            transmittance,
+            multi_scattering,
+            sky_view,
+            sampler,
+            cache: LutCache::default(),
+        }
+    }
+
+    pub fn resources(&self) -> AtmosphereResources {
+        AtmosphereResources {
+            params: self.params.clone(),
+            transmittance: self.transmittance.view.clone(),
+            multi_scattering: self.multi_scattering.view.clone(),
+            sky_view: self.sky_view.view.clone(),
+            sampler: self.sampler.clone(),
+        }
+    }
+
+    /// Regenerates the tables that are stale for `params` and `sun_dir`, before the
+    /// raymarching pass samples them.
+    pub fn update(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        queue: &Queue,
+        params: &AtmosphereParams,
+        sun_dir: Vec3,
+    ) {
+        puffin::profile_function!();
+        let update = self.cache.update(params, sun_dir);
+        if update == LutUpdate::None {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Atmosphere lookup tables pass"),
+            timestamp_writes: Default::default(),
+        });
+        compute_pass.set_bind_group(0, Some(&self.params_bind_group), &[]);
+        if update == LutUpdate::All {
+            queue.write_buffer(&self.params, 0, bytes_of(&params.to_gpu()));
+
+            compute_pass.set_pipeline(&self.transmittance_pipeline);
+            dispatch_lut(&mut compute_pass, &self.transmittance);
+
+            compute_pass.set_pipeline(&self.multi_scattering_pipeline);
+            compute_pass.set_bind_group(2, Some(&self.transmittance_bind_group), &[]);
+            dispatch_lut(&mut compute_pass, &self.multi_scattering);
+        }
+
+        compute_pass.set_pipeline(&self.sky_view_pipeline);
+        compute_pass.set_bind_group(2, Some(&self.transmittance_bind_group), &[]);
+        compute_pass.set_bind_group(3, Some(&self.multi_scattering_bind_group), &[]);
+        compute_pass.set_push_constants(0, bytes_of(&sun_dir.extend(0.0)));
+        dispatch_lut(&mut compute_pass, &self.sky_view);
+    }
+}
+
+fn dispatch_lut(compute_pass: &mut wgpu::ComputePass, lut: &Lut) {
+    compute_pass.set_bind_group(1, Some(&lut.output_bind_group), &[]);
+    compute_pass.dispatch_workgroups(lut.size.0.div_ceil(8), lut.size.1.div_ceil(8), 1);
+}
+
+fn params_layout_entry(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZero::new(size_of::<GpuAtmosphere>() as u64),
+        },
+        count: None,
+    }
+}
+
+fn lut_layout_entry(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn sampler_layout_entry(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+fn probe_coefficients(value: &mut Vec3, ui: &mut Ui, _style: &Style) -> Response {
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut value.x)
+                .speed(0.05)
+                .range(0.0..=1000.0),
+        );
+        ui.add(
+            egui::DragValue::new(&mut value.y)
+                .speed(0.05)
+                .range(0.0..=1000.0),
+        );
+        ui.add(
+            egui::DragValue::new(&mut value.z)
+                .speed(0.05)
+                .range(0.0..=1000.0),
+        );
+    })
+    .response
+}
@@ -0,0 +1,165 @@
+//! The view the scene is raymarched from, `camera_ray` in `raymarching_compute.wgsl`.
+//!
+//! Camera space looks down +z with +y up and +x to the right, `orientation` turns it into
+//! world space. Yaw turns around the world's y axis and pitch tilts around the camera's x axis,
+//! positive pitch looks down. The shader gets the view matrix and builds every pixel's ray
+//! from its rows.
+
+use bytemuck::{Pod, Zeroable};
+use egui_probe::EguiProbe;
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::probes::probe_vec3;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::probe_quat;
+
+/// Keeps the fly controls from tipping over the poles, where yaw turns into roll.
+pub const MAX_PITCH: f32 = 1.55;
+
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    #[egui_probe(with probe_vec3)]
+    pub position: Vec3,
+    #[egui_probe(with probe_quat)]
+    pub orientation: Quat,
+    /// Vertical field of view in degrees.
+    #[egui_probe(range = 1.0..=170.0)]
+    pub fov: f32,
+    /// Distance along the view rays the march starts at.
+    #[egui_probe(range = 0.0..=10.0)]
+    pub near: f32,
+    /// Distance the march gives up at and shows the sky, surfaces fade into it before.
+    #[egui_probe(range = 1.0..=1000.0)]
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 0.0, 3.0),
+            orientation: Quat::IDENTITY,
+            fov: 90.0,
+            near: 0.0,
+            far: 100.0,
+        }
+    }
+}
+
+impl Camera {
+    /// A camera at `position` turned by `yaw` and tilted by `pitch`, in radians.
+    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+        Self {
+            position,
+            orientation: yaw_pitch_orientation(yaw, pitch),
+            ..Default::default()
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.orientation * Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.orientation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.orientation * Vec3::Y
+    }
+
+    /// Yaw and pitch of the view direction, any roll is dropped.
+    pub fn yaw_pitch(&self) -> (f32, f32) {
+        let forward = self.forward();
+        (
+            forward.x.atan2(forward.z),
+            -forward.y.clamp(-1.0, 1.0).asin(),
+        )
+    }
+
+    /// Points the camera by yaw and pitch, clamping the pitch to [`MAX_PITCH`].
+    pub fn set_yaw_pitch(&mut self, yaw: f32, pitch: f32) {
+        self.orientation = yaw_pitch_orientation(yaw, pitch.clamp(-MAX_PITCH, MAX_PITCH));
+    }
+
+    /// Turns the camera towards `target`, upright.
+    pub fn look_at(&mut self, target: Vec3) {
+        let direction = target - self.position;
+        if direction.length_squared() > 1e-8 {
+            let direction = direction.normalize();
+            self.set_yaw_pitch(
+                direction.x.atan2(direction.z),
+                -direction.y.clamp(-1.0, 1.0).asin(),
+            );
+        }
+    }
+
+    /// World to camera space.
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::from_quat(self.orientation.conjugate()) * Mat4::from_translation(-self.position)
+    }
+
+    /// Half the height of the image plane at unit distance.
+    pub fn tan_half_fov(&self) -> f32 {
+        (self.fov.clamp(1.0, 170.0).to_radians() * 0.5).tan()
+    }
+
+    /// Direction of the ray through `pixel` of an image `size` pixels large, the CPU side of
+    /// `camera_ray`. Any unit works for both, like egui's points.
+    pub fn pixel_ray(&self, pixel: Vec2, size: Vec2) -> Vec3 {
+        let mut uv = pixel / size * 2.0 - 1.0;
+        uv.y = -uv.y;
+        uv.x *= size.x / size.y;
+        self.orientation * (uv * self.tan_half_fov()).extend(1.0).normalize()
+    }
+
+    /// Pixel `point` is seen at in an image `size` large, `None` behind the camera. Inverse of
+    /// [`Camera::pixel_ray`].
+    pub fn project(&self, point: Vec3, size: Vec2) -> Option<Vec2> {
+        let local = self.orientation.conjugate() * (point - self.position);
+        if local.z <= 1e-4 {
+            return None;
+        }
+        let mut uv = local.truncate() / (local.z * self.tan_half_fov());
+        uv.x /= size.x / size.y;
+        uv.y = -uv.y;
+        Some((uv + 1.0) * 0.5 * size)
+    }
+
+    pub fn to_gpu(&self) -> GpuCamera {
+        let near = self.near.max(0.0);
+        GpuCamera {
+            view: self.view_matrix(),
+            position: self.position.to_array(),
+            tan_half_fov: self.tan_half_fov(),
+            near,
+            far: self.far.max(near + 0.01),
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+fn yaw_pitch_orientation(yaw: f32, pitch: f32) -> Quat {
+    (Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch)).normalize()
+}
+
+/// Matches `Camera` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuCamera {
+    pub view: Mat4,
+    pub position: [f32; 3],
+    pub tan_half_fov: f32,
+    pub near: f32,
+    pub far: f32,
+    pub _pad: [f32; 2],
+}
+
+impl GpuCamera {
+    /// `camera_ray` of a pixel is this times `(uv, 1)`, normalized. Lets passes after the
+    /// raymarching turn distances back into positions.
+    pub fn ray_basis(&self) -> Mat3 {
+        let camera_to_world = Mat3::from_mat4(self.view).transpose();
+        camera_to_world * Mat3::from_diagonal(Vec3::new(self.tan_half_fov, self.tan_half_fov, 1.0))
+    }
+}
@@ -0,0 +1,1846 @@
+//! CPU port of `raymarching_compute.wgsl`.
+//!
+//! Evaluates the same flattened `RaymarchingObject` list and `RaymarchingConstants` as the compute pass,
+//! function for function, so it can be used as an oracle for the shader, to render thumbnails
+//! without a GPU and for gameplay queries against the scene. Keep it in sync with the shader.
+
+use std::cell::OnceCell;
+use std::f32::consts::PI;
+
+use glam::{IVec3, Mat3, Vec2, Vec3, Vec3Swizzles, Vec4Swizzles};
+
+use crate::object_grid::ObjectGrid;
+use crate::octree::{EMPTY_DISTANCE, Octree};
+use crate::render_passes::raymarching_passes::atmosphere::{
+    AtmosphereParams, GpuAtmosphere, MIE_EXTINCTION_RATIO, MULTI_SCATTERING_LUT_SIZE,
+    SKY_VIEW_LUT_SIZE, TRANSMITTANCE_LUT_SIZE,
+};
+use crate::render_passes::raymarching_passes::camera::{Camera, GpuCamera};
+use crate::render_passes::raymarching_passes::csg::CsgOperation;
+use crate::render_passes::raymarching_passes::fog::{GpuHeightFog, HeightFog};
+use crate::render_passes::raymarching_passes::light::{GpuLight, Light, LightKind};
+use crate::render_passes::raymarching_passes::material::{
+    GpuMaterial, MISSING_MATERIAL, Material, Pattern,
+};
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::{
+    GpuRaymarchingObject, RaymarchingConstants, RaymarchingObject,
+};
+use crate::render_passes::raymarching_passes::volumetrics::{
+    Clouds, FogVolume, GpuClouds, GpuFogVolume, MAX_FOG_VOLUMES,
+};
+use crate::scene_graph::SceneGraph;
+
+const MAX_STEPS: u32 = 80;
+const HIT_DISTANCE: f32 = 0.05;
+const MAX_DISTANCE: f32 = 100.0;
+const FADE_START: f32 = 0.8;
+const AO_FALLOFF: f32 = 0.75;
+const FOG_SKY_DISTANCE: f32 = 1000.0;
+const PATH_HIT_DISTANCE: f32 = 0.001;
+const PATH_SURFACE_OFFSET: f32 = 0.01;
+const MAX_PATH_STEPS: u32 = 256;
+const MAX_PATH_DISTANCE: f32 = 100.0;
+const SECONDARY_STACK_SIZE: usize = 8;
+const MAX_SECONDARY_RAYS: u32 = 16;
+const MIN_RAY_WEIGHT: f32 = 0.01;
+const MAX_GLOSSY_ROUGHNESS: f32 = 0.5;
+const MAX_INTERNAL_REFLECTIONS: u32 = 4;
+const CLOUD_MAX_DISTANCE: f32 = 2000.0;
+const CLOUD_LIGHT_STEPS: u32 = 4;
+const CLOUD_NOISE_OCTAVES: u32 = 4;
+const CLOUD_FORWARD_G: f32 = 0.6;
+const CLOUD_BACK_G: f32 = -0.3;
+const CLOUD_FORWARD_WEIGHT: f32 = 0.7;
+const CLOUD_MULTI_SCATTERING: f32 = 0.3;
+const CLOUD_MULTI_EXTINCTION: f32 = 0.25;
+const MIN_VOLUME_TRANSMITTANCE: f32 = 0.01;
+const FOG_VOLUME_STEP: f32 = 0.1;
+const MAX_FOG_VOLUME_STEPS: u32 = 64;
+const FOG_LIGHT_STEPS: u32 = 3;
+const FOG_LIGHT_STEP: f32 = 0.3;
+/// Keeps the GGX peak finite for perfectly smooth surfaces lit by the sun.
+pub const MIN_ROUGHNESS: f32 = 0.045;
+
+/// Material id the shader uses for rays that leave the scene (the sky).
+pub const MATERIAL_SKY: i32 = -1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfData {
+    pub res: f32,
+    pub material: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    pub albedo: Vec3,
+    pub roughness: f32,
+    pub emissive: Vec3,
+    pub metallic: f32,
+    pub absorption: Vec3,
+    pub ior: f32,
+    pub transmission: f32,
+}
+
+impl Surface {
+    /// `material` at `position`, with its pattern applied.
+    pub fn of_material(material: &GpuMaterial, position: Vec3) -> Self {
+        Self {
+            albedo: Vec3::from(material.albedo) * pattern_color(material.pattern(), position),
+            roughness: material.roughness,
+            emissive: Vec3::from(material.emissive),
+            metallic: material.metallic,
+            absorption: Vec3::from(material.absorption),
+            ior: material.ior,
+            transmission: material.transmission,
+        }
+    }
+
+    pub fn mix(self, other: Self, t: f32) -> Self {
+        Self {
+            albedo: self.albedo.lerp(other.albedo, t),
+            roughness: self.roughness + (other.roughness - self.roughness) * t,
+            emissive: self.emissive.lerp(other.emissive, t),
+            metallic: self.metallic + (other.metallic - self.metallic) * t,
+            absorption: self.absorption.lerp(other.absorption, t),
+            ior: self.ior + (other.ior - self.ior) * t,
+            transmission: self.transmission + (other.transmission - self.transmission) * t,
+        }
+    }
+}
+
+/// Multiplied into the albedo, the shader's `pattern_color`.
+pub fn pattern_color(pattern: Pattern, position: Vec3) -> Vec3 {
+    match pattern {
+        Pattern::None => Vec3::ONE,
+        Pattern::Noise => hash33(position * 5.0),
+        Pattern::Checker => {
+            let cell = (position * 2.0).floor();
+            let odd = (cell.x as i32 + cell.y as i32 + cell.z as i32) & 1;
+            Vec3::splat(if odd == 1 { 0.2 } else { 1.0 })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+    pub material: i32,
+}
+
+pub struct CpuRaymarcher<'a> {
+    constants: RaymarchingConstants,
+    objects: Vec<GpuRaymarchingObject>,
+    grid: ObjectGrid,
+    materials: Vec<GpuMaterial>,
+    lights: Vec<GpuLight>,
+    octree: Option<&'a Octree>,
+    atmosphere_params: AtmosphereParams,
+    fog: GpuHeightFog,
+    clouds: GpuClouds,
+    fog_volumes: Vec<GpuFogVolume>,
+    camera: GpuCamera,
+    /// Generated on the first sky lookup.
+    atmosphere: OnceCell<CpuAtmosphere>,
+}
+
+impl<'a> CpuRaymarcher<'a> {
+    /// Seen from `camera`, only the first `constants.objects_count` objects are evaluated, like
+    /// on the GPU. Shades with [`Material::defaults`], no lights, the default atmosphere, fog
+    /// and clouds and no fog volumes, until [`Self::with_materials`], [`Self::with_lights`],
+    /// [`Self::with_atmosphere`], [`Self::with_fog`], [`Self::with_clouds`] and
+    /// [`Self::with_fog_volumes`] replace them.
+    pub fn new(
+        constants: RaymarchingConstants,
+        objects: &[RaymarchingObject],
+        camera: &Camera,
+    ) -> Self {
+        let count = (constants.objects_count as usize).min(objects.len());
+        let scene = SceneGraph::new(&objects[..count]);
+        Self {
+            constants,
+            objects: scene.gpu_objects(),
+            grid: ObjectGrid::new(&scene),
+            materials: Material::defaults().iter().map(Material::to_gpu).collect(),
+            lights: Vec::new(),
+            octree: None,
+            atmosphere_params: AtmosphereParams::default(),
+            fog: HeightFog::default().to_gpu(),
+            clouds: Clouds::default().to_gpu(),
+            fog_volumes: Vec::new(),
+            camera: camera.to_gpu(),
+            atmosphere: OnceCell::new(),
+        }
+    }
+
+    /// Also evaluates `octree`, like the voxel octree uploaded to the compute pass.
+    pub fn with_octree(mut self, octree: &'a Octree) -> Self {
+        self.octree = Some(octree);
+        self
+    }
+
+    /// Material table the object materials index, like `RenderOptions::materials`.
+    pub fn with_materials(mut self, materials: &[Material]) -> Self {
+        self.materials = materials.iter().map(Material::to_gpu).collect();
+        self
+    }
+
+    /// Lights shaded next to the sun, like `RenderOptions::lights`.
+    pub fn with_lights(mut self, lights: &[Light]) -> Self {
+        self.lights = lights.iter().map(Light::to_gpu).collect();
+        self
+    }
+
+    /// Atmosphere the sky is shaded with, like `RenderOptions::atmosphere`.
+    pub fn with_atmosphere(mut self, params: &AtmosphereParams) -> Self {
+        self.atmosphere_params = *params;
+        self.atmosphere = OnceCell::new();
+        self
+    }
+
+    /// Fog in front of the geometry, like `RenderOptions::fog`.
+    pub fn with_fog(mut self, fog: &HeightFog) -> Self {
+        self.fog = fog.to_gpu();
+        self
+    }
+
+    /// Cloud layer in the sky, like `RenderOptions::clouds`.
+    pub fn with_clouds(mut self, clouds: &Clouds) -> Self {
+        self.clouds = clouds.to_gpu();
+        self
+    }
+
+    /// Fog volumes in the scene, like `RenderOptions::fog_volumes`.
+    pub fn with_fog_volumes(mut self, volumes: &[FogVolume]) -> Self {
+        self.fog_volumes = volumes
+            .iter()
+            .take(MAX_FOG_VOLUMES)
+            .map(FogVolume::to_gpu)
+            .collect();
+        self
+    }
+
+    /// The atmosphere's lookup tables for the current sun.
+    pub fn atmosphere(&self) -> &CpuAtmosphere {
+        self.atmosphere
+            .get_or_init(|| CpuAtmosphere::new(&self.atmosphere_params, self.sun_dir()))
+    }
+
+    pub fn constants(&self) -> &RaymarchingConstants {
+        &self.constants
+    }
+
+    fn sun_dir(&self) -> Vec3 {
+        self.constants.sun_dir.xyz().normalize()
+    }
+
+    fn camera_position(&self) -> Vec3 {
+        Vec3::from(self.camera.position)
+    }
+
+    /// Primary ray for `pixel` (integer coordinates like `global_invocation_id`), as built by
+    /// `compute_main`.
+    pub fn camera_ray(&self, pixel: Vec2) -> (Vec3, Vec3) {
+        let texture_size = Vec2::from(self.constants.texture_size);
+        let aspect = texture_size.x / texture_size.y;
+        let mut uv = (pixel / texture_size) * 2.0 - Vec2::ONE;
+        uv.y = -uv.y;
+        uv.x *= aspect;
+
+        // WGSL `vector * matrix` multiplies by the transpose
+        let view = Mat3::from_mat4(self.camera.view).transpose();
+        let ray_direction = view * (uv * self.camera.tan_half_fov).extend(1.0);
+        (self.camera_position(), ray_direction.normalize())
+    }
+
+    /// Marches a ray through the scene, `None` if it leaves the 100 unit march range.
+    pub fn march(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<RayHit> {
+        let mut distance_traveled = 0.0;
+        for _ in 0..MAX_STEPS {
+            let position = ray_origin + ray_direction * distance_traveled;
+            let res = self.map(position);
+            distance_traveled += self.grid_step(position, res.res);
+
+            if res.res < HIT_DISTANCE || distance_traveled > MAX_DISTANCE {
+                if distance_traveled > MAX_DISTANCE {
+                    return None;
+                }
+                return Some(RayHit {
+                    position,
+                    normal: self.get_normal(position),
+                    distance: distance_traveled,
+                    material: res.material,
+                });
+            }
+        }
+        None
+    }
+
+    /// Linear HDR color of `pixel`, the value `compute_main` stores in the "Raymarching" texture.
+    pub fn shade_pixel(&self, pixel: Vec2) -> Vec3 {
+        let (mut ray_origin, ray_direction) = self.camera_ray(pixel);
+        let far = self.camera.far;
+
+        let mut distance_traveled = self.camera.near;
+        let mut normal = Vec3::ZERO;
+        let mut material = 0;
+        let mut hit = false;
+        for _ in 0..MAX_STEPS {
+            let position = ray_origin + ray_direction * distance_traveled;
+            let res = self.map(position);
+            distance_traveled += self.grid_step(position, res.res);
+
+            if res.res < HIT_DISTANCE || distance_traveled > far {
+                normal = self.get_normal(position);
+                ray_origin = position;
+                material = if distance_traveled > far {
+                    MATERIAL_SKY
+                } else {
+                    hit = true;
+                    res.material
+                };
+                break;
+            }
+        }
+
+        let mut volume_seed = pcg_hash((pixel.x as u32).wrapping_add(pcg_hash(pixel.y as u32)));
+        let jitter = random(&mut volume_seed);
+        let camera = self.camera_position();
+        let (color, haze, volume_distance) = if material == MATERIAL_SKY {
+            let haze = self.height_fog(ray_direction, FOG_SKY_DISTANCE);
+            (self.cloudy_sky(ray_direction, jitter), haze, far)
+        } else {
+            let surface = if hit {
+                self.scene_surface(ray_origin)
+            } else {
+                self.material_surface(material, ray_origin)
+            };
+            let reflected = self.traced_gloss(surface, 0);
+            let mut seed = pcg_hash(pixel.x as u32 + pcg_hash(pixel.y as u32));
+            let color = self.light(normal, ray_origin, ray_direction, surface, reflected)
+                + self.secondary_light(ray_origin, normal, ray_direction, surface, &mut seed);
+            let hit_distance = ray_origin.distance(camera);
+            let haze = self.camera_haze(ray_direction, hit_distance);
+            (color, haze, hit_distance)
+        };
+        let volumes = self.march_fog_volumes(camera, ray_direction, volume_distance, jitter);
+
+        haze.then(volumes).apply(color) * self.constants.exposure
+    }
+
+    /// Renders the whole texture, rows top to bottom.
+    pub fn render(&self) -> Vec<Vec3> {
+        let [width, height] = self.constants.texture_size.map(|size| size as u32);
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| Vec2::new(x as f32, y as f32)))
+            .map(|pixel| self.shade_pixel(pixel))
+            .collect()
+    }
+
+    /// Renders and tone maps like `show_pass.wgsl`, giving RGBA8 pixels for thumbnails.
+    pub fn render_rgba8(&self) -> Vec<u8> {
+        self.render()
+            .into_iter()
+            .flat_map(|hdr| {
+                let sdr = aces_tone_map(hdr) * 255.0;
+                [sdr.x as u8, sdr.y as u8, sdr.z as u8, 255]
+            })
+            .collect()
+    }
+
+    /// Distance to the objects around `position` only, see [`Self::grid_step`].
+    pub fn map(&self, position: Vec3) -> SdfData {
+        let mut res = SdfData {
+            res: EMPTY_DISTANCE,
+            material: -1,
+        };
+        for index in self.grid.lookup(position) {
+            let object = &self.objects[index];
+            let distance = object_distance(position, object);
+            let operation = CsgOperation::from_id(object.operation);
+            let weight = operation.weight(res.res, distance, object.blend_radius);
+            res.res = operation.distance(res.res, distance, object.blend_radius);
+            if weight > 0.5 {
+                res.material = object.material;
+            }
+        }
+        if let Some(octree) = self.octree {
+            res = sdf_union(
+                res,
+                SdfData {
+                    res: octree.distance(position),
+                    material: octree.material,
+                },
+            );
+        }
+        let ground = position.y + 0.75;
+        sdf_union(
+            SdfData {
+                res: ground,
+                material: 0,
+            },
+            res,
+        )
+    }
+
+    /// Clamps a march step by the distance to the objects [`Self::map`] skipped at `p`.
+    pub fn grid_step(&self, p: Vec3, distance: f32) -> f32 {
+        distance.min(self.grid.lookup(p).bound)
+    }
+
+    /// Surface at `p`, mixed across smooth joins. Same fold as [`Self::map`].
+    pub fn scene_surface(&self, p: Vec3) -> Surface {
+        let mut distance = EMPTY_DISTANCE;
+        let mut surface = self.material_surface(-1, p);
+        for index in self.grid.lookup(p) {
+            let object = &self.objects[index];
+            let object_distance = object_distance(p, object);
+            let operation = CsgOperation::from_id(object.operation);
+            let weight = operation.weight(distance, object_distance, object.blend_radius);
+            distance = operation.distance(distance, object_distance, object.blend_radius);
+            surface = surface.mix(self.material_surface(object.material, p), weight);
+        }
+        if let Some(octree) = self.octree {
+            let octree_distance = octree.distance(p);
+            if octree_distance < distance {
+                distance = octree_distance;
+                surface = self.material_surface(octree.material, p);
+            }
+        }
+        if p.y + 0.75 <= distance {
+            surface = self.material_surface(0, p);
+        }
+        surface
+    }
+
+    /// Table entry `index`, [`MISSING_MATERIAL`] outside the table.
+    pub fn table_material(&self, index: i32) -> &GpuMaterial {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.materials.get(index))
+            .unwrap_or(&MISSING_MATERIAL)
+    }
+
+    /// The shader's `material_surface`.
+    pub fn material_surface(&self, index: i32, position: Vec3) -> Surface {
+        Surface::of_material(self.table_material(index), position)
+    }
+
+    pub fn get_normal(&self, p: Vec3) -> Vec3 {
+        let eps = 0.001;
+        let nx =
+            self.map(p + Vec3::new(eps, 0.0, 0.0)).res - self.map(p - Vec3::new(eps, 0.0, 0.0)).res;
+        let ny =
+            self.map(p + Vec3::new(0.0, eps, 0.0)).res - self.map(p - Vec3::new(0.0, eps, 0.0)).res;
+        let nz =
+            self.map(p + Vec3::new(0.0, 0.0, eps)).res - self.map(p - Vec3::new(0.0, 0.0, eps)).res;
+        Vec3::new(nx, ny, nz).normalize()
+    }
+
+    /// Penumbra of the light from `ray_dir`, tinted by the transmissive shapes it passes
+    /// through unrefracted.
+    pub fn soft_shadow(
+        &self,
+        ray_origin: Vec3,
+        ray_dir: Vec3,
+        mint: f32,
+        maxt: f32,
+        k: f32,
+    ) -> Vec3 {
+        let mut res: f32 = 1.0;
+        let mut tint = Vec3::ONE;
+        let mut t = mint;
+        // Where the ray entered a transmissive shape and how that absorbs
+        let mut entered: Option<(f32, Vec3)> = None;
+
+        let mut i = 0;
+        while i < 64 && t < maxt {
+            i += 1;
+            let p = ray_origin + ray_dir * t;
+            let sdf = self.map(p);
+            let h = sdf.res;
+            let material = self.table_material(sdf.material);
+            if h < 0.001 {
+                if material.transmission <= 0.0 {
+                    return Vec3::ZERO;
+                }
+                if entered.is_none() {
+                    entered = Some((t, Vec3::from(material.absorption)));
+                    tint *= material.transmission;
+                }
+                t += (-h).max(PATH_SURFACE_OFFSET);
+                continue;
+            }
+            if let Some((start, absorption)) = entered.take() {
+                tint *= exp3(-absorption * (t - start));
+            }
+            let penumbra = k * h / t;
+            res = res.min(penumbra + (1.0 - penumbra) * material.transmission);
+            t += self.grid_step(p, h);
+        }
+        if let Some((start, absorption)) = entered {
+            tint *= exp3(-absorption * (t - start));
+        }
+
+        res * tint
+    }
+
+    pub fn disney_diffuse(
+        &self,
+        normal: Vec3,
+        view_dir: Vec3,
+        light_dir: Vec3,
+        base_color: Vec3,
+        roughness: f32,
+    ) -> Vec3 {
+        let n = normal;
+        let v = view_dir;
+        let l = light_dir;
+        let h = (l + v).normalize();
+
+        let cos_theta_l = n.dot(l).max(0.0);
+        let cos_theta_v = n.dot(v).max(0.0);
+        let cos_theta_d = l.dot(h).max(0.0);
+
+        // Renormalized so the retro-reflection of rough surfaces doesn't add energy
+        let energy_bias = 0.5 * roughness;
+        let energy_factor = 1.0 + (1.0 / 1.51 - 1.0) * roughness;
+        let fd90 = energy_bias + 2.0 * roughness * cos_theta_d * cos_theta_d;
+
+        let light_scatter = 1.0 + (fd90 - 1.0) * (1.0 - cos_theta_l).powf(5.0);
+        let view_scatter = 1.0 + (fd90 - 1.0) * (1.0 - cos_theta_v).powf(5.0);
+
+        let diffuse = base_color / PI;
+
+        diffuse * light_scatter * view_scatter * energy_factor * cos_theta_l
+    }
+
+    /// Uniform ambient light, split between the lobes with the roughness aware Fresnel.
+    /// `reflected` is the share of the specular lobe a traced reflection stands in for.
+    pub fn ambient_light(
+        &self,
+        normal: Vec3,
+        view_dir: Vec3,
+        surface: Surface,
+        reflected: f32,
+    ) -> Vec3 {
+        let roughness = surface.roughness.max(MIN_ROUGHNESS);
+        let n_dot_v = normal.dot(view_dir).max(1e-4);
+        let f0 = surface_f0(surface);
+        let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+        let diffuse = (Vec3::ONE - fresnel)
+            * (1.0 - surface.metallic)
+            * (1.0 - surface.transmission)
+            * surface.albedo;
+        self.constants.ambient * (diffuse + fresnel * (1.0 - reflected))
+    }
+
+    /// Share of the ambient light reaching `position`, from `ao_samples` distance field taps
+    /// along the normal. 1 when the taps are off.
+    pub fn ambient_occlusion(&self, position: Vec3, normal: Vec3) -> f32 {
+        let samples = self.constants.ao_samples;
+        if samples == 0 {
+            return 1.0;
+        }
+        let mut occlusion = 0.0;
+        let mut total = 0.0;
+        let mut weight = 1.0;
+        for i in 1..=samples {
+            let h = self.constants.ao_radius * i as f32 / samples as f32;
+            let d = self.map(position + normal * h).res;
+            occlusion += weight * ((h - d) / h).clamp(0.0, 1.0);
+            total += weight;
+            weight *= AO_FALLOFF;
+        }
+        1.0 - occlusion / total
+    }
+
+    /// Sun, lights, ambient and emission at a surface point, the shader's `light`. See
+    /// [`Self::ambient_light`] for `reflected`.
+    pub fn light(
+        &self,
+        normal: Vec3,
+        ray_origin: Vec3,
+        ray_dir: Vec3,
+        surface: Surface,
+        reflected: f32,
+    ) -> Vec3 {
+        let view_dir = -ray_dir;
+        let f0 = surface_f0(surface);
+
+        let mut color = self.ambient_light(normal, view_dir, surface, reflected)
+            * self.ambient_occlusion(ray_origin, normal);
+        color += surface.emissive;
+
+        let shadow_origin = ray_origin + normal * 0.01;
+        let sun_dir = self.sun_dir();
+        let sun = self.constants.sun_color.xyz() * self.constants.sun_intensity;
+        if sun.cmpgt(Vec3::ZERO).any() {
+            let shadow = self.soft_shadow(shadow_origin, sun_dir, 0.01, 50.0, 32.0);
+            color += self.brdf(normal, view_dir, sun_dir, surface, f0, 0.0) * sun * shadow;
+        }
+
+        for light in &self.lights {
+            let sample = sample_light(light, ray_origin);
+            if sample.radiance.cmple(Vec3::ZERO).all() || normal.dot(sample.direction) <= 0.0 {
+                continue;
+            }
+            let mut shadow = Vec3::ONE;
+            if light.shadows != 0 {
+                let k = (sample.distance / sample.size.max(1e-3)).clamp(2.0, 32.0);
+                let maxt = (sample.distance - sample.size).max(0.02);
+                shadow = self.soft_shadow(shadow_origin, sample.direction, 0.01, maxt, k);
+            }
+            let brdf = self.brdf(
+                normal,
+                view_dir,
+                sample.direction,
+                surface,
+                f0,
+                sample.size / sample.distance,
+            );
+            color += brdf * sample.radiance * shadow;
+        }
+        color
+    }
+
+    /// Cook-Torrance with a metallic workflow times the cosine term, the shader's `brdf`.
+    /// `light_angle` widens and renormalizes the specular lobe for area lights.
+    pub fn brdf(
+        &self,
+        normal: Vec3,
+        view_dir: Vec3,
+        light_dir: Vec3,
+        surface: Surface,
+        f0: Vec3,
+        light_angle: f32,
+    ) -> Vec3 {
+        let half_dir = (light_dir + view_dir).normalize();
+        let fresnel = fresnel_schlick(half_dir.dot(view_dir).max(0.0), f0);
+        let diffuse_weight =
+            (Vec3::ONE - fresnel) * (1.0 - surface.metallic) * (1.0 - surface.transmission);
+        let diffuse = diffuse_weight
+            * self.disney_diffuse(
+                normal,
+                view_dir,
+                light_dir,
+                surface.albedo,
+                surface.roughness,
+            );
+
+        let roughness = surface.roughness.max(MIN_ROUGHNESS);
+        let alpha = roughness * roughness;
+        let wide_alpha = (alpha + light_angle * 0.5).min(1.0);
+        let normalization = (alpha / wide_alpha) * (alpha / wide_alpha);
+        let specular = specular_ggx(
+            normal,
+            view_dir,
+            light_dir,
+            half_dir,
+            wide_alpha.sqrt(),
+            fresnel,
+        );
+        diffuse + specular * normalization
+    }
+
+    /// Next path direction and its `brdf` over pdf weight, the shader's `sample_brdf`. Only the
+    /// sampling of `path_trace_main` is mirrored, the path tracer itself runs on the GPU.
+    pub fn sample_brdf(
+        &self,
+        normal: Vec3,
+        view_dir: Vec3,
+        surface: Surface,
+        f0: Vec3,
+        u: Vec3,
+    ) -> BrdfSample {
+        let roughness = surface.roughness.max(MIN_ROUGHNESS);
+        let n_dot_v = normal.dot(view_dir).max(1e-4);
+        let p_specular = specular_probability(n_dot_v, surface, f0);
+        let frame = tangent_frame(normal);
+        let direction = if u.z < p_specular {
+            (-view_dir).reflect(frame * sample_ggx_half(u.xy(), roughness))
+        } else {
+            frame * sample_cosine(u.xy())
+        };
+
+        let n_dot_l = normal.dot(direction);
+        if n_dot_l <= 0.0 {
+            return BrdfSample {
+                direction,
+                weight: Vec3::ZERO,
+            };
+        }
+        let half_dir = (direction + view_dir).normalize();
+        let n_dot_h = normal.dot(half_dir).max(0.0);
+        let specular_pdf = distribution_ggx(n_dot_h, roughness) * n_dot_h
+            / (4.0 * view_dir.dot(half_dir).max(1e-4));
+        let diffuse_pdf = n_dot_l / PI;
+        let pdf = diffuse_pdf + (specular_pdf - diffuse_pdf) * p_specular;
+        BrdfSample {
+            direction,
+            weight: self.brdf(normal, view_dir, direction, surface, f0, 0.0) / pdf.max(1e-6),
+        }
+    }
+
+    /// Light seen in a camera ray hit through its reflection and refraction, traced up to
+    /// `bounce_depth` surfaces deep, the shader's `secondary_light`. `seed` picks the glossy
+    /// reflection directions.
+    pub fn secondary_light(
+        &self,
+        position: Vec3,
+        normal: Vec3,
+        ray_direction: Vec3,
+        surface: Surface,
+        seed: &mut u32,
+    ) -> Vec3 {
+        let mut stack = Vec::with_capacity(SECONDARY_STACK_SIZE);
+        let camera_ray = SecondaryRay {
+            origin: position,
+            depth: 0,
+            direction: ray_direction,
+            weight: Vec3::ONE,
+        };
+        self.spawn_secondary_rays(&mut stack, camera_ray, normal, surface, seed);
+        let mut color = Vec3::ZERO;
+        let mut traced = 0;
+        while traced < MAX_SECONDARY_RAYS
+            && let Some(ray) = stack.pop()
+        {
+            traced += 1;
+            let Some(position) = self.trace_path_ray(ray.origin, ray.direction) else {
+                color += ray.weight * self.sky(ray.direction, false);
+                continue;
+            };
+            let normal = self.get_normal(position);
+            let surface = self.scene_surface(position);
+            let reflected = self.traced_gloss(surface, ray.depth);
+            color += ray.weight * self.light(normal, position, ray.direction, surface, reflected);
+            let hit = SecondaryRay {
+                origin: position,
+                ..ray
+            };
+            self.spawn_secondary_rays(&mut stack, hit, normal, surface, seed);
+        }
+        color
+    }
+
+    /// Pushes the reflection and refraction leaving the surface `ray` hit at its origin.
+    fn spawn_secondary_rays(
+        &self,
+        stack: &mut Vec<SecondaryRay>,
+        ray: SecondaryRay,
+        normal: Vec3,
+        surface: Surface,
+        seed: &mut u32,
+    ) {
+        if ray.depth >= self.constants.bounce_depth {
+            return;
+        }
+        let roughness = surface.roughness.max(MIN_ROUGHNESS);
+        let n_dot_v = normal.dot(-ray.direction).max(1e-4);
+        let fresnel = fresnel_schlick_roughness(n_dot_v, surface_f0(surface), roughness);
+
+        let reflection = ray.weight * fresnel * self.traced_gloss(surface, ray.depth);
+        if reflection.max_element() > MIN_RAY_WEIGHT && stack.len() < SECONDARY_STACK_SIZE {
+            let u = Vec2::new(random(seed), random(seed));
+            let half_dir = tangent_frame(normal) * sample_ggx_half(u, roughness);
+            let mut direction = ray.direction.reflect(half_dir);
+            if direction.dot(normal) <= 0.0 {
+                direction = ray.direction.reflect(normal);
+            }
+            stack.push(SecondaryRay {
+                origin: ray.origin + normal * PATH_SURFACE_OFFSET,
+                depth: ray.depth + 1,
+                direction,
+                weight: reflection,
+            });
+        }
+
+        let transmission =
+            ray.weight * (Vec3::ONE - fresnel) * surface.transmission * (1.0 - surface.metallic);
+        if transmission.max_element() > MIN_RAY_WEIGHT
+            && stack.len() < SECONDARY_STACK_SIZE
+            && let Some(refraction) =
+                self.refract_through(ray.origin, normal, ray.direction, surface)
+        {
+            stack.push(SecondaryRay {
+                origin: refraction.origin,
+                depth: ray.depth + 1,
+                direction: refraction.direction,
+                weight: transmission * refraction.transmittance,
+            });
+        }
+    }
+
+    /// Share of the specular ambient light a traced reflection replaces at `depth`, 0 for
+    /// rough surfaces and past `bounce_depth`.
+    pub fn traced_gloss(&self, surface: Surface, depth: u32) -> f32 {
+        if depth >= self.constants.bounce_depth {
+            return 0.0;
+        }
+        1.0 - smoothstep(0.0, MAX_GLOSSY_ROUGHNESS, surface.roughness)
+    }
+
+    /// Follows the ray refracted into the volume at `position` to where it leaves again,
+    /// reflecting it back in where it can't refract out. `None` if it stays trapped.
+    pub fn refract_through(
+        &self,
+        position: Vec3,
+        normal: Vec3,
+        ray_direction: Vec3,
+        surface: Surface,
+    ) -> Option<Refraction> {
+        let f0 = Vec3::splat(dielectric_f0(surface.ior));
+        let mut origin =
+            position - normal * (self.map(position).res.max(0.0) + PATH_SURFACE_OFFSET);
+        let mut direction = ray_direction.refract(normal, 1.0 / surface.ior);
+        let mut travelled = 0.0;
+        for _ in 0..=MAX_INTERNAL_REFLECTIONS {
+            let (exit, hit) = self.trace_inside(origin, direction);
+            travelled += origin.distance(exit);
+            if !hit {
+                break;
+            }
+            let exit_normal = self.get_normal(exit);
+            let refracted = direction.refract(-exit_normal, surface.ior);
+            if refracted != Vec3::ZERO {
+                let fresnel = fresnel_schlick(refracted.dot(exit_normal).max(0.0), f0);
+                return Some(Refraction {
+                    origin: exit + exit_normal * PATH_SURFACE_OFFSET,
+                    direction: refracted,
+                    transmittance: exp3(-surface.absorption * travelled) * (Vec3::ONE - fresnel),
+                });
+            }
+            direction = direction.reflect(-exit_normal);
+            origin = exit - exit_normal * PATH_SURFACE_OFFSET;
+        }
+        None
+    }
+
+    /// The path tracer's tighter march, `None` if the ray leaves the scene. Running out of
+    /// steps counts as a hit.
+    pub fn trace_path_ray(&self, ray_origin: Vec3, ray_direction: Vec3) -> Option<Vec3> {
+        let mut t = 0.0;
+        for _ in 0..MAX_PATH_STEPS {
+            let p = ray_origin + ray_direction * t;
+            let distance = self.map(p).res;
+            if distance < PATH_HIT_DISTANCE {
+                return Some(p);
+            }
+            t += self.grid_step(p, distance);
+            if t > MAX_PATH_DISTANCE {
+                return None;
+            }
+        }
+        Some(ray_origin + ray_direction * t)
+    }
+
+    /// Marches from inside the scene to where the ray leaves it, and whether it does.
+    fn trace_inside(&self, ray_origin: Vec3, ray_direction: Vec3) -> (Vec3, bool) {
+        let mut t = 0.0;
+        for _ in 0..MAX_PATH_STEPS {
+            let p = ray_origin + ray_direction * t;
+            let distance = -self.map(p).res;
+            if distance < PATH_HIT_DISTANCE {
+                return (p, true);
+            }
+            t += self.grid_step(p, distance);
+            if t > MAX_PATH_DISTANCE {
+                break;
+            }
+        }
+        (ray_origin + ray_direction * t, false)
+    }
+
+    /// Atmosphere radiance along a ray, `sun_disk` adds the disk on top of the scattered light.
+    pub fn sky(&self, ray_direction: Vec3, sun_disk: bool) -> Vec3 {
+        let sun_dir = self.sun_dir();
+        let atmosphere = self.atmosphere();
+        let mut luminance = atmosphere.sky_view(ray_direction, sun_dir);
+        let height = atmosphere.params().view_height;
+        if sun_disk && atmosphere.distance_to_ground(height, ray_direction.y) < 0.0 {
+            luminance += atmosphere.sun_disk(ray_direction, sun_dir);
+        }
+
+        luminance * self.constants.sun_color.xyz() * self.constants.sun_intensity
+    }
+
+    /// The sky with the clouds in front of it, the aerial perspective between them and the
+    /// camera and the sun disk behind them. `jitter` in `[0, 1)` offsets the samples.
+    pub fn cloudy_sky(&self, ray_direction: Vec3, jitter: f32) -> Vec3 {
+        let background = self.sky(ray_direction, true);
+        let camera = self.camera_position();
+        let clouds = self.march_clouds(camera, ray_direction, jitter);
+        if clouds.transmittance >= 1.0 {
+            return background;
+        }
+        let air = self.aerial_perspective(ray_direction, clouds.distance);
+        background * clouds.transmittance
+            + clouds.scattered * air.transmittance
+            + air.inscattering * (1.0 - clouds.transmittance)
+    }
+
+    /// Single scattering of the sun and the sky through the cloud layer along a view ray.
+    pub fn march_clouds(&self, ray_origin: Vec3, ray_direction: Vec3, jitter: f32) -> CloudSample {
+        let mut result = CloudSample {
+            transmittance: 1.0,
+            scattered: Vec3::ZERO,
+            distance: 0.0,
+        };
+        let clouds = &self.clouds;
+        if clouds.coverage <= 0.0 || clouds.density <= 0.0 {
+            return result;
+        }
+        let mut start = 0.0;
+        let mut end = CLOUD_MAX_DISTANCE;
+        if ray_direction.y.abs() < 1e-6 {
+            if ray_origin.y < clouds.bottom || ray_origin.y > clouds.top {
+                return result;
+            }
+        } else {
+            let bottom = (clouds.bottom - ray_origin.y) / ray_direction.y;
+            let top = (clouds.top - ray_origin.y) / ray_direction.y;
+            start = bottom.min(top).max(0.0);
+            end = bottom.max(top).min(CLOUD_MAX_DISTANCE);
+        }
+        if start >= end {
+            return result;
+        }
+
+        let atmosphere = self.atmosphere();
+        let a = atmosphere.params();
+        let sun_dir = self.sun_dir();
+        let cos_view_sun = ray_direction.dot(sun_dir);
+        let back = henyey_greenstein(cos_view_sun, CLOUD_BACK_G);
+        let forward = henyey_greenstein(cos_view_sun, CLOUD_FORWARD_G);
+        let phase = back + (forward - back) * CLOUD_FORWARD_WEIGHT;
+        let light = Vec3::from_array(a.sun_illuminance)
+            * self.constants.sun_color.xyz()
+            * self.constants.sun_intensity;
+        let ambient = self.sky(Vec3::Y, false);
+        let step = (end - start) / clouds.steps as f32;
+        let mut weighted_distance = 0.0;
+        let mut opacity = 0.0;
+        for i in 0..clouds.steps {
+            let t = start + (i as f32 + jitter) * step;
+            let p = ray_origin + ray_direction * t;
+            let density = self.cloud_density(p);
+            if density <= 0.0 {
+                continue;
+            }
+            let height =
+                a.view_height + (p.y - self.camera.position[1]).max(0.0) * a.distance_scale;
+            let sun = light
+                * atmosphere.sun_transmittance(height, sun_dir.y)
+                * self.cloud_sun_transmittance(p, sun_dir);
+            let layer = (p.y - clouds.bottom) / (clouds.top - clouds.bottom);
+            let source = sun * phase + ambient * (0.5 + 0.5 * layer);
+
+            let step_transmittance = (-density * step).exp();
+            let absorbed = result.transmittance * (1.0 - step_transmittance);
+            result.scattered += source * absorbed;
+            weighted_distance += t * absorbed;
+            opacity += absorbed;
+            result.transmittance *= step_transmittance;
+            if result.transmittance < MIN_VOLUME_TRANSMITTANCE {
+                break;
+            }
+        }
+        result.distance = if opacity > 0.0 {
+            weighted_distance / opacity
+        } else {
+            start
+        };
+        result
+    }
+
+    /// Extinction per scene unit of the clouds at `p`.
+    pub fn cloud_density(&self, p: Vec3) -> f32 {
+        let clouds = &self.clouds;
+        let layer = (p.y - clouds.bottom) / (clouds.top - clouds.bottom);
+        if layer <= 0.0 || layer >= 1.0 {
+            return 0.0;
+        }
+        let profile = smoothstep(0.0, 0.1, layer) * (1.0 - smoothstep(0.4, 1.0, layer));
+        let wind = Vec3::from_array(clouds.wind);
+        let noise = cloud_noise((p - wind * self.constants.time) / clouds.scale);
+        clouds.density
+            * ((noise * profile - (1.0 - clouds.coverage)) / clouds.coverage).clamp(0.0, 1.0)
+    }
+
+    fn cloud_sun_transmittance(&self, p: Vec3, sun_dir: Vec3) -> f32 {
+        let clouds = &self.clouds;
+        let step = 0.5 * (clouds.top - clouds.bottom) / CLOUD_LIGHT_STEPS as f32;
+        let optical_depth: f32 = (0..CLOUD_LIGHT_STEPS)
+            .map(|i| self.cloud_density(p + sun_dir * (i as f32 + 0.5) * step) * step)
+            .sum();
+        (-optical_depth)
+            .exp()
+            .max(CLOUD_MULTI_SCATTERING * (-optical_depth * CLOUD_MULTI_EXTINCTION).exp())
+    }
+
+    /// Single scattering of the sun and the ambient light in the fog volumes along
+    /// `max_distance` of a view ray, the sun shadowed by the geometry and the fog towards it.
+    pub fn march_fog_volumes(
+        &self,
+        ray_origin: Vec3,
+        ray_direction: Vec3,
+        max_distance: f32,
+        jitter: f32,
+    ) -> Haze {
+        let mut haze = Haze::NONE;
+        if self.fog_volumes.is_empty() {
+            return haze;
+        }
+        let sun_dir = self.sun_dir();
+        let sun = self.constants.sun_color.xyz() * self.constants.sun_intensity;
+        let cos_view_sun = ray_direction.dot(sun_dir);
+        let mut t = 0.0;
+        let mut i = 0;
+        while i < MAX_FOG_VOLUME_STEPS && t < max_distance {
+            i += 1;
+            let distance = self.fog_volumes_distance(ray_origin + ray_direction * t);
+            if distance > FOG_VOLUME_STEP {
+                t += distance;
+                continue;
+            }
+            let step = FOG_VOLUME_STEP.min(max_distance - t);
+            let p = ray_origin + ray_direction * (t + step * jitter);
+            t += step;
+            let medium = self.fog_medium(p, cos_view_sun);
+            if medium.extinction <= 0.0 {
+                continue;
+            }
+            let mut source = Vec3::splat(self.constants.ambient);
+            if sun.cmpgt(Vec3::ZERO).any() {
+                let shadow = self.soft_shadow(p, sun_dir, 0.01, 50.0, 32.0);
+                source += sun * medium.phase * shadow * self.fog_sun_transmittance(p, sun_dir);
+            }
+            source *= medium.albedo;
+
+            let step_transmittance = (-medium.extinction * step).exp();
+            haze.inscattering += haze.transmittance * (source - source * step_transmittance);
+            haze.transmittance *= step_transmittance;
+            if haze.transmittance.max_element() < MIN_VOLUME_TRANSMITTANCE {
+                break;
+            }
+        }
+        haze
+    }
+
+    /// Distance to the nearest fog volume's boundary, negative inside one.
+    pub fn fog_volumes_distance(&self, p: Vec3) -> f32 {
+        self.fog_volumes
+            .iter()
+            .map(|volume| {
+                let local = volume.world_to_local.transform_point3(p);
+                volume.primitive.distance(local)
+            })
+            .fold(EMPTY_DISTANCE, f32::min)
+    }
+
+    fn fog_medium(&self, p: Vec3, cos_view_sun: f32) -> FogMedium {
+        let mut medium = FogMedium {
+            extinction: 0.0,
+            albedo: Vec3::ZERO,
+            phase: 0.0,
+        };
+        for volume in &self.fog_volumes {
+            let density = fog_volume_density(volume, p);
+            medium.extinction += density;
+            medium.albedo += density * Vec3::from_array(volume.albedo);
+            medium.phase += density * henyey_greenstein(cos_view_sun, volume.anisotropy);
+        }
+        if medium.extinction > 0.0 {
+            medium.albedo /= medium.extinction;
+            medium.phase /= medium.extinction;
+        }
+        medium
+    }
+
+    fn fog_sun_transmittance(&self, p: Vec3, sun_dir: Vec3) -> f32 {
+        let mut optical_depth = 0.0;
+        for i in 0..FOG_LIGHT_STEPS {
+            let tap = p + sun_dir * (i as f32 + 0.5) * FOG_LIGHT_STEP;
+            for volume in &self.fog_volumes {
+                optical_depth += fog_volume_density(volume, tap) * FOG_LIGHT_STEP;
+            }
+        }
+        (-optical_depth).exp()
+    }
+
+    /// Scattering of the atmosphere along `distance` scene units of the view ray, through
+    /// uniform air at the camera's height.
+    pub fn aerial_perspective(&self, ray_direction: Vec3, distance: f32) -> Haze {
+        let atmosphere = self.atmosphere();
+        let a = atmosphere.params();
+        let height = a.view_height;
+        let sun_dir = self.sun_dir();
+        let medium = atmosphere.sample_medium(height);
+        let cos_view_sun = ray_direction.dot(sun_dir);
+        let sun = atmosphere.sun_transmittance(height, sun_dir.y);
+        let multi = atmosphere.multi_scattering(height, sun_dir.y);
+        let source = medium.rayleigh * (rayleigh_phase(cos_view_sun) * sun + multi)
+            + medium.mie * (atmosphere.mie_phase(cos_view_sun) * sun + multi);
+
+        let transmittance = exp3(-medium.extinction * distance * a.distance_scale);
+        let light = Vec3::from_array(a.sun_illuminance)
+            * self.constants.sun_color.xyz()
+            * self.constants.sun_intensity;
+        let inscattering =
+            (source - source * transmittance) / medium.extinction.max(Vec3::splat(1e-6)) * light;
+        Haze {
+            transmittance,
+            inscattering,
+        }
+    }
+
+    /// Exponential height fog along `distance` of the view ray, lit by the sky at the horizon.
+    pub fn height_fog(&self, ray_direction: Vec3, distance: f32) -> Haze {
+        let fog = &self.fog;
+        if fog.density <= 0.0 {
+            return Haze::NONE;
+        }
+        let density = fog.density * (-fog.falloff * (self.camera.position[1] - fog.height)).exp();
+        let slope = fog.falloff * ray_direction.y;
+        let mut optical_depth = density * distance;
+        if (slope * distance).abs() > 1e-4 {
+            optical_depth = density * (1.0 - (-slope * distance).min(80.0).exp()) / slope;
+        }
+        let transmittance = (-optical_depth).exp();
+        let horizon = Vec3::new(ray_direction.x, 0.0, ray_direction.z);
+        let light = Vec3::from_array(fog.albedo) * self.sky(horizon, false);
+        Haze {
+            transmittance: Vec3::splat(transmittance),
+            inscattering: light * (1.0 - transmittance),
+        }
+    }
+
+    /// Aerial perspective and fog in front of a surface `distance` away, fading into the
+    /// fogged sky towards the end of the march.
+    pub fn camera_haze(&self, ray_direction: Vec3, distance: f32) -> Haze {
+        let mut haze = self
+            .aerial_perspective(ray_direction, distance)
+            .then(self.height_fog(ray_direction, distance));
+        let far = self.camera.far;
+        let fade = smoothstep(far * FADE_START, far, distance);
+        if fade > 0.0 {
+            let sky_fog = self.height_fog(ray_direction, FOG_SKY_DISTANCE);
+            let background = sky_fog.apply(self.sky(ray_direction, true));
+            haze.transmittance *= 1.0 - fade;
+            haze.inscattering = haze.inscattering.lerp(background, fade);
+        }
+        haze
+    }
+}
+
+/// What the air between the camera and a surface does to its color, `Haze` in the shader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Haze {
+    pub transmittance: Vec3,
+    pub inscattering: Vec3,
+}
+
+impl Haze {
+    pub const NONE: Self = Self {
+        transmittance: Vec3::ONE,
+        inscattering: Vec3::ZERO,
+    };
+
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        color * self.transmittance + self.inscattering
+    }
+
+    /// This haze seen through `far`, `combine_haze` in the shader.
+    pub fn then(self, far: Haze) -> Haze {
+        Haze {
+            transmittance: self.transmittance * far.transmittance,
+            inscattering: far.apply(self.inscattering),
+        }
+    }
+}
+
+/// Light the clouds along a view ray scatter towards the camera, how much of the sky behind
+/// them gets through and how far away they are on average.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CloudSample {
+    pub transmittance: f32,
+    pub scattered: Vec3,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FogMedium {
+    extinction: f32,
+    albedo: Vec3,
+    phase: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SecondaryRay {
+    origin: Vec3,
+    depth: u32,
+    direction: Vec3,
+    weight: Vec3,
+}
+
+/// A ray leaving a transmissive volume, see [`CpuRaymarcher::refract_through`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Refraction {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// Absorbed inside and reflected back in on the way out.
+    pub transmittance: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BrdfSample {
+    pub direction: Vec3,
+    /// `brdf` over the pdf of `direction`, zero below the surface.
+    pub weight: Vec3,
+}
+
+/// Chance of sampling the specular lobe, its share of the reflected light kept within
+/// 0.1..=0.9 so neither lobe goes unsampled.
+pub fn specular_probability(n_dot_v: f32, surface: Surface, f0: Vec3) -> f32 {
+    let fresnel = fresnel_schlick(n_dot_v, f0);
+    let specular = fresnel.dot(Vec3::splat(1.0 / 3.0));
+    let diffuse = ((Vec3::ONE - fresnel)
+        * (1.0 - surface.metallic)
+        * (1.0 - surface.transmission)
+        * surface.albedo)
+        .dot(Vec3::splat(1.0 / 3.0));
+    (specular / (specular + diffuse).max(1e-4)).clamp(0.1, 0.9)
+}
+
+/// Cosine weighted direction around +z.
+pub fn sample_cosine(u: Vec2) -> Vec3 {
+    let r = u.x.sqrt();
+    let phi = 2.0 * PI * u.y;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u.x).max(0.0).sqrt())
+}
+
+/// GGX distributed half vector around +z.
+pub fn sample_ggx_half(u: Vec2, roughness: f32) -> Vec3 {
+    let alpha = roughness * roughness;
+    let cos_theta = ((1.0 - u.x) / (1.0 + (alpha * alpha - 1.0) * u.x)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.y;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Orthonormal basis with `normal` as +z (Duff et al. 2017).
+pub fn tangent_frame(normal: Vec3) -> Mat3 {
+    let s = if normal.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (s + normal.z);
+    let b = normal.x * normal.y * a;
+    let tangent = Vec3::new(1.0 + s * normal.x * normal.x * a, s * b, -s * normal.x);
+    let bitangent = Vec3::new(b, s + normal.y * normal.y * a, -normal.y);
+    Mat3::from_cols(tangent, bitangent, normal)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f32,
+    /// Incoming radiance before shadowing.
+    pub radiance: Vec3,
+    /// Radius of the emitting surface, 0 for punctual lights.
+    pub size: f32,
+}
+
+/// Direction, distance and radiance of `light` at `p`, the shader's `sample_light`.
+pub fn sample_light(light: &GpuLight, p: Vec3) -> LightSample {
+    let light_direction = Vec3::from(light.direction);
+    let light_size = Vec2::from(light.size);
+    let mut position = Vec3::from(light.position);
+    let mut size = 0.0;
+    let kind = LightKind::from_id(light.kind);
+    match kind {
+        LightKind::Rect => {
+            let tangent = light_tangent(light_direction);
+            let bitangent = light_direction.cross(tangent);
+            let local = p - position;
+            position += tangent * local.dot(tangent).clamp(-light_size.x, light_size.x);
+            position += bitangent * local.dot(bitangent).clamp(-light_size.y, light_size.y);
+            size = light_size.length();
+        }
+        LightKind::Sphere => size = light_size.x,
+        LightKind::Point | LightKind::Spot => {}
+    }
+
+    let to_light = position - p;
+    let distance = to_light.length().max(1e-4);
+    let direction = to_light / distance;
+    let mut attenuation = distance_attenuation(distance.max(size), light.range);
+    match kind {
+        LightKind::Spot => {
+            attenuation *= smoothstep(
+                light.cos_outer,
+                light.cos_inner,
+                (-direction).dot(light_direction),
+            )
+        }
+        LightKind::Rect => attenuation *= (-direction).dot(light_direction).max(0.0),
+        LightKind::Point | LightKind::Sphere => {}
+    }
+    LightSample {
+        direction,
+        distance,
+        radiance: Vec3::from(light.radiance) * attenuation,
+        size,
+    }
+}
+
+/// Inverse square falloff, windowed to reach zero at `range`.
+pub fn distance_attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = (1.0 - ratio * ratio * ratio * ratio).clamp(0.0, 1.0);
+    window * window / (distance * distance).max(1e-4)
+}
+
+/// Width axis of rectangle lights facing `normal`.
+fn light_tangent(normal: Vec3) -> Vec3 {
+    let up = if normal.y.abs() > 0.999 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    up.cross(normal).normalize()
+}
+
+/// Specular BRDF times the cosine term: GGX distribution, Smith geometry and the given Fresnel.
+pub fn specular_ggx(
+    normal: Vec3,
+    view_dir: Vec3,
+    light_dir: Vec3,
+    half_dir: Vec3,
+    roughness: f32,
+    fresnel: Vec3,
+) -> Vec3 {
+    let n_dot_l = normal.dot(light_dir).max(0.0);
+    let n_dot_v = normal.dot(view_dir).max(1e-4);
+    let n_dot_h = normal.dot(half_dir).max(0.0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    d * g * fresnel / (4.0 * n_dot_v * n_dot_l.max(1e-4)) * n_dot_l
+}
+
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denom * denom)
+}
+
+/// Schlick-GGX per direction with the `k` of analytic lights.
+pub fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    g_v * g_l
+}
+
+/// Reflectance at normal incidence of a dielectric with index of refraction `ior` in air.
+pub fn dielectric_f0(ior: f32) -> f32 {
+    let r = (ior - 1.0) / (ior + 1.0);
+    r * r
+}
+
+/// Reflectance at normal incidence, metals reflect in their own color.
+pub fn surface_f0(surface: Surface) -> Vec3 {
+    Vec3::splat(dielectric_f0(surface.ior)).lerp(surface.albedo, surface.metallic)
+}
+
+pub fn fresnel_schlick(cos_theta: f32, f0: Vec3) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - cos_theta).powf(5.0)
+}
+
+/// Fresnel averaged over the rough lobe, for the uniform ambient light.
+pub fn fresnel_schlick_roughness(cos_theta: f32, f0: Vec3, roughness: f32) -> Vec3 {
+    f0 + (Vec3::splat(1.0 - roughness).max(f0) - f0) * (1.0 - cos_theta).powf(5.0)
+}
+
+/// Distance in world units, the local distance is scaled by the smallest world scale.
+pub fn object_distance(p: Vec3, object: &GpuRaymarchingObject) -> f32 {
+    let local = object.world_to_local.transform_point3(p);
+    object.primitive.distance(local) * object.scale_bound
+}
+
+pub fn sdf_union(sdf_1: SdfData, sdf_2: SdfData) -> SdfData {
+    if sdf_1.res > sdf_2.res { sdf_2 } else { sdf_1 }
+}
+
+pub fn pcg_hash(v: u32) -> u32 {
+    let state = v.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// Uniform in `[0, 1)`, advances `seed`.
+pub fn random(seed: &mut u32) -> f32 {
+    *seed = pcg_hash(*seed);
+    (*seed >> 8) as f32 / 16777216.0
+}
+
+/// Fractal value noise in `[0, 1]`, the largest features a unit across.
+pub fn cloud_noise(p: Vec3) -> f32 {
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    let mut amplitude = 0.5;
+    let mut q = p;
+    for _ in 0..CLOUD_NOISE_OCTAVES {
+        sum += amplitude * value_noise(q);
+        total += amplitude;
+        amplitude *= 0.5;
+        q = q * 2.0 + Vec3::new(17.0, 5.0, 11.0);
+    }
+    sum / total
+}
+
+fn value_noise(p: Vec3) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = cell.as_ivec3();
+    let lattice = |x: i32, y: i32, z: i32| lattice_value(c + IVec3::new(x, y, z));
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = mix(lattice(0, 0, 0), lattice(1, 0, 0), u.x);
+    let x10 = mix(lattice(0, 1, 0), lattice(1, 1, 0), u.x);
+    let x01 = mix(lattice(0, 0, 1), lattice(1, 0, 1), u.x);
+    let x11 = mix(lattice(0, 1, 1), lattice(1, 1, 1), u.x);
+    mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z)
+}
+
+fn lattice_value(cell: IVec3) -> f32 {
+    let hash = pcg_hash((cell.x as u32).wrapping_add(pcg_hash(
+        (cell.y as u32).wrapping_add(pcg_hash(cell.z as u32)),
+    )));
+    (hash >> 8) as f32 / 16777216.0
+}
+
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom.powf(1.5))
+}
+
+/// Fades in over `edge` below the boundary.
+fn fog_volume_density(volume: &GpuFogVolume, p: Vec3) -> f32 {
+    let local = volume.world_to_local.transform_point3(p);
+    let distance = volume.primitive.distance(local);
+    volume.density * (-distance / volume.edge).clamp(0.0, 1.0)
+}
+
+pub fn hash33(p: Vec3) -> Vec3 {
+    let q = Vec3::new(
+        p.dot(Vec3::new(127.1, 311.7, 74.7)),
+        p.dot(Vec3::new(269.5, 183.3, 246.1)),
+        p.dot(Vec3::new(113.5, 271.9, 124.6)),
+    );
+    let v = Vec3::new(q.x.sin(), q.y.sin(), q.z.sin()) * 43758.547;
+    v - v.floor()
+}
+
+pub fn aces_tone_map(hdr: Vec3) -> Vec3 {
+    let m1 = glam::Mat3::from_cols_array(&[
+        0.59719, 0.07600, 0.02840, 0.35458, 0.90834, 0.13383, 0.04823, 0.01566, 0.83777,
+    ]);
+    let m2 = glam::Mat3::from_cols_array(&[
+        1.60475, -0.10208, -0.00327, -0.53108, 1.10813, -0.07276, -0.07367, -0.00605, 1.07602,
+    ]);
+    let v = m1 * hdr;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.432951) + 0.238081;
+    (m2 * (a / b)).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn exp3(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.exp(), v.y.exp(), v.z.exp())
+}
+
+/// CPU port of `atmosphere_common.wgsl` and the lookup tables of `atmosphere_luts.wgsl`,
+/// generated at the same resolutions, stored at half precision like the Rgba16Float textures
+/// and sampled like the linear clamp-to-edge sampler.
+pub struct CpuAtmosphere {
+    params: GpuAtmosphere,
+    /// `SUN_ANGULAR_RADIUS` and `SUN_SOLID_ANGLE`, which the shader folds into `const`
+    /// expressions.
+    sun_angular_radius: f32,
+    sun_solid_angle: f32,
+    transmittance: CpuLut,
+    multi_scattering: CpuLut,
+    sky_view: CpuLut,
+}
+
+const TRANSMITTANCE_STEPS: f32 = 40.0;
+const MULTI_SCATTERING_STEPS: f32 = 20.0;
+const MULTI_SCATTERING_DIRECTIONS: u32 = 8;
+const SKY_VIEW_STEPS: f32 = 30.0;
+const SAMPLE_OFFSET: f32 = 0.3;
+
+struct Medium {
+    rayleigh: Vec3,
+    mie: Vec3,
+    extinction: Vec3,
+}
+
+struct CpuLut {
+    size: (u32, u32),
+    texels: Vec<Vec3>,
+}
+
+impl CpuLut {
+    fn empty() -> Self {
+        Self {
+            size: (1, 1),
+            texels: vec![Vec3::ZERO],
+        }
+    }
+
+    /// Evaluates `texel` at every texel center, in uv.
+    fn generate(size: (u32, u32), texel: impl Fn(Vec2) -> Vec3) -> Self {
+        let texels = (0..size.1)
+            .flat_map(|y| (0..size.0).map(move |x| Vec2::new(x as f32, y as f32)))
+            .map(|id| {
+                let uv = (id + 0.5) / Vec2::new(size.0 as f32, size.1 as f32);
+                texel(uv).map(|channel| half::f16::from_f32(channel).to_f32())
+            })
+            .collect();
+        Self { size, texels }
+    }
+
+    fn texel(&self, x: i32, y: i32) -> Vec3 {
+        let x = x.clamp(0, self.size.0 as i32 - 1) as usize;
+        let y = y.clamp(0, self.size.1 as i32 - 1) as usize;
+        self.texels[y * self.size.0 as usize + x]
+    }
+
+    fn sample(&self, uv: Vec2) -> Vec3 {
+        let coord = uv * Vec2::new(self.size.0 as f32, self.size.1 as f32) - 0.5;
+        let base = coord.floor();
+        let t = coord - base;
+        let (x, y) = (base.x as i32, base.y as i32);
+        let top = self.texel(x, y).lerp(self.texel(x + 1, y), t.x);
+        let bottom = self.texel(x, y + 1).lerp(self.texel(x + 1, y + 1), t.x);
+        top.lerp(bottom, t.y)
+    }
+}
+
+impl CpuAtmosphere {
+    pub fn new(params: &AtmosphereParams, sun_dir: Vec3) -> Self {
+        // WGSL evaluates the constants as abstract floats, so compute them in f64
+        let sun_angular_radius = (0.53f64 / 2.0).to_radians() * 10.0;
+        let sun_solid_angle = 2.0 * std::f64::consts::PI * (1.0 - sun_angular_radius.cos());
+        let mut atmosphere = Self {
+            params: params.to_gpu(),
+            sun_angular_radius: sun_angular_radius as f32,
+            sun_solid_angle: sun_solid_angle as f32,
+            transmittance: CpuLut::empty(),
+            multi_scattering: CpuLut::empty(),
+            sky_view: CpuLut::empty(),
+        };
+        atmosphere.transmittance = CpuLut::generate(TRANSMITTANCE_LUT_SIZE, |uv| {
+            atmosphere.transmittance_texel(uv)
+        });
+        atmosphere.multi_scattering = CpuLut::generate(MULTI_SCATTERING_LUT_SIZE, |uv| {
+            atmosphere.multi_scattering_texel(uv)
+        });
+        atmosphere.sky_view = CpuLut::generate(SKY_VIEW_LUT_SIZE, |uv| {
+            atmosphere.sky_view_texel(uv, sun_dir)
+        });
+        atmosphere
+    }
+
+    pub fn params(&self) -> &GpuAtmosphere {
+        &self.params
+    }
+
+    fn sample_medium(&self, height: f32) -> Medium {
+        let a = &self.params;
+        let h = height.max(0.0);
+        let rayleigh = Vec3::from_array(a.rayleigh_scattering) * (-h / a.rayleigh_height).exp();
+        let mie = Vec3::from_array(a.mie_scattering) * (-h / a.mie_height).exp();
+        let ozone = Vec3::from_array(a.ozone_absorption) * (-h / a.ozone_height).exp();
+        let extinction = rayleigh + MIE_EXTINCTION_RATIO as f32 * mie + ozone;
+        Medium {
+            rayleigh,
+            mie,
+            extinction,
+        }
+    }
+
+    fn mie_phase(&self, cos_theta: f32) -> f32 {
+        let g = self.params.mie_g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom.powf(1.5))
+    }
+
+    pub fn distance_to_ground(&self, height: f32, mu: f32) -> f32 {
+        let bottom = self.params.bottom_radius;
+        let r = bottom + height;
+        let discriminant = r * r * mu * mu - height * (2.0 * bottom + height);
+        if mu >= 0.0 || discriminant < 0.0 {
+            return -1.0;
+        }
+        -r * mu - discriminant.sqrt()
+    }
+
+    pub fn distance_to_top(&self, height: f32, mu: f32) -> f32 {
+        let r = self.params.bottom_radius + height;
+        let top = self.params.top_radius;
+        let discriminant = r * r * (mu * mu - 1.0) + top * top;
+        (-r * mu + discriminant.max(0.0).sqrt()).max(0.0)
+    }
+
+    fn height_along_ray(&self, height: f32, mu: f32, t: f32) -> f32 {
+        let bottom = self.params.bottom_radius;
+        let rho_squared =
+            height * (2.0 * bottom + height) + t * t + 2.0 * (bottom + height) * mu * t;
+        let clamped = rho_squared.max(0.0);
+        clamped / ((bottom * bottom + clamped).sqrt() + bottom)
+    }
+
+    fn sun_zenith_along_ray(
+        &self,
+        height: f32,
+        sun_mu: f32,
+        cos_view_sun: f32,
+        t: f32,
+        height_at_t: f32,
+    ) -> f32 {
+        let r = self.params.bottom_radius + height;
+        ((r * sun_mu + t * cos_view_sun) / (self.params.bottom_radius + height_at_t))
+            .clamp(-1.0, 1.0)
+    }
+
+    fn transmittance_uv(&self, height: f32, mu: f32) -> Vec2 {
+        let bottom = self.params.bottom_radius;
+        let top = self.params.top_radius;
+        let horizon = (top * top - bottom * bottom).sqrt();
+        let rho = (height * (2.0 * bottom + height)).max(0.0).sqrt();
+        let d_min = top - (bottom + height);
+        let d_max = rho + horizon;
+        let d = self.distance_to_top(height, mu);
+        Vec2::new((d - d_min) / (d_max - d_min).max(1e-6), rho / horizon)
+    }
+
+    fn transmittance_lut_params(&self, uv: Vec2) -> (f32, f32) {
+        let bottom = self.params.bottom_radius;
+        let top = self.params.top_radius;
+        let horizon = (top * top - bottom * bottom).sqrt();
+        let rho = horizon * uv.y;
+        let height = rho * rho / ((rho * rho + bottom * bottom).sqrt() + bottom);
+        let r = bottom + height;
+        let d_min = top - r;
+        let d_max = rho + horizon;
+        let d = d_min + uv.x * (d_max - d_min);
+        let mut mu = 1.0;
+        if d > 0.0 {
+            mu = ((horizon * horizon - rho * rho - d * d) / (2.0 * r * d)).clamp(-1.0, 1.0);
+        }
+        (height, mu)
+    }
+
+    /// Light scattered more than once at `height`, for a sun of unit illuminance.
+    pub fn multi_scattering(&self, height: f32, sun_mu: f32) -> Vec3 {
+        let a = &self.params;
+        let uv = Vec2::new(
+            sun_mu * 0.5 + 0.5,
+            height / (a.top_radius - a.bottom_radius),
+        );
+        self.multi_scattering.sample(uv)
+    }
+
+    /// Transmittance from `height` to the top of the atmosphere, ignoring the ground.
+    pub fn transmittance_to_top(&self, height: f32, mu: f32) -> Vec3 {
+        self.transmittance.sample(self.transmittance_uv(height, mu))
+    }
+
+    fn sun_transmittance(&self, height: f32, mu: f32) -> Vec3 {
+        if self.distance_to_ground(height, mu) >= 0.0 {
+            return Vec3::ZERO;
+        }
+        self.transmittance_to_top(height, mu)
+    }
+
+    fn horizon_dip(&self, height: f32) -> f32 {
+        let bottom = self.params.bottom_radius;
+        ((height * (2.0 * bottom + height)).sqrt() / bottom).atan()
+    }
+
+    fn sky_view_uv(&self, direction: Vec3, sun_dir: Vec3) -> Vec2 {
+        let altitude =
+            direction.y.clamp(-1.0, 1.0).asin() + self.horizon_dip(self.params.view_height);
+        let v = 0.5 + 0.5 * sign(altitude) * (altitude.abs() * 2.0 / PI).min(1.0).sqrt();
+
+        let mut u = 0.0;
+        let horizontal = direction.xz();
+        let sun_horizontal = sun_dir.xz();
+        if horizontal.dot(horizontal) > 1e-10 && sun_horizontal.dot(sun_horizontal) > 1e-10 {
+            let cos_azimuth = horizontal.normalize().dot(sun_horizontal.normalize());
+            u = cos_azimuth.clamp(-1.0, 1.0).acos() / PI;
+        }
+        Vec2::new(u, v)
+    }
+
+    /// Radiance of the sun's disk towards `direction`, through the atmosphere.
+    pub fn sun_disk(&self, direction: Vec3, sun_dir: Vec3) -> Vec3 {
+        let radius = self.sun_angular_radius;
+        let angle = direction.dot(sun_dir).clamp(-1.0, 1.0).acos();
+        let disk = 1.0 - smoothstep(radius * 0.95, radius * 1.05, angle);
+        let sun_radiance = Vec3::from_array(self.params.sun_illuminance) / self.sun_solid_angle;
+        disk * sun_radiance * self.transmittance_to_top(self.params.view_height, direction.y)
+    }
+
+    /// Sky radiance towards `direction` from the sky-view table, before the sun's color.
+    pub fn sky_view(&self, direction: Vec3, sun_dir: Vec3) -> Vec3 {
+        self.sky_view.sample(self.sky_view_uv(direction, sun_dir))
+    }
+
+    /// `transmittance_main`
+    fn transmittance_texel(&self, uv: Vec2) -> Vec3 {
+        let (height, mu) = self.transmittance_lut_params(uv);
+        let step = self.distance_to_top(height, mu) / TRANSMITTANCE_STEPS;
+        let mut optical_depth = Vec3::ZERO;
+        let mut i = 0.0;
+        while i < TRANSMITTANCE_STEPS {
+            let sample_height = self.height_along_ray(height, mu, (i + 0.5) * step);
+            optical_depth += self.sample_medium(sample_height).extinction * step;
+            i += 1.0;
+        }
+        exp3(-optical_depth)
+    }
+
+    /// `multi_scattering_main`
+    fn multi_scattering_texel(&self, uv: Vec2) -> Vec3 {
+        let a = &self.params;
+        let sun_mu = uv.x * 2.0 - 1.0;
+        let height = uv.y * (a.top_radius - a.bottom_radius);
+        let sun_horizontal = (1.0 - sun_mu * sun_mu).max(0.0).sqrt();
+        let isotropic_phase = 1.0 / (4.0 * PI);
+
+        let mut second_order = Vec3::ZERO;
+        let mut transfer = Vec3::ZERO;
+        let directions = MULTI_SCATTERING_DIRECTIONS;
+        for i in 0..directions {
+            let mu = 1.0 - 2.0 * (i as f32 + 0.5) / directions as f32;
+            let horizontal = (1.0 - mu * mu).max(0.0).sqrt();
+            for j in 0..directions {
+                let phi = 2.0 * PI * (j as f32 + 0.5) / directions as f32;
+                let cos_view_sun = horizontal * phi.cos() * sun_horizontal + mu * sun_mu;
+
+                let ground = self.distance_to_ground(height, mu);
+                let distance = if ground >= 0.0 {
+                    ground
+                } else {
+                    self.distance_to_top(height, mu)
+                };
+                let step = distance / MULTI_SCATTERING_STEPS;
+
+                let mut throughput = Vec3::ONE;
+                let mut luminance = Vec3::ZERO;
+                let mut scattered = Vec3::ZERO;
+                let mut s = 0.0;
+                while s < MULTI_SCATTERING_STEPS {
+                    let t = (s + SAMPLE_OFFSET) * step;
+                    let sample_height = self.height_along_ray(height, mu, t);
+                    let medium = self.sample_medium(sample_height);
+                    let step_transmittance = exp3(-medium.extinction * step);
+                    let sample_sun_mu =
+                        self.sun_zenith_along_ray(height, sun_mu, cos_view_sun, t, sample_height);
+                    let scattering = medium.rayleigh + medium.mie;
+
+                    let sun = self.sun_transmittance(sample_height, sample_sun_mu);
+                    luminance += throughput
+                        * integrate_step(
+                            scattering * sun * isotropic_phase,
+                            &medium,
+                            step_transmittance,
+                        );
+                    scattered +=
+                        throughput * integrate_step(scattering, &medium, step_transmittance);
+                    throughput *= step_transmittance;
+                    s += 1.0;
+                }
+
+                if ground >= 0.0 {
+                    let ground_sun_mu =
+                        self.sun_zenith_along_ray(height, sun_mu, cos_view_sun, ground, 0.0);
+                    let irradiance =
+                        self.transmittance_to_top(0.0, ground_sun_mu) * ground_sun_mu.max(0.0);
+                    luminance += throughput * irradiance * Vec3::from_array(a.ground_albedo) / PI;
+                }
+
+                second_order += luminance;
+                transfer += scattered;
+            }
+        }
+        let count = (directions * directions) as f32;
+        (second_order / count) / (1.0 - transfer / count)
+    }
+
+    /// `sky_view_main`
+    fn sky_view_texel(&self, uv: Vec2, sun_dir: Vec3) -> Vec3 {
+        let a = &self.params;
+        let height = a.view_height;
+        let sun_mu = sun_dir.normalize().y.clamp(-1.0, 1.0);
+        let sun_horizontal = (1.0 - sun_mu * sun_mu).max(0.0).sqrt();
+
+        let azimuth = uv.x * PI;
+        let centered = uv.y * 2.0 - 1.0;
+        let elevation = sign(centered) * centered * centered * PI / 2.0 - self.horizon_dip(height);
+        let mu = elevation.sin();
+        let cos_view_sun = elevation.cos() * azimuth.cos() * sun_horizontal + mu * sun_mu;
+        let rayleigh_phase_value = rayleigh_phase(cos_view_sun);
+        let mie_phase_value = self.mie_phase(cos_view_sun);
+
+        let ground = self.distance_to_ground(height, mu);
+        let distance = if ground >= 0.0 {
+            ground
+        } else {
+            self.distance_to_top(height, mu)
+        };
+        let step = distance / SKY_VIEW_STEPS;
+
+        let mut throughput = Vec3::ONE;
+        let mut luminance = Vec3::ZERO;
+        let mut s = 0.0;
+        while s < SKY_VIEW_STEPS {
+            let t = (s + SAMPLE_OFFSET) * step;
+            let sample_height = self.height_along_ray(height, mu, t);
+            let medium = self.sample_medium(sample_height);
+            let step_transmittance = exp3(-medium.extinction * step);
+            let sample_sun_mu =
+                self.sun_zenith_along_ray(height, sun_mu, cos_view_sun, t, sample_height);
+
+            let sun = self.sun_transmittance(sample_height, sample_sun_mu);
+            let multi = self.multi_scattering(sample_height, sample_sun_mu);
+            let source = medium.rayleigh * (rayleigh_phase_value * sun + multi)
+                + medium.mie * (mie_phase_value * sun + multi);
+
+            luminance += throughput * integrate_step(source, &medium, step_transmittance);
+            throughput *= step_transmittance;
+            s += 1.0;
+        }
+        luminance * Vec3::from_array(a.sun_illuminance)
+    }
+}
+
+fn integrate_step(source: Vec3, medium: &Medium, step_transmittance: Vec3) -> Vec3 {
+    (source - source * step_transmittance) / medium.extinction.max(Vec3::splat(1e-6))
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    (3.0 / (16.0 * PI)) * (1.0 + cos_theta * cos_theta)
+}
+
+/// WGSL's `sign`, 0 at 0 unlike [`f32::signum`].
+fn sign(x: f32) -> f32 {
+    if x == 0.0 { 0.0 } else { x.signum() }
+}
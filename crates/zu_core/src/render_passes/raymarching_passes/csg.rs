@@ -0,0 +1,122 @@
+//! Boolean operations between scene objects, `csg_distance` / `csg_weight` in
+//! `raymarching_compute.wgsl`.
+//!
+//! Objects are folded in list order: each one combines with the result of every object before
+//! it, so a subtraction only carves the objects listed above it.
+
+use egui::{Response, Ui};
+use egui_probe::Style;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum CsgOperation {
+    Union = 0,
+    Subtraction = 1,
+    Intersection = 2,
+    SmoothUnion = 3,
+    SmoothSubtraction = 4,
+    SmoothIntersection = 5,
+}
+
+impl CsgOperation {
+    pub const ALL: [CsgOperation; 6] = [
+        CsgOperation::Union,
+        CsgOperation::Subtraction,
+        CsgOperation::Intersection,
+        CsgOperation::SmoothUnion,
+        CsgOperation::SmoothSubtraction,
+        CsgOperation::SmoothIntersection,
+    ];
+
+    /// Unknown ids fall back to a union, like the shader's default case.
+    pub fn from_id(id: u32) -> Self {
+        Self::ALL
+            .get(id as usize)
+            .copied()
+            .unwrap_or(CsgOperation::Union)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CsgOperation::Union => "Union",
+            CsgOperation::Subtraction => "Subtraction",
+            CsgOperation::Intersection => "Intersection",
+            CsgOperation::SmoothUnion => "Smooth union",
+            CsgOperation::SmoothSubtraction => "Smooth subtraction",
+            CsgOperation::SmoothIntersection => "Smooth intersection",
+        }
+    }
+
+    /// Combines the accumulated distance `a` with an object's distance `b`.
+    pub fn distance(self, a: f32, b: f32, blend_radius: f32) -> f32 {
+        let k = blend_radius.max(MIN_BLEND_RADIUS);
+        match self {
+            CsgOperation::Union => a.min(b),
+            CsgOperation::Subtraction => a.max(-b),
+            CsgOperation::Intersection => a.max(b),
+            CsgOperation::SmoothUnion => {
+                let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+                mix(b, a, h) - k * h * (1.0 - h)
+            }
+            CsgOperation::SmoothSubtraction => {
+                let h = (0.5 - 0.5 * (a + b) / k).clamp(0.0, 1.0);
+                mix(a, -b, h) + k * h * (1.0 - h)
+            }
+            CsgOperation::SmoothIntersection => {
+                let h = (0.5 - 0.5 * (b - a) / k).clamp(0.0, 1.0);
+                mix(b, a, h) + k * h * (1.0 - h)
+            }
+        }
+    }
+
+    /// How much of the object's material shows on the combined surface, 0 keeps the
+    /// accumulated material. Cut faces of subtractions keep the material they were cut from.
+    pub fn weight(self, a: f32, b: f32, blend_radius: f32) -> f32 {
+        let k = blend_radius.max(MIN_BLEND_RADIUS);
+        match self {
+            CsgOperation::Union => (b < a) as u32 as f32,
+            CsgOperation::Intersection => (b > a) as u32 as f32,
+            CsgOperation::Subtraction | CsgOperation::SmoothSubtraction => 0.0,
+            CsgOperation::SmoothUnion => 1.0 - (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0),
+            CsgOperation::SmoothIntersection => 1.0 - (0.5 - 0.5 * (b - a) / k).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Smooth operations with a zero radius would divide by zero.
+const MIN_BLEND_RADIUS: f32 = 1e-4;
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+pub fn probe_operation(value: &mut u32, ui: &mut Ui, _style: &Style) -> Response {
+    let mut operation = CsgOperation::from_id(*value);
+    let response = egui::ComboBox::from_id_salt(ui.next_auto_id())
+        .selected_text(operation.name())
+        .show_ui(ui, |ui| {
+            for option in CsgOperation::ALL {
+                ui.selectable_value(&mut operation, option, option.name());
+            }
+        })
+        .response;
+    *value = operation as u32;
+    response
+}
+
+/// Stores an operation id by name in scene files, use with `#[serde(with = "operation_name")]`.
+pub mod operation_name {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::CsgOperation;
+
+    pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        CsgOperation::from_id(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        Ok(CsgOperation::deserialize(deserializer)? as u32)
+    }
+}
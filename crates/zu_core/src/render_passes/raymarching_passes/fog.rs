@@ -0,0 +1,63 @@
+//! Exponential height fog, `height_fog` in `raymarching_compute.wgsl`.
+//!
+//! The fog's density falls off exponentially above `height`, so the amount along a view ray
+//! integrates in closed form. It takes its light from the sky at the horizon, distant
+//! geometry fades into the same color as the sky behind it.
+
+use bytemuck::{Pod, Zeroable};
+use egui_probe::EguiProbe;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::probes::probe_color;
+
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeightFog {
+    /// Extinction per scene unit at `height`, 0 disables the fog.
+    #[egui_probe(range = 0.0..=10.0)]
+    pub density: f32,
+    /// Where the fog has its full `density`.
+    #[egui_probe(range = -1000.0..=1000.0)]
+    pub height: f32,
+    /// How quickly the fog thins out above `height`, per scene unit.
+    #[egui_probe(range = 0.001..=10.0)]
+    pub falloff: f32,
+    /// Fraction of the horizon's light the fog scatters towards the camera.
+    #[egui_probe(with probe_color)]
+    pub albedo: Vec3,
+}
+
+impl Default for HeightFog {
+    fn default() -> Self {
+        Self {
+            density: 0.0,
+            height: 0.0,
+            falloff: 0.5,
+            albedo: Vec3::ONE,
+        }
+    }
+}
+
+impl HeightFog {
+    pub fn to_gpu(&self) -> GpuHeightFog {
+        GpuHeightFog {
+            albedo: self.albedo.max(Vec3::ZERO).to_array(),
+            density: self.density.max(0.0),
+            height: self.height,
+            falloff: self.falloff.max(0.001),
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+/// Matches `HeightFog` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuHeightFog {
+    pub albedo: [f32; 3],
+    pub density: f32,
+    pub height: f32,
+    pub falloff: f32,
+    pub _pad: [f32; 2],
+}
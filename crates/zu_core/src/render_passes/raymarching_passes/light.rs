@@ -0,0 +1,163 @@
+//! Punctual and area lights shaded next to the sun, `sample_light` in
+//! `raymarching_compute.wgsl`.
+//!
+//! Every light fades out with the inverse square of the distance, windowed to reach zero at
+//! `range`. Area lights are shaded from the closest point on their surface and widen the
+//! specular lobe by their apparent size, their shadows get softer penumbrae.
+
+use std::f32::consts::PI;
+
+use bytemuck::{Pod, Zeroable};
+use egui::{Response, Ui};
+use egui_probe::{EguiProbe, Style};
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::probes::{probe_color, probe_vec2, probe_vec3};
+
+/// Narrowest spot cone edge in degrees. The shader's smoothstep is undefined for equal edges,
+/// so a spot with `inner_angle == outer_angle` gets this one.
+pub const MIN_SPOT_EDGE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum LightKind {
+    #[default]
+    Point = 0,
+    Spot = 1,
+    /// Sphere of radius `size.x`.
+    Sphere = 2,
+    /// One sided rectangle facing `direction`, `size` is its half width and half height.
+    Rect = 3,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 4] = [
+        LightKind::Point,
+        LightKind::Spot,
+        LightKind::Sphere,
+        LightKind::Rect,
+    ];
+
+    /// Unknown ids fall back to a point light, like the shader's default case.
+    pub fn from_id(id: u32) -> Self {
+        Self::ALL.get(id as usize).copied().unwrap_or_default()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LightKind::Point => "Point",
+            LightKind::Spot => "Spot",
+            LightKind::Sphere => "Sphere area",
+            LightKind::Rect => "Rectangle area",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Light {
+    #[egui_probe(with probe_kind)]
+    pub kind: LightKind,
+    #[egui_probe(with probe_vec3)]
+    pub position: Vec3,
+    /// Axis of spot lights and the side rectangles emit from.
+    #[egui_probe(with probe_vec3)]
+    pub direction: Vec3,
+    #[egui_probe(with probe_color)]
+    pub color: Vec3,
+    #[egui_probe(range = 0.0..=1000.0)]
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely.
+    #[egui_probe(range = 0.01..=1000.0)]
+    pub range: f32,
+    /// Spot cone in degrees from the axis, full intensity inside `inner_angle`.
+    #[egui_probe(range = 0.0..=90.0)]
+    pub inner_angle: f32,
+    #[egui_probe(range = 0.0..=90.0)]
+    pub outer_angle: f32,
+    /// Sphere radius in `x`, or the rectangle's half width and half height.
+    #[egui_probe(with probe_vec2)]
+    pub size: Vec2,
+    /// Traces an SDF soft shadow towards the light.
+    pub shadows: bool,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Point,
+            position: Vec3::new(0.0, 1.0, 0.0),
+            direction: Vec3::NEG_Y,
+            color: Vec3::ONE,
+            intensity: 5.0,
+            range: 10.0,
+            inner_angle: 20.0,
+            outer_angle: 30.0,
+            size: Vec2::splat(0.25),
+            shadows: true,
+        }
+    }
+}
+
+impl Light {
+    pub fn point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_kind(mut self, kind: LightKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn to_gpu(&self) -> GpuLight {
+        let outer = self.outer_angle.clamp(MIN_SPOT_EDGE, 90.0);
+        let inner = self.inner_angle.clamp(0.0, outer - MIN_SPOT_EDGE);
+        GpuLight {
+            position: self.position.to_array(),
+            kind: self.kind as u32,
+            direction: self.direction.normalize_or(Vec3::NEG_Y).to_array(),
+            range: self.range.max(0.01),
+            radiance: (self.color * self.intensity).to_array(),
+            shadows: self.shadows as u32,
+            cos_inner: (inner * PI / 180.0).cos(),
+            cos_outer: (outer * PI / 180.0).cos(),
+            size: self.size.max(Vec2::ZERO).to_array(),
+        }
+    }
+}
+
+/// Matches `Light` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuLight {
+    pub position: [f32; 3],
+    /// A [`LightKind`] id.
+    pub kind: u32,
+    /// Normalized.
+    pub direction: [f32; 3],
+    pub range: f32,
+    /// Color times intensity.
+    pub radiance: [f32; 3],
+    pub shadows: u32,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    pub size: [f32; 2],
+}
+
+fn probe_kind(value: &mut LightKind, ui: &mut Ui, _style: &Style) -> Response {
+    egui::ComboBox::from_id_salt(ui.next_auto_id())
+        .selected_text(value.name())
+        .show_ui(ui, |ui| {
+            for kind in LightKind::ALL {
+                ui.selectable_value(value, kind, kind.name());
+            }
+        })
+        .response
+}
@@ -0,0 +1,239 @@
+//! Material table indexed by `RaymarchingObject::material`, `material_surface` in
+//! `raymarching_compute.wgsl`.
+//!
+//! Indices outside the table (including the `-1` of empty space) shade with
+//! [`MISSING_MATERIAL`]. The ground always uses material 0.
+
+use bytemuck::{Pod, Zeroable};
+use egui::{Response, Ui};
+use egui_probe::{EguiProbe, Style};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::probes::probe_color;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+/// Procedural pattern multiplied into a material's albedo.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum Pattern {
+    #[default]
+    None = 0,
+    /// Random color per point, the old hard-coded material 2.
+    Noise = 1,
+    /// Half unit 3D checkerboard.
+    Checker = 2,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 3] = [Pattern::None, Pattern::Noise, Pattern::Checker];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::None => "None",
+            Pattern::Noise => "Noise",
+            Pattern::Checker => "Checker",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    pub name: String,
+    #[egui_probe(with probe_color)]
+    pub albedo: Vec3,
+    #[egui_probe(range = 0.0..=1.0)]
+    pub roughness: f32,
+    #[egui_probe(range = 0.0..=1.0)]
+    pub metallic: f32,
+    #[egui_probe(with probe_color)]
+    pub emissive: Vec3,
+    /// Multiplies `emissive`, so surfaces can glow brighter than white.
+    #[egui_probe(range = 0.0..=100.0)]
+    pub emissive_strength: f32,
+    #[egui_probe(with probe_pattern)]
+    pub pattern: Pattern,
+    /// Share of the light refracted into the shape instead of scattering off its surface, 1 for
+    /// glass and water. Only closed shapes have an inside to pass through.
+    #[egui_probe(range = 0.0..=1.0)]
+    pub transmission: f32,
+    /// Index of refraction, 1.33 for water and 1.5 for glass. Also sets how much non-metals
+    /// reflect.
+    #[egui_probe(range = 1.0..=3.0)]
+    pub ior: f32,
+    /// Light absorbed per unit travelled inside transmissive shapes, tints thick glass and
+    /// deep water.
+    #[egui_probe(with probe_absorption)]
+    pub absorption: Vec3,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: "Material".into(),
+            albedo: Vec3::ONE,
+            roughness: 1.0,
+            metallic: 0.0,
+            emissive: Vec3::ONE,
+            emissive_strength: 0.0,
+            pattern: Pattern::None,
+            transmission: 0.0,
+            ior: 1.5,
+            absorption: Vec3::ZERO,
+        }
+    }
+}
+
+/// Shades indices outside the material table, red so unassigned objects stand out.
+pub const MISSING_MATERIAL: GpuMaterial = GpuMaterial {
+    albedo: [1.0, 0.0, 0.0],
+    roughness: 0.5,
+    emissive: [0.0; 3],
+    metallic: 0.0,
+    absorption: [0.0; 3],
+    ior: 1.5,
+    pattern: Pattern::None as u32,
+    transmission: 0.0,
+    _pad: [0; 2],
+};
+
+impl Material {
+    pub fn new(name: &str, albedo: Vec3, roughness: f32) -> Self {
+        Self {
+            name: name.into(),
+            albedo,
+            roughness,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Polished metal, a perfect mirror.
+    pub fn chrome() -> Self {
+        Self {
+            metallic: 1.0,
+            ..Self::new("Chrome", Vec3::new(0.95, 0.93, 0.88), 0.0)
+        }
+    }
+
+    /// Clear glass with a faint green tint where it's thick.
+    pub fn glass() -> Self {
+        Self {
+            transmission: 1.0,
+            absorption: Vec3::new(0.3, 0.05, 0.2),
+            ..Self::new("Glass", Vec3::ONE, 0.0)
+        }
+    }
+
+    /// Water, turning blue-green where it's deep.
+    pub fn water() -> Self {
+        Self {
+            transmission: 1.0,
+            ior: 1.33,
+            absorption: Vec3::new(0.8, 0.25, 0.1),
+            ..Self::new("Water", Vec3::ONE, 0.0)
+        }
+    }
+
+    /// The table new scenes start with, the materials the shader used to hard-code.
+    pub fn defaults() -> Vec<Material> {
+        vec![
+            Material::new("White", Vec3::ONE, 1.0),
+            Material::new("Red", Vec3::X, 0.5),
+            Material::new("Noise", Vec3::ONE, 1.0).with_pattern(Pattern::Noise),
+        ]
+    }
+
+    pub fn to_gpu(&self) -> GpuMaterial {
+        GpuMaterial {
+            albedo: self.albedo.to_array(),
+            roughness: self.roughness,
+            emissive: (self.emissive * self.emissive_strength).to_array(),
+            metallic: self.metallic,
+            absorption: self.absorption.to_array(),
+            ior: self.ior.max(1.0),
+            pattern: self.pattern as u32,
+            transmission: self.transmission,
+            _pad: [0; 2],
+        }
+    }
+}
+
+/// Matches `Material` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuMaterial {
+    pub albedo: [f32; 3],
+    pub roughness: f32,
+    /// Emitted radiance, already scaled by the strength.
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    /// Beer-Lambert coefficients per scene unit.
+    pub absorption: [f32; 3],
+    pub ior: f32,
+    /// A [`Pattern`] id.
+    pub pattern: u32,
+    pub transmission: f32,
+    pub _pad: [u32; 2],
+}
+
+const _: () = assert!(size_of::<GpuMaterial>() == 64);
+
+impl GpuMaterial {
+    pub fn pattern(&self) -> Pattern {
+        Pattern::ALL
+            .get(self.pattern as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Removes material `index` and renumbers the objects' materials to match. Objects that used
+/// it fall back to material 0.
+pub fn remove_material(
+    materials: &mut Vec<Material>,
+    objects: &mut [RaymarchingObject],
+    index: usize,
+) {
+    if index >= materials.len() {
+        return;
+    }
+    materials.remove(index);
+    for object in objects {
+        match object.material.cmp(&(index as i32)) {
+            std::cmp::Ordering::Equal => object.material = 0,
+            std::cmp::Ordering::Greater => object.material -= 1,
+            std::cmp::Ordering::Less => {}
+        }
+    }
+}
+
+fn probe_absorption(value: &mut Vec3, ui: &mut Ui, _style: &Style) -> Response {
+    ui.horizontal(|ui| {
+        for channel in [&mut value.x, &mut value.y, &mut value.z] {
+            ui.add(
+                egui::DragValue::new(channel)
+                    .speed(0.01)
+                    .range(0.0..=f32::INFINITY),
+            );
+        }
+    })
+    .response
+}
+
+fn probe_pattern(value: &mut Pattern, ui: &mut Ui, _style: &Style) -> Response {
+    egui::ComboBox::from_id_salt(ui.next_auto_id())
+        .selected_text(value.name())
+        .show_ui(ui, |ui| {
+            for pattern in Pattern::ALL {
+                ui.selectable_value(value, pattern, pattern.name());
+            }
+        })
+        .response
+}
@@ -1 +1,30 @@
+pub mod ambient_occlusion;
+pub mod atmosphere;
+pub mod camera;
+pub mod cpu_reference;
+pub mod csg;
+pub mod fog;
+pub mod light;
+pub mod material;
+pub mod path_tracing;
+pub mod picking;
+pub mod primitives;
 pub mod raymarching_pass_compute;
+pub mod volumetrics;
+
+/// Source of `shaders/<file_name>` with `atmosphere_common.wgsl` appended. Read at runtime,
+/// resolved against the crate root so headless runs and tests work from any cwd.
+///
+/// Appended rather than prepended: the GL backend looks the push constant types up by handle
+/// in the whole module, so types an entry point doesn't use must not come before them.
+pub(crate) fn read_shader(file_name: &str) -> String {
+    let shaders = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/render_passes/raymarching_passes/shaders"
+    );
+    let read = |name: &str| {
+        std::fs::read_to_string(format!("{shaders}/{name}"))
+            .unwrap_or_else(|err| panic!("Failed to read {name}: {err}"))
+    };
+    format!("{}\n{}", read(file_name), read("atmosphere_common.wgsl"))
+}
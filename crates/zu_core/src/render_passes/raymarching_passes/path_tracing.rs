@@ -0,0 +1,78 @@
+//! Progressive path traced reference, `path_trace_main` in `raymarching_compute.wgsl`.
+//!
+//! Every frame traces one more path per pixel and folds it into the running average in the
+//! "Accumulation" texture, which makes converged images to tune the real-time lighting
+//! against. Any change to the [`RenderOptions`] or the scene time starts the average over,
+//! so the wall clock holds still while path tracing.
+
+use egui_probe::EguiProbe;
+use serde::{Deserialize, Serialize};
+
+use crate::render_passes::render_pass_manager::RenderOptions;
+
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathTracingOptions {
+    /// Replaces the real-time shading with the accumulated reference.
+    pub enabled: bool,
+    /// Bounces after the first hit, 0 traces direct light only.
+    #[egui_probe(range = 0..=16)]
+    pub max_bounces: u32,
+    /// Accumulation stops after this many samples per pixel, 0 never stops.
+    #[egui_probe(range = 0..=65536)]
+    pub max_samples: u32,
+}
+
+impl Default for PathTracingOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bounces: 4,
+            max_samples: 1024,
+        }
+    }
+}
+
+/// Which options and scene time the accumulation texture holds samples of, and how many.
+#[derive(Debug, Default)]
+pub struct Accumulation {
+    options: Option<RenderOptions>,
+    time: Option<f32>,
+    samples: u32,
+}
+
+impl Accumulation {
+    /// Index of the sample to trace this frame, 0 starts a new average. Starts over when
+    /// `options` or `time` differ from the accumulated ones and returns `None` once
+    /// [`PathTracingOptions::max_samples`] are done.
+    pub fn next_sample(&mut self, options: &RenderOptions, time: f32) -> Option<u32> {
+        if self.options.as_ref() != Some(options) || self.time != Some(time) {
+            self.options = Some(options.clone());
+            self.time = Some(time);
+            self.samples = 0;
+        }
+        let max_samples = options.path_tracing.max_samples;
+        if max_samples != 0 && self.samples >= max_samples {
+            return None;
+        }
+        self.samples += 1;
+        Some(self.samples - 1)
+    }
+
+    /// Throws the samples away, for changes outside the options like a resize.
+    pub fn reset(&mut self) {
+        self.options = None;
+        self.time = None;
+        self.samples = 0;
+    }
+
+    /// Scene time of the samples in the average.
+    pub fn time(&self) -> Option<f32> {
+        self.time
+    }
+
+    /// Samples per pixel in the average.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+}
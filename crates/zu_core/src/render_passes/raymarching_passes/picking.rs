@@ -0,0 +1,193 @@
+//! Which object is under a pixel, answered by `pick_main` in `raymarching_compute.wgsl`.
+//!
+//! A pick marches the one pixel's camera ray through the scene the frame renders and writes
+//! the object it hits to a small storage buffer. That is copied into a mappable buffer and
+//! read back without blocking, so the result arrives a frame or a few after the request. The
+//! object index is turned into the object's id with the list that frame rendered, so edits
+//! made while the pick is in flight don't change which object it names.
+
+use std::num::NonZero;
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+use bytemuck::{Pod, Zeroable, bytes_of, pod_read_unaligned};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferAsyncError, BufferUsages, CommandEncoder, Device,
+    Queue,
+};
+
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+/// Matches `Pick` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuPick {
+    pub pixel: [u32; 2],
+    /// Index of the object hit, -1 for the ground, the octree and the sky.
+    pub object: i32,
+    /// Distance from the camera, -1 for the sky.
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickResult {
+    pub pixel: [u32; 2],
+    /// [`RaymarchingObject::id`] of the object hit, `None` where the ground, the octree or the
+    /// sky was hit.
+    pub object: Option<u64>,
+    /// Distance from the camera to the surface hit, `None` for the sky.
+    pub distance: Option<f32>,
+}
+
+impl PickResult {
+    /// `ids` are those of the objects the picking frame rendered, in list order.
+    fn new(pick: GpuPick, ids: &[u64]) -> Self {
+        Self {
+            pixel: pick.pixel,
+            object: usize::try_from(pick.object)
+                .ok()
+                .and_then(|index| ids.get(index).copied()),
+            distance: (pick.distance >= 0.0).then_some(pick.distance),
+        }
+    }
+}
+
+enum PickState {
+    Idle,
+    /// Copied into the readback buffer by a frame that isn't mapped yet.
+    Encoded,
+    Mapping(Receiver<Result<(), BufferAsyncError>>),
+}
+
+/// Pick buffer bound in place of the f32 texture for `pick_main`, and its readback.
+pub struct ObjectPicker {
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    buffer: Buffer,
+    readback: Buffer,
+    requested: Option<[u32; 2]>,
+    /// Ids of the objects the frame running the pick renders.
+    ids: Vec<u64>,
+    state: PickState,
+}
+
+impl ObjectPicker {
+    pub fn new(device: &Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Pick bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZero::new(size_of::<GpuPick>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick"),
+            size: size_of::<GpuPick>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick readback"),
+            size: size_of::<GpuPick>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Pick bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            bind_group_layout,
+            bind_group,
+            buffer,
+            readback,
+            requested: None,
+            ids: Vec::new(),
+            state: PickState::Idle,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Picks `pixel` with the next frame that isn't waiting on an earlier pick. A newer
+    /// request replaces one that hasn't started yet.
+    pub fn request(&mut self, pixel: [u32; 2]) {
+        self.requested = Some(pixel);
+    }
+
+    /// Uploads the requested pixel if a pick can start this frame, which renders `objects`.
+    /// When it returns true the caller dispatches `pick_main` and calls
+    /// [`ObjectPicker::encode_readback`].
+    pub fn begin(&mut self, queue: &Queue, objects: &[RaymarchingObject]) -> bool {
+        if !matches!(self.state, PickState::Idle) {
+            return false;
+        }
+        let Some(pixel) = self.requested.take() else {
+            return false;
+        };
+        let pick = GpuPick {
+            pixel,
+            object: -1,
+            distance: -1.0,
+        };
+        queue.write_buffer(&self.buffer, 0, bytes_of(&pick));
+        self.ids = objects.iter().map(|object| object.id).collect();
+        true
+    }
+
+    pub fn encode_readback(&mut self, encoder: &mut CommandEncoder) {
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback, 0, None);
+        self.state = PickState::Encoded;
+    }
+
+    /// Returns the pick once it's read back, without waiting for the GPU. Call once per frame
+    /// after submitting it.
+    pub fn poll(&mut self, device: &Device) -> Option<PickResult> {
+        if matches!(self.state, PickState::Encoded) {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            self.readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            self.state = PickState::Mapping(receiver);
+        }
+        let PickState::Mapping(receiver) = &self.state else {
+            return None;
+        };
+
+        if let Err(err) = device.poll(wgpu::PollType::Poll) {
+            log::error!("Polling the pick readback failed: {err}");
+        }
+        let result = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Ok(Ok(())) => {
+                let pick: GpuPick = pod_read_unaligned(&self.readback.slice(..).get_mapped_range());
+                self.readback.unmap();
+                Some(PickResult::new(pick, &self.ids))
+            }
+            Ok(Err(err)) => {
+                log::error!("Reading back the pick failed: {err}");
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.state = PickState::Idle;
+        result
+    }
+}
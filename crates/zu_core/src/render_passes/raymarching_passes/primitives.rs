@@ -0,0 +1,276 @@
+//! SDF primitives evaluated by `sd_primitive` in `raymarching_compute.wgsl`.
+//!
+//! Every primitive is centred on its object's position. `Primitive::params` is interpreted
+//! per kind, see [`PrimitiveKind::param_labels`].
+
+use bytemuck::{Pod, Zeroable};
+use egui::{Response, Ui};
+use egui_probe::Style;
+use glam::{Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum PrimitiveKind {
+    Sphere = 0,
+    Box = 1,
+    RoundBox = 2,
+    Torus = 3,
+    Capsule = 4,
+    Cylinder = 5,
+    Cone = 6,
+    Plane = 7,
+    Ellipsoid = 8,
+}
+
+impl PrimitiveKind {
+    pub const ALL: [PrimitiveKind; 9] = [
+        PrimitiveKind::Sphere,
+        PrimitiveKind::Box,
+        PrimitiveKind::RoundBox,
+        PrimitiveKind::Torus,
+        PrimitiveKind::Capsule,
+        PrimitiveKind::Cylinder,
+        PrimitiveKind::Cone,
+        PrimitiveKind::Plane,
+        PrimitiveKind::Ellipsoid,
+    ];
+
+    /// Unknown ids fall back to a sphere, like the shader's default case.
+    pub fn from_id(id: u32) -> Self {
+        Self::ALL
+            .get(id as usize)
+            .copied()
+            .unwrap_or(PrimitiveKind::Sphere)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PrimitiveKind::Sphere => "Sphere",
+            PrimitiveKind::Box => "Box",
+            PrimitiveKind::RoundBox => "Round box",
+            PrimitiveKind::Torus => "Torus",
+            PrimitiveKind::Capsule => "Capsule",
+            PrimitiveKind::Cylinder => "Cylinder",
+            PrimitiveKind::Cone => "Cone",
+            PrimitiveKind::Plane => "Plane",
+            PrimitiveKind::Ellipsoid => "Ellipsoid",
+        }
+    }
+
+    /// Meaning of `params.x`, `.y`, `.z` and `.w`, unused components are left out.
+    pub fn param_labels(self) -> &'static [&'static str] {
+        match self {
+            PrimitiveKind::Sphere => &["radius"],
+            PrimitiveKind::Box => &["half x", "half y", "half z"],
+            PrimitiveKind::RoundBox => &["half x", "half y", "half z", "rounding"],
+            PrimitiveKind::Torus => &["major radius", "minor radius"],
+            PrimitiveKind::Capsule => &["half height", "radius"],
+            PrimitiveKind::Cylinder => &["half height", "radius"],
+            PrimitiveKind::Cone => &["half height", "bottom radius", "top radius"],
+            PrimitiveKind::Plane => &["normal x", "normal y", "normal z", "offset"],
+            PrimitiveKind::Ellipsoid => &["radius x", "radius y", "radius z"],
+        }
+    }
+
+    pub fn default_params(self) -> Vec4 {
+        match self {
+            PrimitiveKind::Sphere => Vec4::new(0.5, 0.0, 0.0, 0.0),
+            PrimitiveKind::Box => Vec4::new(0.5, 0.5, 0.5, 0.0),
+            PrimitiveKind::RoundBox => Vec4::new(0.5, 0.5, 0.5, 0.1),
+            PrimitiveKind::Torus => Vec4::new(0.5, 0.15, 0.0, 0.0),
+            PrimitiveKind::Capsule => Vec4::new(0.3, 0.2, 0.0, 0.0),
+            PrimitiveKind::Cylinder => Vec4::new(0.5, 0.3, 0.0, 0.0),
+            PrimitiveKind::Cone => Vec4::new(0.5, 0.4, 0.0, 0.0),
+            PrimitiveKind::Plane => Vec4::new(0.0, 1.0, 0.0, 0.0),
+            PrimitiveKind::Ellipsoid => Vec4::new(0.6, 0.3, 0.4, 0.0),
+        }
+    }
+}
+
+/// Matches `Primitive` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(from = "PrimitiveFile", into = "PrimitiveFile")]
+pub struct Primitive {
+    pub params: Vec4,
+    /// A [`PrimitiveKind`] id.
+    pub kind: u32,
+    pub _pad: [u32; 3],
+}
+
+const _: () = assert!(size_of::<Primitive>() == 32);
+
+impl Primitive {
+    pub fn new(kind: PrimitiveKind, params: Vec4) -> Self {
+        Self {
+            params,
+            kind: kind as u32,
+            _pad: [0; 3],
+        }
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        Self::new(PrimitiveKind::Sphere, Vec4::new(radius, 0.0, 0.0, 0.0))
+    }
+
+    pub fn kind(&self) -> PrimitiveKind {
+        PrimitiveKind::from_id(self.kind)
+    }
+
+    /// Distance from `p`, relative to the primitive's centre.
+    pub fn distance(&self, p: Vec3) -> f32 {
+        let params = self.params;
+        match self.kind() {
+            PrimitiveKind::Sphere => sd_sphere(p, params.x),
+            PrimitiveKind::Box => sd_box(p, params.xyz()),
+            PrimitiveKind::RoundBox => sd_round_box(p, params.xyz(), params.w),
+            PrimitiveKind::Torus => sd_torus(p, params.xy()),
+            PrimitiveKind::Capsule => sd_capsule(p, params.x, params.y),
+            PrimitiveKind::Cylinder => sd_cylinder(p, params.x, params.y),
+            PrimitiveKind::Cone => sd_capped_cone(p, params.x, params.y, params.z),
+            PrimitiveKind::Plane => sd_plane(p, params.xyz(), params.w),
+            PrimitiveKind::Ellipsoid => sd_ellipsoid(p, params.xyz()),
+        }
+    }
+
+    /// Half size of the box around the surface in local space, `None` for unbounded planes.
+    pub fn half_extents(&self) -> Option<Vec3> {
+        let params = self.params.abs();
+        let half = match self.kind() {
+            PrimitiveKind::Sphere => Vec3::splat(params.x),
+            PrimitiveKind::Box | PrimitiveKind::RoundBox | PrimitiveKind::Ellipsoid => params.xyz(),
+            PrimitiveKind::Torus => Vec3::new(params.x + params.y, params.y, params.x + params.y),
+            PrimitiveKind::Capsule => Vec3::new(params.y, params.x + params.y, params.y),
+            PrimitiveKind::Cylinder => Vec3::new(params.y, params.x, params.y),
+            PrimitiveKind::Cone => {
+                let radius = params.y.max(params.z);
+                Vec3::new(radius, params.x, radius)
+            }
+            PrimitiveKind::Plane => return None,
+        };
+        Some(half)
+    }
+}
+
+/// Scene file form of [`Primitive`], the kind by name and no padding. Missing params take the
+/// kind's defaults.
+#[derive(Serialize, Deserialize)]
+struct PrimitiveFile {
+    kind: PrimitiveKind,
+    params: Option<Vec4>,
+}
+
+impl From<PrimitiveFile> for Primitive {
+    fn from(file: PrimitiveFile) -> Self {
+        Primitive::new(
+            file.kind,
+            file.params.unwrap_or_else(|| file.kind.default_params()),
+        )
+    }
+}
+
+impl From<Primitive> for PrimitiveFile {
+    fn from(primitive: Primitive) -> Self {
+        PrimitiveFile {
+            kind: primitive.kind(),
+            params: Some(primitive.params),
+        }
+    }
+}
+
+impl Default for Primitive {
+    fn default() -> Self {
+        Self::new(
+            PrimitiveKind::Sphere,
+            PrimitiveKind::Sphere.default_params(),
+        )
+    }
+}
+
+/// Kind selector followed by the parameters of the selected kind.
+/// Switching kinds resets the parameters to the new kind's defaults.
+pub fn probe_primitive(value: &mut Primitive, ui: &mut Ui, _style: &Style) -> Response {
+    ui.vertical(|ui| {
+        let mut kind = value.kind();
+        egui::ComboBox::from_id_salt(ui.next_auto_id())
+            .selected_text(kind.name())
+            .show_ui(ui, |ui| {
+                for option in PrimitiveKind::ALL {
+                    ui.selectable_value(&mut kind, option, option.name());
+                }
+            });
+        if kind != value.kind() {
+            *value = Primitive::new(kind, kind.default_params());
+        }
+
+        for (index, label) in kind.param_labels().iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(*label);
+                ui.add(egui::DragValue::new(&mut value.params[index]).speed(0.01));
+            });
+        }
+    })
+    .response
+}
+
+pub fn sd_sphere(p: Vec3, radius: f32) -> f32 {
+    p.length() - radius
+}
+
+pub fn sd_box(p: Vec3, b: Vec3) -> f32 {
+    let q = p.abs() - b;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+}
+
+pub fn sd_round_box(p: Vec3, b: Vec3, r: f32) -> f32 {
+    let q = p.abs() - b + r;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0) - r
+}
+
+pub fn sd_torus(p: Vec3, t: Vec2) -> f32 {
+    Vec2::new(p.xz().length() - t.x, p.y).length() - t.y
+}
+
+/// Vertical capsule, `half_height` is the half length of the inner segment.
+pub fn sd_capsule(p: Vec3, half_height: f32, radius: f32) -> f32 {
+    let q = Vec3::new(p.x, p.y - p.y.clamp(-half_height, half_height), p.z);
+    q.length() - radius
+}
+
+/// Vertical capped cylinder.
+pub fn sd_cylinder(p: Vec3, half_height: f32, radius: f32) -> f32 {
+    let d = Vec2::new(p.xz().length(), p.y).abs() - Vec2::new(radius, half_height);
+    d.x.max(d.y).min(0.0) + d.max(Vec2::ZERO).length()
+}
+
+/// Vertical capped cone, `r1` at the bottom and `r2` at the top.
+pub fn sd_capped_cone(p: Vec3, half_height: f32, r1: f32, r2: f32) -> f32 {
+    let q = Vec2::new(p.xz().length(), p.y);
+    let k1 = Vec2::new(r2, half_height);
+    let k2 = Vec2::new(r2 - r1, 2.0 * half_height);
+    let ca = Vec2::new(
+        q.x - q.x.min(if q.y < 0.0 { r1 } else { r2 }),
+        q.y.abs() - half_height,
+    );
+    let cb = q - k1 + k2 * ((k1 - q).dot(k2) / k2.dot(k2)).clamp(0.0, 1.0);
+    let s = if cb.x < 0.0 && ca.y < 0.0 { -1.0 } else { 1.0 };
+    s * ca.dot(ca).min(cb.dot(cb)).sqrt()
+}
+
+pub fn sd_plane(p: Vec3, normal: Vec3, offset: f32) -> f32 {
+    p.dot(normal.normalize_or(Vec3::Y)) + offset
+}
+
+/// Bound, not exact, so marching takes a few more steps near elongated ellipsoids. Radii are
+/// kept above a small epsilon, a zero radius would divide by zero.
+pub fn sd_ellipsoid(p: Vec3, radii: Vec3) -> f32 {
+    let radii = radii.max(Vec3::splat(1e-4));
+    let k0 = (p / radii).length();
+    let k1 = (p / (radii * radii)).length();
+    if k1 == 0.0 {
+        return -radii.min_element();
+    }
+    k0 * (k0 - 1.0) / k1
+}
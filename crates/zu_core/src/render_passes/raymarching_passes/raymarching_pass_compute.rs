@@ -1,16 +1,43 @@
-use std::{num::NonZero, ops::Range, time::SystemTime};
+use std::{
+    collections::HashSet,
+    f32::consts::PI,
+    num::NonZero,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
 
 use bytemuck::{NoUninit, Pod, Zeroable, bytes_of, cast_slice};
 use egui::{Response, Ui};
 use egui_probe::{EguiProbe, Style};
-use glam::{Vec2, Vec3, Vec3A, Vec4};
+use glam::{EulerRot, Mat4, Quat, Vec3, Vec3A, Vec4};
+use serde::{Deserialize, Serialize};
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, BufferBinding,
-    BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoder, ComputePipelineDescriptor,
-    Device, DynamicOffset, PushConstantRange, Queue, ShaderStages, util::RenderEncoder,
+    BufferBindingType, BufferUsages, CommandEncoder, ComputePipelineDescriptor,
+    Device, DynamicOffset, PushConstantRange, Queue, ShaderStages,
+    util::{BufferInitDescriptor, DeviceExt, RenderEncoder},
 };
 
+use crate::object_grid::{GpuGridCell, GpuObjectGridInfo, ObjectGrid};
+use crate::octree::{GpuOctree, GpuOctreeNode};
+use crate::probes::probe_vec3;
+use crate::render_passes::raymarching_passes::atmosphere::{AtmospherePass, AtmosphereResources};
+use crate::render_passes::raymarching_passes::camera::{Camera, GpuCamera};
+use crate::render_passes::raymarching_passes::csg::{
+    CsgOperation, operation_name, probe_operation,
+};
+use crate::render_passes::raymarching_passes::fog::GpuHeightFog;
+use crate::render_passes::raymarching_passes::light::{GpuLight, Light};
+use crate::render_passes::raymarching_passes::material::{GpuMaterial, Material};
+use crate::render_passes::raymarching_passes::picking::ObjectPicker;
+use crate::render_passes::raymarching_passes::primitives::{Primitive, probe_primitive};
+use crate::render_passes::raymarching_passes::read_shader;
+use crate::render_passes::raymarching_passes::volumetrics::GpuVolumetrics;
+use crate::render_passes::render_pass_manager::RenderOptions;
+use crate::render_passes::storage_buffer::StorageBuffer;
+use crate::scene_graph::SceneGraph;
 use crate::texture_manager::{TextureManager, textures::EngineTexture};
 
 #[repr(C)]
@@ -18,66 +45,258 @@ use crate::texture_manager::{TextureManager, textures::EngineTexture};
 pub struct RaymarchingConstants {
     pub texture_size: [f32; 2],
     pub time: f32,
-    pub rotation: f32,
-
-    pub ray_origin: Vec4,
-
-    pub fov: f32,
     pub objects_count: u32,
-    pub yz_rotation: f32,
-    pub _pad0: f32,
 
     pub sun_dir: Vec4,
     pub sun_color: Vec4,
 
     pub sun_intensity: f32,
     pub exposure: f32,
-    pub _pad1: Vec2,
+    pub lights_count: u32,
+    pub ambient: f32,
+
+    /// Index of the path traced sample, 0 starts a new accumulation.
+    pub sample_index: u32,
+    pub max_bounces: u32,
+    /// Distance field ambient occlusion taps, 0 turns it off.
+    pub ao_samples: u32,
+    pub ao_radius: f32,
+
+    /// Reflection and refraction bounces after the camera ray, 0 traces none.
+    pub bounce_depth: u32,
+    pub materials_count: u32,
+    pub _pad: [u32; 2],
 }
 
-#[repr(C)]
-#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod, EguiProbe)]
+impl RaymarchingConstants {
+    /// Push constants for rendering `options` into a `width` x `height` texture.
+    /// Shared by the compute pass and the CPU reference so both see the same inputs.
+    pub fn new(options: &RenderOptions, width: u32, height: u32, time: f32) -> Self {
+        Self {
+            texture_size: [width as f32, height as f32],
+            time,
+            objects_count: options.raymarching_objects.len() as u32,
+            sun_dir: options.sun_dir.extend(0.0),
+            sun_color: options.sun_color.extend(0.0),
+            sun_intensity: options.sun_intensity,
+            exposure: options.exposure,
+            lights_count: options.lights.len() as u32,
+            ambient: options.ambient,
+            sample_index: 0,
+            max_bounces: options.path_tracing.max_bounces,
+            ao_samples: options.ambient_occlusion.sdf_samples(),
+            ao_radius: options.ambient_occlusion.radius.max(0.01),
+            bounce_depth: options.bounce_depth,
+            materials_count: options.materials.len() as u32,
+            _pad: [0; 2],
+        }
+    }
+}
+
+/// A scene object as edited in the GUI. Flattened into [`GpuRaymarchingObject`]s by
+/// [`crate::scene_graph::SceneGraph`] every frame.
+#[derive(PartialEq, Debug, Clone, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RaymarchingObject {
-    #[egui_probe(with probe_vec4)]
-    pub position: Vec4, //16 bytes
-    pub material: i32, //4 bytes
+    /// Stays with the object when the list is reordered, never handed out twice in a session.
+    #[egui_probe(skip)]
+    pub id: u64,
+    /// Scenes saved before objects had names load them empty, see [`crate::scene::from_toml`].
+    #[serde(default)]
+    pub name: String,
+    /// Translation relative to the parent.
+    #[egui_probe(with probe_vec3)]
+    pub position: Vec3,
+    #[egui_probe(with probe_quat)]
+    pub rotation: Quat,
+    #[egui_probe(with probe_vec3)]
+    pub scale: Vec3,
+    /// Index of the parent object in the object list, -1 for none.
+    pub parent: i32,
+    /// Index into [`RenderOptions::materials`].
+    pub material: i32,
+    #[egui_probe(with probe_primitive)]
+    pub primitive: Primitive,
+    /// A [`CsgOperation`] id, applied against all objects listed before this one.
+    #[egui_probe(with probe_operation)]
+    #[serde(with = "operation_name")]
+    pub operation: u32,
+    /// Blend radius of the smooth operations.
+    #[egui_probe(range = 0.0..=2.0)]
+    pub blend_radius: f32,
+    /// Left out of the render with its children, toggled in the outliner.
     #[egui_probe(skip)]
-    pub _pad0: [f32; 3],
+    pub hidden: bool,
+    /// While any object is soloed only those and their children render. Not saved.
     #[egui_probe(skip)]
-    pub _pad1: [f32; 4],
+    #[serde(skip)]
+    pub solo: bool,
 }
 
-fn probe_vec4(value: &mut Vec4, ui: &mut Ui, _style: &Style) -> Response {
-    ui.horizontal(|ui| {
-        ui.add(egui::DragValue::new(&mut value.x).speed(0.01));
-        ui.add(egui::DragValue::new(&mut value.y).speed(0.01));
-        ui.add(egui::DragValue::new(&mut value.z).speed(0.01));
-        ui.add(egui::DragValue::new(&mut value.w).speed(0.01));
-    })
-    .response
+/// Smallest scale magnitude a transform is built with, a zero scale has no inverse.
+pub const MIN_SCALE: f32 = 0.01;
+
+static NEXT_OBJECT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// An object id not handed out before.
+pub fn next_object_id() -> u64 {
+    NEXT_OBJECT_ID.fetch_add(1, Ordering::Relaxed)
 }
 
-impl Default for RaymarchingObject {
-    fn default() -> Self {
+/// Keeps the ids handed out later above the ones in `objects`, and gives objects sharing an id
+/// a new one. Loaded scenes go through this.
+pub fn claim_object_ids(objects: &mut [RaymarchingObject]) {
+    let max = objects.iter().map(|object| object.id).max().unwrap_or(0);
+    NEXT_OBJECT_ID.fetch_max(max + 1, Ordering::Relaxed);
+    let mut seen = HashSet::new();
+    for object in objects {
+        if !seen.insert(object.id) {
+            object.id = next_object_id();
+        }
+    }
+}
+
+/// Matches `RaymarchingObject` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(PartialEq, Debug, Clone, Copy, Zeroable, Pod)]
+pub struct GpuRaymarchingObject {
+    /// Inverse of the object's world transform.
+    pub world_to_local: Mat4, //64 bytes
+    pub primitive: Primitive, //32 bytes
+    pub material: i32,        //4 bytes
+    pub operation: u32,       //4 bytes
+    pub blend_radius: f32,    //4 bytes
+    /// Smallest world scale factor, local distances times this stay a lower bound.
+    pub scale_bound: f32, //4 bytes
+}
+
+const _: () = assert!(size_of::<GpuRaymarchingObject>() == 112);
+
+impl RaymarchingObject {
+    pub fn new(position: Vec3, primitive: Primitive, material: i32) -> Self {
         Self {
-            position: Vec4::new(0.0, 0.0, 0.0, 0.5),
-            material: 0,
-            _pad0: [0.0, 0.0, 0.0],
-            _pad1: [0.0, 0.0, 0.0, 0.0],
+            id: next_object_id(),
+            name: primitive.kind().name().to_owned(),
+            position,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+            parent: -1,
+            material,
+            primitive,
+            operation: CsgOperation::Union as u32,
+            blend_radius: 0.0,
+            hidden: false,
+            solo: false,
         }
     }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_transform(mut self, rotation: Quat, scale: Vec3) -> Self {
+        self.rotation = rotation;
+        self.scale = scale;
+        self
+    }
+
+    /// Makes this object a child of the object at `parent` in the object list.
+    pub fn with_parent(mut self, parent: usize) -> Self {
+        self.parent = parent as i32;
+        self
+    }
+
+    /// Transform relative to the parent, with [`Self::clamped_scale`].
+    pub fn local_transform(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.clamped_scale(), self.rotation, self.position)
+    }
+
+    /// `scale` with every component kept at least [`MIN_SCALE`] away from zero, signs intact.
+    /// The probe and scene files can set any scale.
+    pub fn clamped_scale(&self) -> Vec3 {
+        let magnitude = self.scale.abs().max(Vec3::splat(MIN_SCALE));
+        Vec3::select(self.scale.cmplt(Vec3::ZERO), -magnitude, magnitude)
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        usize::try_from(self.parent).ok()
+    }
+
+    /// Combines this object with the ones before it using `operation`.
+    pub fn with_operation(mut self, operation: CsgOperation, blend_radius: f32) -> Self {
+        self.operation = operation as u32;
+        self.blend_radius = blend_radius;
+        self
+    }
+
+    pub fn operation(&self) -> CsgOperation {
+        CsgOperation::from_id(self.operation)
+    }
+
+    pub fn sphere(position: Vec3, radius: f32, material: i32) -> Self {
+        Self::new(position, Primitive::sphere(radius), material)
+    }
+}
+
+/// Rotation edited as XYZ euler angles in degrees.
+pub fn probe_quat(value: &mut Quat, ui: &mut Ui, _style: &Style) -> Response {
+    let (x, y, z) = value.to_euler(EulerRot::XYZ);
+    let mut degrees = Vec3::new(x, y, z) * (180.0 / PI);
+    let mut changed = false;
+    let mut response = ui
+        .horizontal(|ui| {
+            for angle in [&mut degrees.x, &mut degrees.y, &mut degrees.z] {
+                changed |= ui
+                    .add(egui::DragValue::new(angle).speed(0.5).suffix("°"))
+                    .changed();
+            }
+        })
+        .response;
+    if changed {
+        let radians = degrees * (PI / 180.0);
+        *value = Quat::from_euler(EulerRot::XYZ, radians.x, radians.y, radians.z);
+        response.mark_changed();
+    }
+    response
+}
+
+impl Default for RaymarchingObject {
+    fn default() -> Self {
+        Self::new(Vec3::ZERO, Primitive::default(), 0)
+    }
 }
 
 pub struct RaymarchingRenderComputePass {
     compute_pipeline: wgpu::ComputePipeline,
+    path_tracing_pipeline: wgpu::ComputePipeline,
+    pick_pipeline: wgpu::ComputePipeline,
     current_time: SystemTime,
+    time_override: Option<f32>,
+    storage_bind_group_layout: wgpu::BindGroupLayout,
     storage_bind_group: BindGroup,
-    storage_buffer: Buffer,
-    objects_count: usize,
+    objects: StorageBuffer<GpuRaymarchingObject>,
+    grid_info: Buffer,
+    grid_cells: StorageBuffer<GpuGridCell>,
+    grid_indices: StorageBuffer<u32>,
+    materials: StorageBuffer<GpuMaterial>,
+    lights: StorageBuffer<GpuLight>,
+    fog: Buffer,
+    atmosphere: AtmosphereResources,
+    volumetrics: Buffer,
+    camera: Buffer,
+    octree_bind_group_layout: wgpu::BindGroupLayout,
+    octree_bind_group: BindGroup,
 }
 
 impl RaymarchingRenderComputePass {
-    pub fn new(device: &Device, queue: &Queue, texture_manager: &mut TextureManager) -> Self {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &mut TextureManager,
+        atmosphere: &AtmospherePass,
+        picker: &ObjectPicker,
+    ) -> Self {
         println!("{}", std::mem::size_of::<Vec3>());
         println!("{}", std::mem::align_of::<Vec3>());
         println!("{}", std::mem::size_of::<RaymarchingConstants>());
@@ -85,64 +304,119 @@ impl RaymarchingRenderComputePass {
         println!("{}", std::mem::size_of::<[f32; 4]>());
         println!("{}", std::mem::align_of::<[f32; 4]>());
 
-        use std::fs;
-
-        let source = fs::read_to_string(
-            "crates/zu_core/src/render_passes/raymarching_passes/shaders/raymarching_compute.wgsl",
-        )
-        .expect("Failed to read raymarching_compute.wgsl");
+        let source = read_shader("raymarching_compute.wgsl");
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Raymarching compute shader"),
             source: wgpu::ShaderSource::Wgsl(source.into()),
         });
 
+        let buffer_entry = |binding, ty, min_binding_size: u64| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: NonZero::new(min_binding_size),
+            },
+            count: None,
+        };
+        let storage = BufferBindingType::Storage { read_only: true };
+        let mut storage_entries = vec![
+            buffer_entry(0, storage, size_of::<GpuRaymarchingObject>() as u64),
+            buffer_entry(
+                1,
+                BufferBindingType::Uniform,
+                size_of::<GpuObjectGridInfo>() as u64,
+            ),
+            buffer_entry(2, storage, size_of::<GpuGridCell>() as u64),
+            buffer_entry(3, storage, size_of::<u32>() as u64),
+            buffer_entry(4, storage, size_of::<GpuMaterial>() as u64),
+            buffer_entry(5, storage, size_of::<GpuLight>() as u64),
+            buffer_entry(
+                6,
+                BufferBindingType::Uniform,
+                size_of::<GpuHeightFog>() as u64,
+            ),
+        ];
+        storage_entries.extend(AtmosphereResources::layout_entries(7));
+        storage_entries.push(buffer_entry(
+            12,
+            BufferBindingType::Uniform,
+            size_of::<GpuVolumetrics>() as u64,
+        ));
+        storage_entries.push(buffer_entry(
+            13,
+            BufferBindingType::Uniform,
+            size_of::<GpuCamera>() as u64,
+        ));
         let storage_bind_group_layout =
             device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: Some("Raymarching objects bind group layout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::COMPUTE,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: NonZero::new(size_of::<RaymarchingObject>() as u64),
-                    },
-                    count: None,
-                }],
+                entries: &storage_entries,
             });
 
-        let storage_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("Raymarching objects"),
-            size: (size_of::<RaymarchingObject>() * 256) as u64,
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
+        let mut objects = StorageBuffer::new(device, "Raymarching objects", 256);
+        objects.write(device, queue, &[GpuRaymarchingObject::zeroed()]);
+        let grid_info = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raymarching object grid info"),
+            contents: bytes_of(&GpuObjectGridInfo::default()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
-
-        queue.write_buffer(
-            &storage_buffer,
-            0,
-            bytes_of(&[RaymarchingObject {
-                position: Vec4::ZERO,
-                material: 0,
-                _pad0: [0.0, 0.0, 0.0],
-                _pad1: [0.0, 0.0, 0.0, 0.0],
-            }]),
+        let grid_cells = StorageBuffer::new(device, "Raymarching object grid cells", 256);
+        let grid_indices = StorageBuffer::new(device, "Raymarching object grid indices", 1024);
+        let materials = StorageBuffer::new(device, "Raymarching materials", 64);
+        let lights = StorageBuffer::new(device, "Raymarching lights", 16);
+        let fog = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raymarching height fog"),
+            contents: bytes_of(&GpuHeightFog::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let volumetrics = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raymarching volumetrics"),
+            contents: bytes_of(&GpuVolumetrics::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let camera = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raymarching camera"),
+            contents: bytes_of(&Camera::default().to_gpu()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let atmosphere = atmosphere.resources();
+        let storage_bind_group = create_storage_bind_group(
+            device,
+            &storage_bind_group_layout,
+            &[
+                objects.buffer(),
+                &grid_info,
+                grid_cells.buffer(),
+                grid_indices.buffer(),
+                materials.buffer(),
+                lights.buffer(),
+                &fog,
+            ],
+            &atmosphere,
+            &[&volumetrics, &camera],
         );
 
-        let storage_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Raymarching objects bind group"),
-            layout: &storage_bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::Buffer(storage_buffer.as_entire_buffer_binding()),
-            }],
-        });
+        let octree_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Raymarching octree bind group layout"),
+                entries: &[
+                    buffer_entry(0, BufferBindingType::Uniform, 0),
+                    buffer_entry(1, storage, 0),
+                    buffer_entry(2, storage, 0),
+                ],
+            });
+        let octree_bind_group =
+            create_octree_bind_group(device, &octree_bind_group_layout, &GpuOctree::default());
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Raymarching compute pass layout descriptor"),
             bind_group_layouts: &[
                 texture_manager.get_compute_mut_bind_group_layout(),
                 &storage_bind_group_layout,
+                &octree_bind_group_layout,
+                texture_manager.get_compute_mut_f32_bind_group_layout(),
             ],
             push_constant_ranges: &[PushConstantRange {
                 stages: ShaderStages::COMPUTE,
@@ -158,68 +432,236 @@ impl RaymarchingRenderComputePass {
             compilation_options: Default::default(),
             cache: Default::default(),
         });
+
+        let path_tracing_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Path tracing compute pass layout descriptor"),
+            bind_group_layouts: &[
+                texture_manager.get_compute_mut_bind_group_layout(),
+                &storage_bind_group_layout,
+                &octree_bind_group_layout,
+                texture_manager.get_compute_mut_f32_bind_group_layout(),
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<RaymarchingConstants>() as u32,
+            }],
+        });
+        let path_tracing_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Path tracing compute pass"),
+            layout: Some(&path_tracing_layout),
+            module: &shader,
+            entry_point: Some("path_trace_main"),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
+
+        let pick_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick compute pass layout descriptor"),
+            bind_group_layouts: &[
+                texture_manager.get_compute_mut_bind_group_layout(),
+                &storage_bind_group_layout,
+                &octree_bind_group_layout,
+                picker.bind_group_layout(),
+            ],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<RaymarchingConstants>() as u32,
+            }],
+        });
+        let pick_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Pick compute pass"),
+            layout: Some(&pick_layout),
+            module: &shader,
+            entry_point: Some("pick_main"),
+            compilation_options: Default::default(),
+            cache: Default::default(),
+        });
         RaymarchingRenderComputePass {
             compute_pipeline,
+            path_tracing_pipeline,
+            pick_pipeline,
             current_time: SystemTime::now(),
+            time_override: None,
+            storage_bind_group_layout,
             storage_bind_group,
-            objects_count: 1,
-            storage_buffer,
+            objects,
+            grid_info,
+            grid_cells,
+            grid_indices,
+            materials,
+            lights,
+            fog,
+            atmosphere,
+            volumetrics,
+            camera,
+            octree_bind_group_layout,
+            octree_bind_group,
         }
     }
 
+    /// Replaces the voxel octree traversed next to the objects, see [`crate::octree::Octree::to_gpu`].
+    pub fn upload_octree(&mut self, device: &Device, octree: &GpuOctree) {
+        puffin::profile_function!();
+        self.octree_bind_group =
+            create_octree_bind_group(device, &self.octree_bind_group_layout, octree);
+    }
+
+    /// Renders at a fixed scene `time` instead of the wall clock, `None` restores the clock.
+    pub fn set_time_override(&mut self, time: Option<f32>) {
+        self.time_override = time;
+    }
+
+    /// Scene time in seconds, the override if one is set.
+    pub fn time(&self) -> f32 {
+        self.time_override
+            .unwrap_or_else(|| self.current_time.elapsed().unwrap().as_secs_f32())
+    }
+
+    /// Flattens the scene graph into the objects buffer, rebuilds the object grid and uploads
+    /// the materials, lights, fog, volumetrics and camera, growing the buffers when the scene
+    /// outgrows them.
+    pub fn upload_objects(&mut self, device: &Device, queue: &Queue, options: &RenderOptions) {
+        puffin::profile_function!();
+        let scene = SceneGraph::new(&options.raymarching_objects);
+        let grid = ObjectGrid::new(&scene);
+        let materials: Vec<_> = options.materials.iter().map(Material::to_gpu).collect();
+        let lights: Vec<_> = options.lights.iter().map(Light::to_gpu).collect();
+        queue.write_buffer(&self.grid_info, 0, bytes_of(&grid.info()));
+        queue.write_buffer(&self.fog, 0, bytes_of(&options.fog.to_gpu()));
+        let volumetrics = GpuVolumetrics::new(&options.clouds, &options.fog_volumes);
+        queue.write_buffer(&self.volumetrics, 0, bytes_of(&volumetrics));
+        queue.write_buffer(&self.camera, 0, bytes_of(&options.camera.to_gpu()));
+        let reallocated = self.objects.write(device, queue, &scene.gpu_objects())
+            | self.grid_cells.write(device, queue, grid.cells())
+            | self.grid_indices.write(device, queue, grid.indices())
+            | self.materials.write(device, queue, &materials)
+            | self.lights.write(device, queue, &lights);
+        if reallocated {
+            self.storage_bind_group = create_storage_bind_group(
+                device,
+                &self.storage_bind_group_layout,
+                &[
+                    self.objects.buffer(),
+                    &self.grid_info,
+                    self.grid_cells.buffer(),
+                    self.grid_indices.buffer(),
+                    self.materials.buffer(),
+                    self.lights.buffer(),
+                    &self.fog,
+                ],
+                &self.atmosphere,
+                &[&self.volumetrics, &self.camera],
+            );
+        }
+    }
+
+    /// Shades the scene into "Raymarching" and writes the normals and hit distances the
+    /// horizon based ambient occlusion works on to "Depth normal".
     pub fn render(
         &mut self,
-        queue: &Queue,
         encoder: &mut CommandEncoder,
         texture_manager: &TextureManager,
         width: u32,
         height: u32,
-        FOV: f32,
-        rotation: f32,
-        ray_origin: Vec3,
-        objects: &[RaymarchingObject],
-        yz_rotation: f32,
-        sun_dir: Vec3,
-        sun_color: Vec3,
-        sun_intensity: f32,
-        exposure: f32,
+        options: &RenderOptions,
     ) {
-        let required_size = (size_of::<RaymarchingObject>() * objects.len()) as u64;
-        if required_size < self.storage_buffer.size() {
-            queue.write_buffer(&self.storage_buffer, 0, cast_slice(objects));
-        } else {
-            panic!("Buffer expansion logic")
-        }
+        let constants = RaymarchingConstants::new(options, width, height, self.time());
+        let depth_normal = texture_manager
+            .get_texture("Depth normal")
+            .unwrap()
+            .compute_mut_group_f32();
+        self.dispatch(
+            encoder,
+            &self.compute_pipeline,
+            texture_manager,
+            constants,
+            depth_normal,
+        );
+    }
+
+    /// Traces one more path per pixel into the "Accumulation" texture and writes the average
+    /// so far to "Raymarching". Sample 0 overwrites the previous accumulation.
+    pub fn render_path_traced(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        texture_manager: &TextureManager,
+        width: u32,
+        height: u32,
+        options: &RenderOptions,
+        sample_index: u32,
+    ) {
+        let mut constants = RaymarchingConstants::new(options, width, height, self.time());
+        constants.sample_index = sample_index;
+        let accumulation = texture_manager
+            .get_texture("Accumulation")
+            .unwrap()
+            .compute_mut_group_f32();
+        self.dispatch(
+            encoder,
+            &self.path_tracing_pipeline,
+            texture_manager,
+            constants,
+            accumulation,
+        );
+    }
 
-        self.objects_count = objects.len();
+    /// Marches the ray of the pixel [`ObjectPicker::begin`] uploaded into the pick buffer.
+    pub fn pick(
+        &self,
+        encoder: &mut CommandEncoder,
+        texture_manager: &TextureManager,
+        width: u32,
+        height: u32,
+        options: &RenderOptions,
+        picker: &ObjectPicker,
+    ) {
+        let constants = RaymarchingConstants::new(options, width, height, self.time());
+        self.dispatch_workgroups(
+            encoder,
+            &self.pick_pipeline,
+            texture_manager,
+            constants,
+            Some(picker.bind_group()),
+            [1, 1],
+        );
+    }
 
+    fn dispatch(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        texture_manager: &TextureManager,
+        constants: RaymarchingConstants,
+        f32_texture: Option<&BindGroup>,
+    ) {
+        let [width, height] = constants.texture_size;
+        let workgroups = [(width as u32).div_ceil(16), (height as u32).div_ceil(16)];
+        self.dispatch_workgroups(
+            encoder,
+            pipeline,
+            texture_manager,
+            constants,
+            f32_texture,
+            workgroups,
+        );
+    }
+
+    /// `group_3` is the f32 texture, or the pick buffer for `pick_main`.
+    fn dispatch_workgroups(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        texture_manager: &TextureManager,
+        constants: RaymarchingConstants,
+        group_3: Option<&BindGroup>,
+        [wg_x, wg_y]: [u32; 2],
+    ) {
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Raymarching compute pass"),
             timestamp_writes: Default::default(),
         });
-        compute_pass.set_pipeline(&self.compute_pipeline);
-        compute_pass.set_push_constants(
-            0,
-            bytes_of(&RaymarchingConstants {
-                texture_size: [width as f32, height as f32],
-                time: self.current_time.elapsed().unwrap().as_secs_f32(),
-                rotation: rotation,
-                ray_origin: ray_origin.extend(0.0),
-                fov: FOV,
-                objects_count: objects.len() as u32,
-                yz_rotation,
-
-                sun_dir: sun_dir.extend(0.0),
-
-                sun_color: sun_color.extend(0.0),
-
-                sun_intensity,
-
-                exposure,
-                _pad0: 0.0,
-                _pad1: Vec2::ZERO,
-            }),
-        );
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_push_constants(0, bytes_of(&constants));
         compute_pass.set_bind_group(
             0,
             texture_manager
@@ -229,12 +671,96 @@ impl RaymarchingRenderComputePass {
             &[],
         );
         compute_pass.set_bind_group(1, Some(&self.storage_bind_group), &[]);
-        let wg_x = (width + 15) / 16;
-        let wg_y = (height + 15) / 16;
+        compute_pass.set_bind_group(2, Some(&self.octree_bind_group), &[]);
+        compute_pass.set_bind_group(3, group_3, &[]);
         compute_pass.dispatch_workgroups(wg_x, wg_y, 1);
     }
 }
 
+/// Binds `buffers` in order: objects, grid info, grid cells, grid indices, materials, lights,
+/// fog. The atmosphere follows them, then the `uniforms`: volumetrics and camera.
+fn create_storage_bind_group(
+    device: &Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: &[&Buffer],
+    atmosphere: &AtmosphereResources,
+    uniforms: &[&Buffer],
+) -> BindGroup {
+    let mut entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| BindGroupEntry {
+            binding: binding as u32,
+            resource: BindingResource::Buffer(buffer.as_entire_buffer_binding()),
+        })
+        .collect();
+    entries.extend(atmosphere.bind_group_entries(buffers.len() as u32));
+    for uniform in uniforms {
+        entries.push(BindGroupEntry {
+            binding: entries.len() as u32,
+            resource: BindingResource::Buffer(uniform.as_entire_buffer_binding()),
+        });
+    }
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Raymarching objects bind group"),
+        layout,
+        entries: &entries,
+    })
+}
+
+fn create_octree_bind_group(
+    device: &Device,
+    layout: &wgpu::BindGroupLayout,
+    octree: &GpuOctree,
+) -> BindGroup {
+    // Bindings can't be empty, an empty tree still gets one node and one distance
+    let nodes: &[GpuOctreeNode] = if octree.nodes.is_empty() {
+        &[GpuOctreeNode::default()]
+    } else {
+        &octree.nodes
+    };
+    let bricks: &[f32] = if octree.bricks.is_empty() {
+        &[0.0]
+    } else {
+        &octree.bricks
+    };
+
+    let info_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Raymarching octree info"),
+        contents: bytes_of(&octree.info),
+        usage: BufferUsages::UNIFORM,
+    });
+    let nodes_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Raymarching octree nodes"),
+        contents: cast_slice(nodes),
+        usage: BufferUsages::STORAGE,
+    });
+    let bricks_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Raymarching octree bricks"),
+        contents: cast_slice(bricks),
+        usage: BufferUsages::STORAGE,
+    });
+
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Raymarching octree bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: info_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: nodes_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: bricks_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
 #[derive(Debug, Clone, Copy, EguiProbe)]
 pub struct RaymarchingOptions {}
 
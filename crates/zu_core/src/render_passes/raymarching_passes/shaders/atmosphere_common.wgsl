@@ -0,0 +1,165 @@
+// Atmosphere model shared by atmosphere_luts.wgsl and raymarching_compute.wgsl, appended to
+// both. The including shader declares `atmosphere`, `transmittance_lut`,
+// `multi_scattering_lut` and `lut_sampler`.
+//
+// Based on "A Scalable and Production Ready Sky and Atmosphere Rendering Technique"
+// (Hillaire 2020). Mirrored by AtmosphereLuts in cpu_reference.rs.
+
+const PI: f32 = 3.14159265359f;
+
+// Matches GpuAtmosphere in atmosphere.rs. Distances in km, coefficients per km
+struct Atmosphere {
+    rayleigh_scattering: vec3<f32>,
+    rayleigh_height: f32,
+    mie_scattering: vec3<f32>,
+    mie_height: f32,
+    ozone_absorption: vec3<f32>,
+    ozone_height: f32,
+    ground_albedo: vec3<f32>,
+    mie_g: f32,
+    // At the top of the atmosphere, in the units the sky is shaded in
+    sun_illuminance: vec3<f32>,
+    bottom_radius: f32,
+    top_radius: f32,
+    // Height of the camera above the ground
+    view_height: f32,
+    // Km of air per scene unit for the aerial perspective
+    distance_scale: f32,
+    _pad: f32,
+}
+
+// Mie particles absorb part of the light they don't scatter
+const MIE_EXTINCTION_RATIO: f32 = 1.11;
+// Ten times the real sun so the disk stays visible at low resolutions
+const SUN_ANGULAR_RADIUS: f32 = radians(0.53 / 2.0) * 10;
+const SUN_SOLID_ANGLE: f32 = 2 * PI * (1 - cos(SUN_ANGULAR_RADIUS));
+
+struct Medium {
+    rayleigh: vec3<f32>,
+    mie: vec3<f32>,
+    extinction: vec3<f32>,
+}
+
+// Scattering and extinction at `height` above the ground
+fn sample_medium(height: f32) -> Medium {
+    let h = max(height, 0.0);
+    let rayleigh = atmosphere.rayleigh_scattering * exp(-h / atmosphere.rayleigh_height);
+    let mie = atmosphere.mie_scattering * exp(-h / atmosphere.mie_height);
+    let ozone = atmosphere.ozone_absorption * exp(-h / atmosphere.ozone_height);
+    let extinction = rayleigh + MIE_EXTINCTION_RATIO * mie + ozone;
+    return Medium(rayleigh, mie, extinction);
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    return (3.0 / (16.0 * PI)) * (1.0 + cos_theta * cos_theta);
+}
+
+fn mie_phase(cos_theta: f32) -> f32 {
+    let g = atmosphere.mie_g;
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * pow(denom, 1.5));
+}
+
+// Distance from `height` along a ray with zenith cosine `mu` to the ground, -1 if it misses.
+// Works on heights rather than positions, r² - R² loses the meters near the ground in f32
+fn distance_to_ground(height: f32, mu: f32) -> f32 {
+    let r = atmosphere.bottom_radius + height;
+    let discriminant = r * r * mu * mu - height * (2.0 * atmosphere.bottom_radius + height);
+    if mu >= 0.0 || discriminant < 0.0 {
+        return -1.0;
+    }
+    return -r * mu - sqrt(discriminant);
+}
+
+// Distance from `height` inside the atmosphere along a ray with zenith cosine `mu` to its top
+fn distance_to_top(height: f32, mu: f32) -> f32 {
+    let r = atmosphere.bottom_radius + height;
+    let discriminant = r * r * (mu * mu - 1.0) + atmosphere.top_radius * atmosphere.top_radius;
+    return max(-r * mu + sqrt(max(discriminant, 0.0)), 0.0);
+}
+
+// Height after travelling `t` from `height` along a ray with zenith cosine `mu`
+fn height_along_ray(height: f32, mu: f32, t: f32) -> f32 {
+    let bottom = atmosphere.bottom_radius;
+    let rho_squared = height * (2.0 * bottom + height) + t * t + 2.0 * (bottom + height) * mu * t;
+    let clamped = max(rho_squared, 0.0);
+    return clamped / (sqrt(bottom * bottom + clamped) + bottom);
+}
+
+// Zenith cosine of the sun at that point, `cos_view_sun` is the angle between ray and sun
+fn sun_zenith_along_ray(height: f32, sun_mu: f32, cos_view_sun: f32, t: f32, height_at_t: f32) -> f32 {
+    let r = atmosphere.bottom_radius + height;
+    return clamp((r * sun_mu + t * cos_view_sun) / (atmosphere.bottom_radius + height_at_t), -1.0, 1.0);
+}
+
+// Transmittance LUT coordinates, Bruneton's mapping with more texels towards the horizon
+fn transmittance_uv(height: f32, mu: f32) -> vec2<f32> {
+    let bottom = atmosphere.bottom_radius;
+    let top = atmosphere.top_radius;
+    let horizon = sqrt(top * top - bottom * bottom);
+    let rho = sqrt(max(height * (2.0 * bottom + height), 0.0));
+    let d_min = top - (bottom + height);
+    let d_max = rho + horizon;
+    let d = distance_to_top(height, mu);
+    return vec2<f32>((d - d_min) / max(d_max - d_min, 1e-6), rho / horizon);
+}
+
+// Inverse of transmittance_uv, returns the height and zenith cosine
+fn transmittance_lut_params(uv: vec2<f32>) -> vec2<f32> {
+    let bottom = atmosphere.bottom_radius;
+    let top = atmosphere.top_radius;
+    let horizon = sqrt(top * top - bottom * bottom);
+    let rho = horizon * uv.y;
+    let height = rho * rho / (sqrt(rho * rho + bottom * bottom) + bottom);
+    let r = bottom + height;
+    let d_min = top - r;
+    let d_max = rho + horizon;
+    let d = d_min + uv.x * (d_max - d_min);
+    var mu = 1.0;
+    if d > 0.0 {
+        mu = clamp((horizon * horizon - rho * rho - d * d) / (2.0 * r * d), -1.0, 1.0);
+    }
+    return vec2<f32>(height, mu);
+}
+
+// Light scattered more than once at `height`, for a sun of unit illuminance at zenith cosine `sun_mu`
+fn multi_scattering(height: f32, sun_mu: f32) -> vec3<f32> {
+    let uv = vec2<f32>(sun_mu * 0.5 + 0.5, height / (atmosphere.top_radius - atmosphere.bottom_radius));
+    return textureSampleLevel(multi_scattering_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+// Transmittance from `height` to the top of the atmosphere, ignoring the ground
+fn transmittance_to_top(height: f32, mu: f32) -> vec3<f32> {
+    return textureSampleLevel(transmittance_lut, lut_sampler, transmittance_uv(height, mu), 0.0).rgb;
+}
+
+// Sunlight reaching `height` with the sun at zenith cosine `mu`, zero in the planet's shadow
+fn sun_transmittance(height: f32, mu: f32) -> vec3<f32> {
+    if distance_to_ground(height, mu) >= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    return transmittance_to_top(height, mu);
+}
+
+// How far the horizon dips below the horizontal at `height`
+fn horizon_dip(height: f32) -> f32 {
+    let bottom = atmosphere.bottom_radius;
+    return atan(sqrt(height * (2.0 * bottom + height)) / bottom);
+}
+
+// Sky-view LUT coordinates: the angle to the sun's azimuth horizontally, mirrored since the
+// sky is symmetric around the sun, and the elevation above the horizon vertically, squeezed
+// towards the horizon where the sky changes fastest
+fn sky_view_uv(direction: vec3<f32>, sun_dir: vec3<f32>) -> vec2<f32> {
+    let altitude = asin(clamp(direction.y, -1.0, 1.0)) + horizon_dip(atmosphere.view_height);
+    let v = 0.5 + 0.5 * sign(altitude) * sqrt(min(abs(altitude) * 2.0 / PI, 1.0));
+
+    var u = 0.0;
+    let horizontal = direction.xz;
+    let sun_horizontal = sun_dir.xz;
+    if dot(horizontal, horizontal) > 1e-10 && dot(sun_horizontal, sun_horizontal) > 1e-10 {
+        let cos_azimuth = dot(normalize(horizontal), normalize(sun_horizontal));
+        u = acos(clamp(cos_azimuth, -1.0, 1.0)) / PI;
+    }
+    return vec2<f32>(u, v);
+}
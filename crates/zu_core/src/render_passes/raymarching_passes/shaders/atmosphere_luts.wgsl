@@ -0,0 +1,175 @@
+// Lookup tables of the sky, regenerated by AtmospherePass in atmosphere.rs when the
+// atmosphere or the sun changes. atmosphere_common.wgsl is appended.
+
+@group(0) @binding(0) var<uniform> atmosphere: Atmosphere;
+
+@group(1) @binding(0) var output_lut: texture_storage_2d<rgba16float, write>;
+
+// Not bound while generating the transmittance LUT itself
+@group(2) @binding(0) var transmittance_lut: texture_2d<f32>;
+@group(2) @binding(1) var lut_sampler: sampler;
+
+// Only bound for the sky-view LUT
+@group(3) @binding(0) var multi_scattering_lut: texture_2d<f32>;
+
+struct LutConstants {
+    sun_dir: vec4<f32>,
+}
+
+var<push_constant> constants: LutConstants;
+
+const TRANSMITTANCE_STEPS: f32 = 40.0;
+const MULTI_SCATTERING_STEPS: f32 = 20.0;
+// Square root of the directions gathered per multi-scattering texel
+const MULTI_SCATTERING_DIRECTIONS: u32 = 8u;
+const SKY_VIEW_STEPS: f32 = 30.0;
+// Where inside each step the medium is sampled
+const SAMPLE_OFFSET: f32 = 0.3;
+
+// Texel center of `id` in uv, or -1 outside the texture
+fn lut_uv(id: vec2<u32>) -> vec2<f32> {
+    let size = textureDimensions(output_lut);
+    if any(id >= size) {
+        return vec2<f32>(-1.0);
+    }
+    return (vec2<f32>(id) + 0.5) / vec2<f32>(size);
+}
+
+// Transmittance to the top of the atmosphere per height and zenith cosine
+@compute @workgroup_size(8, 8)
+fn transmittance_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let uv = lut_uv(id.xy);
+    if uv.x < 0.0 {
+        return;
+    }
+    let params = transmittance_lut_params(uv);
+    let height = params.x;
+    let mu = params.y;
+
+    let step = distance_to_top(height, mu) / TRANSMITTANCE_STEPS;
+    var optical_depth = vec3<f32>(0.0);
+    for (var i = 0.0; i < TRANSMITTANCE_STEPS; i += 1.0) {
+        let sample_height = height_along_ray(height, mu, (i + 0.5) * step);
+        optical_depth += sample_medium(sample_height).extinction * step;
+    }
+    textureStore(output_lut, id.xy, vec4<f32>(exp(-optical_depth), 1.0));
+}
+
+// Radiance scattered towards the viewer from one step of `medium` lit by `source`, integrated
+// over the step's falloff instead of assuming constant transmittance. Channels without
+// extinction, in an empty atmosphere, scatter next to nothing instead of dividing by zero
+fn integrate_step(source: vec3<f32>, medium: Medium, step_transmittance: vec3<f32>) -> vec3<f32> {
+    return (source - source * step_transmittance) / max(medium.extinction, vec3<f32>(1e-6));
+}
+
+// Light scattered twice or more towards any direction, per height and sun zenith cosine, for
+// a sun of unit illuminance. Hillaire's approximation: second order scattering gathered over
+// the sphere with an isotropic phase, extended to all orders as a geometric series
+@compute @workgroup_size(8, 8)
+fn multi_scattering_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let uv = lut_uv(id.xy);
+    if uv.x < 0.0 {
+        return;
+    }
+    let sun_mu = uv.x * 2.0 - 1.0;
+    let height = uv.y * (atmosphere.top_radius - atmosphere.bottom_radius);
+    let sun_horizontal = sqrt(max(1.0 - sun_mu * sun_mu, 0.0));
+    let isotropic_phase = 1.0 / (4.0 * PI);
+
+    var second_order = vec3<f32>(0.0);
+    var transfer = vec3<f32>(0.0);
+    let directions = MULTI_SCATTERING_DIRECTIONS;
+    for (var i = 0u; i < directions; i++) {
+        // Uniform over the sphere: zenith cosines evenly spaced, azimuths evenly spaced
+        let mu = 1.0 - 2.0 * (f32(i) + 0.5) / f32(directions);
+        let horizontal = sqrt(max(1.0 - mu * mu, 0.0));
+        for (var j = 0u; j < directions; j++) {
+            let phi = 2.0 * PI * (f32(j) + 0.5) / f32(directions);
+            let cos_view_sun = horizontal * cos(phi) * sun_horizontal + mu * sun_mu;
+
+            let ground = distance_to_ground(height, mu);
+            var distance = distance_to_top(height, mu);
+            if ground >= 0.0 {
+                distance = ground;
+            }
+            let step = distance / MULTI_SCATTERING_STEPS;
+
+            var throughput = vec3<f32>(1.0);
+            var luminance = vec3<f32>(0.0);
+            var scattered = vec3<f32>(0.0);
+            for (var s = 0.0; s < MULTI_SCATTERING_STEPS; s += 1.0) {
+                let t = (s + SAMPLE_OFFSET) * step;
+                let sample_height = height_along_ray(height, mu, t);
+                let medium = sample_medium(sample_height);
+                let step_transmittance = exp(-medium.extinction * step);
+                let sample_sun_mu = sun_zenith_along_ray(height, sun_mu, cos_view_sun, t, sample_height);
+                let scattering = medium.rayleigh + medium.mie;
+
+                let sun = sun_transmittance(sample_height, sample_sun_mu);
+                luminance += throughput * integrate_step(scattering * sun * isotropic_phase, medium, step_transmittance);
+                scattered += throughput * integrate_step(scattering, medium, step_transmittance);
+                throughput *= step_transmittance;
+            }
+
+            if ground >= 0.0 {
+                let ground_sun_mu = sun_zenith_along_ray(height, sun_mu, cos_view_sun, ground, 0.0);
+                let irradiance = transmittance_to_top(0.0, ground_sun_mu) * max(ground_sun_mu, 0.0);
+                luminance += throughput * irradiance * atmosphere.ground_albedo / PI;
+            }
+
+            second_order += luminance;
+            transfer += scattered;
+        }
+    }
+    let count = f32(directions * directions);
+    // Every further order is the previous one times the transfer, sum the series
+    let all_orders = (second_order / count) / (1.0 - transfer / count);
+    textureStore(output_lut, id.xy, vec4<f32>(all_orders, 1.0));
+}
+
+// Sky radiance around the viewer, see sky_view_uv for the parametrization. The ground isn't
+// shaded, rays below the horizon only gather the air in front of it
+@compute @workgroup_size(8, 8)
+fn sky_view_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let uv = lut_uv(id.xy);
+    if uv.x < 0.0 {
+        return;
+    }
+    let height = atmosphere.view_height;
+    let sun_mu = clamp(normalize(constants.sun_dir.xyz).y, -1.0, 1.0);
+    let sun_horizontal = sqrt(max(1.0 - sun_mu * sun_mu, 0.0));
+
+    let azimuth = uv.x * PI;
+    let centered = uv.y * 2.0 - 1.0;
+    let elevation = sign(centered) * centered * centered * PI / 2.0 - horizon_dip(height);
+    let mu = sin(elevation);
+    let cos_view_sun = cos(elevation) * cos(azimuth) * sun_horizontal + mu * sun_mu;
+    let rayleigh_phase_value = rayleigh_phase(cos_view_sun);
+    let mie_phase_value = mie_phase(cos_view_sun);
+
+    let ground = distance_to_ground(height, mu);
+    var distance = distance_to_top(height, mu);
+    if ground >= 0.0 {
+        distance = ground;
+    }
+    let step = distance / SKY_VIEW_STEPS;
+
+    var throughput = vec3<f32>(1.0);
+    var luminance = vec3<f32>(0.0);
+    for (var s = 0.0; s < SKY_VIEW_STEPS; s += 1.0) {
+        let t = (s + SAMPLE_OFFSET) * step;
+        let sample_height = height_along_ray(height, mu, t);
+        let medium = sample_medium(sample_height);
+        let step_transmittance = exp(-medium.extinction * step);
+        let sample_sun_mu = sun_zenith_along_ray(height, sun_mu, cos_view_sun, t, sample_height);
+
+        let sun = sun_transmittance(sample_height, sample_sun_mu);
+        let multi = multi_scattering(sample_height, sample_sun_mu);
+        let source = medium.rayleigh * (rayleigh_phase_value * sun + multi)
+            + medium.mie * (mie_phase_value * sun + multi);
+
+        luminance += throughput * integrate_step(source, medium, step_transmittance);
+        throughput *= step_transmittance;
+    }
+    textureStore(output_lut, id.xy, vec4<f32>(luminance * atmosphere.sun_illuminance, 1.0));
+}
@@ -0,0 +1,105 @@
+// Horizon based ambient occlusion over the "Depth normal" texture compute_main writes, run by
+// HorizonAoPass in ambient_occlusion.rs. For a few screen directions around every pixel it
+// finds the highest neighbouring surface above the tangent plane, within `radius`, and darkens
+// the ambient share of the pixel by how much of the sky those horizons hide.
+
+@group(0) @binding(0) var color_texture: texture_storage_2d<rgba16float, read_write>;
+@group(1) @binding(0) var depth_normal_texture: texture_storage_2d<rgba32float, read_write>;
+
+// Matches HorizonAoConstants in ambient_occlusion.rs
+struct HorizonAoConstants {
+    // Columns of GpuCamera::ray_basis in camera.rs
+    ray_x: vec4<f32>,
+    ray_y: vec4<f32>,
+    ray_z: vec4<f32>,
+    texture_size: vec2<f32>,
+    radius: f32,
+    pixel_angle: f32,
+    directions: u32,
+    steps: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+var<push_constant> constants: HorizonAoConstants;
+
+const PI: f32 = 3.14159265359f;
+// Neighbours need to rise this far above the tangent plane to occlude. compute_main stops
+// within its 0.05 hit threshold of a surface, so flat ground isn't flat in the depth
+const HEIGHT_BIAS: f32 = 0.05;
+// Keeps nearby surfaces from searching the whole screen
+const MAX_RADIUS_PIXELS: f32 = 64.0;
+
+@compute @workgroup_size(8, 8)
+fn horizon_ao_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(constants.texture_size);
+    let pixel = vec2<i32>(id.xy);
+    if any(pixel >= size) {
+        return;
+    }
+    let center = textureLoad(depth_normal_texture, pixel);
+    // The sky and surfaces without ambient light stay as they are
+    if center.z < 0.0 || center.w <= 0.0 {
+        return;
+    }
+    let normal = decode_normal(center.xy);
+    let position = camera_ray(pixel) * center.z;
+    let radius_pixels = min(constants.radius / (center.z * constants.pixel_angle), MAX_RADIUS_PIXELS);
+    if radius_pixels < 1.0 {
+        return;
+    }
+
+    // Rotates the directions per pixel, trading banding for noise
+    let noise = fract(52.9829189 * fract(dot(vec2<f32>(pixel), vec2<f32>(0.06711056, 0.00583715))));
+    var occlusion = 0.0;
+    for (var d = 0u; d < constants.directions; d++) {
+        let angle = (f32(d) + noise) * 2.0 * PI / f32(constants.directions);
+        let direction = vec2<f32>(cos(angle), sin(angle));
+        var horizon = 0.0;
+        for (var s = 0u; s < constants.steps; s++) {
+            let offset = direction * radius_pixels * (f32(s) + 1.0) / f32(constants.steps);
+            let sample_pixel = pixel + vec2<i32>(round(offset));
+            if any(sample_pixel < vec2<i32>(0)) || any(sample_pixel >= size) {
+                break;
+            }
+            let sample = textureLoad(depth_normal_texture, sample_pixel);
+            if sample.z < 0.0 {
+                continue;
+            }
+            let to_sample = camera_ray(sample_pixel) * sample.z - position;
+            let length = length(to_sample);
+            if length < 1e-4 || length > constants.radius {
+                continue;
+            }
+            // Surfaces towards the edge of the radius fade out instead of popping
+            let falloff = 1.0 - (length * length) / (constants.radius * constants.radius);
+            let elevation = (dot(normal, to_sample) - HEIGHT_BIAS) / length;
+            horizon = max(horizon, elevation * falloff);
+        }
+        occlusion += horizon;
+    }
+    let visibility = clamp(1.0 - occlusion / f32(constants.directions), 0.0, 1.0);
+
+    let color = textureLoad(color_texture, pixel);
+    let darkened = color.rgb * (1.0 - center.w * (1.0 - visibility));
+    textureStore(color_texture, pixel, vec4<f32>(darkened, color.a));
+}
+
+// Same direction as camera_ray in raymarching_compute.wgsl
+fn camera_ray(pixel: vec2<i32>) -> vec3<f32> {
+    let aspect = constants.texture_size.x / constants.texture_size.y;
+    var uv = (vec2<f32>(pixel) / constants.texture_size) * 2.0 - vec2<f32>(1.0, 1.0);
+    uv.y = -uv.y;
+    uv.x *= aspect;
+    return normalize(constants.ray_x.xyz * uv.x + constants.ray_y.xyz * uv.y + constants.ray_z.xyz);
+}
+
+// Inverse of encode_normal in raymarching_compute.wgsl
+fn decode_normal(p: vec2<f32>) -> vec3<f32> {
+    var n = vec3<f32>(p, 1.0 - abs(p.x) - abs(p.y));
+    if n.z < 0.0 {
+        let folded = (1.0 - abs(n.yx)) * select(vec2<f32>(-1.0), vec2<f32>(1.0), n.xy >= vec2<f32>(0.0));
+        n = vec3<f32>(folded, n.z);
+    }
+    return normalize(n);
+}
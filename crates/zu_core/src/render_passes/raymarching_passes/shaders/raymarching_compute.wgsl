@@ -1,105 +1,1087 @@
-@group(0) @binding(0) var output_texture: texture_storage_2d<rgba16float, read_write>;
-
-@group(1) @binding(0) var<storage, read> objects: array<RaymarchingObject>;
-
-struct RaymarchingObject {
-    position: vec4<f32>,
-    material: i32,
-    pad: vec3<f32>,
-}
-
+// Declared first, the GL backend looks the push constant types up by handle in the whole
+// module, so they must come before every type an entry point may leave out
 struct PushConstants {
     texture_size: vec2<f32>,
     time: f32,
-    rotation: f32,
-
-    ray_origin: vec4<f32>,
-
-    fov: f32,
     objects_count: u32,
-    yz_rotation: f32,
-    _pad0: f32,
 
     sun_dir: vec4<f32>,
     sun_color: vec4<f32>,
 
     sun_intensity: f32,
     exposure: f32,
-    _pad1: vec2<f32>,
+    lights_count: u32,
+    ambient: f32,
+
+    sample_index: u32,
+    max_bounces: u32,
+    ao_samples: u32,
+    ao_radius: f32,
+
+    bounce_depth: u32,
+    materials_count: u32,
+    _pad0: u32,
+    _pad1: u32,
 };
 
 var<push_constant> constants: PushConstants;
 
-const PI: f32 = 3.14159265359f;
+@group(0) @binding(0) var output_texture: texture_storage_2d<rgba16float, read_write>;
+
+@group(1) @binding(0) var<storage, read> objects: array<RaymarchingObject>;
+@group(1) @binding(1) var<uniform> grid: ObjectGridInfo;
+@group(1) @binding(2) var<storage, read> grid_cells: array<GridCell>;
+@group(1) @binding(3) var<storage, read> grid_indices: array<u32>;
+@group(1) @binding(4) var<storage, read> materials: array<Material>;
+@group(1) @binding(5) var<storage, read> lights: array<Light>;
+@group(1) @binding(6) var<uniform> fog: HeightFog;
+// Written by AtmospherePass, see atmosphere_common.wgsl
+@group(1) @binding(7) var<uniform> atmosphere: Atmosphere;
+@group(1) @binding(8) var transmittance_lut: texture_2d<f32>;
+@group(1) @binding(9) var multi_scattering_lut: texture_2d<f32>;
+@group(1) @binding(10) var sky_view_lut: texture_2d<f32>;
+@group(1) @binding(11) var lut_sampler: sampler;
+@group(1) @binding(12) var<uniform> volumetrics: Volumetrics;
+@group(1) @binding(13) var<uniform> camera: Camera;
+
+@group(2) @binding(0) var<uniform> octree: OctreeInfo;
+@group(2) @binding(1) var<storage, read> octree_nodes: array<OctreeNode>;
+@group(2) @binding(2) var<storage, read> octree_bricks: array<f32>;
+
+// Only bound for path_trace_main, running average of the samples so far
+@group(3) @binding(0) var accumulation_texture: texture_storage_2d<rgba32float, read_write>;
+// Bound in its place for compute_main: the octahedral normal, the distance from the camera
+// (-1 for the sky) and the share of the color that is ambient light, for the horizon AO pass
+@group(3) @binding(0) var depth_normal_texture: texture_storage_2d<rgba32float, read_write>;
+// And for pick_main, the pixel to pick and what its ray hit, see picking.rs
+@group(3) @binding(0) var<storage, read_write> pick: Pick;
+
+// Matches GpuRaymarchingObject / Primitive in Rust, 112 bytes
+struct RaymarchingObject {
+    world_to_local: mat4x4<f32>,
+    primitive: Primitive,
+    material: i32,
+    operation: u32,
+    blend_radius: f32,
+    scale_bound: f32,
+}
+
+// params are interpreted per kind, see PrimitiveKind::param_labels
+struct Primitive {
+    params: vec4<f32>,
+    kind: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// Matches GpuMaterial in material.rs, 64 bytes
+struct Material {
+    albedo: vec3<f32>,
+    roughness: f32,
+    emissive: vec3<f32>,
+    metallic: f32,
+    absorption: vec3<f32>,
+    ior: f32,
+    pattern: u32,
+    transmission: f32,
+    _pad0: u32,
+    _pad1: u32,
+}
+
+// Matches GpuLight in light.rs, 64 bytes
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
+    radiance: vec3<f32>,
+    shadows: u32,
+    cos_inner: f32,
+    cos_outer: f32,
+    size: vec2<f32>,
+}
+
+// Matches GpuHeightFog in fog.rs
+struct HeightFog {
+    albedo: vec3<f32>,
+    density: f32,
+    height: f32,
+    falloff: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+// Matches GpuCamera in camera.rs
+struct Camera {
+    // World to camera space, camera space looks down +z
+    view: mat4x4<f32>,
+    position: vec3<f32>,
+    tan_half_fov: f32,
+    near: f32,
+    far: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+// Matches GpuVolumetrics / GpuClouds / GpuFogVolume in volumetrics.rs
+struct Volumetrics {
+    clouds: Clouds,
+    volume_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+    volumes: array<FogVolume, 8>,
+}
+
+struct Clouds {
+    wind: vec3<f32>,
+    coverage: f32,
+    density: f32,
+    bottom: f32,
+    top: f32,
+    scale: f32,
+    steps: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+struct FogVolume {
+    world_to_local: mat4x4<f32>,
+    primitive: Primitive,
+    albedo: vec3<f32>,
+    density: f32,
+    anisotropy: f32,
+    edge: f32,
+    _pad0: f32,
+    _pad1: f32,
+}
+
+// Matches GpuObjectGridInfo / GpuGridCell in object_grid.rs
+struct ObjectGridInfo {
+    origin: vec3<f32>,
+    cell_size: f32,
+    dims: vec3<u32>,
+    global_count: u32,
+}
+
+struct GridCell {
+    first: u32,
+    count: u32,
+}
+
+// Matches GpuOctreeInfo / GpuOctreeNode in octree.rs
+struct OctreeInfo {
+    origin: vec3<f32>,
+    size: f32,
+    node_count: u32,
+    depth: u32,
+    material: i32,
+    leaf_size: f32,
+}
+
+struct OctreeNode {
+    first_child: u32,
+    child_mask: u32,
+    brick: u32,
+    _pad: u32,
+}
+
+// Matches GpuPick in Rust
+struct Pick {
+    pixel: vec2<u32>,
+    // -1 for the ground, the octree and the sky
+    object: i32,
+    // -1 for the sky
+    distance: f32,
+}
+
+const MAX_STEPS: i32 = 80;
+const HIT_DISTANCE: f32 = 0.05;
+
+struct CameraHit {
+    // Where the march stopped, the camera where it ran out of steps
+    position: vec3<f32>,
+    // -1 past the far plane, where the sky shows
+    material: i32,
+    // Stopped at a surface
+    hit: bool,
+}
 
+// Marches a camera ray into the scene, the march compute_main shades and pick_main names
+fn march_camera_ray(ray_direction: vec3<f32>) -> CameraHit {
+    var distance_traveled = camera.near;
+    for (var i = 0; i < MAX_STEPS; i++) {
+        let position = camera.position + ray_direction * distance_traveled;
+        let res = map(position);
+        distance_traveled += grid_step(position, res.res);
+        if distance_traveled > camera.far {
+            return CameraHit(position, -1, false);
+        }
+        if res.res < HIT_DISTANCE {
+            return CameraHit(position, res.material, true);
+        }
+    }
+    return CameraHit(camera.position, 0, false);
+}
 
 @compute @workgroup_size(16, 16)
 fn compute_main(@builtin(global_invocation_id) id: vec3<u32>) {
     let pixelCoord = vec2<f32>(id.xy);
+    let ray_direction = camera_ray(pixelCoord);
+
+    let march = march_camera_ray(ray_direction);
+    let ray_origin = march.position;
+    let material = march.material;
+    let hit = march.hit;
+    var color = vec3<f32>(0.0);
+    var normal = vec3<f32>(0.0);
+    if hit {
+        normal = get_normal(ray_origin);
+    }
+
+    // let diff = max(0.0, dot(normal, normalize(constants.sun_dir)));
+
+    // color = diff * constants.sun_color + vec3<f32>(0.1, 0.1, 0.1);
+    // Offsets the volumetric samples per pixel, the same every frame so a still image doesn't
+    // flicker. Only the path tracer averages over samples
+    var volume_seed = pcg_hash(id.x + pcg_hash(id.y));
+    let jitter = random(&volume_seed);
+    var haze: Haze;
+    var ambient = vec3<f32>(0.0);
+    var depth_normal = vec4<f32>(0.0, 0.0, -1.0, 0.0);
+    var volume_distance = camera.far;
+    if material == -1 {
+        color = cloudy_sky(ray_direction, jitter);
+        haze = height_fog(ray_direction, FOG_SKY_DISTANCE);
+    } else {
+        var surface = material_surface(material, ray_origin);
+        if hit {
+            surface = scene_surface(ray_origin);
+        }
+        let reflected = traced_gloss(surface, 0u);
+        var seed = pcg_hash(id.x + pcg_hash(id.y));
+        color = light(normal, ray_origin, ray_direction, surface, reflected);
+        color += secondary_light(ray_origin, normal, ray_direction, surface, &seed);
+        let hit_distance = distance(ray_origin, camera.position);
+        haze = camera_haze(ray_direction, hit_distance);
+        ambient = ambient_light(normal, -ray_direction, surface, reflected);
+        depth_normal = vec4<f32>(encode_normal(normal), hit_distance, 0.0);
+        volume_distance = hit_distance;
+    }
+    let volumes = march_fog_volumes(camera.position, ray_direction, volume_distance, jitter);
+    haze = combine_haze(haze, volumes);
+    ambient *= haze.transmittance;
+    color = color * haze.transmittance + haze.inscattering;
+    depth_normal.w = clamp(luminance(ambient) / max(luminance(color), 1e-6), 0.0, 1.0);
+
+    color *= constants.exposure;
+
+    textureStore(output_texture, vec2<i32>(pixelCoord), vec4(color, 1.0));
+    textureStore(depth_normal_texture, vec2<i32>(pixelCoord), depth_normal);
+}
+
+// Marches the picked pixel's ray like compute_main and names the object it stops at
+@compute @workgroup_size(1)
+fn pick_main() {
+    let march = march_camera_ray(camera_ray(vec2<f32>(pick.pixel)));
+    if march.hit {
+        pick.object = scene_object(march.position);
+        pick.distance = distance(march.position, camera.position);
+    }
+}
+
+// Path traced reference, mirrors sample_brdf in cpu_reference.rs. Traces one path per pixel
+// per dispatch through the same scene, lit by the sun, the lights, emissive surfaces and the
+// atmosphere, and folds it into the running average in accumulation_texture.
+@compute @workgroup_size(16, 16)
+fn path_trace_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(vec2<f32>(id.xy) >= constants.texture_size) {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    var seed = pcg_hash(id.x + pcg_hash(id.y + pcg_hash(constants.sample_index)));
+
+    // Jittered inside the pixel so edges converge antialiased
+    let jitter = vec2<f32>(random(&seed), random(&seed)) - 0.5;
+    var ray_direction = camera_ray(vec2<f32>(id.xy) + jitter);
+    var ray_origin = camera.position + ray_direction * camera.near;
+    let volume_jitter = random(&seed);
+
+    let sun_dir = normalize(constants.sun_dir.xyz);
+    let sun = constants.sun_color.xyz * constants.sun_intensity;
+    var radiance = vec3<f32>(0.0);
+    var throughput = vec3<f32>(1.0);
+    for (var bounce = 0u; bounce <= constants.max_bounces; bounce++) {
+        var hit = trace_path_ray(ray_origin, ray_direction);
+        // Only the camera rays pass through the haze, and stop at the far limit
+        if bounce == 0u {
+            if hit.hit && distance(hit.position, camera.position) > camera.far {
+                hit.hit = false;
+            }
+            var haze = height_fog(ray_direction, FOG_SKY_DISTANCE);
+            var volume_distance = camera.far;
+            if hit.hit {
+                volume_distance = distance(hit.position, camera.position);
+                haze = camera_haze(ray_direction, volume_distance);
+            }
+            let volumes = march_fog_volumes(camera.position, ray_direction, volume_distance, volume_jitter);
+            haze = combine_haze(haze, volumes);
+            radiance += haze.inscattering;
+            throughput *= haze.transmittance;
+        }
+        if !hit.hit {
+            // Bounces already sampled the sun directly, only camera rays see its disk and the
+            // clouds
+            if bounce == 0u {
+                radiance += throughput * cloudy_sky(ray_direction, volume_jitter);
+            } else {
+                radiance += throughput * sky(ray_direction, false);
+            }
+            break;
+        }
+
+        let normal = get_normal(hit.position);
+        let surface = scene_surface(hit.position);
+        let view_dir = -ray_direction;
+        let f0 = surface_f0(surface);
+        radiance += throughput * surface.emissive;
+
+        let shadow_origin = hit.position + normal * PATH_SURFACE_OFFSET;
+        if any(sun > vec3<f32>(0.0)) && dot(normal, sun_dir) > 0.0 && visible(shadow_origin, sun_dir, MAX_PATH_DISTANCE) {
+            radiance += throughput * brdf(normal, view_dir, sun_dir, surface, f0, 0.0) * sun;
+        }
+        for (var i = 0u; i < constants.lights_count; i++) {
+            let light = lights[i];
+            let sample = sample_light_point(light, hit.position, vec2<f32>(random(&seed), random(&seed)));
+            if all(sample.radiance <= vec3<f32>(0.0)) || dot(normal, sample.direction) <= 0.0 {
+                continue;
+            }
+            let maxt = max(sample.distance - LIGHT_SURFACE_MARGIN, 0.0);
+            if light.shadows != 0u && !visible(shadow_origin, sample.direction, maxt) {
+                continue;
+            }
+            radiance += throughput * brdf(normal, view_dir, sample.direction, surface, f0, 0.0) * sample.radiance;
+        }
+
+        if bounce == constants.max_bounces {
+            break;
+        }
+        // Transmissive surfaces refract the share of the light their brdf leaves out, the paths
+        // sampling the brdf instead are weighted up to make up for it
+        let transmit = surface.transmission * (1.0 - surface.metallic);
+        if transmit > 0.0 && random(&seed) < transmit {
+            let fresnel = fresnel_schlick(max(dot(normal, view_dir), 1e-4), f0);
+            let refraction = refract_through(hit.position, normal, ray_direction, surface);
+            throughput *= (1.0 - fresnel) * refraction.transmittance;
+            ray_origin = refraction.origin;
+            ray_direction = refraction.direction;
+        } else {
+            let sample = sample_brdf(normal, view_dir, surface, f0, vec3<f32>(random(&seed), random(&seed), random(&seed)));
+            throughput *= sample.weight / (1.0 - transmit);
+            ray_origin = shadow_origin;
+            ray_direction = sample.direction;
+        }
+        if all(throughput <= vec3<f32>(0.0)) {
+            break;
+        }
+        // Russian roulette, dim paths are ended early and the survivors weighted up
+        if bounce >= 2u {
+            let survive = min(max(throughput.x, max(throughput.y, throughput.z)), 0.95);
+            if random(&seed) >= survive {
+                break;
+            }
+            throughput /= survive;
+        }
+    }
+
+    var average = vec3<f32>(0.0);
+    if constants.sample_index > 0u {
+        average = textureLoad(accumulation_texture, pixel).rgb;
+    }
+    // A NaN or infinite sample would stick in the average forever, skip it
+    if is_finite(radiance) {
+        average += (radiance - average) / f32(constants.sample_index + 1u);
+    }
+    textureStore(accumulation_texture, pixel, vec4<f32>(average, f32(constants.sample_index + 1u)));
+    textureStore(output_texture, pixel, vec4<f32>(average * constants.exposure, 1.0));
+}
+
+const PATH_HIT_DISTANCE: f32 = 0.001;
+const PATH_SURFACE_OFFSET: f32 = 0.01;
+const MAX_PATH_STEPS: i32 = 256;
+const MAX_PATH_DISTANCE: f32 = 100.0;
+// Shadow rays stop this short of a light, so the geometry it's mounted on doesn't shadow it
+const LIGHT_SURFACE_MARGIN: f32 = 0.05;
+
+struct PathHit {
+    position: vec3<f32>,
+    hit: bool,
+}
+
+// Tighter than the real-time march, running out of steps counts as a hit since that only
+// happens creeping along a surface
+fn trace_path_ray(ray_origin: vec3<f32>, ray_direction: vec3<f32>) -> PathHit {
+    var t = 0.0;
+    for (var i = 0; i < MAX_PATH_STEPS; i++) {
+        let p = ray_origin + ray_direction * t;
+        let distance = map(p).res;
+        if distance < PATH_HIT_DISTANCE {
+            return PathHit(p, true);
+        }
+        t += grid_step(p, distance);
+        if t > MAX_PATH_DISTANCE {
+            return PathHit(p, false);
+        }
+    }
+    return PathHit(ray_origin + ray_direction * t, true);
+}
+
+// Hard shadow ray, true if nothing is hit before `maxt`
+fn visible(ray_origin: vec3<f32>, ray_dir: vec3<f32>, maxt: f32) -> bool {
+    var t = 0.0;
+    for (var i = 0; i < MAX_PATH_STEPS && t < maxt; i++) {
+        let p = ray_origin + ray_dir * t;
+        let h = map(p).res;
+        if h < PATH_HIT_DISTANCE {
+            return false;
+        }
+        t += grid_step(p, h);
+    }
+    return true;
+}
+
+// Reflection and refraction rays waiting to be traced
+const SECONDARY_STACK_SIZE: u32 = 8u;
+// Caps the rays per pixel, a glass ball in front of a mirror branches at every bounce
+const MAX_SECONDARY_RAYS: u32 = 16u;
+// Rays carrying less than this share of the pixel aren't worth tracing
+const MIN_RAY_WEIGHT: f32 = 0.01;
+// Reflections blur out up to this roughness, rougher surfaces only reflect the ambient light
+const MAX_GLOSSY_ROUGHNESS: f32 = 0.5;
+// Total internal reflections followed inside a volume before the light counts as trapped
+const MAX_INTERNAL_REFLECTIONS: u32 = 4u;
+
+struct SecondaryRay {
+    origin: vec3<f32>,
+    depth: u32,
+    direction: vec3<f32>,
+    // Share of the pixel the light along the ray makes up
+    weight: vec3<f32>,
+}
+
+// Light the camera sees in a surface through its reflection and refraction, traced depth first
+// up to bounce_depth surfaces deep. The surfaces on the way are lit like the one the camera
+// ray hit, without the haze. Mirrors secondary_light in cpu_reference.rs
+fn secondary_light(position: vec3<f32>, normal: vec3<f32>, ray_direction: vec3<f32>, surface: Surface, seed: ptr<function, u32>) -> vec3<f32> {
+    var stack: array<SecondaryRay, SECONDARY_STACK_SIZE>;
+    var count = 0u;
+    spawn_secondary_rays(&stack, &count, position, normal, ray_direction, surface, vec3<f32>(1.0), 0u, seed);
+    var color = vec3<f32>(0.0);
+    for (var traced = 0u; traced < MAX_SECONDARY_RAYS && count > 0u; traced++) {
+        count -= 1u;
+        let ray = stack[count];
+        let hit = trace_path_ray(ray.origin, ray.direction);
+        if !hit.hit {
+            // The sun's disk is already the highlight of the sun light
+            color += ray.weight * sky(ray.direction, false);
+            continue;
+        }
+        let hit_normal = get_normal(hit.position);
+        let hit_surface = scene_surface(hit.position);
+        let reflected = traced_gloss(hit_surface, ray.depth);
+        color += ray.weight * light(hit_normal, hit.position, ray.direction, hit_surface, reflected);
+        spawn_secondary_rays(&stack, &count, hit.position, hit_normal, ray.direction, hit_surface, ray.weight, ray.depth, seed);
+    }
+    return color;
+}
+
+// Pushes the rays leaving a surface hit at `depth`: a reflection while the surface is glossy,
+// picked from its GGX lobe, and the refraction out of the volume behind it while it's
+// transmissive
+fn spawn_secondary_rays(
+    stack: ptr<function, array<SecondaryRay, SECONDARY_STACK_SIZE>>,
+    count: ptr<function, u32>,
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    ray_direction: vec3<f32>,
+    surface: Surface,
+    weight: vec3<f32>,
+    depth: u32,
+    seed: ptr<function, u32>,
+) {
+    if depth >= constants.bounce_depth {
+        return;
+    }
+    let roughness = max(surface.roughness, MIN_ROUGHNESS);
+    let n_dot_v = max(dot(normal, -ray_direction), 1e-4);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, surface_f0(surface), roughness);
+
+    let reflection = weight * fresnel * traced_gloss(surface, depth);
+    if max_component(reflection) > MIN_RAY_WEIGHT && *count < SECONDARY_STACK_SIZE {
+        let u = vec2<f32>(random(seed), random(seed));
+        var direction = reflect(ray_direction, tangent_frame(normal) * sample_ggx_half(u, roughness));
+        if dot(direction, normal) <= 0.0 {
+            direction = reflect(ray_direction, normal);
+        }
+        (*stack)[*count] = SecondaryRay(position + normal * PATH_SURFACE_OFFSET, depth + 1u, direction, reflection);
+        *count += 1u;
+    }
+
+    let transmission = weight * (1.0 - fresnel) * surface.transmission * (1.0 - surface.metallic);
+    if max_component(transmission) > MIN_RAY_WEIGHT && *count < SECONDARY_STACK_SIZE {
+        let refraction = refract_through(position, normal, ray_direction, surface);
+        if refraction.exits {
+            (*stack)[*count] = SecondaryRay(refraction.origin, depth + 1u, refraction.direction, transmission * refraction.transmittance);
+            *count += 1u;
+        }
+    }
+}
+
+// Share of the specular ambient light a traced reflection replaces at `depth`. Smooth surfaces
+// reflect the scene, rough ones keep the uniform ambient light, and past bounce_depth nothing
+// is traced
+fn traced_gloss(surface: Surface, depth: u32) -> f32 {
+    if depth >= constants.bounce_depth {
+        return 0.0;
+    }
+    return 1.0 - smoothstep(0.0, MAX_GLOSSY_ROUGHNESS, surface.roughness);
+}
+
+struct Refraction {
+    origin: vec3<f32>,
+    direction: vec3<f32>,
+    // Absorbed inside and reflected back in on the way out
+    transmittance: vec3<f32>,
+    // False when the light never found its way out
+    exits: bool,
+}
+
+// Follows the ray refracted into the volume at `position` to where it leaves again, reflecting
+// it back in wherever it meets the surface too flat to refract. The volume absorbs along the
+// way with Beer-Lambert
+fn refract_through(position: vec3<f32>, normal: vec3<f32>, ray_direction: vec3<f32>, surface: Surface) -> Refraction {
+    let f0 = vec3<f32>(dielectric_f0(surface.ior));
+    // The march stops short of the surface, start just below it
+    let entry = position - normal * (max(map(position).res, 0.0) + PATH_SURFACE_OFFSET);
+    var origin = entry;
+    var direction = refract(ray_direction, normal, 1.0 / surface.ior);
+    var travelled = 0.0;
+    for (var i = 0u; i <= MAX_INTERNAL_REFLECTIONS; i++) {
+        let exit = trace_inside(origin, direction);
+        travelled += distance(origin, exit.position);
+        if !exit.hit {
+            break;
+        }
+        let exit_normal = get_normal(exit.position);
+        let refracted = refract(direction, -exit_normal, surface.ior);
+        if any(refracted != vec3<f32>(0.0)) {
+            let fresnel = fresnel_schlick(max(dot(refracted, exit_normal), 0.0), f0);
+            let transmittance = exp(-surface.absorption * travelled) * (1.0 - fresnel);
+            return Refraction(exit.position + exit_normal * PATH_SURFACE_OFFSET, refracted, transmittance, true);
+        }
+        direction = reflect(direction, -exit_normal);
+        origin = exit.position - exit_normal * PATH_SURFACE_OFFSET;
+    }
+    return Refraction(entry, direction, vec3<f32>(0.0), false);
+}
+
+// trace_path_ray from inside the scene to where the ray leaves it. Volumes without a way out,
+// like the ground, never hit
+fn trace_inside(ray_origin: vec3<f32>, ray_direction: vec3<f32>) -> PathHit {
+    var t = 0.0;
+    for (var i = 0; i < MAX_PATH_STEPS; i++) {
+        let p = ray_origin + ray_direction * t;
+        let distance = -map(p).res;
+        if distance < PATH_HIT_DISTANCE {
+            return PathHit(p, true);
+        }
+        t += grid_step(p, distance);
+        if t > MAX_PATH_DISTANCE {
+            break;
+        }
+    }
+    return PathHit(ray_origin + ray_direction * t, false);
+}
+
+fn max_component(v: vec3<f32>) -> f32 {
+    return max(v.x, max(v.y, v.z));
+}
+
+// Like sample_light, but area lights are shaded from a random point `u` on their surface,
+// which averages out to soft shadows
+fn sample_light_point(light: Light, p: vec3<f32>, u: vec2<f32>) -> LightSample {
+    var point_light = light;
+    if light.kind == LIGHT_SPHERE {
+        let z = 1.0 - 2.0 * u.x;
+        let r = sqrt(max(1.0 - z * z, 0.0));
+        let phi = 2.0 * PI * u.y;
+        point_light.position += vec3<f32>(r * cos(phi), r * sin(phi), z) * light.size.x;
+        point_light.kind = LIGHT_POINT;
+    } else if light.kind == LIGHT_RECT {
+        let tangent = light_tangent(light.direction);
+        let bitangent = cross(light.direction, tangent);
+        point_light.position += tangent * (2.0 * u.x - 1.0) * light.size.x;
+        point_light.position += bitangent * (2.0 * u.y - 1.0) * light.size.y;
+        point_light.size = vec2<f32>(0.0);
+    }
+    return sample_light(point_light, p);
+}
+
+struct BrdfSample {
+    direction: vec3<f32>,
+    // brdf over the pdf of `direction`, zero below the surface
+    weight: vec3<f32>,
+}
+
+// Picks the specular lobe with probability specular_probability and the cosine weighted
+// diffuse lobe otherwise, weighted by the pdf of both so either choice is unbiased
+fn sample_brdf(normal: vec3<f32>, view_dir: vec3<f32>, surface: Surface, f0: vec3<f32>, u: vec3<f32>) -> BrdfSample {
+    let roughness = max(surface.roughness, MIN_ROUGHNESS);
+    let n_dot_v = max(dot(normal, view_dir), 1e-4);
+    let p_specular = specular_probability(n_dot_v, surface, f0);
+    let frame = tangent_frame(normal);
+    var direction: vec3<f32>;
+    if u.z < p_specular {
+        direction = reflect(-view_dir, frame * sample_ggx_half(u.xy, roughness));
+    } else {
+        direction = frame * sample_cosine(u.xy);
+    }
+
+    let n_dot_l = dot(normal, direction);
+    if n_dot_l <= 0.0 {
+        return BrdfSample(direction, vec3<f32>(0.0));
+    }
+    let half_dir = normalize(direction + view_dir);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+    let specular_pdf = distribution_ggx(n_dot_h, roughness) * n_dot_h / (4.0 * max(dot(view_dir, half_dir), 1e-4));
+    let diffuse_pdf = n_dot_l / PI;
+    let pdf = mix(diffuse_pdf, specular_pdf, p_specular);
+    return BrdfSample(direction, brdf(normal, view_dir, direction, surface, f0, 0.0) / max(pdf, 1e-6));
+}
+
+// Share of the reflected light in the specular lobe, kept away from 0 and 1 so neither
+// lobe goes unsampled
+fn specular_probability(n_dot_v: f32, surface: Surface, f0: vec3<f32>) -> f32 {
+    let fresnel = fresnel_schlick(n_dot_v, f0);
+    let specular = dot(fresnel, vec3<f32>(1.0 / 3.0));
+    let diffuse = dot((1.0 - fresnel) * (1.0 - surface.metallic) * (1.0 - surface.transmission) * surface.albedo, vec3<f32>(1.0 / 3.0));
+    return clamp(specular / max(specular + diffuse, 1e-4), 0.1, 0.9);
+}
+
+// Cosine weighted direction around +z
+fn sample_cosine(u: vec2<f32>) -> vec3<f32> {
+    let r = sqrt(u.x);
+    let phi = 2.0 * PI * u.y;
+    return vec3<f32>(r * cos(phi), r * sin(phi), sqrt(max(1.0 - u.x, 0.0)));
+}
+
+// GGX distributed half vector around +z
+fn sample_ggx_half(u: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let alpha = roughness * roughness;
+    let cos_theta = sqrt((1.0 - u.x) / (1.0 + (alpha * alpha - 1.0) * u.x));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 2.0 * PI * u.y;
+    return vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+// Orthonormal basis with `normal` as +z (Duff et al. 2017)
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    let s = select(-1.0, 1.0, normal.z >= 0.0);
+    let a = -1.0 / (s + normal.z);
+    let b = normal.x * normal.y * a;
+    let tangent = vec3<f32>(1.0 + s * normal.x * normal.x * a, s * b, -s * normal.x);
+    let bitangent = vec3<f32>(b, s + normal.y * normal.y * a, -normal.y);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+fn pcg_hash(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1)
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = pcg_hash(*seed);
+    return f32(*seed >> 8u) / 16777216.0;
+}
+
+fn is_finite(v: vec3<f32>) -> bool {
+    let bits = bitcast<vec3<u32>>(v) & vec3<u32>(0x7fffffffu);
+    return all(bits < vec3<u32>(0x7f800000u));
+}
+
+// Direction through `pixel`, fractional pixels land between the pixel centers
+fn camera_ray(pixel: vec2<f32>) -> vec3<f32> {
     let aspect =  constants.texture_size.x / constants.texture_size.y;
-    var uv = (pixelCoord / constants.texture_size) * 2.0 - vec2<f32>(1.0, 1.0);
+    var uv = (pixel / constants.texture_size) * 2.0 - vec2<f32>(1.0, 1.0);
     uv.y = -uv.y;
     uv.x *= aspect;
 
+    // Multiplied from the left the view's rotation turns camera space back into world space
+    let view = mat3x3<f32>(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz);
+    return normalize(vec3<f32>(uv * camera.tan_half_fov, 1.0) * view);
+}
 
-    var ray_origin = constants.ray_origin.xyz;
-    var ray_direction = normalize(vec3<f32>(uv * rot2d(constants.rotation) * constants.fov, 1.0));
-    let yz = ray_direction.yz * rot2d(constants.yz_rotation);
-    ray_direction = normalize(vec3<f32>(ray_direction.x, yz));
+// Atmosphere radiance along a ray, `sun_disk` adds the disk on top of the scattered light
+fn sky(ray_direction: vec3<f32>, sun_disk: bool) -> vec3<f32> {
+    let sun_dir = normalize(constants.sun_dir.xyz);
+    let uv = sky_view_uv(ray_direction, sun_dir);
+    var luminance = textureSampleLevel(sky_view_lut, lut_sampler, uv, 0.0).rgb;
+    let height = atmosphere.view_height;
+    if sun_disk && distance_to_ground(height, ray_direction.y) < 0.0 {
+        let angle = acos(clamp(dot(ray_direction, sun_dir), -1.0, 1.0));
+        let disk = 1.0 - smoothstep(SUN_ANGULAR_RADIUS * 0.95, SUN_ANGULAR_RADIUS * 1.05, angle);
+        let sun_radiance = atmosphere.sun_illuminance / SUN_SOLID_ANGLE;
+        luminance += disk * sun_radiance * transmittance_to_top(height, ray_direction.y);
+    }
 
-    var distance_traveled = 0.0;
-    var color = vec3<f32>(0.0);
-    var normal = vec3<f32>(0.0);
-    var material = 0;
-    for (var i = 0; i < 80; i++) {
-        let new_ray_position = ray_origin + ray_direction.xyz * distance_traveled;
-        let res = map(new_ray_position);
-        let distance = res.res;
-        distance_traveled += distance;
-
-        if distance < 0.05 || distance_traveled > 100.0 {
-
-            normal = get_normal(new_ray_position);
-            ray_origin = new_ray_position;
-            if distance_traveled > 100.0 {
-                 material = -1;
-            } else {
-                material = res.material;
-            }
+    return luminance * constants.sun_color.xyz * constants.sun_intensity;
+}
+
+// Clouds further along the view ray are lost in the aerial perspective anyway
+const CLOUD_MAX_DISTANCE: f32 = 2000.0;
+const CLOUD_LIGHT_STEPS: u32 = 4u;
+const CLOUD_NOISE_OCTAVES: u32 = 4u;
+// Droplets scatter mostly forwards, with a weaker lobe back towards the sun
+const CLOUD_FORWARD_G: f32 = 0.6;
+const CLOUD_BACK_G: f32 = -0.3;
+const CLOUD_FORWARD_WEIGHT: f32 = 0.7;
+// Light scattered more than once still gets through thick clouds, modelled as a weaker share
+// of the sunlight that is extinguished more slowly
+const CLOUD_MULTI_SCATTERING: f32 = 0.3;
+const CLOUD_MULTI_EXTINCTION: f32 = 0.25;
+// Marches stop once this little of the background gets through
+const MIN_VOLUME_TRANSMITTANCE: f32 = 0.01;
+
+// Light the clouds along a view ray scatter towards the camera, how much of the sky behind
+// them gets through and how far away they are on average
+struct CloudSample {
+    transmittance: f32,
+    scattered: vec3<f32>,
+    distance: f32,
+}
+
+// The sky with the clouds in front of it, the aerial perspective between them and the camera
+// and the sun disk behind them. `jitter` in [0, 1) offsets the samples
+fn cloudy_sky(ray_direction: vec3<f32>, jitter: f32) -> vec3<f32> {
+    let background = sky(ray_direction, true);
+    let clouds = march_clouds(camera.position, ray_direction, jitter);
+    if clouds.transmittance >= 1.0 {
+        return background;
+    }
+    // The sky already holds the air in front of the clouds, only the share they hide of it is
+    // taken back out
+    let air = aerial_perspective(ray_direction, clouds.distance);
+    return background * clouds.transmittance + clouds.scattered * air.transmittance
+        + air.inscattering * (1.0 - clouds.transmittance);
+}
+
+// Single scattering of the sun and the sky through the cloud layer along a view ray
+fn march_clouds(ray_origin: vec3<f32>, ray_direction: vec3<f32>, jitter: f32) -> CloudSample {
+    var result = CloudSample(1.0, vec3<f32>(0.0), 0.0);
+    let clouds = volumetrics.clouds;
+    if clouds.coverage <= 0.0 || clouds.density <= 0.0 {
+        return result;
+    }
+    // Where the ray is between the bottom and the top of the layer
+    var start = 0.0;
+    var end = CLOUD_MAX_DISTANCE;
+    if abs(ray_direction.y) < 1e-6 {
+        if ray_origin.y < clouds.bottom || ray_origin.y > clouds.top {
+            return result;
+        }
+    } else {
+        let bottom = (clouds.bottom - ray_origin.y) / ray_direction.y;
+        let top = (clouds.top - ray_origin.y) / ray_direction.y;
+        start = max(min(bottom, top), 0.0);
+        end = min(max(bottom, top), CLOUD_MAX_DISTANCE);
+    }
+    if start >= end {
+        return result;
+    }
 
+    let sun_dir = normalize(constants.sun_dir.xyz);
+    let cos_view_sun = dot(ray_direction, sun_dir);
+    let phase = mix(henyey_greenstein(cos_view_sun, CLOUD_BACK_G), henyey_greenstein(cos_view_sun, CLOUD_FORWARD_G), CLOUD_FORWARD_WEIGHT);
+    let light = atmosphere.sun_illuminance * constants.sun_color.xyz * constants.sun_intensity;
+    let ambient = sky(vec3<f32>(0.0, 1.0, 0.0), false);
+    let step = (end - start) / f32(clouds.steps);
+    var weighted_distance = 0.0;
+    var opacity = 0.0;
+    for (var i = 0u; i < clouds.steps; i++) {
+        let t = start + (f32(i) + jitter) * step;
+        let p = ray_origin + ray_direction * t;
+        let density = cloud_density(p);
+        if density <= 0.0 {
+            continue;
+        }
+        // The sun through the air above the scene, then through the cloud towards it
+        let height = atmosphere.view_height + max(p.y - camera.position.y, 0.0) * atmosphere.distance_scale;
+        let sun = light * sun_transmittance(height, sun_dir.y) * cloud_sun_transmittance(p, sun_dir);
+        // Cloud bases only see the sky through the cloud above them
+        let layer = (p.y - clouds.bottom) / (clouds.top - clouds.bottom);
+        let source = sun * phase + ambient * mix(0.5, 1.0, layer);
+
+        let step_transmittance = exp(-density * step);
+        let absorbed = result.transmittance * (1.0 - step_transmittance);
+        result.scattered += source * absorbed;
+        weighted_distance += t * absorbed;
+        opacity += absorbed;
+        result.transmittance *= step_transmittance;
+        if result.transmittance < MIN_VOLUME_TRANSMITTANCE {
             break;
         }
     }
+    result.distance = select(start, weighted_distance / opacity, opacity > 0.0);
+    return result;
+}
 
-    // let diff = max(0.0, dot(normal, normalize(constants.sun_dir)));
+// Extinction per scene unit of the clouds at `p`
+fn cloud_density(p: vec3<f32>) -> f32 {
+    let clouds = volumetrics.clouds;
+    let layer = (p.y - clouds.bottom) / (clouds.top - clouds.bottom);
+    if layer <= 0.0 || layer >= 1.0 {
+        return 0.0;
+    }
+    // Flat bases and rounded tops
+    let profile = smoothstep(0.0, 0.1, layer) * (1.0 - smoothstep(0.4, 1.0, layer));
+    let noise = cloud_noise((p - clouds.wind * constants.time) / clouds.scale);
+    return clouds.density * clamp((noise * profile - (1.0 - clouds.coverage)) / clouds.coverage, 0.0, 1.0);
+}
 
-    // color = diff * constants.sun_color + vec3<f32>(0.1, 0.1, 0.1);
-    switch material {
-        case 0: {
-            color = light(normal, ray_origin, ray_direction, vec3<f32>(1.0), 1.0);
+// Share of the sunlight reaching `p` through the cloud between it and the sun
+fn cloud_sun_transmittance(p: vec3<f32>, sun_dir: vec3<f32>) -> f32 {
+    let clouds = volumetrics.clouds;
+    let step = 0.5 * (clouds.top - clouds.bottom) / f32(CLOUD_LIGHT_STEPS);
+    var optical_depth = 0.0;
+    for (var i = 0u; i < CLOUD_LIGHT_STEPS; i++) {
+        optical_depth += cloud_density(p + sun_dir * (f32(i) + 0.5) * step) * step;
+    }
+    return max(exp(-optical_depth), CLOUD_MULTI_SCATTERING * exp(-optical_depth * CLOUD_MULTI_EXTINCTION));
+}
+
+// Fractal value noise in [0, 1], the largest features a unit across
+fn cloud_noise(p: vec3<f32>) -> f32 {
+    var sum = 0.0;
+    var total = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var i = 0u; i < CLOUD_NOISE_OCTAVES; i++) {
+        sum += amplitude * value_noise(q);
+        total += amplitude;
+        amplitude *= 0.5;
+        // Shifted so the lattices of the octaves don't line up
+        q = q * 2.0 + vec3<f32>(17.0, 5.0, 11.0);
+    }
+    return sum / total;
+}
+
+// Smoothly interpolated between random values on the integer lattice, hashed from the cell
+// rather than from float math so it's the same on every device
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = vec3<i32>(cell);
+    let x00 = mix(lattice_value(c), lattice_value(c + vec3<i32>(1, 0, 0)), u.x);
+    let x10 = mix(lattice_value(c + vec3<i32>(0, 1, 0)), lattice_value(c + vec3<i32>(1, 1, 0)), u.x);
+    let x01 = mix(lattice_value(c + vec3<i32>(0, 0, 1)), lattice_value(c + vec3<i32>(1, 0, 1)), u.x);
+    let x11 = mix(lattice_value(c + vec3<i32>(0, 1, 1)), lattice_value(c + vec3<i32>(1, 1, 1)), u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+fn lattice_value(cell: vec3<i32>) -> f32 {
+    let hash = pcg_hash(bitcast<u32>(cell.x) + pcg_hash(bitcast<u32>(cell.y) + pcg_hash(bitcast<u32>(cell.z))));
+    return f32(hash >> 8u) / 16777216.0;
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * pow(denom, 1.5));
+}
+
+// Fog volumes are sampled this far apart inside, and sphere traced up to in between
+const FOG_VOLUME_STEP: f32 = 0.1;
+const MAX_FOG_VOLUME_STEPS: i32 = 64;
+const FOG_LIGHT_STEPS: u32 = 3u;
+const FOG_LIGHT_STEP: f32 = 0.3;
+
+// What the fog volumes at a point do to light passing through, the albedo and phase averaged
+// over the overlapping volumes by their density
+struct FogMedium {
+    extinction: f32,
+    albedo: vec3<f32>,
+    phase: f32,
+}
+
+// Single scattering of the sun and the ambient light in the fog volumes along `max_distance`
+// of a view ray. The sun is shadowed by the scene's geometry and the fog towards it
+fn march_fog_volumes(ray_origin: vec3<f32>, ray_direction: vec3<f32>, max_distance: f32, jitter: f32) -> Haze {
+    var haze = Haze(vec3<f32>(1.0), vec3<f32>(0.0));
+    if volumetrics.volume_count == 0u {
+        return haze;
+    }
+    let sun_dir = normalize(constants.sun_dir.xyz);
+    let sun = constants.sun_color.xyz * constants.sun_intensity;
+    let cos_view_sun = dot(ray_direction, sun_dir);
+    var t = 0.0;
+    for (var i = 0; i < MAX_FOG_VOLUME_STEPS && t < max_distance; i++) {
+        let distance = fog_volumes_distance(ray_origin + ray_direction * t);
+        if distance > FOG_VOLUME_STEP {
+            t += distance;
+            continue;
+        }
+        let step = min(FOG_VOLUME_STEP, max_distance - t);
+        let p = ray_origin + ray_direction * (t + step * jitter);
+        t += step;
+        let medium = fog_medium(p, cos_view_sun);
+        if medium.extinction <= 0.0 {
+            continue;
         }
-        case 2: {
-            let ran = hash33(ray_origin * 5.0);
-            color = light(normal, ray_origin, ray_direction, ran, 1.0);
+        var source = vec3<f32>(constants.ambient);
+        if any(sun > vec3<f32>(0.0)) {
+            let shadow = soft_shadow(p, sun_dir, 0.01, 50.0, 32.0);
+            source += sun * medium.phase * shadow * fog_sun_transmittance(p, sun_dir);
         }
-        case  -1: {
-            let sun_dir = normalize(constants.sun_dir.xyz);
-            let distance_out = intersectRaySphereFromInside(origin_view, ray_direction, radiusAtmo);
-            let view_out = origin_view + ray_direction * distance_out;
-            var luminance = compute_luminance(view_out, sun_dir);
-            luminance += direct_light_from_sun(ray_direction, view_out, sun_dir);
-            let sky_exposure = 1.0 / 120000.0 * 2;
+        source *= medium.albedo;
 
-            color = luminance * sky_exposure * constants.sun_color.xyz * constants.sun_intensity;
+        let step_transmittance = exp(-medium.extinction * step);
+        haze.inscattering += haze.transmittance * (source - source * step_transmittance);
+        haze.transmittance *= step_transmittance;
+        if max_component(haze.transmittance) < MIN_VOLUME_TRANSMITTANCE {
+            break;
         }
-        default: {
-            color = light(normal, ray_origin, ray_direction, vec3<f32>(1.0, 0.0, 0.0), 0.5);
+    }
+    return haze;
+}
+
+// Distance to the nearest fog volume's boundary, negative inside one
+fn fog_volumes_distance(p: vec3<f32>) -> f32 {
+    var distance = EMPTY_DISTANCE;
+    for (var i = 0u; i < volumetrics.volume_count; i++) {
+        let volume = volumetrics.volumes[i];
+        let local = (volume.world_to_local * vec4<f32>(p, 1.0)).xyz;
+        distance = min(distance, sd_primitive(local, volume.primitive));
+    }
+    return distance;
+}
+
+fn fog_medium(p: vec3<f32>, cos_view_sun: f32) -> FogMedium {
+    var medium = FogMedium(0.0, vec3<f32>(0.0), 0.0);
+    for (var i = 0u; i < volumetrics.volume_count; i++) {
+        let volume = volumetrics.volumes[i];
+        let density = fog_volume_density(volume, p);
+        medium.extinction += density;
+        medium.albedo += density * volume.albedo;
+        medium.phase += density * henyey_greenstein(cos_view_sun, volume.anisotropy);
+    }
+    if medium.extinction > 0.0 {
+        medium.albedo /= medium.extinction;
+        medium.phase /= medium.extinction;
+    }
+    return medium;
+}
+
+// Fades in over `edge` below the boundary, so the volumes have no hard outline
+fn fog_volume_density(volume: FogVolume, p: vec3<f32>) -> f32 {
+    let local = (volume.world_to_local * vec4<f32>(p, 1.0)).xyz;
+    let distance = sd_primitive(local, volume.primitive);
+    return volume.density * clamp(-distance / volume.edge, 0.0, 1.0);
+}
+
+// Share of the sunlight reaching `p` through the fog between it and the sun
+fn fog_sun_transmittance(p: vec3<f32>, sun_dir: vec3<f32>) -> f32 {
+    var optical_depth = 0.0;
+    for (var i = 0u; i < FOG_LIGHT_STEPS; i++) {
+        let tap = p + sun_dir * (f32(i) + 0.5) * FOG_LIGHT_STEP;
+        for (var v = 0u; v < volumetrics.volume_count; v++) {
+            optical_depth += fog_volume_density(volumetrics.volumes[v], tap) * FOG_LIGHT_STEP;
         }
     }
+    return exp(-optical_depth);
+}
 
-    color *= constants.exposure;
+// Surfaces fade into the fogged sky over the last fifth of the march to camera.far, so it has
+// no edge
+const FADE_START: f32 = 0.8;
+// How far away the sky counts as for the height fog
+const FOG_SKY_DISTANCE: f32 = 1000.0;
+
+// What the air between the camera and a surface does to its color:
+// color * transmittance + inscattering
+struct Haze {
+    transmittance: vec3<f32>,
+    inscattering: vec3<f32>,
+}
 
-    textureStore(output_texture, vec2<i32>(pixelCoord), vec4(color, 1.0));
+// `near` seen through `far`
+fn combine_haze(near: Haze, far: Haze) -> Haze {
+    return Haze(near.transmittance * far.transmittance, near.inscattering * far.transmittance + far.inscattering);
+}
+
+// Scattering of the atmosphere along `distance` scene units of the view ray. The air is taken
+// as uniform at the camera's height, which holds while the scene stays far below the scale
+// heights, and lit like the sky-view LUT
+fn aerial_perspective(ray_direction: vec3<f32>, distance: f32) -> Haze {
+    let height = atmosphere.view_height;
+    let sun_dir = normalize(constants.sun_dir.xyz);
+    let medium = sample_medium(height);
+    let cos_view_sun = dot(ray_direction, sun_dir);
+    let sun = sun_transmittance(height, sun_dir.y);
+    let multi = multi_scattering(height, sun_dir.y);
+    let source = medium.rayleigh * (rayleigh_phase(cos_view_sun) * sun + multi)
+        + medium.mie * (mie_phase(cos_view_sun) * sun + multi);
+
+    let transmittance = exp(-medium.extinction * distance * atmosphere.distance_scale);
+    let light = atmosphere.sun_illuminance * constants.sun_color.xyz * constants.sun_intensity;
+    let inscattering = (source - source * transmittance) / max(medium.extinction, vec3<f32>(1e-6)) * light;
+    return Haze(transmittance, inscattering);
+}
+
+// Exponential height fog along `distance` of the view ray, lit by the sky at the horizon
+fn height_fog(ray_direction: vec3<f32>, distance: f32) -> Haze {
+    if fog.density <= 0.0 {
+        return Haze(vec3<f32>(1.0), vec3<f32>(0.0));
+    }
+    let density = fog.density * exp(-fog.falloff * (camera.position.y - fog.height));
+    // Integral of the density along the ray, the limit is a straight line through a layer
+    let slope = fog.falloff * ray_direction.y;
+    var optical_depth = density * distance;
+    if abs(slope * distance) > 1e-4 {
+        optical_depth = density * (1.0 - exp(min(-slope * distance, 80.0))) / slope;
+    }
+    let transmittance = exp(-optical_depth);
+    let light = fog.albedo * sky(vec3<f32>(ray_direction.x, 0.0, ray_direction.z), false);
+    return Haze(vec3<f32>(transmittance), light * (1.0 - transmittance));
+}
+
+// Aerial perspective and fog in front of a surface `distance` away
+fn camera_haze(ray_direction: vec3<f32>, distance: f32) -> Haze {
+    var haze = combine_haze(aerial_perspective(ray_direction, distance), height_fog(ray_direction, distance));
+    let fade = smoothstep(camera.far * FADE_START, camera.far, distance);
+    if fade > 0.0 {
+        let sky_fog = height_fog(ray_direction, FOG_SKY_DISTANCE);
+        let background = sky(ray_direction, true) * sky_fog.transmittance + sky_fog.inscattering;
+        haze.transmittance *= 1.0 - fade;
+        haze.inscattering = mix(haze.inscattering, background, fade);
+    }
+    return haze;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Octahedral mapping of a unit vector to [-1, 1]², decoded in horizon_ao.wgsl
+fn encode_normal(n: vec3<f32>) -> vec2<f32> {
+    let p = n.xy / (abs(n.x) + abs(n.y) + abs(n.z));
+    if n.z >= 0.0 {
+        return p;
+    }
+    return (1.0 - abs(p.yx)) * select(vec2<f32>(-1.0), vec2<f32>(1.0), p >= vec2<f32>(0.0));
 }
 
 fn hash33(p: vec3<f32>) -> vec3<f32> {
@@ -128,69 +1110,570 @@ fn get_sun(ray_direction: vec3<f32>) -> vec3<f32>
 fn disney_diffuse(
     normal: vec3<f32>,
     view_dir: vec3<f32>,
+    light_dir: vec3<f32>,
     base_color: vec3<f32>,
     roughness: f32
 ) -> vec3<f32> {
 
     let N = normal;
     let V = view_dir;
-    let L = normalize(constants.sun_dir.xyz);
+    let L = light_dir;
     let H = normalize(L + V);
 
     let cos_theta_l = max(dot(N, L), 0.0);
     let cos_theta_v = max(dot(N, V), 0.0);
     let cos_theta_d = max(dot(L, H), 0.0);
 
-    let fd90 = 0.5 + 2.0 * roughness * cos_theta_d * cos_theta_d;
+    // Renormalized so the retro-reflection of rough surfaces doesn't add energy
+    let energy_bias = 0.5 * roughness;
+    let energy_factor = mix(1.0, 1.0 / 1.51, roughness);
+    let fd90 = energy_bias + 2.0 * roughness * cos_theta_d * cos_theta_d;
 
     let light_scatter = 1.0 + (fd90 - 1.0) * pow(1.0 - cos_theta_l, 5.0);
     let view_scatter  = 1.0 + (fd90 - 1.0) * pow(1.0 - cos_theta_v, 5.0);
 
     let diffuse = base_color / PI;
 
-    return diffuse * light_scatter * view_scatter * cos_theta_l;
+    return diffuse * light_scatter * view_scatter * energy_factor * cos_theta_l;
 }
 
+// Sun, lights, ambient and emission at a surface point, see ambient_light for `reflected`
 fn light(
     normal: vec3<f32>,
     ray_origin: vec3<f32>,
     ray_dir: vec3<f32>,
-    base_color: vec3<f32>,
+    surface: Surface,
+    reflected: f32,
+) -> vec3<f32> {
+     let view_dir = -ray_dir;
+     let f0 = surface_f0(surface);
+
+     var color = ambient_light(normal, view_dir, surface, reflected) * ambient_occlusion(ray_origin, normal);
+     color += surface.emissive;
+
+     let shadow_origin = ray_origin + normal * 0.01;
+     let sun_dir = normalize(constants.sun_dir.xyz);
+     let sun = constants.sun_color.xyz * constants.sun_intensity;
+     if any(sun > vec3<f32>(0.0)) {
+         let shadow = soft_shadow(shadow_origin, sun_dir, 0.01, 50, 32);
+         color += brdf(normal, view_dir, sun_dir, surface, f0, 0.0) * sun * shadow;
+     }
+
+     for (var i = 0u; i < constants.lights_count; i++) {
+         let light = lights[i];
+         let sample = sample_light(light, ray_origin);
+         if all(sample.radiance <= vec3<f32>(0.0)) || dot(normal, sample.direction) <= 0.0 {
+             continue;
+         }
+         var shadow = vec3<f32>(1.0);
+         if light.shadows != 0u {
+             // Penumbrae widen with the light's apparent size, and the march stops at its
+             // surface so geometry right behind an area light doesn't shadow it
+             let k = clamp(sample.distance / max(sample.size, 1e-3), 2.0, 32.0);
+             let maxt = max(sample.distance - sample.size, 0.02);
+             shadow = soft_shadow(shadow_origin, sample.direction, 0.01, maxt, k);
+         }
+         let brdf = brdf(normal, view_dir, sample.direction, surface, f0, sample.size / sample.distance);
+         color += brdf * sample.radiance * shadow;
+     }
+     return color;
+}
+
+// Cook-Torrance: GGX distribution, Smith geometry and Schlick Fresnel in a metallic workflow,
+// times the cosine term. Light reflected by the specular lobe never reaches the diffuse lobe,
+// and metals have no diffuse. `light_angle` is the light's size over its distance, it widens
+// the specular lobe and renormalizes it so area lights keep their energy.
+fn brdf(
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    light_dir: vec3<f32>,
+    surface: Surface,
+    f0: vec3<f32>,
+    light_angle: f32,
+) -> vec3<f32> {
+    let half_dir = normalize(light_dir + view_dir);
+    let fresnel = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+    let diffuse_weight = (1.0 - fresnel) * (1.0 - surface.metallic) * (1.0 - surface.transmission);
+    let diffuse = diffuse_weight * disney_diffuse(normal, view_dir, light_dir, surface.albedo, surface.roughness);
+
+    let roughness = max(surface.roughness, MIN_ROUGHNESS);
+    let alpha = roughness * roughness;
+    let wide_alpha = min(alpha + light_angle * 0.5, 1.0);
+    let normalization = (alpha / wide_alpha) * (alpha / wide_alpha);
+    let specular = specular_ggx(normal, view_dir, light_dir, half_dir, sqrt(wide_alpha), fresnel);
+    return diffuse + specular * normalization;
+}
+
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_SPOT: u32 = 1u;
+const LIGHT_SPHERE: u32 = 2u;
+const LIGHT_RECT: u32 = 3u;
+
+struct LightSample {
+    direction: vec3<f32>,
+    distance: f32,
+    // Incoming radiance before shadowing
+    radiance: vec3<f32>,
+    // Radius of the emitting surface, 0 for punctual lights
+    size: f32,
+}
+
+// Mirrors sample_light in cpu_reference.rs
+fn sample_light(light: Light, p: vec3<f32>) -> LightSample {
+    var position = light.position;
+    var size = 0.0;
+    if light.kind == LIGHT_RECT {
+        // Shaded from the closest point on the rectangle
+        let tangent = light_tangent(light.direction);
+        let bitangent = cross(light.direction, tangent);
+        let local = p - light.position;
+        position += tangent * clamp(dot(local, tangent), -light.size.x, light.size.x);
+        position += bitangent * clamp(dot(local, bitangent), -light.size.y, light.size.y);
+        size = length(light.size);
+    } else if light.kind == LIGHT_SPHERE {
+        size = light.size.x;
+    }
+
+    let to_light = position - p;
+    let distance = max(length(to_light), 1e-4);
+    let direction = to_light / distance;
+    var attenuation = distance_attenuation(max(distance, size), light.range);
+    if light.kind == LIGHT_SPOT {
+        attenuation *= smoothstep(light.cos_outer, light.cos_inner, dot(-direction, light.direction));
+    } else if light.kind == LIGHT_RECT {
+        attenuation *= max(dot(-direction, light.direction), 0.0);
+    }
+    return LightSample(direction, distance, light.radiance * attenuation, size);
+}
+
+// Inverse square falloff, windowed to reach zero at range
+fn distance_attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / max(distance * distance, 1e-4);
+}
+
+// Rectangle width axis, perpendicular to the normal and horizontal unless the normal is vertical
+fn light_tangent(normal: vec3<f32>) -> vec3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(normal.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    return normalize(cross(up, normal));
+}
+
+// Keeps the GGX peak finite for perfectly smooth surfaces lit by the sun
+const MIN_ROUGHNESS: f32 = 0.045;
+
+// Specular BRDF times the cosine term, mirrors specular_ggx in cpu_reference.rs
+fn specular_ggx(
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    light_dir: vec3<f32>,
+    half_dir: vec3<f32>,
     roughness: f32,
+    fresnel: vec3<f32>,
 ) -> vec3<f32> {
-     let diffuse = disney_diffuse(normal, -ray_dir, base_color, roughness);
-     let light_dir = normalize(constants.sun_dir.xyz);
-     let ambient = 0.3;
-     let shadow = soft_shadow(ray_origin + normal * 0.01, light_dir, 0.01, 50, 32);
-     return ambient+diffuse * (constants.sun_color.xyz * constants.sun_intensity) * shadow;
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 1e-4);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    return d * g * fresnel / (4.0 * n_dot_v * max(n_dot_l, 1e-4)) * n_dot_l;
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denom * denom);
+}
+
+// Schlick-GGX per direction with the k of analytic lights
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+// Reflectance at normal incidence of a dielectric with index of refraction `ior` in air,
+// 0.04 for glass
+fn dielectric_f0(ior: f32) -> f32 {
+    let r = (ior - 1.0) / (ior + 1.0);
+    return r * r;
+}
+
+// Metals reflect in their own color
+fn surface_f0(surface: Surface) -> vec3<f32> {
+    return mix(vec3<f32>(dielectric_f0(surface.ior)), surface.albedo, surface.metallic);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Uniform ambient light, split between the lobes with the roughness aware Fresnel. `reflected`
+// is the share of the specular lobe a traced reflection stands in for
+fn ambient_light(normal: vec3<f32>, view_dir: vec3<f32>, surface: Surface, reflected: f32) -> vec3<f32> {
+    let roughness = max(surface.roughness, MIN_ROUGHNESS);
+    let n_dot_v = max(dot(normal, view_dir), 1e-4);
+    let f0 = surface_f0(surface);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * (1.0 - surface.transmission) * surface.albedo;
+    return constants.ambient * (diffuse + fresnel * (1.0 - reflected));
 }
 
-fn soft_shadow(ray_origin: vec3<f32>, ray_dir: vec3<f32>, mint: f32, maxt: f32, k: f32) -> f32 {
+// Weight of every distance field AO tap relative to the one before
+const AO_FALLOFF: f32 = 0.75;
+
+// Share of the ambient light reaching `position`, from `ao_samples` taps of the distance field
+// along the normal. Open space is at least as far away as the tap is from the surface, any
+// shortfall is geometry blocking the sky. Closer taps weigh more, they see the contact shadows
+fn ambient_occlusion(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if constants.ao_samples == 0u {
+        return 1.0;
+    }
+    var occlusion = 0.0;
+    var total = 0.0;
+    var weight = 1.0;
+    for (var i = 1u; i <= constants.ao_samples; i++) {
+        let h = constants.ao_radius * f32(i) / f32(constants.ao_samples);
+        let d = map(position + normal * h).res;
+        occlusion += weight * clamp((h - d) / h, 0.0, 1.0);
+        total += weight;
+        weight *= AO_FALLOFF;
+    }
+    return 1.0 - occlusion / total;
+}
+
+// Rough surfaces reflect less at grazing angles, for light from every direction
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Transmissive shapes let their share of the light through instead of refracting it, absorbed
+// along the way, so glass and water cast tinted shadows rather than black ones
+fn soft_shadow(ray_origin: vec3<f32>, ray_dir: vec3<f32>, mint: f32, maxt: f32, k: f32) -> vec3<f32> {
     var res = 1.0;
+    var tint = vec3<f32>(1.0);
     var t = mint;
+    // Where the ray entered a transmissive shape, negative outside of one
+    var entered = -1.0;
+    var absorption = vec3<f32>(0.0);
 
     for(var i=0; i<64 && t < maxt; i++) {
-        let h = map(ray_origin + ray_dir*t).res;
-        if (h<0.001) {return 0.0;}
-        res = min(res, k*h/t);
-        t += h;
+        let p = ray_origin + ray_dir*t;
+        let sdf = map(p);
+        let h = sdf.res;
+        let m = table_material(sdf.material);
+        if (h<0.001) {
+            if m.transmission <= 0.0 {return vec3<f32>(0.0);}
+            if entered < 0.0 {
+                entered = t;
+                absorption = m.absorption;
+                tint *= m.transmission;
+            }
+            t += max(-h, PATH_SURFACE_OFFSET);
+            continue;
+        }
+        if entered >= 0.0 {
+            tint *= exp(-absorption * (t - entered));
+            entered = -1.0;
+        }
+        res = min(res, mix(k*h/t, 1.0, m.transmission));
+        t += grid_step(p, h);
+    }
+    if entered >= 0.0 {
+        tint *= exp(-absorption * (t - entered));
     }
 
-    return res;
+    return res * tint;
 }
 
 
 fn map(new_ray_position: vec3<f32>) -> SdfData {
-    var res = SdfData(new_ray_position.y + 0.75, -1);
-    for (var i = 0u; i < constants.objects_count; i = i + 1u) {
-        var object = objects[i];
-        res = sdf_union(res, SdfData(sdSphere(new_ray_position - object.position.xyz, object.position.w), object.material));
+    var res = SdfData(EMPTY_DISTANCE, -1);
+    // Only the objects around the point, march loops step by grid_step to stay safe
+    var lookup = grid_lookup(new_ray_position);
+    while grid_has_next(lookup) {
+        let object = objects[grid_next(&lookup)];
+        let distance = object_distance(new_ray_position, object);
+        let weight = csg_weight(res.res, distance, object.operation, object.blend_radius);
+        res.res = csg_distance(res.res, distance, object.operation, object.blend_radius);
+        res.material = select(res.material, object.material, weight > 0.5);
     }
+    res = sdf_union(res, SdfData(sd_octree(new_ray_position), octree.material));
     let ground = new_ray_position.y + 0.75;
     res = sdf_union(SdfData(ground, 0), res);
     return res;
 }
 
+// Distance in world units, the local distance is scaled by the smallest world scale
+fn object_distance(p: vec3<f32>, object: RaymarchingObject) -> f32 {
+    let local = (object.world_to_local * vec4<f32>(p, 1.0)).xyz;
+    return sd_primitive(local, object.primitive) * object.scale_bound;
+}
+
+struct Surface {
+    albedo: vec3<f32>,
+    roughness: f32,
+    emissive: vec3<f32>,
+    metallic: f32,
+    absorption: vec3<f32>,
+    ior: f32,
+    transmission: f32,
+}
+
+const PATTERN_NOISE: u32 = 1u;
+const PATTERN_CHECKER: u32 = 2u;
+
+// Mirrors MISSING_MATERIAL, for indices outside the material table
+const MISSING_MATERIAL = Material(vec3<f32>(1.0, 0.0, 0.0), 0.5, vec3<f32>(0.0), 0.0, vec3<f32>(0.0), 1.5, 0u, 0.0, 0u, 0u);
+
+// Table entry `index`, MISSING_MATERIAL outside the table
+fn table_material(index: i32) -> Material {
+    if index >= 0 && u32(index) < constants.materials_count {
+        return materials[index];
+    }
+    return MISSING_MATERIAL;
+}
+
+fn material_surface(index: i32, position: vec3<f32>) -> Surface {
+    let m = table_material(index);
+    return Surface(m.albedo * pattern_color(m.pattern, position), m.roughness, m.emissive, m.metallic, m.absorption, m.ior, m.transmission);
+}
+
+// Multiplied into the albedo, mirrors pattern_color in cpu_reference.rs
+fn pattern_color(pattern: u32, position: vec3<f32>) -> vec3<f32> {
+    switch pattern {
+        case PATTERN_NOISE: {
+            return hash33(position * 5.0);
+        }
+        case PATTERN_CHECKER: {
+            let cell = floor(position * 2.0);
+            let odd = (i32(cell.x) + i32(cell.y) + i32(cell.z)) & 1;
+            return vec3<f32>(select(1.0, 0.2, odd == 1));
+        }
+        default: {
+            return vec3<f32>(1.0);
+        }
+    }
+}
+
+fn mix_surface(a: Surface, b: Surface, t: f32) -> Surface {
+    return Surface(
+        mix(a.albedo, b.albedo, t),
+        mix(a.roughness, b.roughness, t),
+        mix(a.emissive, b.emissive, t),
+        mix(a.metallic, b.metallic, t),
+        mix(a.absorption, b.absorption, t),
+        mix(a.ior, b.ior, t),
+        mix(a.transmission, b.transmission, t),
+    );
+}
+
+// Same fold as map(), but mixes surfaces across smooth joins instead of picking one material
+fn scene_surface(p: vec3<f32>) -> Surface {
+    var distance = EMPTY_DISTANCE;
+    var surface = material_surface(-1, p);
+    var lookup = grid_lookup(p);
+    while grid_has_next(lookup) {
+        let object = objects[grid_next(&lookup)];
+        let object_distance = object_distance(p, object);
+        let weight = csg_weight(distance, object_distance, object.operation, object.blend_radius);
+        distance = csg_distance(distance, object_distance, object.operation, object.blend_radius);
+        surface = mix_surface(surface, material_surface(object.material, p), weight);
+    }
+    let octree_distance = sd_octree(p);
+    if octree_distance < distance {
+        distance = octree_distance;
+        surface = material_surface(octree.material, p);
+    }
+    if p.y + 0.75 <= distance {
+        surface = material_surface(0, p);
+    }
+    return surface;
+}
+
+// Same fold as map(), the object whose material it would pick, -1 where the ground or the
+// octree is closer
+fn scene_object(p: vec3<f32>) -> i32 {
+    var distance = EMPTY_DISTANCE;
+    var index = -1;
+    var lookup = grid_lookup(p);
+    while grid_has_next(lookup) {
+        let object_index = grid_next(&lookup);
+        let object = objects[object_index];
+        let object_distance = object_distance(p, object);
+        let weight = csg_weight(distance, object_distance, object.operation, object.blend_radius);
+        distance = csg_distance(distance, object_distance, object.operation, object.blend_radius);
+        index = select(index, i32(object_index), weight > 0.5);
+    }
+    if sd_octree(p) < distance || p.y + 0.75 <= distance {
+        return -1;
+    }
+    return index;
+}
+
+const EMPTY_DISTANCE: f32 = 1e9;
+const GRID_MARGIN: f32 = 0.1;
+
+// Cursor over the unbounded objects merged with the objects of the cell around a point
+struct GridLookup {
+    global: u32,
+    cell: u32,
+    cell_end: u32,
+    // Lower bound on the distance to every object the cursor skips
+    bound: f32,
+}
+
+// Mirrors ObjectGrid::lookup
+fn grid_lookup(p: vec3<f32>) -> GridLookup {
+    var lookup = GridLookup(0u, 0u, 0u, EMPTY_DISTANCE);
+    if grid.dims.x == 0u {
+        return lookup;
+    }
+
+    let local = p - grid.origin;
+    let half = vec3<f32>(grid.dims) * grid.cell_size * 0.5;
+    let outside = sdBox(local - half, half);
+    if outside > 0.0 {
+        lookup.bound = outside + GRID_MARGIN;
+        return lookup;
+    }
+
+    let cell = min(vec3<u32>(max(local / grid.cell_size, vec3<f32>(0.0))), grid.dims - 1u);
+    let cell_min = vec3<f32>(cell) * grid.cell_size;
+    let to_exit = min(local - cell_min, cell_min + grid.cell_size - local);
+    lookup.bound = max(min(to_exit.x, min(to_exit.y, to_exit.z)), 0.0) + GRID_MARGIN;
+    let range = grid_cells[cell.x + cell.y * grid.dims.x + cell.z * grid.dims.x * grid.dims.y];
+    lookup.cell = range.first;
+    lookup.cell_end = range.first + range.count;
+    return lookup;
+}
+
+// Clamps a step by distance to the objects map() skipped at p
+fn grid_step(p: vec3<f32>, distance: f32) -> f32 {
+    return min(distance, grid_lookup(p).bound);
+}
+
+fn grid_has_next(lookup: GridLookup) -> bool {
+    return lookup.global < grid.global_count || lookup.cell < lookup.cell_end;
+}
+
+// Next object index in list order, so CSG operations fold like without the grid
+fn grid_next(lookup: ptr<function, GridLookup>) -> u32 {
+    let has_global = (*lookup).global < grid.global_count;
+    let has_cell = (*lookup).cell < (*lookup).cell_end;
+    if has_global && (!has_cell || grid_indices[(*lookup).global] < grid_indices[(*lookup).cell]) {
+        (*lookup).global += 1u;
+        return grid_indices[(*lookup).global - 1u];
+    }
+    (*lookup).cell += 1u;
+    return grid_indices[(*lookup).cell - 1u];
+}
+const CSG_UNION: u32 = 0u;
+const CSG_SUBTRACTION: u32 = 1u;
+const CSG_INTERSECTION: u32 = 2u;
+const CSG_SMOOTH_UNION: u32 = 3u;
+const CSG_SMOOTH_SUBTRACTION: u32 = 4u;
+const CSG_SMOOTH_INTERSECTION: u32 = 5u;
+const MIN_BLEND_RADIUS: f32 = 1e-4;
+
+// Combines the accumulated distance a with an object's distance b, mirrors CsgOperation::distance
+fn csg_distance(a: f32, b: f32, operation: u32, blend_radius: f32) -> f32 {
+    let k = max(blend_radius, MIN_BLEND_RADIUS);
+    switch operation {
+        case CSG_SUBTRACTION: { return max(a, -b); }
+        case CSG_INTERSECTION: { return max(a, b); }
+        case CSG_SMOOTH_UNION: {
+            let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+            return mix(b, a, h) - k * h * (1.0 - h);
+        }
+        case CSG_SMOOTH_SUBTRACTION: {
+            let h = clamp(0.5 - 0.5 * (a + b) / k, 0.0, 1.0);
+            return mix(a, -b, h) + k * h * (1.0 - h);
+        }
+        case CSG_SMOOTH_INTERSECTION: {
+            let h = clamp(0.5 - 0.5 * (b - a) / k, 0.0, 1.0);
+            return mix(b, a, h) + k * h * (1.0 - h);
+        }
+        default: { return min(a, b); }
+    }
+}
+
+// How much of the object's material shows on the combined surface, mirrors CsgOperation::weight
+fn csg_weight(a: f32, b: f32, operation: u32, blend_radius: f32) -> f32 {
+    let k = max(blend_radius, MIN_BLEND_RADIUS);
+    switch operation {
+        case CSG_SUBTRACTION, CSG_SMOOTH_SUBTRACTION: { return 0.0; }
+        case CSG_INTERSECTION: { return select(0.0, 1.0, b > a); }
+        case CSG_SMOOTH_UNION: { return 1.0 - clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0); }
+        case CSG_SMOOTH_INTERSECTION: { return 1.0 - clamp(0.5 - 0.5 * (b - a) / k, 0.0, 1.0); }
+        default: { return select(0.0, 1.0, b < a); }
+    }
+}
+
+const NO_BRICK: u32 = 0xffffffffu;
+const BRICK_SIZE: u32 = 8u;
+const OCTREE_EXIT_EPSILON: f32 = 0.01;
+
+// Distance to the voxel octree, mirrors Octree::distance
+fn sd_octree(p: vec3<f32>) -> f32 {
+    if octree.node_count == 0u {
+        return EMPTY_DISTANCE;
+    }
+    let local = p - octree.origin;
+    let half = vec3<f32>(octree.size * 0.5);
+    let outside = sdBox(local - half, half);
+    if outside > 0.0 {
+        return outside + octree.leaf_size * OCTREE_EXIT_EPSILON;
+    }
+
+    var node_index = 0u;
+    var cell_min = vec3<f32>(0.0);
+    var cell_size = octree.size;
+    for (var level = 0u; level <= octree.depth; level++) {
+        let node = octree_nodes[node_index];
+        if node.brick != NO_BRICK {
+            return sample_brick(node.brick, (local - cell_min) / cell_size);
+        }
+        cell_size *= 0.5;
+        let upper = local >= cell_min + cell_size;
+        let child = select(0u, 1u, upper.x) | select(0u, 2u, upper.y) | select(0u, 4u, upper.z);
+        cell_min += select(vec3<f32>(0.0), vec3<f32>(cell_size), upper);
+        let bit = 1u << child;
+        if (node.child_mask & bit) == 0u {
+            break;
+        }
+        node_index = node.first_child + countOneBits(node.child_mask & (bit - 1u));
+    }
+
+    // Empty cells have no surface inside, so leaving the cell is always safe
+    let to_min = local - cell_min;
+    let to_max = cell_min + cell_size - local;
+    let to_exit = min(to_min, to_max);
+    return max(min(to_exit.x, min(to_exit.y, to_exit.z)), 0.0) + octree.leaf_size * OCTREE_EXIT_EPSILON;
+}
+
+fn brick_distance(brick: u32, x: u32, y: u32, z: u32) -> f32 {
+    return octree_bricks[brick * BRICK_SIZE * BRICK_SIZE * BRICK_SIZE + x + y * BRICK_SIZE + z * BRICK_SIZE * BRICK_SIZE];
+}
+
+fn sample_brick(brick: u32, t: vec3<f32>) -> f32 {
+    let grid = clamp(t, vec3<f32>(0.0), vec3<f32>(1.0)) * f32(BRICK_SIZE - 1u);
+    let base = min(floor(grid), vec3<f32>(f32(BRICK_SIZE - 2u)));
+    let f = grid - base;
+    let i = vec3<u32>(base);
+
+    let x00 = mix(brick_distance(brick, i.x, i.y, i.z), brick_distance(brick, i.x + 1u, i.y, i.z), f.x);
+    let x10 = mix(brick_distance(brick, i.x, i.y + 1u, i.z), brick_distance(brick, i.x + 1u, i.y + 1u, i.z), f.x);
+    let x01 = mix(brick_distance(brick, i.x, i.y, i.z + 1u), brick_distance(brick, i.x + 1u, i.y, i.z + 1u), f.x);
+    let x11 = mix(brick_distance(brick, i.x, i.y + 1u, i.z + 1u), brick_distance(brick, i.x + 1u, i.y + 1u, i.z + 1u), f.x);
+    return mix(mix(x00, x10, f.y), mix(x01, x11, f.y), f.z);
+}
+
 struct SdfData {
     res: f32,
     material: i32
@@ -234,138 +1717,93 @@ fn repeat(p: vec3<f32>, c: f32) -> vec3<f32> {
 }
 
 
-fn sdSphere(new_ray_position: vec3<f32>, radius: f32 ) -> f32
-{
-  return length(new_ray_position) - radius;
-}
-
-fn sdRoundBox(p: vec3<f32>, b: vec3<f32>, r: f32 ) -> f32
-{
-  let q = abs(p) - b + r;
-  return length(max(q, vec3<f32>(0.0))) + min(max(q.x,max(q.y,q.z)),0.0) - r;
-}
-
-fn rot2d(angle: f32) -> mat2x2<f32> {
-    let sin = sin(angle);
-    let cos = cos(angle);
-    return mat2x2<f32>(cos, -sin, sin, cos);
-}
-
-//https://cpp-rendering.io/sky-and-atmosphere-rendering/
-
-const Hr = 8000;
-const Hm = 1200;
-const Ho = 8000;
-
-const rayleigh = vec3(5.8, 13.5, 33.1) * 1e-6;
-const mie = vec3(21, 21, 21) * 1e-6;
-const ozone = vec3(3.426, 8.298, 0.356) * 0.06 * 1e-5;
-const radiusEarth = 6360e3;
-const radiusAtmo = 6420e3;
-const ZenithH = radiusAtmo - radiusEarth;
-const origin_view = vec3(0, radiusEarth + 1, 0);
-const originH = origin_view.y - radiusEarth;
-const STEPS = 8;
-
-const radius_size = radians(0.53 / 2.0) * 10;
-const sun_solid_angle = 2 * PI * (1 - cos(radius_size));
-
-//vec3 TrZenith = transmittance(origin_view, vec3(0, radiusEarth + ZenithH, 0));
-const TrZenith = exp(-(rayleigh * Hr * (exp(-originH / Hr) - exp(-ZenithH / Hr)) +
-                            mie * Hm * (exp(-originH / Hm) - exp(-ZenithH / Hm)) +
-                            ozone * Ho * (exp(-originH / Ho) - exp(-ZenithH / Ho))));
-
-const illuminanceGround = 120000 * (vec3(1.0, 1.0, 1.0));
-const L_outerspace = (illuminanceGround / sun_solid_angle) / TrZenith;
-
-fn compute_luminance(out_atmosphere: vec3<f32>, sun_dir: vec3<f32>) -> vec3<f32> {
-    let ds = (out_atmosphere - origin_view) / STEPS;
-    let direction = normalize(ds);
-    var acc = vec3(0.0);
-
-    for (var i = 0.0; i < STEPS; i+=1.0) {
-        let s = origin_view + (i + 0.5) * ds;
-        acc += transmittance(origin_view, s) * j(s, direction, sun_dir);
+const PRIMITIVE_SPHERE: u32 = 0u;
+const PRIMITIVE_BOX: u32 = 1u;
+const PRIMITIVE_ROUND_BOX: u32 = 2u;
+const PRIMITIVE_TORUS: u32 = 3u;
+const PRIMITIVE_CAPSULE: u32 = 4u;
+const PRIMITIVE_CYLINDER: u32 = 5u;
+const PRIMITIVE_CONE: u32 = 6u;
+const PRIMITIVE_PLANE: u32 = 7u;
+const PRIMITIVE_ELLIPSOID: u32 = 8u;
+
+fn sd_primitive(p: vec3<f32>, primitive: Primitive) -> f32 {
+    let params = primitive.params;
+    switch primitive.kind {
+        case PRIMITIVE_BOX: { return sdBox(p, params.xyz); }
+        case PRIMITIVE_ROUND_BOX: { return sdRoundBox(p, params.xyz, params.w); }
+        case PRIMITIVE_TORUS: { return sdTorus(p, params.xy); }
+        case PRIMITIVE_CAPSULE: { return sdCapsule(p, params.x, params.y); }
+        case PRIMITIVE_CYLINDER: { return sdCylinder(p, params.x, params.y); }
+        case PRIMITIVE_CONE: { return sdCappedCone(p, params.x, params.y, params.z); }
+        case PRIMITIVE_PLANE: { return sdPlane(p, params.xyz, params.w); }
+        case PRIMITIVE_ELLIPSOID: { return sdEllipsoid(p, params.xyz); }
+        default: { return sdSphere(p, params.x); }
     }
-
-    return acc * length(ds);
 }
 
-fn intersectRaySphereFromInside(rayOrigin: vec3<f32>, rayDir: vec3<f32>, radius: f32) -> f32 {
-    let b = dot(rayOrigin, rayDir);
-    let c = dot(rayOrigin, rayOrigin) - radius * radius;
-    let discriminant = b * b - c;
-    let t = -b + sqrt(discriminant);
-    return t;
-}
-
-fn j(position: vec3<f32>, view_dir: vec3<f32>, sun_dir: vec3<f32>) -> vec3<f32> {
-    let distance_out_atmosphere =
-        intersectRaySphereFromInside(position, sun_dir, radiusAtmo);
-    let out_atmosphere = position + sun_dir * distance_out_atmosphere;
-
-    let trToSun = transmittance(position, out_atmosphere);
-    let rayleigh_diffusion = sigma_s_rayleigh(position) * rayleigh_phase(view_dir, sun_dir);
-    let mie_diffusion = sigma_s_mie(position) * mie_phase(view_dir, sun_dir);
-
-    return L_outerspace * trToSun * sun_solid_angle * (rayleigh_diffusion + mie_diffusion);
-}
-
-fn rayleigh_phase(view_dir: vec3<f32>, sun_dir: vec3<f32>) -> f32 {
-    let mu = dot(view_dir, sun_dir);
-    return (3.0 / (16.0 * PI)) * (1.f + mu * mu);
+fn sdTorus(p: vec3<f32>, t: vec2<f32>) -> f32
+{
+  let q = vec2<f32>(length(p.xz) - t.x, p.y);
+  return length(q) - t.y;
 }
 
-fn mie_phase(view_dir: vec3<f32>, sun_dir: vec3<f32>) -> f32 {
-    let mu = dot(view_dir, sun_dir);
-    let g = 0.76;
-    let denom = 1.0 + g * g - 2.0 * g * mu;
-    return (1.0 - g * g) / (4.0 * PI * pow(denom, 1.5));
+fn sdCapsule(p: vec3<f32>, half_height: f32, radius: f32) -> f32
+{
+  let q = vec3<f32>(p.x, p.y - clamp(p.y, -half_height, half_height), p.z);
+  return length(q) - radius;
 }
 
-fn sigma_s_rayleigh(position: vec3<f32>) -> vec3<f32> {
-    let h = length(position) - radiusEarth;
-    return rayleigh * exp(-h / Hr);
+fn sdCylinder(p: vec3<f32>, half_height: f32, radius: f32) -> f32
+{
+  let d = abs(vec2<f32>(length(p.xz), p.y)) - vec2<f32>(radius, half_height);
+  return min(max(d.x, d.y), 0.0) + length(max(d, vec2<f32>(0.0)));
 }
 
-fn sigma_s_mie(position: vec3<f32>) -> vec3<f32> {
-    let h = length(position) - radiusEarth;
-    return mie * exp(-h / Hm);
+fn sdCappedCone(p: vec3<f32>, half_height: f32, r1: f32, r2: f32) -> f32
+{
+  let q = vec2<f32>(length(p.xz), p.y);
+  let k1 = vec2<f32>(r2, half_height);
+  let k2 = vec2<f32>(r2 - r1, 2.0 * half_height);
+  let ca = vec2<f32>(q.x - min(q.x, select(r2, r1, q.y < 0.0)), abs(q.y) - half_height);
+  let cb = q - k1 + k2 * clamp(dot(k1 - q, k2) / dot(k2, k2), 0.0, 1.0);
+  let s = select(1.0, -1.0, cb.x < 0.0 && ca.y < 0.0);
+  return s * sqrt(min(dot(ca, ca), dot(cb, cb)));
 }
 
-fn sigma_a_ozone(position: vec3<f32>) -> vec3<f32> {
-    let h = length(position) - radiusEarth;
-    return ozone * exp(-h / Ho);
+fn sdPlane(p: vec3<f32>, normal: vec3<f32>, offset: f32) -> f32
+{
+  var n = vec3<f32>(0.0, 1.0, 0.0);
+  if dot(normal, normal) > 0.0 {
+    n = normalize(normal);
+  }
+  return dot(p, n) + offset;
 }
 
-fn sigma_t(position: vec3<f32>) -> vec3<f32> {
-    return sigma_s_rayleigh(position) +
-           1.11 * sigma_s_mie(position) +
-           sigma_a_ozone(position);
+fn sdEllipsoid(p: vec3<f32>, ellipsoid_radii: vec3<f32>) -> f32
+{
+  let radii = max(ellipsoid_radii, vec3<f32>(1e-4));
+  let k0 = length(p / radii);
+  let k1 = length(p / (radii * radii));
+  if k1 == 0.0 {
+    return -min(radii.x, min(radii.y, radii.z));
+  }
+  return k0 * (k0 - 1.0) / k1;
 }
 
-fn integrate_sigma_t(from_vec: vec3<f32>, to_vec: vec3<f32>) -> vec3<f32> {
-    let ds = (to_vec - from_vec) / f32(STEPS);
-    var accumulation = vec3<f32>(0, 0, 0);
-
-    for (var i = 0.0; i < STEPS; i+=1.0) {
-        let s = from_vec + (i + 0.5) * ds;
-        accumulation += sigma_t(s);
-    }
-
-    return accumulation * length(ds);
+fn sdSphere(new_ray_position: vec3<f32>, radius: f32 ) -> f32
+{
+  return length(new_ray_position) - radius;
 }
 
-fn transmittance(from_vec: vec3<f32>, to_vec: vec3<f32>) -> vec3<f32> {
-    let integral = integrate_sigma_t(from_vec, to_vec);
-    return exp(-integral);
+fn sdBox(p: vec3<f32>, b: vec3<f32>) -> f32
+{
+  let q = abs(p) - b;
+  return length(max(q, vec3<f32>(0.0))) + min(max(q.x,max(q.y,q.z)),0.0);
 }
 
-fn direct_light_from_sun(direction: vec3<f32>, out_atmosphere: vec3<f32>, sun_dir: vec3<f32>) -> vec3<f32> {
-    let cos_theta = dot(direction, sun_dir);
-
-    let angle = acos(cos_theta);
-    let disk = 1.0 - smoothstep(radius_size * 0.95, radius_size * 1.05, angle);
-
-    return disk * L_outerspace * transmittance(origin_view, out_atmosphere);
+fn sdRoundBox(p: vec3<f32>, b: vec3<f32>, r: f32 ) -> f32
+{
+  let q = abs(p) - b + r;
+  return length(max(q, vec3<f32>(0.0))) + min(max(q.x,max(q.y,q.z)),0.0) - r;
 }
@@ -0,0 +1,192 @@
+//! Participating media marched along the camera rays, on top of the closed form haze of
+//! `fog.rs`: a layer of noise driven clouds in the sky, `march_clouds` in
+//! `raymarching_compute.wgsl`, and fog volumes bounded by primitives placed in the scene,
+//! `march_fog_volumes`.
+//!
+//! Both scatter the sun once, dimmed by the medium between the sample and the sun, and the fog
+//! volumes are also shadowed by the scene's geometry, so objects cast light shafts through
+//! them. The samples are offset per pixel and frame, which turns banding into noise that the
+//! path tracer averages away.
+
+use bytemuck::{Pod, Zeroable};
+use egui_probe::EguiProbe;
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::probes::{probe_color, probe_vec3};
+use crate::render_passes::raymarching_passes::primitives::{Primitive, probe_primitive};
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::probe_quat;
+
+/// Fog volumes the shader has room for, the rest of the list isn't rendered.
+pub const MAX_FOG_VOLUMES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Clouds {
+    /// Share of the sky the clouds cover, 0 disables them.
+    #[egui_probe(range = 0.0..=1.0)]
+    pub coverage: f32,
+    /// Extinction per scene unit in the thickest parts.
+    #[egui_probe(range = 0.0..=10.0)]
+    pub density: f32,
+    /// Height of the cloud bases, in scene units.
+    #[egui_probe(range = -1000.0..=1000.0)]
+    pub bottom: f32,
+    /// Height of the highest tops.
+    #[egui_probe(range = -1000.0..=1000.0)]
+    pub top: f32,
+    /// Size of the largest cloud features, in scene units.
+    #[egui_probe(range = 1.0..=1000.0)]
+    pub scale: f32,
+    /// How far the clouds drift per second.
+    #[egui_probe(with probe_vec3)]
+    pub wind: Vec3,
+    /// Samples along every view ray through the layer.
+    #[egui_probe(range = 1..=128)]
+    pub steps: u32,
+}
+
+impl Default for Clouds {
+    fn default() -> Self {
+        Self {
+            coverage: 0.0,
+            density: 0.3,
+            bottom: 25.0,
+            top: 40.0,
+            scale: 40.0,
+            wind: Vec3::new(2.0, 0.0, 1.0),
+            steps: 32,
+        }
+    }
+}
+
+impl Clouds {
+    pub fn to_gpu(&self) -> GpuClouds {
+        GpuClouds {
+            wind: self.wind.to_array(),
+            coverage: self.coverage.clamp(0.0, 1.0),
+            density: self.density.max(0.0),
+            bottom: self.bottom,
+            top: self.top.max(self.bottom + 0.01),
+            scale: self.scale.max(0.01),
+            steps: self.steps.max(1),
+            _pad: [0; 3],
+        }
+    }
+}
+
+/// Fog filling a primitive, placed in the scene like an object but never hit by the rays.
+#[derive(Debug, Clone, Copy, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FogVolume {
+    #[egui_probe(with probe_vec3)]
+    pub position: Vec3,
+    #[egui_probe(with probe_quat)]
+    pub rotation: Quat,
+    #[egui_probe(with probe_primitive)]
+    pub primitive: Primitive,
+    /// Extinction per scene unit inside, away from the boundary.
+    #[egui_probe(range = 0.0..=20.0)]
+    pub density: f32,
+    /// Fraction of the light the fog scatters rather than absorbs.
+    #[egui_probe(with probe_color)]
+    pub albedo: Vec3,
+    /// From -1 scattering back towards the light to 1 scattering on past it, positive values
+    /// make the fog glow looking towards the sun.
+    #[egui_probe(range = -0.9..=0.9)]
+    pub anisotropy: f32,
+    /// Depth below the boundary over which the density fades in, in scene units.
+    #[egui_probe(range = 0.0..=5.0)]
+    pub edge: f32,
+}
+
+impl Default for FogVolume {
+    fn default() -> Self {
+        Self::new(Vec3::ZERO, Primitive::default(), 1.0)
+    }
+}
+
+impl FogVolume {
+    pub fn new(position: Vec3, primitive: Primitive, density: f32) -> Self {
+        Self {
+            position,
+            rotation: Quat::IDENTITY,
+            primitive,
+            density,
+            albedo: Vec3::ONE,
+            anisotropy: 0.3,
+            edge: 0.2,
+        }
+    }
+
+    pub fn to_gpu(&self) -> GpuFogVolume {
+        GpuFogVolume {
+            world_to_local: Mat4::from_rotation_translation(self.rotation, self.position).inverse(),
+            primitive: self.primitive,
+            albedo: self.albedo.clamp(Vec3::ZERO, Vec3::ONE).to_array(),
+            density: self.density.max(0.0),
+            anisotropy: self.anisotropy.clamp(-0.9, 0.9),
+            edge: self.edge.max(1e-3),
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+/// Matches `Clouds` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuClouds {
+    pub wind: [f32; 3],
+    pub coverage: f32,
+    pub density: f32,
+    pub bottom: f32,
+    pub top: f32,
+    pub scale: f32,
+    pub steps: u32,
+    pub _pad: [u32; 3],
+}
+
+const _: () = assert!(size_of::<GpuClouds>() == 48);
+
+/// Matches `FogVolume` in `raymarching_compute.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuFogVolume {
+    pub world_to_local: Mat4,
+    pub primitive: Primitive,
+    pub albedo: [f32; 3],
+    pub density: f32,
+    pub anisotropy: f32,
+    pub edge: f32,
+    pub _pad: [f32; 2],
+}
+
+const _: () = assert!(size_of::<GpuFogVolume>() == 128);
+
+/// Matches `Volumetrics` in `raymarching_compute.wgsl`, a uniform so the fog volumes don't
+/// take up one of the storage buffers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GpuVolumetrics {
+    pub clouds: GpuClouds,
+    pub volume_count: u32,
+    pub _pad: [u32; 3],
+    pub volumes: [GpuFogVolume; MAX_FOG_VOLUMES],
+}
+
+const _: () = assert!(size_of::<GpuVolumetrics>() == 1088);
+
+impl GpuVolumetrics {
+    /// Keeps the first [`MAX_FOG_VOLUMES`] of `volumes`.
+    pub fn new(clouds: &Clouds, volumes: &[FogVolume]) -> Self {
+        let mut gpu = Self {
+            clouds: clouds.to_gpu(),
+            volume_count: volumes.len().min(MAX_FOG_VOLUMES) as u32,
+            ..Self::zeroed()
+        };
+        for (gpu, volume) in gpu.volumes.iter_mut().zip(volumes) {
+            *gpu = volume.to_gpu();
+        }
+        gpu
+    }
+}
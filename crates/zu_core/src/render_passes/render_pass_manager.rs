@@ -1,129 +1,144 @@
-use std::f32::consts::PI;
-
-use egui::{Response, Ui};
-use egui_probe::{EguiProbe, Style};
-use glam::{Vec3, Vec4};
-use wgpu::{CommandEncoder, Device, Queue, TextureView};
+use egui_probe::EguiProbe;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use wgpu::{CommandEncoder, Device, Features, Limits, Queue, TextureFormat, TextureView};
 
+use crate::capture::CaptureManager;
+use crate::octree::Octree;
+use crate::probes::{probe_color, probe_vec3};
 use crate::render_passes::quad_vertex::QuadVertexRenderPass;
+use crate::render_passes::raymarching_passes::ambient_occlusion::{
+    AmbientOcclusion, AoMode, HorizonAoPass,
+};
+use crate::render_passes::raymarching_passes::atmosphere::{AtmosphereParams, AtmospherePass};
+use crate::render_passes::raymarching_passes::camera::Camera;
+use crate::render_passes::raymarching_passes::fog::HeightFog;
+use crate::render_passes::raymarching_passes::light::Light;
+use crate::render_passes::raymarching_passes::material::Material;
+use crate::render_passes::raymarching_passes::path_tracing::{Accumulation, PathTracingOptions};
+use crate::render_passes::raymarching_passes::picking::{ObjectPicker, PickResult};
 use crate::render_passes::raymarching_passes::raymarching_pass_compute::{
-    RaymarchingObject, RaymarchingRenderComputePass,
+    RaymarchingConstants, RaymarchingObject, RaymarchingRenderComputePass,
 };
+use crate::render_passes::raymarching_passes::volumetrics::{Clouds, FogVolume};
 use crate::render_passes::show_pass::ShowRenderPass;
 use crate::texture_manager::{
     TextureManager,
     textures::{EngineTexture, TextureType},
 };
 
-#[derive(Debug, Clone, EguiProbe)]
+#[derive(Debug, Clone, PartialEq, EguiProbe, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RenderOptions {
-    show: String,
-    #[egui_probe(with probe_fov)]
-    FOV: f32,
-    #[egui_probe(with probe_rotation)]
-    rotation: f32,
-    #[egui_probe(with probe_vec3)]
-    ray_origin: Vec3,
-    raymarching_objects: Vec<RaymarchingObject>,
-    #[egui_probe(with probe_rotation)]
-    yz_rotation: f32,
+    pub show: String,
+    /// Moved with the fly and orbit controls in the window.
+    pub camera: Camera,
+    /// Edited in the GUI's outliner, clicking one in the window selects it there.
+    #[egui_probe(skip)]
+    pub raymarching_objects: Vec<RaymarchingObject>,
+    /// Edited in the GUI's materials window.
+    #[egui_probe(skip)]
+    pub materials: Vec<Material>,
     #[egui_probe(with probe_vec3)]
-    sun_dir: Vec3,
+    pub sun_dir: Vec3,
     #[egui_probe(with probe_color)]
-    sun_color: Vec3,
+    pub sun_color: Vec3,
     #[egui_probe(range = 0.0..=100.0)]
-    sun_intensity: f32,
+    pub sun_intensity: f32,
     #[egui_probe(range = 0.0..=100.0)]
-    exposure: f32,
-}
-
-fn probe_color(value: &mut Vec3, ui: &mut Ui, _style: &Style) -> Response {
-    let mut color = value.to_array();
-    let responese = ui
-        .horizontal(|ui| ui.color_edit_button_rgb(&mut color))
-        .response;
-    *value = Vec3::new(color[0], color[1], color[2]);
-    responese
-}
-
-fn probe_vec3(value: &mut Vec3, ui: &mut Ui, _style: &Style) -> Response {
-    ui.horizontal(|ui| {
-        ui.add(egui::DragValue::new(&mut value.x).speed(0.01));
-        ui.add(egui::DragValue::new(&mut value.y).speed(0.01));
-        ui.add(egui::DragValue::new(&mut value.z).speed(0.01));
-    })
-    .response
-}
-
-fn probe_rotation(value: &mut f32, ui: &mut Ui, _style: &Style) -> Response {
-    ui.horizontal(|ui| {
-        ui.add(
-            egui::Slider::new(value, 0.0..=(2.0 * PI))
-                .step_by(0.001)
-                .fixed_decimals(9)
-                .trailing_fill(true),
-        );
-    })
-    .response
-}
-
-fn probe_fov(value: &mut f32, ui: &mut Ui, _style: &Style) -> Response {
-    ui.horizontal(|ui| {
-        ui.add(
-            egui::Slider::new(value, 1.0..=120.0)
-                .step_by(0.1)
-                .fixed_decimals(1)
-                .trailing_fill(true),
-        );
-    })
-    .response
+    pub exposure: f32,
+    /// Uniform light from every direction, lower it for night and indoor scenes.
+    #[egui_probe(range = 0.0..=2.0)]
+    pub ambient: f32,
+    /// How much of the ambient light nearby geometry blocks.
+    pub ambient_occlusion: AmbientOcclusion,
+    /// Surfaces deep that reflections and refractions are traced, 0 leaves every surface
+    /// opaque and reflecting only the ambient light.
+    #[egui_probe(range = 0..=8)]
+    pub bounce_depth: u32,
+    /// Point, spot and area lights shaded next to the sun.
+    pub lights: Vec<Light>,
+    pub path_tracing: PathTracingOptions,
+    pub atmosphere: AtmosphereParams,
+    pub fog: HeightFog,
+    pub clouds: Clouds,
+    /// Fog bounded by primitives, lit through the scene's shadows. Only the first
+    /// [`crate::render_passes::raymarching_passes::volumetrics::MAX_FOG_VOLUMES`] are rendered.
+    pub fog_volumes: Vec<FogVolume>,
 }
 
 impl Default for RenderOptions {
     fn default() -> Self {
         Self {
             show: "Raymarching".into(), // Show scene texture directly
-            rotation: 0.0,
-            FOV: 1.0,
-            ray_origin: Vec3::new(0.0, 0.0, 3.0),
+            camera: Camera::default(),
             raymarching_objects: vec![
-                RaymarchingObject {
-                    position: Vec4::new(-0.5, 0.0, 0.0, 0.5),
-                    material: 0,
-                    _pad0: [0.0, 0.0, 0.0],
-                    _pad1: [0.0, 0.0, 0.0, 0.0],
-                },
-                RaymarchingObject {
-                    position: Vec4::new(0.5, 0.0, 0.0, 0.5),
-                    material: 0,
-                    _pad0: [0.0, 0.0, 0.0],
-                    _pad1: [0.0, 0.0, 0.0, 0.0],
-                },
+                RaymarchingObject::sphere(Vec3::new(-0.5, 0.0, 0.0), 0.5, 0),
+                RaymarchingObject::sphere(Vec3::new(0.5, 0.0, 0.0), 0.5, 0),
             ],
-            yz_rotation: 0.0,
+            materials: Material::defaults(),
             sun_dir: Vec3::new(1.0, 1.0, 0.5),
             sun_color: Vec3::new(1.0, 1.0, 1.0),
             sun_intensity: 1.0,
             exposure: 1.0,
+            ambient: 0.3,
+            ambient_occlusion: AmbientOcclusion::default(),
+            bounce_depth: 2,
+            lights: Vec::new(),
+            path_tracing: PathTracingOptions::default(),
+            atmosphere: AtmosphereParams::default(),
+            fog: HeightFog::default(),
+            clouds: Clouds::default(),
+            fog_volumes: Vec::new(),
         }
     }
 }
 
 pub struct RenderPassManager {
     raymarching_pass: RaymarchingRenderComputePass,
+    atmosphere_pass: AtmospherePass,
+    horizon_ao_pass: HorizonAoPass,
+    picker: ObjectPicker,
     show_pass: ShowRenderPass,
     quad_render_pass: QuadVertexRenderPass,
     render_options: RenderOptions,
     texture_manager: TextureManager,
+    capture_manager: CaptureManager,
+    octree: Octree,
+    octree_dirty: bool,
+    accumulation: Accumulation,
+    time_override: Option<f32>,
     width: u32,
     height: u32,
 }
 
 impl RenderPassManager {
+    /// Device features the render passes depend on (push constants, read-write f16 storage textures).
+    pub fn required_features() -> Features {
+        Features::PUSH_CONSTANTS | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+    }
+
+    /// Features that are used when available; software adapters often lack them.
+    pub fn optional_features() -> Features {
+        Features::FLOAT32_FILTERABLE
+    }
+
+    pub fn required_limits() -> Limits {
+        Limits {
+            max_push_constant_size: 128,
+            max_compute_workgroup_size_x: 64,
+            max_compute_workgroup_size_y: 64,
+            max_compute_invocations_per_workgroup: 1024,
+            ..Limits::default()
+        }
+    }
+
+    /// `output_format` is the format of the view passed to [`RenderPassManager::render`],
+    /// either the surface format or an offscreen target.
     pub fn new(
         device: &Device,
         queue: &Queue,
-        config: &wgpu::SurfaceConfiguration,
+        output_format: TextureFormat,
         width: u32,
         height: u32,
     ) -> RenderPassManager {
@@ -135,11 +150,34 @@ impl RenderPassManager {
             TextureType::StandardF16,
             1.0,
         );
+        texture_manager.create_texture(
+            "Accumulation",
+            (width, height),
+            device,
+            TextureType::StandardF32,
+            1.0,
+        );
+        texture_manager.create_texture(
+            "Depth normal",
+            (width, height),
+            device,
+            TextureType::StandardF32,
+            1.0,
+        );
         let quad_render_pass = QuadVertexRenderPass::new(device);
 
-        let show_pass = ShowRenderPass::new(device, config, &quad_render_pass);
-        let raymarching_pass =
-            RaymarchingRenderComputePass::new(device, queue, &mut texture_manager);
+        let show_pass = ShowRenderPass::new(device, output_format, &quad_render_pass);
+        let atmosphere_pass = AtmospherePass::new(device);
+        let picker = ObjectPicker::new(device);
+        let raymarching_pass = RaymarchingRenderComputePass::new(
+            device,
+            queue,
+            &mut texture_manager,
+            &atmosphere_pass,
+            &picker,
+        );
+        let horizon_ao_pass = HorizonAoPass::new(device, &texture_manager);
+        let capture_manager = CaptureManager::new(device, &quad_render_pass);
         Self {
             quad_render_pass,
 
@@ -147,10 +185,18 @@ impl RenderPassManager {
             show_pass,
 
             texture_manager,
+            capture_manager,
+            octree: Octree::default(),
+            octree_dirty: false,
+            accumulation: Accumulation::default(),
+            time_override: None,
 
             width,
             height,
             raymarching_pass,
+            atmosphere_pass,
+            horizon_ao_pass,
+            picker,
         }
     }
 
@@ -168,6 +214,7 @@ impl RenderPassManager {
         }
 
         self.texture_manager.resize(device, (width, height));
+        self.accumulation.reset();
         self.width = width;
         self.height = height;
     }
@@ -177,33 +224,156 @@ impl RenderPassManager {
         queue: &Queue,
         view: &TextureView,
         encoder: &mut CommandEncoder,
-        _device: &Device,
+        device: &Device,
     ) {
         puffin::profile_function!();
-        self.raymarching_pass.render(
-            queue,
+        if self.octree_dirty {
+            self.raymarching_pass
+                .upload_octree(device, &self.octree.to_gpu());
+            self.octree_dirty = false;
+            self.accumulation.reset();
+        }
+        let sequence_time = self.capture_manager.begin_frame();
+        let mut time = sequence_time.or(self.time_override);
+        if self.render_options.path_tracing.enabled && time.is_none() {
+            // The clock stops while accumulating, the average would start over every frame
+            time = self.accumulation.time();
+        }
+        self.raymarching_pass.set_time_override(time);
+        self.raymarching_pass
+            .upload_objects(device, queue, &self.render_options);
+        if self
+            .picker
+            .begin(queue, &self.render_options.raymarching_objects)
+        {
+            self.raymarching_pass.pick(
+                encoder,
+                &self.texture_manager,
+                self.width,
+                self.height,
+                &self.render_options,
+                &self.picker,
+            );
+            self.picker.encode_readback(encoder);
+        }
+        self.atmosphere_pass.update(
             encoder,
-            &self.texture_manager,
-            self.width,
-            self.height,
-            self.render_options.FOV,
-            self.render_options.rotation,
-            self.render_options.ray_origin,
-            &self.render_options.raymarching_objects,
-            self.render_options.yz_rotation,
+            queue,
+            &self.render_options.atmosphere,
             self.render_options.sun_dir,
-            self.render_options.sun_color,
-            self.render_options.sun_intensity,
-            self.render_options.exposure,
         );
+        if self.render_options.path_tracing.enabled {
+            // Once converged the last average stays in the texture
+            let time = self.raymarching_pass.time();
+            if let Some(sample_index) = self.accumulation.next_sample(&self.render_options, time) {
+                self.raymarching_pass.render_path_traced(
+                    encoder,
+                    &self.texture_manager,
+                    self.width,
+                    self.height,
+                    &self.render_options,
+                    sample_index,
+                );
+            }
+        } else {
+            self.accumulation.reset();
+            self.raymarching_pass.render(
+                encoder,
+                &self.texture_manager,
+                self.width,
+                self.height,
+                &self.render_options,
+            );
+            let ambient_occlusion = &self.render_options.ambient_occlusion;
+            if ambient_occlusion.mode == AoMode::Horizon {
+                let constants =
+                    RaymarchingConstants::new(&self.render_options, self.width, self.height, 0.0);
+                self.horizon_ao_pass.render(
+                    encoder,
+                    &self.texture_manager,
+                    &constants,
+                    &self.render_options.camera.to_gpu(),
+                    ambient_occlusion,
+                );
+            }
+        }
 
-        if let Some(texture) = self.texture_manager.get_texture(&self.render_options.show) {
+        let show_texture = self.texture_manager.get_texture(&self.render_options.show);
+        if let Some(texture) = show_texture {
             self.show_pass
                 .render(encoder, texture.bind_group(), view, &self.quad_render_pass);
         }
+        self.capture_manager.encode(
+            device,
+            encoder,
+            show_texture.map(|texture| texture.bind_group()),
+            &self.quad_render_pass,
+            self.width,
+            self.height,
+        );
+    }
+
+    /// Writes out the captures encoded by the last [`RenderPassManager::render`]. Call after
+    /// submitting that frame's encoder.
+    pub fn finish_captures(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> anyhow::Result<Vec<std::path::PathBuf>> {
+        self.capture_manager
+            .finish(device, queue, &self.texture_manager)
+    }
+
+    /// Queues a pick of the object at pixel `(x, y)` of the output, returned by
+    /// [`RenderPassManager::poll_pick`] once the GPU answered it. Ignored while the output has
+    /// no pixels.
+    pub fn pick(&mut self, x: u32, y: u32) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        self.picker
+            .request([x.min(self.width - 1), y.min(self.height - 1)]);
+    }
+
+    /// The last requested pick once it's read back. Call after submitting each frame, it
+    /// doesn't wait for the GPU.
+    pub fn poll_pick(&mut self, device: &Device) -> Option<PickResult> {
+        self.picker.poll(device)
+    }
+
+    /// Renders every following frame at a fixed scene `time` instead of the wall clock.
+    pub fn set_time(&mut self, time: Option<f32>) {
+        self.time_override = time;
+    }
+
+    pub fn capture_manager(&mut self) -> &mut CaptureManager {
+        &mut self.capture_manager
+    }
+
+    pub fn texture_manager(&self) -> &TextureManager {
+        &self.texture_manager
+    }
+
+    /// Path traced samples per pixel in the "Accumulation" texture.
+    pub fn path_tracing_samples(&self) -> u32 {
+        self.accumulation.samples()
+    }
+
+    pub fn octree(&self) -> &Octree {
+        &self.octree
+    }
+
+    /// Voxel octree rendered next to the objects, re-uploaded before the next frame.
+    pub fn octree_mut(&mut self) -> &mut Octree {
+        self.octree_dirty = true;
+        &mut self.octree
     }
 
     pub fn get_options(&mut self) -> &mut RenderOptions {
         &mut self.render_options
     }
+
+    pub fn get_options_and_capture(&mut self) -> (&mut RenderOptions, &mut CaptureManager) {
+        (&mut self.render_options, &mut self.capture_manager)
+    }
 }
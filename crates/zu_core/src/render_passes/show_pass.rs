@@ -1,6 +1,6 @@
 use wgpu::{
     BindGroup,
-    CommandEncoder, Device, TextureView,
+    CommandEncoder, Device, TextureFormat, TextureView,
 };
 
 use crate::render_passes::quad_vertex::QuadVertexRenderPass;
@@ -15,7 +15,7 @@ pub struct ShowRenderPass {
 impl ShowRenderPass {
     pub fn new(
         device: &Device,
-        config: &wgpu::SurfaceConfiguration,
+        output_format: TextureFormat,
         quad_render_pass: &QuadVertexRenderPass,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("./shaders/show_pass.wgsl"));
@@ -68,7 +68,7 @@ impl ShowRenderPass {
                 module: &shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: output_format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -0,0 +1,87 @@
+use bytemuck::{Pod, cast_slice};
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Device, Queue};
+
+/// Storage buffer of `T` that grows geometrically and only uploads elements that changed.
+///
+/// Keeps a CPU copy of the last upload to diff against. When [`StorageBuffer::write`] has to
+/// reallocate, bind groups referencing [`StorageBuffer::buffer`] must be recreated.
+pub struct StorageBuffer<T: Pod + PartialEq> {
+    label: &'static str,
+    buffer: Buffer,
+    capacity: usize,
+    uploaded: Vec<T>,
+}
+
+impl<T: Pod + PartialEq> StorageBuffer<T> {
+    pub fn new(device: &Device, label: &'static str, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            label,
+            buffer: create_buffer::<T>(device, label, capacity),
+            capacity,
+            uploaded: Vec::new(),
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Elements in the last upload.
+    pub fn len(&self) -> usize {
+        self.uploaded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uploaded.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Uploads `data`, writing only the runs of elements that differ from the last upload.
+    /// Returns `true` if the buffer was reallocated.
+    pub fn write(&mut self, device: &Device, queue: &Queue, data: &[T]) -> bool {
+        puffin::profile_function!();
+        let reallocated = data.len() > self.capacity;
+        if reallocated {
+            while self.capacity < data.len() {
+                self.capacity *= 2;
+            }
+            log::info!("Growing {} to {} elements", self.label, self.capacity);
+            self.buffer = create_buffer::<T>(device, self.label, self.capacity);
+            self.uploaded.clear();
+        }
+
+        let mut index = 0;
+        while index < data.len() {
+            if self.uploaded.get(index) == Some(&data[index]) {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < data.len() && self.uploaded.get(index) != Some(&data[index]) {
+                index += 1;
+            }
+            queue.write_buffer(
+                &self.buffer,
+                (start * size_of::<T>()) as u64,
+                cast_slice(&data[start..index]),
+            );
+        }
+
+        self.uploaded.clear();
+        self.uploaded.extend_from_slice(data);
+        reallocated
+    }
+}
+
+fn create_buffer<T>(device: &Device, label: &str, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: (size_of::<T>() * capacity) as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
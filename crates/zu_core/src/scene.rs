@@ -0,0 +1,106 @@
+//! Scene files: [`RenderOptions`] and every object saved as human-editable TOML.
+//!
+//! Files carry a `version`. Loading upgrades older layouts one version at a time in
+//! [`migrate`] before deserializing, and missing fields fall back to their defaults, so old
+//! scenes keep loading as the format grows. The voxel octree is generated data and not saved.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, bail};
+use glam::Quat;
+use serde::Serialize;
+
+use crate::render_passes::raymarching_passes::camera::Camera;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::claim_object_ids;
+use crate::render_passes::render_pass_manager::RenderOptions;
+
+/// Version written by [`save`]. Bump it and add a step to [`migrate`] when a field is renamed,
+/// moved or changes meaning.
+pub const SCENE_VERSION: u32 = 2;
+pub const SCENE_EXTENSION: &str = "toml";
+
+#[derive(Serialize)]
+struct SceneFile<'a> {
+    version: u32,
+    #[serde(flatten)]
+    options: &'a RenderOptions,
+}
+
+pub fn to_toml(options: &RenderOptions) -> anyhow::Result<String> {
+    Ok(toml::to_string_pretty(&SceneFile {
+        version: SCENE_VERSION,
+        options,
+    })?)
+}
+
+pub fn from_toml(source: &str) -> anyhow::Result<RenderOptions> {
+    let mut table: toml::Table = toml::from_str(source)?;
+    let version = match table.remove("version") {
+        Some(version) => version
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("Scene version is not a positive integer")?,
+        None => bail!("Scene has no version"),
+    };
+    if version > SCENE_VERSION {
+        bail!("Scene version {version} is newer than the supported version {SCENE_VERSION}");
+    }
+    for from in version..SCENE_VERSION {
+        migrate(&mut table, from)?;
+    }
+    let mut options: RenderOptions = table.try_into()?;
+    // Objects from before ids and names get fresh ids and are named after their primitive
+    claim_object_ids(&mut options.raymarching_objects);
+    for object in &mut options.raymarching_objects {
+        if object.name.is_empty() {
+            object.name = object.primitive.kind().name().to_owned();
+        }
+    }
+    Ok(options)
+}
+
+pub fn load(path: &Path) -> anyhow::Result<RenderOptions> {
+    let source =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    from_toml(&source).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+pub fn save(path: &Path, options: &RenderOptions) -> anyhow::Result<()> {
+    fs::write(path, to_toml(options)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Upgrades a scene table from version `from` to `from + 1`.
+fn migrate(table: &mut toml::Table, from: u32) -> anyhow::Result<()> {
+    match from {
+        1 => {
+            // The loose camera fields became `camera`: the two rotations turn into its
+            // orientation and the image plane's half height into a vertical FOV in degrees
+            let mut take = |key: &str| -> anyhow::Result<Option<f32>> {
+                table
+                    .remove(key)
+                    .map(|value| value.try_into())
+                    .transpose()
+                    .with_context(|| format!("Scene {key} is not a number"))
+            };
+            let rotation = take("rotation")?.unwrap_or(0.0);
+            let yz_rotation = take("yz_rotation")?.unwrap_or(0.0);
+            let mut camera = Camera {
+                orientation: Quat::from_rotation_x(yz_rotation) * Quat::from_rotation_z(rotation),
+                ..Default::default()
+            };
+            if let Some(half_height) = take("fov")? {
+                camera.fov = (2.0 * half_height.atan()).to_degrees();
+            }
+            if let Some(position) = table.remove("ray_origin") {
+                camera.position = position
+                    .try_into()
+                    .context("Scene ray_origin is not a vector")?;
+            }
+            table.insert("camera".into(), toml::Value::try_from(camera)?);
+            Ok(())
+        }
+        _ => bail!("Unknown scene version {from}"),
+    }
+}
@@ -0,0 +1,214 @@
+//! Parent/child hierarchy of the raymarching objects.
+//!
+//! Objects reference their parent by index in `RenderOptions::raymarching_objects`. The graph
+//! resolves every object's world transform and flattens the list into the storage buffer
+//! layout, keeping list order so CSG operations still fold in the same order. Hiding or
+//! soloing an object applies to its children too.
+
+use glam::{Mat4, Vec3};
+
+use crate::object_grid::Aabb;
+use crate::render_passes::raymarching_passes::raymarching_pass_compute::{
+    GpuRaymarchingObject, RaymarchingObject,
+};
+
+pub struct SceneGraph<'a> {
+    objects: &'a [RaymarchingObject],
+    world_transforms: Vec<Mat4>,
+    scale_bounds: Vec<f32>,
+    visible: Vec<bool>,
+}
+
+impl<'a> SceneGraph<'a> {
+    /// Resolves world transforms. Parents outside the list count as no parent, and an object
+    /// closing a parent cycle is treated as a root.
+    pub fn new(objects: &'a [RaymarchingObject]) -> Self {
+        let mut world_transforms = vec![Mat4::IDENTITY; objects.len()];
+        let mut scale_bounds = vec![1.0; objects.len()];
+        // Hidden and soloed by the object or an ancestor
+        let mut hidden = vec![false; objects.len()];
+        let mut soloed = vec![false; objects.len()];
+        let mut resolved = vec![false; objects.len()];
+        let mut on_path = vec![false; objects.len()];
+        let mut path = Vec::new();
+
+        for index in 0..objects.len() {
+            // Walk up to the first resolved ancestor or a root, then resolve back down
+            let mut current = Some(index);
+            while let Some(object) = current {
+                if resolved[object] || on_path[object] {
+                    break;
+                }
+                on_path[object] = true;
+                path.push(object);
+                current = objects[object]
+                    .parent()
+                    .filter(|parent| *parent < objects.len());
+            }
+            let (mut parent_transform, mut parent_scale, mut parent_hidden, mut parent_solo) =
+                match current {
+                    Some(parent) if resolved[parent] => (
+                        world_transforms[parent],
+                        scale_bounds[parent],
+                        hidden[parent],
+                        soloed[parent],
+                    ),
+                    _ => (Mat4::IDENTITY, 1.0, false, false),
+                };
+
+            for object in path.drain(..).rev() {
+                let local = &objects[object];
+                parent_transform *= local.local_transform();
+                parent_scale *= local.clamped_scale().abs().min_element();
+                parent_hidden |= local.hidden;
+                parent_solo |= local.solo;
+                world_transforms[object] = parent_transform;
+                scale_bounds[object] = parent_scale;
+                hidden[object] = parent_hidden;
+                soloed[object] = parent_solo;
+                resolved[object] = true;
+                on_path[object] = false;
+            }
+        }
+
+        let any_solo = objects.iter().any(|object| object.solo);
+        let visible = hidden
+            .iter()
+            .zip(&soloed)
+            .map(|(hidden, soloed)| !hidden && (soloed | !any_solo))
+            .collect();
+        Self {
+            objects,
+            world_transforms,
+            scale_bounds,
+            visible,
+        }
+    }
+
+    pub fn objects(&self) -> &'a [RaymarchingObject] {
+        self.objects
+    }
+
+    pub fn world_transform(&self, index: usize) -> Mat4 {
+        self.world_transforms[index]
+    }
+
+    pub fn world_position(&self, index: usize) -> Vec3 {
+        self.world_transforms[index].w_axis.truncate()
+    }
+
+    /// Whether the object renders: neither it nor an ancestor is hidden, and when any object is
+    /// soloed, it or an ancestor is.
+    pub fn is_visible(&self, index: usize) -> bool {
+        self.visible[index]
+    }
+
+    /// World space box around the object's surface, `None` for unbounded primitives.
+    pub fn world_bounds(&self, index: usize) -> Option<Aabb> {
+        let half = self.objects[index].primitive.half_extents()?;
+        Some(Aabb::new(-half, half).transformed(self.world_transforms[index]))
+    }
+
+    /// Indices of the direct children of `index`.
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(move |(_, object)| object.parent() == Some(index))
+            .map(|(child, _)| child)
+    }
+
+    /// The storage buffer contents, one entry per object in list order.
+    pub fn gpu_objects(&self) -> Vec<GpuRaymarchingObject> {
+        self.objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| GpuRaymarchingObject {
+                world_to_local: self.world_transforms[index].inverse(),
+                primitive: object.primitive,
+                material: object.material,
+                operation: object.operation,
+                blend_radius: object.blend_radius,
+                scale_bound: self.scale_bounds[index],
+            })
+            .collect()
+    }
+}
+
+/// Removes object `index` and renumbers the parents to match. Its children move to its parent
+/// and keep their world transform.
+pub fn remove_object(objects: &mut Vec<RaymarchingObject>, index: usize) {
+    if index >= objects.len() {
+        return;
+    }
+    let removed = objects.remove(index);
+    let renumber = |parent: i32| {
+        if parent > index as i32 {
+            parent - 1
+        } else {
+            parent
+        }
+    };
+    for object in objects.iter_mut() {
+        if object.parent() == Some(index) {
+            let transform = removed.local_transform() * object.local_transform();
+            (object.scale, object.rotation, object.position) =
+                transform.to_scale_rotation_translation();
+            object.parent = renumber(removed.parent);
+        } else {
+            object.parent = renumber(object.parent);
+        }
+    }
+}
+
+/// Moves object `from` to index `to` in the list, which changes the order CSG operations fold
+/// in, and renumbers the parents to match.
+pub fn move_object(objects: &mut Vec<RaymarchingObject>, from: usize, to: usize) {
+    if from >= objects.len() || to >= objects.len() || from == to {
+        return;
+    }
+    let object = objects.remove(from);
+    objects.insert(to, object);
+    let renumber = |index: usize| {
+        if index == from {
+            to
+        } else if from < index && index <= to {
+            index - 1
+        } else if to <= index && index < from {
+            index + 1
+        } else {
+            index
+        }
+    };
+    for object in objects.iter_mut() {
+        if let Some(parent) = object.parent() {
+            object.parent = renumber(parent) as i32;
+        }
+    }
+}
+
+/// Index of the object with `id`.
+pub fn object_index(objects: &[RaymarchingObject], id: u64) -> Option<usize> {
+    objects.iter().position(|object| object.id == id)
+}
+
+/// World position of object `index`, resolving only its parent chain. Matches
+/// [`SceneGraph::world_position`] except inside a parent cycle, where the chain stops at the
+/// object that would close it.
+pub fn world_position(objects: &[RaymarchingObject], index: usize) -> Vec3 {
+    let mut chain = vec![index];
+    let mut current = objects[index].parent();
+    while let Some(parent) = current.filter(|parent| *parent < objects.len())
+        && !chain.contains(&parent)
+    {
+        chain.push(parent);
+        current = objects[parent].parent();
+    }
+    let world = chain
+        .into_iter()
+        .rev()
+        .fold(Mat4::IDENTITY, |world, object| {
+            world * objects[object].local_transform()
+        });
+    world.w_axis.truncate()
+}
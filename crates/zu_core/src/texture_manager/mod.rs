@@ -1,3 +1,4 @@
+pub mod readback;
 pub mod textures;
 
 use indexmap::IndexMap;
@@ -9,6 +10,9 @@ pub struct BindGroupLayouts {
     compute_texture: BindGroupLayout,
     compute_mut_texture: BindGroupLayout,
     texture: BindGroupLayout,
+    compute_texture_unfilterable: BindGroupLayout,
+    compute_mut_texture_f32: BindGroupLayout,
+    texture_unfilterable: BindGroupLayout,
 }
 
 impl BindGroupLayouts {
@@ -63,6 +67,59 @@ impl BindGroupLayouts {
                     }],
                 },
             ),
+            texture_unfilterable: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Unfilterable texture Bind Group Layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
+                },
+            ),
+            compute_texture_unfilterable: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Unfilterable compute texture Bind Group Layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    }],
+                },
+            ),
+            compute_mut_texture_f32: device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Compute mut f32 texture Bind Group Layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::ReadWrite,
+                            format: TextureFormat::Rgba32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    }],
+                },
+            ),
         }
     }
 }
@@ -157,6 +214,10 @@ impl TextureManager {
         &self.bind_group_layouts.compute_mut_texture
     }
 
+    pub fn get_compute_mut_f32_bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layouts.compute_mut_texture_f32
+    }
+
     pub fn get_bind_group_layouts(&self) -> &BindGroupLayouts {
         &self.bind_group_layouts
     }
@@ -0,0 +1,70 @@
+use wgpu::{Device, Queue, Texture};
+
+pub fn padded_bytes_per_row(unpadded_row_bytes: u32) -> u32 {
+    // WebGPU requires bytes_per_row be a multiple of 256
+    const ALIGN: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded_row_bytes.div_ceil(ALIGN) * ALIGN
+}
+
+/// Copies `texture` into a mappable buffer and blocks until it can be read back.
+///
+/// Returns tightly packed rows (the `COPY_BYTES_PER_ROW_ALIGNMENT` padding is stripped),
+/// in the texture's own format. The texture must have `COPY_SRC` usage.
+pub fn read_texture(device: &Device, queue: &Queue, texture: &Texture) -> anyhow::Result<Vec<u8>> {
+    puffin::profile_function!();
+
+    let width = texture.width();
+    let height = texture.height();
+    let bytes_per_pixel = texture
+        .format()
+        .block_copy_size(None)
+        .ok_or_else(|| anyhow::anyhow!("Texture format {:?} can't be copied", texture.format()))?;
+    let unpadded_bytes_per_row = width * bytes_per_pixel;
+    let bytes_per_row = padded_bytes_per_row(unpadded_bytes_per_row);
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Texture readback buffer"),
+        size: (bytes_per_row * height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Texture readback encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+    let submission = queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::PollType::Wait {
+        submission_index: Some(submission),
+        timeout: None,
+    })?;
+    receiver.recv()??;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let mapped = slice.get_mapped_range();
+        for row in mapped.chunks_exact(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    Ok(pixels)
+}
@@ -2,24 +2,28 @@ use crate::texture_manager::{
     BindGroupLayouts,
     textures::{
         scene_texture::SceneTexture, standard::StandardTexture, standard_f16::StandardTextureF16,
+        standard_f32::StandardTextureF32,
     },
 };
 
 use wgpu::{
-    BindGroup, Device, Sampler, TextureView,
+    BindGroup, Device, Sampler, Texture, TextureView,
 };
 
 pub mod scene_texture;
 pub mod standard;
 pub mod standard_f16;
+pub mod standard_f32;
 
 pub enum TextureType {
     Standard,
     StandardF16,
+    StandardF32,
     SceneTexture,
 }
 
 pub trait EngineTexture {
+    fn texture(&self) -> &Texture;
     fn view(&self) -> &TextureView;
     fn bind_group(&self) -> &BindGroup;
     fn compute_bind_group(&self) -> &BindGroup;
@@ -40,10 +44,14 @@ pub trait EngineTexture {
 pub enum ManagedTexture {
     Standart(StandardTexture),
     StandartF16(StandardTextureF16),
+    StandartF32(StandardTextureF32),
     SceneTexture(SceneTexture),
 }
 
 impl EngineTexture for ManagedTexture {
+    fn texture(&self) -> &Texture {
+        self.as_engine_texture().texture()
+    }
     fn view(&self) -> &TextureView {
         self.as_engine_texture().view()
     }
@@ -114,6 +122,14 @@ impl ManagedTexture {
                 sampler,
                 resolution_scale,
             )),
+            TextureType::StandardF32 => ManagedTexture::StandartF32(StandardTextureF32::new(
+                name,
+                resolution,
+                device,
+                bind_group_layouts,
+                sampler,
+                resolution_scale,
+            )),
             TextureType::SceneTexture => ManagedTexture::SceneTexture(SceneTexture::new(
                 name,
                 resolution,
@@ -129,6 +145,7 @@ impl ManagedTexture {
         match self {
             ManagedTexture::Standart(standard) => standard,
             ManagedTexture::StandartF16(standard_f16) => standard_f16,
+            ManagedTexture::StandartF32(standard_f32) => standard_f32,
             ManagedTexture::SceneTexture(scene_texture) => scene_texture,
         }
     }
@@ -137,6 +154,7 @@ impl ManagedTexture {
         match self {
             ManagedTexture::Standart(standard) => standard,
             ManagedTexture::StandartF16(standard_f16) => standard_f16,
+            ManagedTexture::StandartF32(standard_f32) => standard_f32,
             ManagedTexture::SceneTexture(scene_texture) => scene_texture,
         }
     }
@@ -157,6 +175,14 @@ impl ManagedTexture {
         }
     }
 
+    pub fn standard_f32(&self) -> Option<&StandardTextureF32> {
+        if let ManagedTexture::StandartF32(standart) = self {
+            Some(standart)
+        } else {
+            None
+        }
+    }
+
     pub fn scene(&self) -> Option<&SceneTexture> {
         if let ManagedTexture::SceneTexture(scene) = self {
             Some(scene)
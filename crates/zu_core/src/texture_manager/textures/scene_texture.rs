@@ -1,11 +1,12 @@
 use glam::Vec2;
 use log::info;
 use wgpu::{
-    BindGroup, Device, Queue, Sampler, TextureView,
+    BindGroup, Device, Queue, Sampler, Texture, TextureView,
 };
 
 use crate::texture_manager::{
     BindGroupLayouts,
+    readback::padded_bytes_per_row,
     textures::{EngineTexture, standard::StandardTexture},
 };
 
@@ -15,6 +16,10 @@ pub struct SceneTexture {
 }
 
 impl EngineTexture for SceneTexture {
+    fn texture(&self) -> &Texture {
+        self.texture.texture()
+    }
+
     fn view(&self) -> &TextureView {
         self.texture.view()
     }
@@ -187,9 +192,3 @@ impl SceneTexture {
         );
     }
 }
-
-fn padded_bytes_per_row(unpadded_row_bytes: u32) -> u32 {
-    // WebGPU requires bytes_per_row be a multiple of 256
-    const ALIGN: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-    unpadded_row_bytes.div_ceil(ALIGN) * ALIGN
-}
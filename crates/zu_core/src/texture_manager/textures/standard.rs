@@ -33,6 +33,7 @@ impl StandardTexture {
             format: wgpu::TextureFormat::Rgba8Unorm,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::STORAGE_BINDING,
             label: Some(name),
@@ -81,6 +82,10 @@ impl StandardTexture {
 }
 
 impl EngineTexture for StandardTexture {
+    fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
     fn view(&self) -> &TextureView {
         &self.view
     }
@@ -33,6 +33,7 @@ impl StandardTextureF16 {
             format: wgpu::TextureFormat::Rgba16Float,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::STORAGE_BINDING,
             label: Some(name),
@@ -81,6 +82,10 @@ impl StandardTextureF16 {
 }
 
 impl EngineTexture for StandardTextureF16 {
+    fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
     fn view(&self) -> &TextureView {
         &self.view
     }
@@ -0,0 +1,152 @@
+//! Ambient occlusion: the distance field taps in the CPU reference, which mirrors the shader,
+//! and the horizon based pass on a software adapter.
+
+mod common;
+
+use common::test_renderer;
+use glam::{Quat, Vec2, Vec3};
+use zu_core::RenderOptions;
+use zu_core::golden::scenes;
+use zu_core::headless::HeadlessRenderer;
+use zu_core::render_passes::raymarching_passes::ambient_occlusion::{AmbientOcclusion, AoMode};
+use zu_core::render_passes::raymarching_passes::camera::Camera;
+use zu_core::render_passes::raymarching_passes::cpu_reference::CpuRaymarcher;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::{
+    RaymarchingConstants, RaymarchingObject,
+};
+use zu_core::texture_manager::{readback::read_texture, textures::EngineTexture};
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 72;
+
+#[test]
+fn ray_basis_matches_camera_ray() {
+    let camera = Camera {
+        orientation: Quat::from_rotation_z(0.4) * Camera::new(Vec3::ZERO, 0.8, -0.4).orientation,
+        fov: 70.0,
+        ..Default::default()
+    };
+    let options = RenderOptions {
+        camera,
+        ..Default::default()
+    };
+    let constants = RaymarchingConstants::new(&options, WIDTH, HEIGHT, 0.0);
+    let raymarcher = CpuRaymarcher::new(constants, &[], &camera);
+    let basis = camera.to_gpu().ray_basis();
+    for pixel in [Vec2::ZERO, Vec2::new(40.0, 12.0), Vec2::new(95.0, 71.0)] {
+        let (_, direction) = raymarcher.camera_ray(pixel);
+        let mut uv = pixel / Vec2::new(WIDTH as f32, HEIGHT as f32) * 2.0 - Vec2::ONE;
+        uv.y = -uv.y;
+        uv.x *= WIDTH as f32 / HEIGHT as f32;
+        let from_basis = (basis * uv.extend(1.0)).normalize();
+        assert!(
+            direction.distance(from_basis) < 1e-5,
+            "{pixel}: {direction} {from_basis}"
+        );
+    }
+}
+
+#[test]
+fn distance_field_occlusion_darkens_contacts() {
+    let options = RenderOptions {
+        raymarching_objects: vec![RaymarchingObject::sphere(
+            Vec3::new(0.0, -0.25, 0.0),
+            0.5,
+            0,
+        )],
+        ambient_occlusion: AmbientOcclusion {
+            mode: AoMode::Sdf,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let objects = &options.raymarching_objects;
+    let constants = RaymarchingConstants::new(&options, 1, 1, 0.0);
+    let raymarcher = CpuRaymarcher::new(constants, objects, &options.camera);
+
+    // The ground is at y = -0.75, the sphere rests on it
+    let open = raymarcher.ambient_occlusion(Vec3::new(3.0, -0.75, 3.0), Vec3::Y);
+    assert_eq!(open, 1.0);
+    let beside = raymarcher.ambient_occlusion(Vec3::new(0.6, -0.75, 0.0), Vec3::Y);
+    let under = raymarcher.ambient_occlusion(Vec3::new(0.3, -0.75, 0.0), Vec3::Y);
+    assert!(under < beside && beside < 1.0, "{under} {beside}");
+    assert!(under < 0.5, "{under}");
+
+    // Off by default
+    let off = RenderOptions {
+        ambient_occlusion: AmbientOcclusion::default(),
+        ..options.clone()
+    };
+    let constants = RaymarchingConstants::new(&off, 1, 1, 0.0);
+    let raymarcher = CpuRaymarcher::new(constants, objects, &options.camera);
+    assert_eq!(
+        raymarcher.ambient_occlusion(Vec3::new(0.3, -0.75, 0.0), Vec3::Y),
+        1.0
+    );
+}
+
+/// Renders a frame and reads back the HDR "Raymarching" texture.
+fn render_gpu(renderer: &mut HeadlessRenderer, options: &RenderOptions) -> Vec<Vec3> {
+    *renderer.get_options() = options.clone();
+    renderer.render_pass_manager().set_time(Some(0.0));
+    renderer.render().expect("Failed to render");
+
+    let texture = renderer
+        .render_pass_manager()
+        .texture_manager()
+        .get_texture("Raymarching")
+        .unwrap()
+        .texture()
+        .clone();
+    read_texture(&renderer.device, &renderer.queue, &texture)
+        .unwrap()
+        .chunks_exact(8)
+        .map(|pixel| {
+            let channel = |i: usize| half::f16::from_le_bytes([pixel[i], pixel[i + 1]]).to_f32();
+            Vec3::new(channel(0), channel(2), channel(4))
+        })
+        .collect()
+}
+
+#[test]
+fn horizon_pass_only_darkens_near_geometry() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+    let scene = scenes()
+        .into_iter()
+        .find(|scene| scene.name == "contact")
+        .unwrap();
+    let with_mode = |mode| RenderOptions {
+        ambient_occlusion: AmbientOcclusion {
+            mode,
+            ..scene.options.ambient_occlusion
+        },
+        ..scene.options.clone()
+    };
+    let off = render_gpu(&mut renderer, &with_mode(AoMode::Off));
+    let horizon = render_gpu(&mut renderer, &with_mode(AoMode::Horizon));
+
+    let mut darkened = 0;
+    for (off, horizon) in off.iter().zip(&horizon) {
+        // Within the f16 precision of the texture
+        assert!(
+            horizon.cmple(*off * 1.002 + 1e-3).all(),
+            "{horizon} brighter than {off}"
+        );
+        if horizon.max_element() < off.max_element() * 0.9 {
+            darkened += 1;
+        }
+    }
+    assert!(darkened > 20, "{darkened} pixels darkened");
+
+    // Open ground in front of the camera and the sky are left alone
+    let bottom_rows = (WIDTH * (HEIGHT - 8)) as usize..;
+    for (off, horizon) in off[bottom_rows.clone()].iter().zip(&horizon[bottom_rows]) {
+        assert!(
+            (*off - *horizon).abs().max_element() < 1e-3,
+            "{off} {horizon}"
+        );
+    }
+    assert_eq!(off[..WIDTH as usize], horizon[..WIDTH as usize]);
+}
@@ -0,0 +1,105 @@
+//! Sky lookup tables, through the CPU port that mirrors `atmosphere_luts.wgsl`.
+
+use glam::Vec3;
+use zu_core::render_passes::raymarching_passes::atmosphere::{
+    AtmosphereParams, LutCache, LutUpdate,
+};
+use zu_core::render_passes::raymarching_passes::cpu_reference::CpuAtmosphere;
+
+#[test]
+fn lookup_tables_regenerate_only_when_stale() {
+    let mut params = AtmosphereParams::default();
+    let sun_dir = Vec3::new(1.0, 1.0, 0.5);
+    let mut cache = LutCache::default();
+    assert_eq!(cache.update(&params, sun_dir), LutUpdate::All);
+    assert_eq!(cache.update(&params, sun_dir), LutUpdate::None);
+
+    // The sky-view table is relative to the sun's azimuth
+    assert_eq!(
+        cache.update(&params, Vec3::new(-0.5, 1.0, 1.0)),
+        LutUpdate::None
+    );
+    assert_eq!(
+        cache.update(&params, Vec3::new(1.0, 0.2, 0.5)),
+        LutUpdate::SkyView
+    );
+
+    params.mie_g = 0.8;
+    assert_eq!(
+        cache.update(&params, Vec3::new(1.0, 0.2, 0.5)),
+        LutUpdate::All
+    );
+    assert_eq!(
+        cache.update(&params, Vec3::new(1.0, 0.2, 0.5)),
+        LutUpdate::None
+    );
+}
+
+#[test]
+fn zenith_sun_lights_the_ground_with_two() {
+    let atmosphere = CpuAtmosphere::new(&AtmosphereParams::default(), Vec3::Y);
+    let params = atmosphere.params();
+    let ground = Vec3::from_array(params.sun_illuminance)
+        * atmosphere.transmittance_to_top(params.view_height, 1.0);
+    assert!(
+        (ground - Vec3::splat(2.0)).abs().max_element() < 0.02,
+        "{ground}"
+    );
+
+    // Grazing sunlight crosses far more air and loses the blue first
+    let horizon = atmosphere.transmittance_to_top(params.view_height, 0.02);
+    assert!(horizon.max_element() < 0.5, "{horizon}");
+    assert!(horizon.z < horizon.x, "{horizon}");
+}
+
+#[test]
+fn sky_is_blue_at_noon_and_red_at_sunset() {
+    let params = AtmosphereParams::default();
+
+    let noon = CpuAtmosphere::new(&params, Vec3::new(0.3, 1.0, 0.0));
+    let zenith = noon.sky_view(Vec3::Y, Vec3::new(0.3, 1.0, 0.0));
+    assert!(zenith.z > zenith.y && zenith.y > zenith.x, "{zenith}");
+
+    let sun_dir = Vec3::new(1.0, 0.02, 0.0).normalize();
+    let sunset = CpuAtmosphere::new(&params, sun_dir);
+    let towards_sun = sunset.sky_view(Vec3::new(1.0, 0.05, 0.0).normalize(), sun_dir);
+    assert!(towards_sun.x > towards_sun.z, "{towards_sun}");
+
+    // Multiple scattering keeps the sky lit past the terminator, dimmer than at noon
+    let away = sunset.sky_view(Vec3::new(-1.0, 0.3, 0.0).normalize(), sun_dir);
+    assert!(away.min_element() > 0.0, "{away}");
+    assert!(away.length() < zenith.length(), "{away} {zenith}");
+
+    // Thinning the air dims the sky
+    let thin = AtmosphereParams {
+        rayleigh: params.rayleigh * 0.1,
+        mie: params.mie * 0.1,
+        ..params
+    };
+    let thin_zenith = CpuAtmosphere::new(&thin, Vec3::new(0.3, 1.0, 0.0))
+        .sky_view(Vec3::Y, Vec3::new(0.3, 1.0, 0.0));
+    assert!(
+        thin_zenith.length() < zenith.length() * 0.5,
+        "{thin_zenith} {zenith}"
+    );
+}
+
+#[test]
+fn empty_atmosphere_is_black_and_clear() {
+    let empty = AtmosphereParams {
+        rayleigh: Vec3::ZERO,
+        mie: Vec3::ZERO,
+        ozone: Vec3::ZERO,
+        ..AtmosphereParams::default()
+    };
+    let sun_dir = Vec3::new(0.3, 1.0, 0.0).normalize();
+    let atmosphere = CpuAtmosphere::new(&empty, sun_dir);
+    let height = atmosphere.params().view_height;
+    assert_eq!(atmosphere.transmittance_to_top(height, 0.02), Vec3::ONE);
+
+    for direction in [Vec3::Y, Vec3::new(1.0, 0.05, 0.0).normalize(), Vec3::NEG_Y] {
+        let sky = atmosphere.sky_view(direction, sun_dir);
+        assert!(sky.is_finite(), "{direction}: {sky}");
+        assert!(sky.max_element() < 1e-6, "{direction}: {sky}");
+    }
+}
@@ -0,0 +1,155 @@
+//! Energy conservation of the specular BRDF in the CPU reference, which mirrors the shader,
+//! and the importance sampling of the path tracer.
+
+use std::f32::consts::PI;
+
+use glam::Vec3;
+use zu_core::render_passes::raymarching_passes::cpu_reference::{
+    CpuRaymarcher, Surface, distribution_ggx, specular_ggx,
+};
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingConstants;
+use zu_core::render_passes::render_pass_manager::RenderOptions;
+
+const STEPS: u32 = 256;
+
+/// Fraction of light from every direction that `brdf` reflects towards `view_dir`, integrated
+/// over the hemisphere of light directions around +y. `brdf` already includes the cosine term.
+///
+/// Combines a grid uniform over the hemisphere, for the diffuse lobe, with a grid distributed
+/// like the GGX half vectors of `roughness`, which resolves even the sharpest specular peak.
+/// The balance heuristic weights every sample by both densities.
+fn directional_albedo(view_dir: Vec3, roughness: f32, brdf: impl Fn(Vec3) -> Vec3) -> Vec3 {
+    let uniform_pdf = 1.0 / (2.0 * PI);
+    let ggx_pdf = |light_dir: Vec3| {
+        let half_dir = (light_dir + view_dir).normalize();
+        distribution_ggx(half_dir.y, roughness) * half_dir.y / (4.0 * half_dir.dot(view_dir))
+    };
+
+    let mut sum = Vec3::ZERO;
+    for i in 0..STEPS {
+        for j in 0..STEPS {
+            let u = (i as f32 + 0.5) / STEPS as f32;
+            let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * PI;
+            let direction = |cos_theta: f32| {
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+            };
+
+            // Uniform in cos(theta) and phi, so every sample covers the same solid angle
+            let light_dir = direction(u);
+            sum += brdf(light_dir) / (uniform_pdf + ggx_pdf(light_dir));
+
+            // GGX half vector, mirrored into a light direction
+            let alpha2 = roughness.powi(4);
+            let half_dir = direction(((1.0 - u) / (1.0 + (alpha2 - 1.0) * u)).sqrt());
+            let light_dir = 2.0 * view_dir.dot(half_dir) * half_dir - view_dir;
+            if light_dir.y > 0.0 {
+                sum += brdf(light_dir) / (uniform_pdf + ggx_pdf(light_dir));
+            }
+        }
+    }
+    sum / (STEPS * STEPS) as f32
+}
+
+fn view(angle: f32) -> Vec3 {
+    Vec3::new(angle.sin(), angle.cos(), 0.0)
+}
+
+#[test]
+fn specular_lobe_never_creates_energy() {
+    for roughness in [0.045, 0.1, 0.3, 0.6, 1.0] {
+        for angle in [0.0, 0.5, 1.0, 1.4] {
+            let view_dir = view(angle);
+            let albedo = directional_albedo(view_dir, roughness, |light_dir| {
+                let half_dir = (light_dir + view_dir).normalize();
+                specular_ggx(Vec3::Y, view_dir, light_dir, half_dir, roughness, Vec3::ONE)
+            });
+            assert!(
+                albedo.x <= 1.01,
+                "roughness {roughness} at {angle} rad reflects {}",
+                albedo.x
+            );
+        }
+    }
+}
+
+#[test]
+fn diffuse_and_specular_sum_below_one() {
+    // White dielectric, the diffuse lobe only gets what the Fresnel term lets through
+    let options = RenderOptions::default();
+    let raymarcher = CpuRaymarcher::new(
+        RaymarchingConstants::new(&options, 1, 1, 0.0),
+        &[],
+        &options.camera,
+    );
+    let f0 = Vec3::splat(0.04);
+    for roughness in [0.045, 0.1, 0.5, 1.0] {
+        let surface = Surface {
+            albedo: Vec3::ONE,
+            roughness,
+            emissive: Vec3::ZERO,
+            metallic: 0.0,
+            absorption: Vec3::ZERO,
+            ior: 1.5,
+            transmission: 0.0,
+        };
+        for angle in [0.0, 0.7, 1.3] {
+            let view_dir = view(angle);
+            let albedo = directional_albedo(view_dir, roughness, |light_dir| {
+                raymarcher.brdf(Vec3::Y, view_dir, light_dir, surface, f0, 0.0)
+            });
+            assert!(
+                albedo.max_element() <= 1.01,
+                "roughness {roughness} at {angle} rad reflects {albedo}"
+            );
+        }
+    }
+}
+
+#[test]
+fn importance_sampling_matches_integration() {
+    let options = RenderOptions::default();
+    let raymarcher = CpuRaymarcher::new(
+        RaymarchingConstants::new(&options, 1, 1, 0.0),
+        &[],
+        &options.camera,
+    );
+    for (roughness, metallic) in [(0.2, 0.0), (0.5, 0.0), (1.0, 0.0), (0.3, 1.0), (0.8, 1.0)] {
+        let surface = Surface {
+            albedo: Vec3::new(0.9, 0.6, 0.3),
+            roughness,
+            emissive: Vec3::ZERO,
+            metallic,
+            absorption: Vec3::ZERO,
+            ior: 1.5,
+            transmission: 0.0,
+        };
+        let f0 = Vec3::splat(0.04).lerp(surface.albedo, metallic);
+        for angle in [0.0, 0.7, 1.2] {
+            let view_dir = view(angle);
+            let expected = directional_albedo(view_dir, roughness, |light_dir| {
+                raymarcher.brdf(Vec3::Y, view_dir, light_dir, surface, f0, 0.0)
+            });
+
+            // Stratified over the directions, the lobe choice walks the golden ratio sequence
+            let mut sum = Vec3::ZERO;
+            for i in 0..STEPS {
+                for j in 0..STEPS {
+                    let u = Vec3::new(
+                        (i as f32 + 0.5) / STEPS as f32,
+                        (j as f32 + 0.5) / STEPS as f32,
+                        ((i * STEPS + j) as f32 * 0.618_034).fract(),
+                    );
+                    sum += raymarcher
+                        .sample_brdf(Vec3::Y, view_dir, surface, f0, u)
+                        .weight;
+                }
+            }
+            let estimate = sum / (STEPS * STEPS) as f32;
+            assert!(
+                (estimate - expected).abs().max_element() < 0.02,
+                "roughness {roughness} metallic {metallic} at {angle} rad: sampled {estimate}, integrated {expected}"
+            );
+        }
+    }
+}
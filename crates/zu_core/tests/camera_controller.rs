@@ -0,0 +1,148 @@
+//! Fly and orbit controls driven by window events.
+
+use std::f32::consts::FRAC_PI_2;
+
+use glam::Vec3;
+use winit::dpi::PhysicalPosition;
+use winit::event::{
+    DeviceId, ElementState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent,
+};
+use winit::keyboard::KeyCode;
+use zu_core::camera_controller::{CameraController, CameraMode};
+use zu_core::render_passes::raymarching_passes::camera::{Camera, MAX_PITCH};
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+/// Orbiting a sphere at the origin from 3 units down -z.
+fn orbit() -> (CameraController, Camera, Vec<RaymarchingObject>) {
+    let mut controller = CameraController::default();
+    controller.mode = CameraMode::Orbit;
+    let objects = vec![RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0)];
+    controller.orbit_object = Some(objects[0].id);
+    let camera = Camera::new(Vec3::new(0.0, 0.0, -3.0), 0.0, 0.0);
+    (controller, camera, objects)
+}
+
+/// Drags the mouse `dy` pixels down with the right button held.
+fn look_down(controller: &mut CameraController, dy: f64) {
+    let device_id = DeviceId::dummy();
+    let cursor = |y| WindowEvent::CursorMoved {
+        device_id,
+        position: PhysicalPosition::new(100.0, y),
+    };
+    let button = |state| WindowEvent::MouseInput {
+        device_id,
+        state,
+        button: MouseButton::Right,
+    };
+    controller.event(&cursor(100.0), false);
+    controller.event(&button(ElementState::Pressed), false);
+    controller.event(&cursor(100.0 + dy), false);
+    controller.event(&button(ElementState::Released), false);
+}
+
+fn scroll(controller: &mut CameraController, lines: f32) {
+    let wheel = WindowEvent::MouseWheel {
+        device_id: DeviceId::dummy(),
+        delta: MouseScrollDelta::LineDelta(0.0, lines),
+        phase: TouchPhase::Moved,
+    };
+    controller.event(&wheel, false);
+}
+
+#[test]
+fn orbit_zooms_in_no_closer_than_the_limit() {
+    let (mut controller, mut camera, objects) = orbit();
+    scroll(&mut controller, 2.0);
+    controller.update(&mut camera, &objects, 0.1);
+    assert!(
+        (camera.position.length() - 3.0 * 0.81).abs() < 1e-4,
+        "{camera:?}"
+    );
+
+    scroll(&mut controller, 1000.0);
+    controller.update(&mut camera, &objects, 0.1);
+    let distance = camera.position.length();
+    assert!((distance - 0.1).abs() < 1e-4, "{distance}");
+    assert!(camera.forward().dot(-camera.position / distance) > 0.9999);
+}
+
+#[test]
+fn orbit_follows_the_object_through_reorders() {
+    let (mut controller, mut camera, mut objects) = orbit();
+    // The orbited sphere moves to the end of the list, under a parent at x = 2
+    objects.insert(
+        0,
+        RaymarchingObject::sphere(Vec3::new(2.0, 0.0, 0.0), 0.5, 0),
+    );
+    objects[1].parent = 0;
+    scroll(&mut controller, 1.0);
+    controller.update(&mut camera, &objects, 0.1);
+    let pivot = Vec3::new(2.0, 0.0, 0.0);
+    let direction = (pivot - camera.position).normalize();
+    assert!(camera.forward().dot(direction) > 0.9999, "{camera:?}");
+
+    // Orbits the point in front once the object is gone
+    objects.remove(1);
+    let before = camera;
+    controller.update(&mut camera, &objects, 0.1);
+    assert_eq!(camera.position, before.position);
+}
+
+#[test]
+fn orbit_pitch_stops_above_the_pole() {
+    let (mut controller, mut camera, objects) = orbit();
+    look_down(&mut controller, 10_000.0);
+    controller.update(&mut camera, &objects, 0.1);
+    let (_, pitch) = camera.yaw_pitch();
+    assert!((pitch - MAX_PITCH).abs() < 1e-3, "{pitch}");
+    // Still circling the sphere at the same distance, from above
+    assert!((camera.position.length() - 3.0).abs() < 1e-4, "{camera:?}");
+    assert!(camera.position.y > 2.9, "{camera:?}");
+}
+
+#[test]
+fn fly_pitch_stops_at_the_limit() {
+    let mut controller = CameraController::default();
+    let mut camera = Camera::default();
+    look_down(&mut controller, -10_000.0);
+    controller.update(&mut camera, &[], 0.1);
+    let (_, pitch) = camera.yaw_pitch();
+    assert!((pitch + MAX_PITCH).abs() < 1e-3, "{pitch}");
+}
+
+#[test]
+fn fly_moves_in_the_camera_frame() {
+    // Turned to look down +x, forward is +x and right is -z
+    let start = Vec3::new(1.0, 2.0, 3.0);
+    let mut camera = Camera::new(start, FRAC_PI_2, 0.0);
+    let mut controller = CameraController::default();
+    controller.speed = 2.0;
+    controller.set_key_held(KeyCode::KeyW, true);
+    controller.update(&mut camera, &[], 0.5);
+    assert!(
+        camera.position.abs_diff_eq(start + camera.forward(), 1e-5),
+        "{camera:?}"
+    );
+    assert!(camera.forward().abs_diff_eq(Vec3::X, 1e-5));
+
+    // Diagonals aren't faster, shift is
+    let start = camera.position;
+    controller.set_key_held(KeyCode::KeyW, false);
+    controller.set_key_held(KeyCode::KeyD, true);
+    controller.set_key_held(KeyCode::KeyE, true);
+    controller.set_key_held(KeyCode::ShiftLeft, true);
+    controller.update(&mut camera, &[], 0.5);
+    let expected = (camera.right() + Vec3::Y).normalize() * 4.0;
+    assert!(
+        camera.position.abs_diff_eq(start + expected, 1e-5),
+        "{camera:?}"
+    );
+
+    // Released keys stop
+    let start = camera.position;
+    for code in [KeyCode::KeyD, KeyCode::KeyE, KeyCode::ShiftLeft] {
+        controller.set_key_held(code, false);
+    }
+    controller.update(&mut camera, &[], 0.5);
+    assert_eq!(camera.position, start);
+}
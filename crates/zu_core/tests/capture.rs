@@ -0,0 +1,163 @@
+//! Writing captures: file formats, sequence naming and failures on a software adapter.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::test_renderer;
+use zu_core::capture::{CaptureManager, CaptureSource, read_png, write_pfm, write_png};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 24;
+
+/// An empty directory for one test's output.
+fn output_dir(test: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("capture")
+        .join(test);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Size of an RGB PFM written by [`write_pfm`].
+fn pfm_len(width: u32, height: u32) -> usize {
+    format!("PF\n{width} {height}\n-1.0\n").len() + (width * height * 12) as usize
+}
+
+#[test]
+fn pfm_is_rgb_bottom_row_first() {
+    let path = output_dir("pfm").join("image.pfm");
+    #[rustfmt::skip]
+    let rgba = [
+        1.0, 2.0, 3.0, 9.0,
+        4.0, 5.0, 6.0, 9.0,
+    ];
+    write_pfm(&path, 1, 2, &rgba).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    let header = b"PF\n1 2\n-1.0\n";
+    assert_eq!(&bytes[..header.len()], header);
+    let floats: Vec<f32> = bytes[header.len()..]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    assert_eq!(floats, [4.0, 5.0, 6.0, 1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn png_round_trips() {
+    let path = output_dir("png").join("image.png");
+    let rgba: Vec<u8> = (0..3 * 2 * 4).map(|i| i as u8 * 10).collect();
+    write_png(&path, 3, 2, &rgba).unwrap();
+    assert_eq!(read_png(&path).unwrap(), (3, 2, rgba));
+}
+
+#[test]
+fn failed_capture_does_not_drop_the_others() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+    let directory = output_dir("failure");
+    let captures = renderer.render_pass_manager().capture_manager();
+    captures.capture(CaptureSource::ToneMapped, directory.join("tone_mapped.png"));
+    captures.capture(
+        CaptureSource::Texture("Missing".into()),
+        directory.join("missing.pfm"),
+    );
+    // Rgba16Float
+    captures.capture(
+        CaptureSource::Texture(CaptureManager::HDR_TEXTURE.into()),
+        directory.join("hdr.pfm"),
+    );
+    // Rgba32Float
+    captures.capture(
+        CaptureSource::Texture("Accumulation".into()),
+        directory.join("accumulation.pfm"),
+    );
+
+    let err = renderer.render().unwrap_err().to_string();
+    assert!(err.contains("1 of 4"), "{err}");
+    assert!(err.contains("missing.pfm"), "{err}");
+
+    let (width, height, _) = read_png(&directory.join("tone_mapped.png")).unwrap();
+    assert_eq!((width, height), (WIDTH, HEIGHT));
+    let hdr = fs::read(directory.join("hdr.pfm")).unwrap();
+    assert_eq!(hdr.len(), pfm_len(WIDTH, HEIGHT));
+    assert!(hdr.starts_with(format!("PF\n{WIDTH} {HEIGHT}\n").as_bytes()));
+    assert!(directory.join("accumulation.pfm").exists());
+    assert!(!directory.join("missing.pfm").exists());
+
+    // The queue is empty again
+    renderer.render().unwrap();
+}
+
+#[test]
+fn tone_mapped_capture_without_a_shown_texture_fails() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+    let directory = output_dir("not_shown");
+    let captures = renderer.render_pass_manager().capture_manager();
+    captures.capture(CaptureSource::ToneMapped, directory.join("shown.png"));
+    renderer.render().unwrap();
+
+    // The target still holds the last frame, which mustn't be saved again
+    renderer.get_options().show = "Missing".into();
+    let captures = renderer.render_pass_manager().capture_manager();
+    captures.capture(CaptureSource::ToneMapped, directory.join("missing.png"));
+    let err = renderer.render().unwrap_err().to_string();
+    assert!(err.contains("Nothing to capture"), "{err}");
+    assert!(directory.join("shown.png").exists());
+    assert!(!directory.join("missing.png").exists());
+}
+
+#[test]
+fn sequence_frames_are_numbered() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+    let directory = output_dir("sequence");
+    let captures = renderer.render_pass_manager().capture_manager();
+    captures.options.directory = directory.to_string_lossy().into_owned();
+    captures.options.save_hdr = true;
+    captures.options.sequence_frames = 2;
+    captures.start_sequence();
+
+    for _ in 0..3 {
+        renderer.render().unwrap();
+    }
+    assert!(
+        !renderer
+            .render_pass_manager()
+            .capture_manager()
+            .is_recording_sequence()
+    );
+
+    let mut sequences: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(sequences.len(), 1);
+    let sequence = sequences.remove(0);
+    assert!(
+        (sequence.file_name().unwrap().to_string_lossy()).starts_with("sequence_"),
+        "{}",
+        sequence.display()
+    );
+    let mut files: Vec<_> = fs::read_dir(&sequence)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "frame_00000.pfm",
+            "frame_00000.png",
+            "frame_00001.pfm",
+            "frame_00001.png"
+        ]
+    );
+}
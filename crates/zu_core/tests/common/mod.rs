@@ -0,0 +1,24 @@
+//! Shared setup of the GPU tests.
+
+use log::warn;
+use zu_core::headless::HeadlessRenderer;
+
+/// Set to skip GPU tests on machines without a software adapter instead of failing them.
+pub const SKIP_GPU_TESTS_VAR: &str = "ZU_SKIP_GPU_TESTS";
+
+/// Renderer for a GPU test on the software adapter. Panics when none is available, unless
+/// [`SKIP_GPU_TESTS_VAR`] is set, then returns `None` and the test should return early.
+pub fn test_renderer(width: u32, height: u32) -> Option<HeadlessRenderer> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    match pollster::block_on(HeadlessRenderer::new(width, height, true)) {
+        Ok(renderer) => Some(renderer),
+        Err(err) if std::env::var_os(SKIP_GPU_TESTS_VAR).is_some() => {
+            warn!("Skipping GPU test, no software adapter available: {err}");
+            None
+        }
+        Err(err) => panic!(
+            "No software adapter available: {err:#}. Install one (e.g. Mesa's llvmpipe) or set \
+             {SKIP_GPU_TESTS_VAR}=1 to skip GPU tests"
+        ),
+    }
+}
@@ -0,0 +1,235 @@
+//! Checks the CPU port of `raymarching_compute.wgsl` against the GPU on a software adapter.
+
+mod common;
+
+use common::test_renderer;
+use glam::{Vec3, Vec4};
+use zu_core::RenderOptions;
+use zu_core::golden::scenes;
+use zu_core::headless::HeadlessRenderer;
+use zu_core::object_grid::ObjectGrid;
+use zu_core::octree::{EMPTY_DISTANCE, Octree};
+use zu_core::render_passes::raymarching_passes::camera::Camera;
+use zu_core::render_passes::raymarching_passes::cpu_reference::{CpuRaymarcher, object_distance};
+use zu_core::render_passes::raymarching_passes::csg::CsgOperation;
+use zu_core::render_passes::raymarching_passes::primitives::{Primitive, PrimitiveKind};
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::{
+    GpuRaymarchingObject, RaymarchingConstants, RaymarchingObject,
+};
+use zu_core::scene_graph::SceneGraph;
+use zu_core::texture_manager::{readback::read_texture, textures::EngineTexture};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// Renders a frame and reads back the HDR "Raymarching" texture.
+fn render_gpu(renderer: &mut HeadlessRenderer, options: &RenderOptions, time: f32) -> Vec<Vec3> {
+    *renderer.get_options() = options.clone();
+    renderer.render_pass_manager().set_time(Some(time));
+    renderer.render().expect("Failed to render");
+
+    let texture = renderer
+        .render_pass_manager()
+        .texture_manager()
+        .get_texture("Raymarching")
+        .unwrap()
+        .texture()
+        .clone();
+    read_texture(&renderer.device, &renderer.queue, &texture)
+        .unwrap()
+        .chunks_exact(8)
+        .map(|pixel| {
+            let channel = |i: usize| half::f16::from_le_bytes([pixel[i], pixel[i + 1]]).to_f32();
+            Vec3::new(channel(0), channel(2), channel(4))
+        })
+        .collect()
+}
+
+fn assert_matches(name: &str, gpu: &[Vec3], cpu: &[Vec3]) {
+    // Silhouette edges may land on either side of the hit threshold, so allow a few outliers
+    let mismatched = gpu
+        .iter()
+        .zip(cpu)
+        .filter(|(gpu, cpu)| (**gpu - **cpu).abs().max_element() > 0.02 + 0.02 * cpu.max_element())
+        .count();
+    assert!(
+        mismatched <= gpu.len() / 50,
+        "{name}: {mismatched}/{} pixels differ between CPU and GPU",
+        gpu.len()
+    );
+}
+
+#[test]
+fn cpu_reference_matches_gpu() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    for scene in scenes() {
+        let gpu = render_gpu(&mut renderer, &scene.options, scene.time);
+        let constants = RaymarchingConstants::new(&scene.options, WIDTH, HEIGHT, scene.time);
+        let cpu = CpuRaymarcher::new(
+            constants,
+            &scene.options.raymarching_objects,
+            &scene.options.camera,
+        )
+        .with_materials(&scene.options.materials)
+        .with_lights(&scene.options.lights)
+        .with_atmosphere(&scene.options.atmosphere)
+        .with_fog(&scene.options.fog)
+        .with_clouds(&scene.options.clouds)
+        .with_fog_volumes(&scene.options.fog_volumes)
+        .render();
+        assert_matches(scene.name, &gpu, &cpu);
+    }
+}
+
+#[test]
+fn octree_traversal_matches_cpu() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    let mut octree = Octree::new(Vec3::new(-2.0, -1.0, -2.0), 4.0, 3);
+    octree.material = 1;
+    let center = Vec3::new(0.3, 0.0, 0.2);
+    octree.insert_sdf(center - 0.8, center + 0.8, |p| (p - center).length() - 0.6);
+    octree.remove_sdf(center - 0.5, center + 0.5, |p| {
+        let q = (p - center - Vec3::new(0.0, 0.4, -0.4)).abs() - 0.3;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+    });
+    assert!(octree.sample(center + Vec3::new(0.6, 0.0, 0.0)).is_some());
+    assert!(octree.sample(Vec3::new(1.9, 2.9, 1.9)).is_none());
+    *renderer.render_pass_manager().octree_mut() = octree.clone();
+
+    let options = RenderOptions {
+        raymarching_objects: Vec::new(),
+        camera: Camera::new(Vec3::new(0.0, 0.2, -3.0), 0.0, 0.0),
+        sun_dir: Vec3::new(0.5, 1.0, -0.8),
+        ..Default::default()
+    };
+    let gpu = render_gpu(&mut renderer, &options, 0.0);
+    let constants = RaymarchingConstants::new(&options, WIDTH, HEIGHT, 0.0);
+    let raymarcher = CpuRaymarcher::new(constants, &options.raymarching_objects, &options.camera)
+        .with_octree(&octree);
+    let camera = options.camera.position;
+    let hit = raymarcher.march(camera, (center - camera).normalize());
+    assert_eq!(hit.map(|hit| hit.material), Some(1), "Octree not hit");
+    assert_matches("octree", &gpu, &raymarcher.render());
+}
+
+#[test]
+fn growing_object_buffer_matches_cpu() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    // More objects than the initial buffer holds, then edit one so only its range uploads
+    let mut options = RenderOptions {
+        raymarching_objects: (0..300)
+            .map(|i| {
+                let position = Vec3::new(
+                    (i % 20) as f32 * 0.2 - 1.9,
+                    (i / 20) as f32 * 0.2 - 1.4,
+                    0.5,
+                );
+                RaymarchingObject::sphere(position, 0.08, i % 2)
+            })
+            .collect(),
+        camera: Camera::new(Vec3::new(0.0, 0.0, -3.0), 0.0, 0.0),
+        ..Default::default()
+    };
+    render_gpu(&mut renderer, &options, 0.0);
+    options.raymarching_objects[150].primitive = Primitive::sphere(0.3);
+    options.raymarching_objects.truncate(200);
+
+    let gpu = render_gpu(&mut renderer, &options, 0.0);
+    let constants = RaymarchingConstants::new(&options, WIDTH, HEIGHT, 0.0);
+    let cpu = CpuRaymarcher::new(constants, &options.raymarching_objects, &options.camera).render();
+    assert_matches("grown objects", &gpu, &cpu);
+}
+
+/// 10k spheres in a field with a box carved out of it. Avoids the hashed material, its noise
+/// is too sensitive to float precision this far from the origin.
+fn sphere_field() -> Vec<RaymarchingObject> {
+    let mut objects: Vec<_> = (0..10_000)
+        .map(|i| {
+            let position = Vec3::new((i % 100) as f32 * 0.2 - 10.0, -0.6, (i / 100) as f32 * 0.2);
+            RaymarchingObject::sphere(position, 0.08, i % 2)
+        })
+        .collect();
+    objects.push(
+        RaymarchingObject::new(
+            Vec3::new(0.0, -0.6, 2.0),
+            Primitive::new(PrimitiveKind::Box, Vec4::new(1.0, 0.2, 1.0, 0.0)),
+            0,
+        )
+        .with_operation(CsgOperation::Subtraction, 0.0),
+    );
+    objects
+}
+
+fn fold(objects: &[GpuRaymarchingObject], indices: impl Iterator<Item = usize>, p: Vec3) -> f32 {
+    indices.fold(EMPTY_DISTANCE, |distance, index| {
+        let object = &objects[index];
+        CsgOperation::from_id(object.operation).distance(
+            distance,
+            object_distance(p, object),
+            object.blend_radius,
+        )
+    })
+}
+
+#[test]
+fn object_grid_lookup_matches_brute_force() {
+    let mut objects = sphere_field();
+    objects.push(
+        RaymarchingObject::sphere(Vec3::new(1.0, -0.5, 3.0), 0.3, 1)
+            .with_operation(CsgOperation::SmoothUnion, 0.4),
+    );
+    let scene = SceneGraph::new(&objects);
+    let gpu_objects = scene.gpu_objects();
+    let grid = ObjectGrid::new(&scene);
+
+    for x in -60..60 {
+        for z in -10..110 {
+            let p = Vec3::new(
+                x as f32 * 0.171,
+                -0.55 + (x + z) as f32 % 3.0 * 0.05,
+                z as f32 * 0.183,
+            );
+            let lookup = grid.lookup(p);
+            let bound = lookup.bound;
+            let evaluated: Vec<_> = lookup.collect();
+            assert!(
+                evaluated.len() < gpu_objects.len() / 100,
+                "{} objects evaluated at {p}",
+                evaluated.len()
+            );
+
+            let brute = fold(&gpu_objects, 0..gpu_objects.len(), p);
+            if brute < bound - 1e-4 {
+                let culled = fold(&gpu_objects, evaluated.into_iter(), p);
+                assert!((brute - culled).abs() < 1e-4, "{brute} != {culled} at {p}");
+            }
+        }
+    }
+}
+
+#[test]
+fn object_grid_matches_cpu() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    let options = RenderOptions {
+        raymarching_objects: sphere_field(),
+        camera: Camera::new(Vec3::new(0.0, 0.5, -3.0), 0.0, 0.3),
+        ..Default::default()
+    };
+
+    let gpu = render_gpu(&mut renderer, &options, 0.0);
+    let constants = RaymarchingConstants::new(&options, WIDTH, HEIGHT, 0.0);
+    let cpu = CpuRaymarcher::new(constants, &options.raymarching_objects, &options.camera).render();
+    assert_matches("object grid", &gpu, &cpu);
+}
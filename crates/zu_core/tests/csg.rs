@@ -0,0 +1,137 @@
+//! Boolean operations, on their own and folded over the scene by the CPU reference.
+
+use glam::Vec3;
+use zu_core::RenderOptions;
+use zu_core::render_passes::raymarching_passes::cpu_reference::CpuRaymarcher;
+use zu_core::render_passes::raymarching_passes::csg::CsgOperation;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::{
+    RaymarchingConstants, RaymarchingObject,
+};
+
+const K: f32 = 0.4;
+
+fn assert_near(actual: f32, expected: f32, what: &str) {
+    assert!(
+        (actual - expected).abs() < 1e-5,
+        "{what}: {actual}, expected {expected}"
+    );
+}
+
+#[test]
+fn hard_operations() {
+    use CsgOperation::*;
+    // (accumulated, object) outside both, inside only the accumulated one, inside both
+    let cases = [(1.0, 2.0), (-0.5, 0.3), (-0.5, -0.2)];
+    let expected = [
+        (Union, [1.0, -0.5, -0.5]),
+        (Subtraction, [1.0, -0.3, 0.2]),
+        (Intersection, [2.0, 0.3, -0.2]),
+    ];
+    for (operation, expected) in expected {
+        for ((a, b), expected) in cases.into_iter().zip(expected) {
+            let what = format!("{} of {a} and {b}", operation.name());
+            assert_near(operation.distance(a, b, 0.0), expected, &what);
+        }
+    }
+}
+
+#[test]
+fn smooth_operations_blend_within_the_radius() {
+    use CsgOperation::*;
+    // Further apart than the radius they match the hard operations
+    for (smooth, hard) in [
+        (SmoothUnion, Union),
+        (SmoothSubtraction, Subtraction),
+        (SmoothIntersection, Intersection),
+    ] {
+        for (a, b) in [(1.0, 2.0), (-0.9, 0.3), (1.0, -0.5)] {
+            let what = format!("{} of {a} and {b}", smooth.name());
+            assert_near(smooth.distance(a, b, K), hard.distance(a, b, K), &what);
+        }
+    }
+
+    // Where both are equal the blend moves the surface by a quarter of the radius
+    assert_near(SmoothUnion.distance(0.2, 0.2, K), 0.1, "smooth union");
+    assert_near(
+        SmoothIntersection.distance(0.2, 0.2, K),
+        0.3,
+        "smooth intersection",
+    );
+    assert_near(
+        SmoothSubtraction.distance(0.2, -0.2, K),
+        0.3,
+        "smooth subtraction",
+    );
+
+    // Smooth unions only add material, smooth intersections only remove it
+    for i in 0..=20 {
+        let a = -0.5 + i as f32 * 0.05;
+        let b = 0.1;
+        assert!(SmoothUnion.distance(a, b, K) <= Union.distance(a, b, K) + 1e-6);
+        assert!(SmoothIntersection.distance(a, b, K) >= Intersection.distance(a, b, K) - 1e-6);
+        assert!(SmoothSubtraction.distance(a, b, K) >= Subtraction.distance(a, b, K) - 1e-6);
+    }
+
+    // A zero radius must not divide by zero
+    assert!(SmoothUnion.distance(0.2, 0.2, 0.0).is_finite());
+}
+
+/// Signed distance and material at `x` on the line through a unit sphere at x = 0, followed
+/// by one of material 1 at x = 1 combined with `operation`. High above the ground plane, which
+/// [`CpuRaymarcher::map`] adds.
+fn two_spheres(operation: CsgOperation, blend_radius: f32, x: f32) -> (f32, i32) {
+    let y = 3.0;
+    let objects = vec![
+        RaymarchingObject::sphere(Vec3::new(0.0, y, 0.0), 1.0, 0),
+        RaymarchingObject::sphere(Vec3::new(1.0, y, 0.0), 1.0, 1)
+            .with_operation(operation, blend_radius),
+    ];
+    let options = RenderOptions {
+        raymarching_objects: objects,
+        ..Default::default()
+    };
+    let constants = RaymarchingConstants::new(&options, 4, 4, 0.0);
+    let sdf = CpuRaymarcher::new(constants, &options.raymarching_objects, &options.camera)
+        .map(Vec3::new(x, y, 0.0));
+    (sdf.res, sdf.material)
+}
+
+#[test]
+fn operations_fold_over_the_scene() {
+    use CsgOperation::*;
+    // Inside only the first sphere, inside both, inside only the second, outside both
+    let points = [-0.5, 0.5, 1.5, 3.0];
+    let inside = [
+        (Union, [true, true, true, false]),
+        (Subtraction, [true, false, false, false]),
+        (Intersection, [false, true, false, false]),
+        (SmoothUnion, [true, true, true, false]),
+        (SmoothSubtraction, [true, false, false, false]),
+        (SmoothIntersection, [false, true, false, false]),
+    ];
+    for (operation, inside) in inside {
+        for (x, inside) in points.into_iter().zip(inside) {
+            let (distance, _) = two_spheres(operation, 0.2, x);
+            assert_eq!(
+                distance < 0.0,
+                inside,
+                "{} at x = {x}: {distance}",
+                operation.name()
+            );
+        }
+    }
+
+    // Exact values on the axis, both spheres are 1 - |x - center| deep there
+    assert_near(two_spheres(Union, 0.0, 0.5).0, -0.5, "union");
+    assert_near(two_spheres(Union, 0.0, 1.5).0, -0.5, "union");
+    assert_near(two_spheres(Subtraction, 0.0, -0.5).0, -0.5, "subtraction");
+    assert_near(two_spheres(Subtraction, 0.0, 0.5).0, 0.5, "subtraction");
+    assert_near(two_spheres(Intersection, 0.0, 0.5).0, -0.5, "intersection");
+    assert_near(two_spheres(Intersection, 0.0, -0.5).0, 0.5, "intersection");
+
+    // The closer object's material shows, cut faces keep the material they were cut from
+    assert_eq!(two_spheres(Union, 0.0, -0.5).1, 0);
+    assert_eq!(two_spheres(Union, 0.0, 1.5).1, 1);
+    assert_eq!(two_spheres(Subtraction, 0.0, 0.1).1, 0);
+    assert_eq!(two_spheres(Intersection, 0.0, 0.2).1, 1);
+}
@@ -0,0 +1,100 @@
+//! Aerial perspective and height fog in the CPU reference, which mirrors the shader.
+
+use glam::Vec3;
+use zu_core::render_passes::raymarching_passes::atmosphere::AtmosphereParams;
+use zu_core::render_passes::raymarching_passes::cpu_reference::{CpuRaymarcher, Haze};
+use zu_core::render_passes::raymarching_passes::fog::HeightFog;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingConstants;
+use zu_core::render_passes::render_pass_manager::RenderOptions;
+
+/// Light fog down to the ground, the default has none.
+fn fog() -> HeightFog {
+    HeightFog {
+        density: 0.02,
+        ..HeightFog::default()
+    }
+}
+
+fn foggy_raymarcher(fog: &HeightFog) -> CpuRaymarcher<'static> {
+    let options = RenderOptions::default();
+    CpuRaymarcher::new(
+        RaymarchingConstants::new(&options, 1, 1, 0.0),
+        &[],
+        &options.camera,
+    )
+    .with_atmosphere(&options.atmosphere)
+    .with_fog(fog)
+}
+
+#[test]
+fn fog_thickens_with_distance_and_thins_with_height() {
+    let fog = fog();
+    let raymarcher = foggy_raymarcher(&fog);
+    let horizontal = Vec3::new(1.0, 0.0, 0.5).normalize();
+    let near = raymarcher.height_fog(horizontal, 5.0);
+    let far = raymarcher.height_fog(horizontal, 50.0);
+    assert!(near.transmittance.x < 1.0, "{near:?}");
+    assert!(
+        far.transmittance.x < near.transmittance.x,
+        "{near:?} {far:?}"
+    );
+    assert!(far.inscattering.x > near.inscattering.x, "{near:?} {far:?}");
+
+    // Looking up leaves the fog, looking down goes deeper into it
+    let up = raymarcher.height_fog(Vec3::new(1.0, 0.5, 0.5).normalize(), 50.0);
+    let down = raymarcher.height_fog(Vec3::new(1.0, -0.5, 0.5).normalize(), 50.0);
+    assert!(up.transmittance.x > far.transmittance.x, "{up:?} {far:?}");
+    assert!(
+        down.transmittance.x < far.transmittance.x,
+        "{down:?} {far:?}"
+    );
+
+    assert_eq!(
+        foggy_raymarcher(&HeightFog::default()).height_fog(horizontal, 50.0),
+        Haze::NONE
+    );
+}
+
+#[test]
+fn distant_surfaces_fade_into_the_sky() {
+    let raymarcher = foggy_raymarcher(&fog());
+    let direction = Vec3::new(0.0, 0.05, -1.0).normalize();
+
+    // Nearby the air barely changes anything, but blue is scattered first
+    let near = raymarcher.camera_haze(direction, 10.0);
+    assert!(near.transmittance.min_element() > 0.7, "{near:?}");
+    let air = raymarcher.aerial_perspective(direction, 10.0);
+    assert!(air.transmittance.z < air.transmittance.x, "{air:?}");
+
+    // At the end of the march only the fogged sky is left
+    let end = raymarcher.camera_haze(direction, 100.0);
+    assert_eq!(end.transmittance, Vec3::ZERO);
+    let sky = raymarcher
+        .height_fog(direction, 1000.0)
+        .apply(raymarcher.sky(direction, true));
+    assert!(
+        (end.inscattering - sky).abs().max_element() < 1e-5,
+        "{end:?} {sky:?}"
+    );
+}
+
+#[test]
+fn empty_atmosphere_leaves_surfaces_clear() {
+    let empty = AtmosphereParams {
+        rayleigh: Vec3::ZERO,
+        mie: Vec3::ZERO,
+        ozone: Vec3::ZERO,
+        ..AtmosphereParams::default()
+    };
+    let options = RenderOptions::default();
+    let raymarcher = CpuRaymarcher::new(
+        RaymarchingConstants::new(&options, 1, 1, 0.0),
+        &[],
+        &options.camera,
+    )
+    .with_atmosphere(&empty);
+    let air = raymarcher.aerial_perspective(Vec3::new(1.0, 0.1, 0.0).normalize(), 50.0);
+    assert_eq!(air.transmittance, Vec3::ONE);
+    assert!(air.inscattering.is_finite(), "{air:?}");
+    assert_eq!(air.inscattering, Vec3::ZERO);
+}
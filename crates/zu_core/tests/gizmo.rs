@@ -0,0 +1,159 @@
+//! Dragging the handles of the selected object.
+
+use glam::{Quat, Vec2, Vec3};
+use zu_core::gizmo::{Gizmo, GizmoMode, Handle};
+use zu_core::render_passes::raymarching_passes::camera::Camera;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+const SIZE: Vec2 = Vec2::new(800.0, 600.0);
+
+/// Looking down +z at an object at the origin, the handles are 1.25 long.
+fn scene() -> (Camera, Vec<RaymarchingObject>) {
+    let camera = Camera::new(Vec3::new(0.0, 0.0, -5.0), 0.0, 0.0);
+    (camera, vec![RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0)])
+}
+
+/// Drags `handle` from world point `from` to `to`.
+fn drag(
+    gizmo: &mut Gizmo,
+    handle: Handle,
+    camera: &Camera,
+    objects: &mut [RaymarchingObject],
+    from: Vec3,
+    to: Vec3,
+    snap: bool,
+) {
+    let from = camera.project(from, SIZE).unwrap();
+    let to = camera.project(to, SIZE).unwrap();
+    assert!(gizmo.begin_drag(handle, camera, SIZE, objects, 0, from));
+    gizmo.drag_to(camera, SIZE, objects, to, snap);
+    gizmo.end_drag();
+}
+
+#[test]
+fn projecting_inverts_pixel_rays() {
+    let camera = Camera::new(Vec3::new(1.0, 2.0, -3.0), 0.4, 0.2);
+    let point = Vec3::new(0.5, 1.0, 2.0);
+    let pixel = camera.project(point, SIZE).unwrap();
+    let ray = camera.pixel_ray(pixel, SIZE);
+    let expected = (point - camera.position).normalize();
+    assert!(ray.distance(expected) < 1e-5, "{ray} != {expected}");
+
+    let centre = camera
+        .project(camera.position + camera.forward(), SIZE)
+        .unwrap();
+    assert!(centre.distance(SIZE * 0.5) < 1e-3, "{centre}");
+    assert_eq!(
+        camera.project(camera.position - camera.forward(), SIZE),
+        None
+    );
+}
+
+#[test]
+fn pointer_over_an_arrow_hovers_it() {
+    let (camera, objects) = scene();
+    let gizmo = Gizmo::default();
+    let tip = camera.project(Vec3::new(1.0, 0.0, 0.0), SIZE).unwrap();
+    assert_eq!(
+        gizmo.handle_at(&camera, SIZE, &objects, 0, tip),
+        Some(Handle::Axis(0))
+    );
+    let above = camera.project(Vec3::new(0.0, 1.0, 0.0), SIZE).unwrap();
+    assert_eq!(
+        gizmo.handle_at(&camera, SIZE, &objects, 0, above),
+        Some(Handle::Axis(1))
+    );
+    assert_eq!(
+        gizmo.handle_at(&camera, SIZE, &objects, 0, Vec2::new(20.0, 20.0)),
+        None
+    );
+}
+
+#[test]
+fn dragging_an_arrow_moves_along_it() {
+    let (camera, mut objects) = scene();
+    let mut gizmo = Gizmo::default();
+    let (from, to) = (Vec3::new(0.5, 0.0, 0.0), Vec3::new(1.5, 0.0, 0.0));
+    drag(
+        &mut gizmo,
+        Handle::Axis(0),
+        &camera,
+        &mut objects,
+        from,
+        to,
+        false,
+    );
+    let position = objects[0].position;
+    assert!(position.distance(Vec3::X) < 1e-3, "{position}");
+
+    // 0.9 rounds to the 0.25 grid
+    let (camera, mut objects) = scene();
+    let to = Vec3::new(1.4, 0.0, 0.0);
+    drag(
+        &mut gizmo,
+        Handle::Axis(0),
+        &camera,
+        &mut objects,
+        from,
+        to,
+        true,
+    );
+    let position = objects[0].position;
+    assert!(position.distance(Vec3::X) < 1e-5, "{position}");
+}
+
+#[test]
+fn dragging_a_ring_turns_around_its_axis() {
+    let (camera, mut objects) = scene();
+    let mut gizmo = Gizmo::default();
+    gizmo.mode = GizmoMode::Rotate;
+    let (from, to) = (Vec3::new(1.25, 0.0, 0.0), Vec3::new(0.0, 1.25, 0.0));
+    drag(
+        &mut gizmo,
+        Handle::Ring(2),
+        &camera,
+        &mut objects,
+        from,
+        to,
+        false,
+    );
+    let expected = Quat::from_rotation_z(90f32.to_radians());
+    assert!(objects[0].rotation.angle_between(expected) < 1e-3);
+
+    // 40 degrees rounds to the 15 degree steps
+    let (camera, mut objects) = scene();
+    let angle = 40f32.to_radians();
+    let to = Vec3::new(angle.cos(), angle.sin(), 0.0) * 1.25;
+    drag(
+        &mut gizmo,
+        Handle::Ring(2),
+        &camera,
+        &mut objects,
+        from,
+        to,
+        true,
+    );
+    let expected = Quat::from_rotation_z(45f32.to_radians());
+    assert!(objects[0].rotation.angle_between(expected) < 1e-3);
+}
+
+#[test]
+fn dragging_a_scale_handle_scales_one_axis() {
+    let (camera, mut objects) = scene();
+    let mut gizmo = Gizmo::default();
+    gizmo.mode = GizmoMode::Scale;
+    let start = objects[0].scale;
+    let (from, to) = (Vec3::new(0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    drag(
+        &mut gizmo,
+        Handle::ScaleAxis(0),
+        &camera,
+        &mut objects,
+        from,
+        to,
+        false,
+    );
+    let scale = objects[0].scale;
+    let expected = start * Vec3::new(2.0, 1.0, 1.0);
+    assert!(scale.distance(expected) < 1e-3, "{scale} != {expected}");
+}
@@ -0,0 +1,50 @@
+//! Golden-image regression test for the raymarcher.
+//!
+//! Renders every scene from `zu_core::golden::scenes()` on a software adapter and compares
+//! it with `tests/golden/<scene>.png`. Failing scenes leave `<scene>.actual.png` and
+//! `<scene>.diff.png` in the cargo target tmp dir.
+//!
+//! After an intentional look change, regenerate the references with:
+//! `ZU_BLESS_GOLDEN=1 cargo test -p zu_core --test golden`
+//!
+//! Without a software adapter the GPU tests fail, set `ZU_SKIP_GPU_TESTS=1` to skip them.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::test_renderer;
+use zu_core::golden::{GoldenHarness, Tolerance, scenes};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+#[test]
+fn raymarcher_matches_golden_images() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+
+    let harness = GoldenHarness {
+        reference_dir: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden"),
+        output_dir: PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden"),
+        tolerance: Tolerance::default(),
+        bless: std::env::var_os("ZU_BLESS_GOLDEN").is_some(),
+    };
+
+    let results = harness
+        .run_all(&mut renderer, &scenes())
+        .expect("Failed to render golden scenes");
+    for result in &results {
+        eprintln!("{result}");
+    }
+
+    let failed: Vec<_> = results.iter().filter(|result| !result.is_ok()).collect();
+    assert!(
+        failed.is_empty(),
+        "{} golden scene(s) failed on {}, see {}",
+        failed.len(),
+        renderer.adapter_info.name,
+        harness.output_dir.display()
+    );
+}
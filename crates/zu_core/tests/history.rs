@@ -0,0 +1,105 @@
+//! Undo and redo of edits to the render options.
+
+use glam::Vec3;
+use zu_core::RenderOptions;
+use zu_core::history::History;
+use zu_core::render_passes::raymarching_passes::light::Light;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+fn labels(history: &History) -> Vec<&str> {
+    history
+        .steps()
+        .iter()
+        .map(|step| step.label.as_str())
+        .collect()
+}
+
+#[test]
+fn undo_and_redo_restore_each_step() {
+    let mut options = RenderOptions::default();
+    let mut history = History::new(&options);
+    let start = options.clone();
+
+    options.sun_intensity = 3.0;
+    assert!(history.record(&options, false));
+    options
+        .raymarching_objects
+        .push(RaymarchingObject::sphere(Vec3::Y, 0.25, 0));
+    options.lights.push(Light::default());
+    assert!(history.record(&options, false));
+    let end = options.clone();
+    assert_eq!(
+        labels(&history),
+        ["Edit sun intensity", "Add object, Add light"]
+    );
+
+    assert!(history.undo(&mut options));
+    assert_eq!(options.sun_intensity, 3.0);
+    assert_eq!(options.raymarching_objects, start.raymarching_objects);
+    assert!(options.lights.is_empty());
+    assert!(history.undo(&mut options));
+    assert_eq!(options, start);
+    assert!(!history.undo(&mut options));
+
+    assert!(history.redo(&mut options));
+    assert!(history.redo(&mut options));
+    assert_eq!(options, end);
+    assert!(!history.redo(&mut options));
+    // Nothing changed since the last step
+    assert!(!history.record(&options, false));
+}
+
+#[test]
+fn edits_while_editing_become_one_step() {
+    let mut options = RenderOptions::default();
+    let mut history = History::new(&options);
+
+    for x in 1..=10 {
+        options.raymarching_objects[1].position.x = x as f32 * 0.1;
+        assert!(!history.record(&options, true));
+    }
+    assert!(history.record(&options, false));
+    assert_eq!(labels(&history), ["Edit object 1"]);
+
+    history.undo(&mut options);
+    assert_eq!(options.raymarching_objects[1].position.x, 0.5);
+}
+
+#[test]
+fn camera_moves_are_not_recorded_or_undone() {
+    let mut options = RenderOptions::default();
+    let mut history = History::new(&options);
+
+    options.camera.position = Vec3::new(1.0, 2.0, 3.0);
+    assert!(!history.record(&options, false));
+    options.exposure = 2.0;
+    assert!(history.record(&options, false));
+    options.camera.position = Vec3::ZERO;
+    history.undo(&mut options);
+    assert_eq!(options.exposure, 1.0);
+    assert_eq!(options.camera.position, Vec3::ZERO);
+}
+
+#[test]
+fn jumping_and_editing_drop_the_steps_after() {
+    let mut options = RenderOptions::default();
+    let mut history = History::new(&options);
+    for ambient in [0.1, 0.2, 0.4] {
+        options.ambient = ambient;
+        history.record(&options, false);
+    }
+
+    history.jump_to(1, &mut options);
+    assert_eq!(options.ambient, 0.1);
+    assert_eq!(history.position(), 1);
+    history.jump_to(3, &mut options);
+    assert_eq!(options.ambient, 0.4);
+    history.jump_to(0, &mut options);
+    assert_eq!(options.ambient, 0.3);
+
+    history.redo(&mut options);
+    options.materials[0].name = "Renamed".to_owned();
+    assert!(history.record(&options, false));
+    assert_eq!(labels(&history), ["Edit ambient", "Edit material 0"]);
+    assert!(!history.can_redo());
+}
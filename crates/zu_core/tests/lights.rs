@@ -0,0 +1,95 @@
+//! Light falloff and shapes in the CPU reference, which mirrors the shader.
+
+use glam::{Vec2, Vec3};
+use zu_core::render_passes::raymarching_passes::cpu_reference::{
+    distance_attenuation, sample_light,
+};
+use zu_core::render_passes::raymarching_passes::light::{Light, LightKind, MIN_SPOT_EDGE};
+
+#[test]
+fn attenuation_is_inverse_square_and_ends_at_range() {
+    let near = distance_attenuation(0.5, 100.0);
+    let far = distance_attenuation(1.0, 100.0);
+    assert!((near / far - 4.0).abs() < 0.01, "{near} / {far}");
+    assert_eq!(distance_attenuation(10.0, 10.0), 0.0);
+    assert_eq!(distance_attenuation(12.0, 10.0), 0.0);
+    assert!(distance_attenuation(9.0, 10.0) > 0.0);
+    assert!(distance_attenuation(0.0, 10.0).is_finite());
+}
+
+#[test]
+fn spot_lights_only_light_their_cone() {
+    let spot = Light {
+        direction: Vec3::NEG_Y,
+        inner_angle: 10.0,
+        outer_angle: 20.0,
+        ..Light::point(Vec3::Y, Vec3::ONE, 1.0).with_kind(LightKind::Spot)
+    }
+    .to_gpu();
+
+    let below = sample_light(&spot, Vec3::ZERO);
+    assert_eq!(below.direction, Vec3::Y);
+    assert!((below.radiance.x - distance_attenuation(1.0, spot.range)).abs() < 1e-6);
+
+    // 45 degrees off the axis
+    assert_eq!(
+        sample_light(&spot, Vec3::new(1.0, 0.0, 0.0)).radiance,
+        Vec3::ZERO
+    );
+    // Between the inner and outer angle
+    let edge = sample_light(&spot, Vec3::new(15f32.to_radians().tan(), 0.0, 0.0));
+    assert!(edge.radiance.x > 0.0 && edge.radiance.x < below.radiance.x);
+}
+
+#[test]
+fn hard_edged_spots_keep_a_narrow_edge() {
+    for angle in [0.0, 15.0, 90.0] {
+        let spot = Light {
+            direction: Vec3::NEG_Y,
+            inner_angle: angle,
+            outer_angle: angle,
+            ..Light::point(Vec3::Y, Vec3::ONE, 1.0).with_kind(LightKind::Spot)
+        }
+        .to_gpu();
+        assert!(spot.cos_inner > spot.cos_outer, "{angle}: {spot:?}");
+
+        // Inside, on and outside the cone
+        for offset in [-2.0 * MIN_SPOT_EDGE, 0.0, 2.0 * MIN_SPOT_EDGE] {
+            let off_axis = (angle + offset).clamp(0.0, 89.0).to_radians();
+            let point = Vec3::new(off_axis.tan(), 0.0, 0.0);
+            let radiance = sample_light(&spot, point).radiance;
+            assert!(radiance.is_finite(), "{angle} {offset}: {radiance}");
+        }
+    }
+}
+
+#[test]
+fn area_lights_are_shaded_from_their_surface() {
+    let sphere = Light {
+        size: Vec2::splat(0.5),
+        ..Light::point(Vec3::ZERO, Vec3::ONE, 1.0).with_kind(LightKind::Sphere)
+    }
+    .to_gpu();
+    // Inside the sphere the falloff is capped at the radius
+    let inside = sample_light(&sphere, Vec3::new(0.1, 0.0, 0.0));
+    assert_eq!(inside.size, 0.5);
+    assert!((inside.radiance.x - distance_attenuation(0.5, sphere.range)).abs() < 1e-6);
+
+    let rect = Light {
+        direction: Vec3::NEG_Y,
+        size: Vec2::new(1.0, 0.5),
+        ..Light::point(Vec3::new(0.0, 2.0, 0.0), Vec3::ONE, 1.0).with_kind(LightKind::Rect)
+    }
+    .to_gpu();
+    // Closest point on the rectangle is straight above, clamped to its extent
+    let under_corner = sample_light(&rect, Vec3::new(3.0, 1.0, 3.0));
+    assert!((under_corner.direction.y - 1.0 / 11.25f32.sqrt()).abs() < 1e-4);
+    let beside = sample_light(&rect, Vec3::new(0.5, 1.0, 0.2));
+    assert!((beside.distance - 1.0).abs() < 1e-5);
+    assert_eq!(beside.direction, Vec3::Y);
+    // Rectangles only emit from their front
+    assert_eq!(
+        sample_light(&rect, Vec3::new(0.0, 3.0, 0.0)).radiance,
+        Vec3::ZERO
+    );
+}
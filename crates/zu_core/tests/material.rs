@@ -0,0 +1,66 @@
+//! Material table editing and lookup.
+
+use glam::Vec3;
+use zu_core::RenderOptions;
+use zu_core::render_passes::raymarching_passes::cpu_reference::CpuRaymarcher;
+use zu_core::render_passes::raymarching_passes::material::{
+    MISSING_MATERIAL, Material, Pattern, remove_material,
+};
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::{
+    RaymarchingConstants, RaymarchingObject,
+};
+
+#[test]
+fn removing_a_material_renumbers_objects() {
+    let mut materials = Material::defaults();
+    let mut objects: Vec<_> = [0, 1, 2, -1]
+        .into_iter()
+        .map(|material| RaymarchingObject::sphere(Vec3::ZERO, 0.5, material))
+        .collect();
+
+    remove_material(&mut materials, &mut objects, 1);
+    assert_eq!(materials.len(), 2);
+    assert_eq!(materials[1].name, "Noise");
+    let assigned: Vec<_> = objects.iter().map(|object| object.material).collect();
+    assert_eq!(assigned, [0, 0, 1, -1]);
+
+    // Out of range indices leave everything as is
+    remove_material(&mut materials, &mut objects, 5);
+    assert_eq!(materials.len(), 2);
+}
+
+#[test]
+fn indices_outside_the_table_use_the_missing_material() {
+    let options = RenderOptions {
+        materials: vec![
+            Material {
+                metallic: 0.5,
+                emissive: Vec3::X,
+                emissive_strength: 3.0,
+                ..Material::new("Glow", Vec3::splat(0.5), 0.25)
+            },
+            Material::new("Checker", Vec3::ONE, 1.0).with_pattern(Pattern::Checker),
+        ],
+        ..Default::default()
+    };
+    let constants = RaymarchingConstants::new(&options, 4, 4, 0.0);
+    let raymarcher = CpuRaymarcher::new(constants, &options.raymarching_objects, &options.camera)
+        .with_materials(&options.materials);
+
+    let glow = raymarcher.material_surface(0, Vec3::ZERO);
+    assert_eq!(glow.albedo, Vec3::splat(0.5));
+    assert_eq!(glow.roughness, 0.25);
+    assert_eq!(glow.metallic, 0.5);
+    assert_eq!(glow.emissive, Vec3::new(3.0, 0.0, 0.0));
+
+    let light = raymarcher.material_surface(1, Vec3::splat(0.25));
+    let dark = raymarcher.material_surface(1, Vec3::new(0.75, 0.25, 0.25));
+    assert_eq!(light.albedo, Vec3::ONE);
+    assert!(dark.albedo.max_element() < 0.5);
+
+    for index in [-1, 2, 100] {
+        let missing = raymarcher.material_surface(index, Vec3::ZERO);
+        assert_eq!(missing.albedo, Vec3::from(MISSING_MATERIAL.albedo));
+        assert_eq!(missing.roughness, MISSING_MATERIAL.roughness);
+    }
+}
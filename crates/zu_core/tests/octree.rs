@@ -0,0 +1,119 @@
+//! Editing the sparse voxel octree on the CPU.
+
+use glam::{UVec3, Vec3};
+use zu_core::octree::{BRICK_VOXELS, Brick, EMPTY_DISTANCE, Octree};
+
+/// 16³ leaves of 0.25 around the origin.
+fn octree() -> Octree {
+    Octree::new(Vec3::splat(-2.0), 4.0, 4)
+}
+
+fn sphere(radius: f32) -> impl Fn(Vec3) -> f32 {
+    move |p| p.length() - radius
+}
+
+fn leaves(octree: &Octree) -> impl Iterator<Item = UVec3> {
+    let resolution = octree.resolution();
+    (0..resolution.pow(3)).map(move |i| {
+        UVec3::new(
+            i % resolution,
+            i / resolution % resolution,
+            i / resolution.pow(2),
+        )
+    })
+}
+
+#[test]
+fn insert_and_remove_brick() {
+    let mut octree = octree();
+    assert!(octree.is_empty());
+    assert_eq!(octree.distance(Vec3::ZERO), EMPTY_DISTANCE);
+
+    let leaf = UVec3::new(3, 9, 15);
+    octree.insert_brick(leaf, Brick::filled(0.1));
+    assert!(!octree.is_empty());
+    assert_eq!(octree.brick(leaf), Some(&Brick::filled(0.1)));
+    assert_eq!(octree.sample(octree.leaf_min(leaf) + 0.1), Some(0.1));
+    assert_eq!(octree.brick(UVec3::new(3, 9, 14)), None);
+
+    assert_eq!(octree.remove_brick(leaf), Some(Brick::filled(0.1)));
+    assert_eq!(octree.remove_brick(leaf), None);
+    assert!(octree.is_empty(), "Empty branches are pruned");
+}
+
+#[test]
+fn inserted_shape_keeps_its_interior() {
+    let mut octree = octree();
+    octree.insert_sdf(Vec3::splat(-1.3), Vec3::splat(1.3), sphere(1.2));
+
+    let center = octree.leaf_at(Vec3::ZERO).unwrap();
+    assert!(octree.is_solid(center));
+    assert_eq!(octree.brick(center), None);
+    assert!(octree.sample(Vec3::ZERO).unwrap() < 0.0);
+    assert!(octree.distance(Vec3::ZERO) < 0.0);
+
+    // Near the surface the bricks hold the distance, outside there is nothing
+    let surface = Vec3::new(1.2, 0.0, 0.0);
+    assert!(octree.brick(octree.leaf_at(surface).unwrap()).is_some());
+    assert!(octree.distance(surface).abs() < 0.02);
+    assert!(octree.distance(Vec3::new(0.0, 1.1, 0.0)) < 0.0);
+    assert!(octree.distance(Vec3::new(0.0, 1.4, 0.0)) > 0.0);
+    assert_eq!(octree.sample(Vec3::splat(1.9)), None);
+}
+
+#[test]
+fn re_editing_keeps_the_interior() {
+    let mut octree = octree();
+    octree.insert_sdf(Vec3::splat(-1.3), Vec3::splat(1.3), sphere(1.2));
+    let solid: Vec<_> = leaves(&octree)
+        .filter(|leaf| octree.is_solid(*leaf))
+        .collect();
+    assert!(!solid.is_empty());
+
+    // Visits every leaf without touching the first sphere
+    octree.insert_sdf(Vec3::splat(-2.0), Vec3::splat(2.0), |p| {
+        (p - Vec3::splat(1.7)).length() - 0.2
+    });
+    assert!(solid.iter().all(|leaf| octree.is_solid(*leaf)));
+    assert!(octree.distance(Vec3::ZERO) < 0.0);
+    assert!(octree.distance(Vec3::splat(1.7)) < 0.0);
+}
+
+#[test]
+fn carving_opens_the_interior() {
+    let mut octree = octree();
+    octree.insert_sdf(Vec3::splat(-1.3), Vec3::splat(1.3), sphere(1.2));
+    octree.remove_sdf(Vec3::splat(-0.7), Vec3::splat(0.7), sphere(0.6));
+
+    let center = octree.leaf_at(Vec3::ZERO).unwrap();
+    assert!(!octree.is_solid(center));
+    assert!(octree.distance(Vec3::ZERO) > 0.0);
+    // The shell between the two spheres stays inside
+    assert!(octree.distance(Vec3::new(0.9, 0.0, 0.0)) < 0.0);
+
+    // Carving everything empties the tree
+    octree.remove_sdf(Vec3::splat(-2.0), Vec3::splat(2.0), sphere(10.0));
+    assert!(octree.is_empty());
+}
+
+#[test]
+fn solid_cells_share_one_gpu_brick() {
+    let mut octree = octree();
+    octree.insert_sdf(Vec3::splat(-1.3), Vec3::splat(1.3), sphere(1.2));
+    let bricks = leaves(&octree)
+        .filter(|leaf| octree.brick(*leaf).is_some())
+        .count();
+    let solid = leaves(&octree)
+        .filter(|leaf| octree.is_solid(*leaf))
+        .count();
+    assert!(solid > 1);
+
+    let gpu = octree.to_gpu();
+    assert_eq!(gpu.bricks.len(), (bricks + 1) * BRICK_VOXELS);
+    let solid_bricks = gpu.bricks.chunks_exact(BRICK_VOXELS).filter(|brick| {
+        brick
+            .iter()
+            .all(|distance| *distance == -octree.leaf_size())
+    });
+    assert_eq!(solid_bricks.count(), 1);
+}
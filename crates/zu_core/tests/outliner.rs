@@ -0,0 +1,117 @@
+//! Rows, selection and editing in the outliner.
+
+use egui::Modifiers;
+use glam::Vec3;
+use zu_core::outliner::Outliner;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+/// Rows as (index, depth).
+fn rows(outliner: &Outliner, objects: &[RaymarchingObject]) -> Vec<(usize, usize)> {
+    (outliner.rows(objects).iter())
+        .map(|row| (row.index, row.depth))
+        .collect()
+}
+
+/// `Head` with children `Arm` and `Hat`, `Arm` with child `Hand`, and a root `Ground`.
+fn tree() -> Vec<RaymarchingObject> {
+    vec![
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0)
+            .with_name("Hand")
+            .with_parent(2),
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0).with_name("Head"),
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0)
+            .with_name("Arm")
+            .with_parent(1),
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0).with_name("Ground"),
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0)
+            .with_name("Hat")
+            .with_parent(1),
+    ]
+}
+
+#[test]
+fn rows_follow_the_hierarchy() {
+    let objects = tree();
+    let mut outliner = Outliner::default();
+    assert_eq!(
+        rows(&outliner, &objects),
+        [(1, 0), (2, 1), (0, 2), (4, 1), (3, 0)]
+    );
+
+    outliner.filter = "h".to_owned();
+    assert_eq!(rows(&outliner, &objects), [(0, 0), (1, 0), (4, 0)]);
+
+    // A parent cycle still shows every object
+    let mut objects = tree();
+    objects[1] = objects[1].clone().with_parent(0);
+    let outliner = Outliner::default();
+    let mut shown: Vec<_> = rows(&outliner, &objects)
+        .into_iter()
+        .map(|(index, _)| index)
+        .collect();
+    shown.sort();
+    assert_eq!(shown, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn clicks_select_like_a_list() {
+    let objects = tree();
+    let mut outliner = Outliner::default();
+    let rows = outliner.rows(&objects);
+    let ids = |indices: &[usize]| -> Vec<u64> {
+        indices.iter().map(|index| objects[*index].id).collect()
+    };
+
+    outliner.click(&objects, &rows, 2, Modifiers::NONE);
+    assert_eq!(outliner.selection, ids(&[2]));
+    // Shift selects the rows in between in display order, ending on the clicked one
+    outliner.click(&objects, &rows, 4, Modifiers::SHIFT);
+    assert_eq!(outliner.selection, ids(&[2, 0, 4]));
+    assert_eq!(outliner.active(&objects), Some(4));
+    outliner.click(&objects, &rows, 0, Modifiers::COMMAND);
+    assert_eq!(outliner.selection, ids(&[2, 4]));
+    outliner.click(&objects, &rows, 3, Modifiers::COMMAND);
+    assert_eq!(outliner.selection, ids(&[2, 4, 3]));
+    outliner.click(&objects, &rows, 1, Modifiers::NONE);
+    assert_eq!(outliner.selection, ids(&[1]));
+
+    outliner.select(&objects, Some(0));
+    assert_eq!(outliner.active(&objects), Some(0));
+    outliner.select(&objects, None);
+    assert_eq!(outliner.active(&objects), None);
+}
+
+#[test]
+fn duplicates_keep_their_hierarchy_and_get_new_ids() {
+    let mut objects = tree();
+    let mut outliner = Outliner::default();
+    outliner.selection = vec![objects[2].id, objects[0].id];
+    outliner.duplicate_selection(&mut objects);
+
+    assert_eq!(objects.len(), 7);
+    let (hand, arm) = (&objects[5], &objects[6]);
+    assert_eq!(
+        (hand.name.as_str(), arm.name.as_str()),
+        ("Hand copy", "Arm copy")
+    );
+    assert_eq!(hand.parent(), Some(6));
+    assert_eq!(arm.parent(), Some(1));
+    assert_ne!(hand.id, objects[0].id);
+    assert_eq!(outliner.selection, [hand.id, arm.id]);
+}
+
+#[test]
+fn deleting_the_selection_keeps_the_rest() {
+    let mut objects = tree();
+    let mut outliner = Outliner::default();
+    let (hand, ground) = (objects[0].id, objects[3].id);
+    outliner.selection = vec![objects[1].id, objects[2].id];
+    outliner.delete_selection(&mut objects);
+
+    let names: Vec<_> = objects.iter().map(|object| object.name.as_str()).collect();
+    assert_eq!(names, ["Hand", "Ground", "Hat"]);
+    assert_eq!(objects[0].id, hand);
+    assert_eq!(objects[1].id, ground);
+    assert!(objects.iter().all(|object| object.parent().is_none()));
+    assert!(outliner.selection.is_empty());
+}
@@ -0,0 +1,106 @@
+//! Sample accumulation of the path traced reference mode.
+
+mod common;
+
+use common::test_renderer;
+use zu_core::golden::scenes;
+use zu_core::headless::HeadlessRenderer;
+use zu_core::render_passes::raymarching_passes::path_tracing::Accumulation;
+use zu_core::render_passes::render_pass_manager::RenderOptions;
+use zu_core::texture_manager::readback::read_texture;
+use zu_core::texture_manager::textures::EngineTexture;
+
+#[test]
+fn accumulation_restarts_when_options_or_time_change() {
+    let mut options = RenderOptions::default();
+    options.path_tracing.max_samples = 3;
+    let mut accumulation = Accumulation::default();
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(0));
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(1));
+
+    options.exposure = 2.0;
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(0));
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(1));
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(2));
+    assert_eq!(accumulation.next_sample(&options, 0.0), None);
+    assert_eq!(accumulation.samples(), 3);
+
+    options.raymarching_objects[0].position.y += 0.1;
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(0));
+
+    // Clouds drift with the time, animated frames start over too
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(1));
+    assert_eq!(accumulation.next_sample(&options, 0.5), Some(0));
+    assert_eq!(accumulation.time(), Some(0.5));
+
+    accumulation.reset();
+    assert_eq!(accumulation.samples(), 0);
+    assert_eq!(accumulation.time(), None);
+    assert_eq!(accumulation.next_sample(&options, 0.0), Some(0));
+}
+
+/// Per pixel sample counts, the alpha channel of the "Accumulation" texture.
+fn accumulated_counts(renderer: &mut HeadlessRenderer) -> Vec<f32> {
+    let texture = renderer
+        .render_pass_manager()
+        .texture_manager()
+        .get_texture("Accumulation")
+        .unwrap()
+        .texture()
+        .clone();
+    let pixels = read_texture(&renderer.device, &renderer.queue, &texture).unwrap();
+    pixels
+        .chunks_exact(16)
+        .map(|pixel| f32::from_le_bytes([pixel[12], pixel[13], pixel[14], pixel[15]]))
+        .collect()
+}
+
+#[test]
+fn path_tracer_accumulates_on_gpu() {
+    let Some(mut renderer) = test_renderer(32, 24) else {
+        return;
+    };
+
+    // Only sky above the horizon, which the path tracer sees exactly like the real-time pass
+    let sky = scenes()
+        .into_iter()
+        .find(|scene| scene.name == "sky")
+        .unwrap();
+    *renderer.get_options() = sky.options;
+    let real_time = renderer.render().unwrap();
+
+    renderer.get_options().path_tracing.enabled = true;
+    let mut path_traced = Vec::new();
+    for _ in 0..3 {
+        path_traced = renderer.render().unwrap();
+    }
+    assert_eq!(renderer.render_pass_manager().path_tracing_samples(), 3);
+    assert!(accumulated_counts(&mut renderer).iter().all(|&count| count == 3.0));
+
+    // Pixels are jittered, so compare the average of the top rows
+    let top_rows = 32 * 4 * 6;
+    let average = |pixels: &[u8]| {
+        pixels[..top_rows]
+            .iter()
+            .map(|&channel| channel as f32)
+            .sum::<f32>()
+            / top_rows as f32
+    };
+    let difference = (average(&real_time) - average(&path_traced)).abs();
+    assert!(difference < 2.0, "Sky differs by {difference}");
+
+    renderer.get_options().sun_intensity *= 0.5;
+    renderer.render().unwrap();
+    assert_eq!(renderer.render_pass_manager().path_tracing_samples(), 1);
+    assert!(accumulated_counts(&mut renderer).iter().all(|&count| count == 1.0));
+
+    // The wall clock held still, a set time starts over
+    renderer.render_pass_manager().set_time(Some(1.0));
+    renderer.render().unwrap();
+    renderer.render().unwrap();
+    assert_eq!(renderer.render_pass_manager().path_tracing_samples(), 2);
+
+    renderer.resize(16, 12);
+    renderer.render().unwrap();
+    assert_eq!(renderer.render_pass_manager().path_tracing_samples(), 1);
+}
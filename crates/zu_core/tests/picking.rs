@@ -0,0 +1,83 @@
+//! Picking objects by pixel on a software adapter.
+
+mod common;
+
+use common::test_renderer;
+use glam::Vec3;
+use zu_core::RenderOptions;
+use zu_core::render_passes::raymarching_passes::camera::Camera;
+use zu_core::render_passes::raymarching_passes::csg::CsgOperation;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+#[test]
+fn picks_the_object_under_a_pixel() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+    *renderer.get_options() = RenderOptions {
+        raymarching_objects: vec![
+            RaymarchingObject::sphere(Vec3::new(-0.8, 0.0, 0.0), 0.4, 0),
+            RaymarchingObject::sphere(Vec3::new(0.8, 0.0, 0.0), 0.4, 0),
+            // Carves a hole into the front of the second sphere
+            RaymarchingObject::sphere(Vec3::new(0.8, 0.0, -0.35), 0.2, 0)
+                .with_operation(CsgOperation::Subtraction, 0.0),
+        ],
+        camera: Camera::new(Vec3::new(0.0, 0.0, -3.0), 0.0, 0.0),
+        ..Default::default()
+    };
+
+    let ids: Vec<u64> = renderer
+        .get_options()
+        .raymarching_objects
+        .iter()
+        .map(|object| object.id)
+        .collect();
+
+    let left = renderer.pick(25, 24).unwrap();
+    assert_eq!(left.pixel, [25, 24]);
+    assert_eq!(left.object, Some(ids[0]));
+    let distance = left.distance.unwrap();
+    assert!((2.4..3.0).contains(&distance), "{distance}");
+
+    // Like its material, the hole belongs to the sphere it's carved into
+    assert_eq!(renderer.pick(41, 24).unwrap().object, Some(ids[1]));
+    assert_eq!(renderer.pick(38, 24).unwrap().object, Some(ids[1]));
+
+    let ground = renderer.pick(32, HEIGHT - 1).unwrap();
+    assert_eq!(ground.object, None);
+    assert!(ground.distance.is_some());
+    let sky = renderer.pick(32, 0).unwrap();
+    assert_eq!((sky.object, sky.distance), (None, None));
+
+    // Pixels outside the output are clamped to its edge
+    assert_eq!(
+        renderer.pick(1000, 1000).unwrap().pixel,
+        [WIDTH - 1, HEIGHT - 1]
+    );
+}
+
+#[test]
+fn picks_name_the_object_even_if_the_list_changes() {
+    let Some(mut renderer) = test_renderer(WIDTH, HEIGHT) else {
+        return;
+    };
+    let left = RaymarchingObject::sphere(Vec3::new(-0.8, 0.0, 0.0), 0.4, 0);
+    let id = left.id;
+    *renderer.get_options() = RenderOptions {
+        raymarching_objects: vec![
+            left,
+            RaymarchingObject::sphere(Vec3::new(0.8, 0.0, 0.0), 0.4, 0),
+        ],
+        camera: Camera::new(Vec3::new(0.0, 0.0, -3.0), 0.0, 0.0),
+        ..Default::default()
+    };
+
+    // The list is reordered while the pick of the left sphere is read back
+    renderer.render_pass_manager().pick(25, 24);
+    renderer.render().unwrap();
+    renderer.get_options().raymarching_objects.swap(0, 1);
+    assert_eq!(renderer.pick(25, 24).unwrap().object, Some(id));
+}
@@ -0,0 +1,136 @@
+//! Reflection and refraction rays in the CPU reference, which mirrors the shader.
+
+use glam::{Vec3, Vec4};
+use zu_core::render_passes::raymarching_passes::cpu_reference::{CpuRaymarcher, dielectric_f0};
+use zu_core::render_passes::raymarching_passes::material::Material;
+use zu_core::render_passes::raymarching_passes::primitives::{Primitive, PrimitiveKind};
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::{
+    RaymarchingConstants, RaymarchingObject,
+};
+use zu_core::render_passes::render_pass_manager::RenderOptions;
+
+fn raymarcher(options: &RenderOptions) -> CpuRaymarcher<'static> {
+    CpuRaymarcher::new(
+        RaymarchingConstants::new(options, 1, 1, 0.0),
+        &options.raymarching_objects,
+        &options.camera,
+    )
+    .with_materials(&options.materials)
+    .with_atmosphere(&options.atmosphere)
+}
+
+/// `prop` is material 1, material 2 a red lamp.
+fn scene(objects: Vec<RaymarchingObject>, prop: Material) -> RenderOptions {
+    let lamp = Material {
+        emissive: Vec3::X,
+        emissive_strength: 5.0,
+        ..Material::new("Lamp", Vec3::ZERO, 1.0)
+    };
+    RenderOptions {
+        raymarching_objects: objects,
+        materials: vec![Material::default(), prop, lamp],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn mirrors_reflect_the_scene() {
+    // A red lamp behind the camera, seen in a chrome ball
+    let options = scene(
+        vec![
+            RaymarchingObject::sphere(Vec3::new(0.0, 1.0, 0.0), 0.5, 1),
+            RaymarchingObject::sphere(Vec3::new(0.0, 1.0, -4.0), 0.5, 2),
+        ],
+        Material::chrome(),
+    );
+    let mirror = raymarcher(&options);
+    let position = Vec3::new(0.0, 1.0, -0.5);
+    let surface = mirror.material_surface(1, position);
+    let light = |raymarcher: &CpuRaymarcher| {
+        raymarcher.secondary_light(position, Vec3::NEG_Z, Vec3::Z, surface, &mut 0)
+    };
+    let reflection = light(&mirror);
+    assert!(reflection.x > 3.0, "{reflection}");
+    assert!(reflection.y < 0.1 && reflection.z < 0.1, "{reflection}");
+
+    // Without bounces only the ambient light is reflected
+    let flat = RenderOptions {
+        bounce_depth: 0,
+        ..options.clone()
+    };
+    let flat = raymarcher(&flat);
+    assert_eq!(light(&flat), Vec3::ZERO);
+    assert_eq!(flat.traced_gloss(surface, 0), 0.0);
+}
+
+#[test]
+fn rough_surfaces_trace_no_reflections() {
+    let options = scene(
+        vec![RaymarchingObject::sphere(Vec3::new(0.0, 1.0, 0.0), 0.5, 1)],
+        Material::new("Chalk", Vec3::ONE, 0.8),
+    );
+    let raymarcher = raymarcher(&options);
+    let position = Vec3::new(0.0, 1.0, -0.5);
+    let surface = raymarcher.material_surface(1, position);
+    assert_eq!(raymarcher.traced_gloss(surface, 0), 0.0);
+    let light = raymarcher.secondary_light(position, Vec3::NEG_Z, Vec3::Z, surface, &mut 0);
+    assert_eq!(light, Vec3::ZERO);
+}
+
+#[test]
+fn light_passes_straight_through_the_middle_of_a_glass_ball() {
+    let glass = Material::glass();
+    let options = scene(
+        vec![RaymarchingObject::sphere(Vec3::new(0.0, 1.0, 0.0), 0.5, 1)],
+        glass.clone(),
+    );
+    let raymarcher = raymarcher(&options);
+    let position = Vec3::new(0.0, 1.0, -0.5);
+    let surface = raymarcher.material_surface(1, position);
+    let refraction = raymarcher
+        .refract_through(position, Vec3::NEG_Z, Vec3::Z, surface)
+        .unwrap();
+
+    assert!(
+        refraction.direction.distance(Vec3::Z) < 1e-3,
+        "{refraction:?}"
+    );
+    assert!(refraction.origin.z > 0.5, "{refraction:?}");
+    // Absorbed along the diameter and partly reflected back in on the way out
+    let expected = (-glass.absorption * 1.0).exp() * (1.0 - dielectric_f0(glass.ior));
+    assert!(
+        (refraction.transmittance - expected).abs().max_element() < 0.02,
+        "{refraction:?} {expected}"
+    );
+}
+
+#[test]
+fn denser_media_shift_the_view_further() {
+    // Through a slab the light leaves parallel to how it came in, offset sideways
+    let offset = |material: Material| {
+        let slab = RaymarchingObject::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Primitive::new(PrimitiveKind::Box, Vec4::new(2.0, 2.0, 0.5, 0.0)),
+            1,
+        );
+        let options = scene(vec![slab], material);
+        let raymarcher = raymarcher(&options);
+        let position = Vec3::new(0.0, 1.0, -0.5);
+        let direction = Vec3::new(0.5, 0.0, 1.0).normalize();
+        let surface = raymarcher.material_surface(1, position);
+        let refraction = raymarcher
+            .refract_through(position, Vec3::NEG_Z, direction, surface)
+            .unwrap();
+        assert!(
+            refraction.direction.distance(direction) < 1e-3,
+            "{refraction:?}"
+        );
+        // How far the exit is from where the unbent ray would cross the far side
+        let unbent = position + direction * (1.0 / direction.z);
+        (unbent.x - refraction.origin.x).abs()
+    };
+    let water = offset(Material::water());
+    let glass = offset(Material::glass());
+    assert!(water > 0.05, "{water}");
+    assert!(glass > water, "{water} {glass}");
+}
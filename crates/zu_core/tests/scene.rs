@@ -0,0 +1,129 @@
+//! Scene file round trips and version handling.
+
+use glam::{Vec2, Vec3, Vec4};
+use zu_core::golden::scenes;
+use zu_core::render_passes::raymarching_passes::csg::CsgOperation;
+use zu_core::render_passes::raymarching_passes::primitives::{Primitive, PrimitiveKind};
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+use zu_core::render_passes::render_pass_manager::RenderOptions;
+use zu_core::scene::{self, SCENE_VERSION};
+
+#[test]
+fn golden_scenes_round_trip() {
+    for golden in scenes() {
+        let source = scene::to_toml(&golden.options).expect("Failed to write scene");
+        let loaded = scene::from_toml(&source)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err:#}\n{source}", golden.name));
+        assert_eq!(loaded, golden.options, "{} changed:\n{source}", golden.name);
+    }
+}
+
+#[test]
+fn saved_scene_is_readable() {
+    let source = scene::to_toml(&RenderOptions::default()).unwrap();
+    assert!(source.starts_with(&format!("version = {SCENE_VERSION}")));
+    assert!(source.contains("kind = \"sphere\""), "{source}");
+    assert!(source.contains("operation = \"union\""), "{source}");
+}
+
+#[test]
+fn missing_fields_use_defaults() {
+    let options = scene::from_toml(
+        r#"
+        version = 1
+        exposure = 2.0
+
+        [[raymarching_objects]]
+        position = [0.0, 1.0, 0.0]
+        operation = "smooth_subtraction"
+        primitive = { kind = "box" }
+        "#,
+    )
+    .unwrap();
+
+    let defaults = RenderOptions::default();
+    assert_eq!(options.exposure, 2.0);
+    assert_eq!(options.camera, defaults.camera);
+    assert_eq!(options.raymarching_objects.len(), 1);
+
+    let object = &options.raymarching_objects[0];
+    assert_eq!(object.name, "Box");
+    assert_eq!(object.position, Vec3::Y);
+    assert_eq!(object.operation(), CsgOperation::SmoothSubtraction);
+    assert_eq!(
+        object.primitive,
+        Primitive::new(PrimitiveKind::Box, PrimitiveKind::Box.default_params())
+    );
+
+    let object = scene::from_toml(
+        r#"
+        version = 1
+        [[raymarching_objects]]
+        primitive = { kind = "torus", params = [1.0, 0.25, 0.0, 0.0] }
+        "#,
+    )
+    .unwrap()
+    .raymarching_objects
+    .remove(0);
+    assert_eq!(object.primitive.kind(), PrimitiveKind::Torus);
+    assert_eq!(object.primitive.params, Vec4::new(1.0, 0.25, 0.0, 0.0));
+}
+
+#[test]
+fn version_1_camera_keeps_its_view() {
+    let options = scene::from_toml(
+        r#"
+        version = 1
+        fov = 0.7
+        rotation = 0.4
+        yz_rotation = 5.9
+        ray_origin = [1.0, 2.0, -3.0]
+        "#,
+    )
+    .unwrap();
+    let camera = options.camera;
+    assert_eq!(camera.position, Vec3::new(1.0, 2.0, -3.0));
+
+    // Rays through the image plane turned by `rotation`, then tilted by `yz_rotation`
+    let basis = camera.to_gpu().ray_basis();
+    for uv in [Vec2::ZERO, Vec2::new(0.3, -0.2), Vec2::new(-1.2, 0.9)] {
+        let (sin, cos) = 0.4f32.sin_cos();
+        let (yz_sin, yz_cos) = 5.9f32.sin_cos();
+        let x = (uv.x * cos - uv.y * sin) * 0.7;
+        let y = (uv.x * sin + uv.y * cos) * 0.7;
+        let old = Vec3::new(x, y * yz_cos - yz_sin, y * yz_sin + yz_cos).normalize();
+        let new = (basis * uv.extend(1.0)).normalize();
+        assert!(old.distance(new) < 1e-5, "{uv}: {old} {new}");
+    }
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    assert!(scene::from_toml("exposure = 1.0").is_err());
+    assert!(scene::from_toml(&format!("version = {}", SCENE_VERSION + 1)).is_err());
+    assert!(scene::from_toml("version = -1").is_err());
+    assert!(scene::from_toml("version = 1\nexposure = \"bright\"").is_err());
+}
+
+#[test]
+fn loaded_object_ids_stay_unique() {
+    let options = scene::from_toml(
+        r#"
+        version = 2
+        [[raymarching_objects]]
+        id = 1000
+        name = "Kept"
+        [[raymarching_objects]]
+        id = 1000
+        [[raymarching_objects]]
+        "#,
+    )
+    .unwrap();
+    let objects = &options.raymarching_objects;
+    assert_eq!(objects[0].id, 1000);
+    assert_eq!(objects[0].name, "Kept");
+    assert!(objects[1].id != 1000 && objects[2].id != 1000);
+    assert_ne!(objects[1].id, objects[2].id);
+    // New objects are numbered after the loaded ones
+    assert!(RaymarchingObject::default().id > 1000);
+}
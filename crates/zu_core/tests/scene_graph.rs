@@ -0,0 +1,196 @@
+//! Parent/child hierarchy of the raymarching objects.
+
+use glam::{Quat, UVec3, Vec3};
+use zu_core::object_grid::{MIN_GRID_OBJECTS, ObjectGrid};
+use zu_core::octree::EMPTY_DISTANCE;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::RaymarchingObject;
+use zu_core::scene_graph::{SceneGraph, move_object, object_index, remove_object, world_position};
+
+#[test]
+fn removing_an_object_keeps_its_children_in_place() {
+    let mut objects = vec![
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0),
+        RaymarchingObject::sphere(Vec3::new(1.0, 0.0, 0.0), 0.5, 0)
+            .with_transform(Quat::from_rotation_y(0.5), Vec3::splat(2.0))
+            .with_parent(3),
+        RaymarchingObject::sphere(Vec3::new(0.0, 1.0, 0.0), 0.5, 0).with_parent(1),
+        RaymarchingObject::sphere(Vec3::new(0.0, 0.0, 2.0), 0.5, 0),
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0).with_parent(3),
+    ];
+    let before = SceneGraph::new(&objects).world_position(2);
+
+    remove_object(&mut objects, 1);
+    let parents: Vec<_> = objects.iter().map(|object| object.parent).collect();
+    assert_eq!(parents, [-1, 2, -1, 2]);
+    let after = SceneGraph::new(&objects).world_position(1);
+    assert!(before.distance(after) < 1e-5, "{before} != {after}");
+
+    // Out of range indices leave everything as is
+    remove_object(&mut objects, 7);
+    assert_eq!(objects.len(), 4);
+}
+
+#[test]
+fn moving_an_object_keeps_the_hierarchy() {
+    let mut objects: Vec<_> = (0..5)
+        .map(|index| RaymarchingObject::sphere(Vec3::X * index as f32, 0.5, 0))
+        .collect();
+    objects[1] = objects[1].clone().with_parent(0);
+    objects[3] = objects[3].clone().with_parent(4);
+    objects[4] = objects[4].clone().with_parent(1);
+    let ids: Vec<_> = objects.iter().map(|object| object.id).collect();
+    let scene = SceneGraph::new(&objects);
+    let before: Vec<_> = (0..5).map(|index| scene.world_position(index)).collect();
+
+    move_object(&mut objects, 0, 3);
+    move_object(&mut objects, 4, 1);
+    let order: Vec<_> = objects.iter().map(|object| object.id).collect();
+    assert_eq!(order, [ids[1], ids[4], ids[2], ids[3], ids[0]]);
+    let scene = SceneGraph::new(&objects);
+    for (id, before) in ids.iter().zip(before) {
+        let index = object_index(&objects, *id).unwrap();
+        assert_eq!(scene.world_position(index), before, "object {id}");
+    }
+}
+
+#[test]
+fn hidden_and_soloed_objects_apply_to_children() {
+    let mut objects = vec![
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0),
+        RaymarchingObject::sphere(Vec3::X, 0.5, 0).with_parent(0),
+        RaymarchingObject::sphere(Vec3::Y, 0.5, 0),
+    ];
+    let visible = |objects: &[RaymarchingObject]| {
+        let scene = SceneGraph::new(objects);
+        (0..objects.len())
+            .map(|index| scene.is_visible(index))
+            .collect::<Vec<_>>()
+    };
+    objects[0].hidden = true;
+    assert_eq!(visible(&objects), [false, false, true]);
+
+    objects[0].hidden = false;
+    objects[0].solo = true;
+    assert_eq!(visible(&objects), [true, true, false]);
+    objects[1].hidden = true;
+    assert_eq!(visible(&objects), [true, false, false]);
+}
+
+#[test]
+fn hidden_objects_are_left_out_of_the_grid() {
+    // A hidden object after a row big enough to get cells, and in a small scene without them
+    for count in [MIN_GRID_OBJECTS, 1] {
+        let mut objects: Vec<_> = (0..=count)
+            .map(|index| RaymarchingObject::sphere(Vec3::X * 2.0 * index as f32, 0.5, 0))
+            .collect();
+        objects[count].hidden = true;
+        let hidden = count as u32;
+        let grid = ObjectGrid::new(&SceneGraph::new(&objects));
+        assert_eq!(grid.dims() == UVec3::ZERO, count < MIN_GRID_OBJECTS);
+        assert!(!grid.indices().contains(&hidden));
+        let at_hidden = Vec3::X * 2.0 * count as f32;
+        assert!(grid.lookup(at_hidden).all(|index| index != count));
+        assert!(grid.lookup(Vec3::ZERO).any(|index| index == 0));
+    }
+}
+
+#[test]
+fn small_scenes_evaluate_every_object() {
+    let objects: Vec<_> = (0..3)
+        .map(|index| RaymarchingObject::sphere(Vec3::X * 4.0 * index as f32, 0.5, 0))
+        .collect();
+    let grid = ObjectGrid::new(&SceneGraph::new(&objects));
+    assert!(grid.cells().is_empty());
+    let lookup = grid.lookup(Vec3::new(100.0, 0.0, 0.0));
+    assert_eq!(lookup.bound, EMPTY_DISTANCE);
+    assert_eq!(lookup.collect::<Vec<_>>(), [0, 1, 2]);
+}
+
+#[test]
+fn transforms_propagate_to_descendants() {
+    let rotation = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+    // Children listed before their parents still resolve
+    let objects = vec![
+        RaymarchingObject::sphere(Vec3::new(0.0, 0.0, 1.0), 0.5, 0).with_parent(1),
+        RaymarchingObject::sphere(Vec3::new(1.0, 0.0, 0.0), 0.5, 0)
+            .with_transform(Quat::IDENTITY, Vec3::new(1.0, 0.5, 1.0))
+            .with_parent(2),
+        RaymarchingObject::sphere(Vec3::new(0.0, 2.0, 0.0), 0.5, 0)
+            .with_transform(rotation, Vec3::splat(2.0)),
+    ];
+    let graph = SceneGraph::new(&objects);
+
+    // The root's rotation turns +x into -z and its scale doubles the offsets below it
+    let expected = [
+        Vec3::new(0.0, 2.0, -2.0) + Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, -2.0),
+        Vec3::new(0.0, 2.0, 0.0),
+    ];
+    for (index, expected) in expected.into_iter().enumerate() {
+        let position = graph.world_position(index);
+        assert!(position.distance(expected) < 1e-5, "{index}: {position}");
+        let alone = world_position(&objects, index);
+        assert!(alone.distance(expected) < 1e-5, "{index}: {alone}");
+    }
+    let world = graph.world_transform(0);
+    let manual =
+        objects[2].local_transform() * objects[1].local_transform() * objects[0].local_transform();
+    assert!(world.abs_diff_eq(manual, 1e-5));
+
+    // Distances scale by the smallest scale along the chain
+    let gpu = graph.gpu_objects();
+    let scale_bounds: Vec<_> = gpu.iter().map(|object| object.scale_bound).collect();
+    assert_eq!(scale_bounds, [1.0, 1.0, 2.0]);
+    assert!(gpu[0].world_to_local.abs_diff_eq(world.inverse(), 1e-5));
+    assert_eq!(graph.children(2).collect::<Vec<_>>(), [1]);
+}
+
+#[test]
+fn parent_cycles_and_invalid_parents_become_roots() {
+    let objects = vec![
+        // 0 and 1 parent each other, the walk from 0 breaks the cycle above 1
+        RaymarchingObject::sphere(Vec3::new(1.0, 0.0, 0.0), 0.5, 0).with_parent(1),
+        RaymarchingObject::sphere(Vec3::new(0.0, 1.0, 0.0), 0.5, 0).with_parent(0),
+        RaymarchingObject::sphere(Vec3::new(0.0, 0.0, 1.0), 0.5, 0).with_parent(0),
+        RaymarchingObject::sphere(Vec3::new(2.0, 0.0, 0.0), 0.5, 0).with_parent(3),
+        RaymarchingObject::sphere(Vec3::new(3.0, 0.0, 0.0), 0.5, 0).with_parent(9),
+    ];
+    let graph = SceneGraph::new(&objects);
+
+    let expected = [
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(3.0, 0.0, 0.0),
+    ];
+    for (index, expected) in expected.into_iter().enumerate() {
+        let position = graph.world_position(index);
+        assert!(position.distance(expected) < 1e-5, "{index}: {position}");
+    }
+    // Alone, the walk from 1 breaks the cycle above 0 instead
+    for index in [0, 2, 3, 4] {
+        let alone = world_position(&objects, index);
+        assert!(alone.distance(expected[index]) < 1e-5, "{index}: {alone}");
+    }
+    assert_eq!(world_position(&objects, 1), Vec3::new(1.0, 1.0, 0.0));
+    assert_eq!(graph.gpu_objects().len(), objects.len());
+}
+
+#[test]
+fn zero_scales_still_invert() {
+    let objects = vec![
+        RaymarchingObject::sphere(Vec3::ZERO, 0.5, 0)
+            .with_transform(Quat::IDENTITY, Vec3::new(0.0, 1.0, -0.0)),
+        RaymarchingObject::sphere(Vec3::X, 0.5, 0)
+            .with_transform(Quat::IDENTITY, Vec3::new(-2.0, 0.0, 1.0))
+            .with_parent(0),
+    ];
+    let gpu = SceneGraph::new(&objects).gpu_objects();
+    for (index, object) in gpu.iter().enumerate() {
+        assert!(object.world_to_local.is_finite(), "{index}: {object:?}");
+        assert!(object.scale_bound > 0.0, "{index}: {object:?}");
+    }
+    // Negative scales keep mirroring
+    assert_eq!(objects[1].clamped_scale(), Vec3::new(-2.0, 0.01, 1.0));
+}
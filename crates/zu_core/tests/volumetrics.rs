@@ -0,0 +1,163 @@
+//! Clouds and fog volumes in the CPU reference, which mirrors the shader.
+
+use glam::{Vec2, Vec3};
+use zu_core::render_passes::raymarching_passes::camera::Camera;
+use zu_core::render_passes::raymarching_passes::cpu_reference::{CpuRaymarcher, Haze, cloud_noise};
+use zu_core::render_passes::raymarching_passes::primitives::Primitive;
+use zu_core::render_passes::raymarching_passes::raymarching_pass_compute::{
+    RaymarchingConstants, RaymarchingObject,
+};
+use zu_core::render_passes::raymarching_passes::volumetrics::{Clouds, FogVolume};
+use zu_core::render_passes::render_pass_manager::RenderOptions;
+
+fn raymarcher(options: &RenderOptions, time: f32) -> CpuRaymarcher<'static> {
+    CpuRaymarcher::new(
+        RaymarchingConstants::new(options, 1, 1, time),
+        &options.raymarching_objects,
+        &options.camera,
+    )
+    .with_atmosphere(&options.atmosphere)
+    .with_clouds(&options.clouds)
+    .with_fog_volumes(&options.fog_volumes)
+}
+
+#[test]
+fn overcast_clouds_hide_the_sky() {
+    let overcast = RenderOptions {
+        clouds: Clouds {
+            coverage: 1.0,
+            density: 2.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let cloudy = raymarcher(&overcast, 0.0);
+    let up = Vec3::new(0.2, 1.0, 0.1).normalize();
+    let camera = overcast.camera.position;
+    let clouds = cloudy.march_clouds(camera, up, 0.5);
+    assert!(clouds.transmittance < 0.05, "{clouds:?}");
+    assert!(clouds.scattered.min_element() > 0.0, "{clouds:?}");
+    let clouds_bottom = overcast.clouds.bottom - camera.y;
+    assert!(clouds.distance > clouds_bottom, "{clouds:?}");
+
+    // Looking down never reaches the layer
+    let down = cloudy.march_clouds(camera, -up, 0.5);
+    assert_eq!(down.transmittance, 1.0);
+
+    // No clouds by default
+    let clear = RenderOptions {
+        clouds: Clouds::default(),
+        ..overcast
+    };
+    let clear = raymarcher(&clear, 0.0);
+    assert_eq!(clear.cloudy_sky(up, 0.5), clear.sky(up, true));
+}
+
+#[test]
+fn clouds_drift_with_the_wind() {
+    let options = RenderOptions {
+        clouds: Clouds {
+            coverage: 0.45,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let wind = options.clouds.wind;
+    let p = Vec3::new(3.0, 30.0, -7.0);
+    let now = raymarcher(&options, 0.0).cloud_density(p);
+    let later = raymarcher(&options, 10.0).cloud_density(p + wind * 10.0);
+    assert!(now > 0.0, "{now}");
+    assert!((now - later).abs() < 1e-4, "{now} {later}");
+
+    for i in 0..100 {
+        let noise = cloud_noise(Vec3::new(i as f32 * 0.37, i as f32 * -1.3, i as f32 * 0.71));
+        assert!((0.0..=1.0).contains(&noise), "{noise}");
+    }
+}
+
+/// A fog ball of radius 1 on the view axis, 3 units in front of the camera.
+fn fog_ball(objects: Vec<RaymarchingObject>) -> RenderOptions {
+    RenderOptions {
+        raymarching_objects: objects,
+        camera: Camera::new(Vec3::ZERO, 0.0, 0.0),
+        ambient: 0.0,
+        sun_dir: Vec3::Y,
+        fog_volumes: vec![FogVolume {
+            edge: 1e-3,
+            ..FogVolume::new(Vec3::new(0.0, 0.0, 3.0), Primitive::sphere(1.0), 0.5)
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn fog_volumes_absorb_along_their_chord() {
+    let options = fog_ball(Vec::new());
+    let raymarcher = raymarcher(&options, 0.0);
+    let through = raymarcher.march_fog_volumes(Vec3::ZERO, Vec3::Z, 100.0, 0.5);
+    let expected = (-0.5f32 * 2.0).exp();
+    assert!(
+        (through.transmittance.x - expected).abs() < 0.03,
+        "{through:?} {expected}"
+    );
+    assert!(through.inscattering.x > 0.0, "{through:?}");
+
+    // Stopping at a surface halfway through only crosses half of it
+    let half = raymarcher.march_fog_volumes(Vec3::ZERO, Vec3::Z, 3.0, 0.5);
+    assert!(
+        half.transmittance.x > through.transmittance.x,
+        "{half:?} {through:?}"
+    );
+
+    let past = Vec3::new(0.5, 0.0, 1.0).normalize();
+    assert_eq!(
+        raymarcher.march_fog_volumes(Vec3::ZERO, past, 100.0, 0.5),
+        Haze::NONE
+    );
+}
+
+#[test]
+fn geometry_shadows_the_fog() {
+    let lit = fog_ball(Vec::new());
+    // A roof over the fog blocks the sun straight above
+    let shaded = fog_ball(vec![RaymarchingObject::sphere(
+        Vec3::new(0.0, 3.0, 3.0),
+        1.5,
+        0,
+    )]);
+    let scattered = |options: &RenderOptions| {
+        raymarcher(options, 0.0)
+            .march_fog_volumes(Vec3::ZERO, Vec3::Z, 100.0, 0.5)
+            .inscattering
+            .x
+    };
+    let lit = scattered(&lit);
+    let shaded = scattered(&shaded);
+    assert!(shaded < lit * 0.2, "{lit} {shaded}");
+}
+
+#[test]
+fn still_fog_does_not_flicker() {
+    // The volume jitter only depends on the pixel, frames at different times match. A soft
+    // edge and a partial shadow make the result depend on where the samples land
+    let mut options = fog_ball(vec![RaymarchingObject::sphere(
+        Vec3::new(0.8, 3.0, 3.0),
+        1.0,
+        0,
+    )]);
+    options.fog_volumes[0].edge = 0.5;
+    // Pixel (4, 3) of an 8 by 8 frame looks slightly up through the ball
+    let shade = |time: f32| {
+        CpuRaymarcher::new(
+            RaymarchingConstants::new(&options, 8, 8, time),
+            &options.raymarching_objects,
+            &options.camera,
+        )
+        .with_atmosphere(&options.atmosphere)
+        .with_fog_volumes(&options.fog_volumes)
+        .shade_pixel(Vec2::new(4.0, 3.0))
+    };
+    let now = shade(0.0);
+    assert!(now.max_element() > 0.0, "{now}");
+    assert_eq!(now, shade(1.5));
+}